@@ -0,0 +1,58 @@
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use midi_types::{Channel, Control, MidiMessage, Note, Value7};
+use rtpmidi::packets::midi_packets::midi_event::MidiEvent;
+use rtpmidi::packets::midi_packets::midi_packet::MidiPacket;
+use rtpmidi::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use zerocopy::FromBytes;
+use zerocopy::network_endian::{U16, U32};
+
+const COMMAND_COUNTS: [usize; 3] = [1, 16, 128];
+
+/// A chord-heavy stream: runs of notes on one channel (so running status applies) broken up by
+/// controller changes and clock ticks, with the small delta times a live performance produces.
+fn dense_command_list(count: usize) -> Vec<MidiEvent<'static>> {
+    (0..count)
+        .map(|i| {
+            let message = match i % 8 {
+                7 => MidiMessage::ControlChange(Channel::C2, Control::from(1), Value7::from((i % 128) as u8)),
+                6 => MidiMessage::TimingClock,
+                _ => MidiMessage::NoteOn(Channel::C1, Note::from((36 + i % 48) as u8), Value7::from(100)),
+            };
+            let delta_time = if i == 0 { None } else { Some((i % 3) as u32 * 40) };
+            MidiEvent::new(delta_time, RtpMidiMessage::MidiMessage(message))
+        })
+        .collect()
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for count in COMMAND_COUNTS {
+        let commands = dense_command_list(count);
+        let mut buffer = [0u8; 2048];
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("write_into", count), &commands, |b, commands| {
+            b.iter(|| MidiPacket::write_into(&mut buffer, U16::new(1), U32::new(2), U32::new(3), black_box(commands), false).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for count in COMMAND_COUNTS {
+        let packet = MidiPacket::new_as_bytes(U16::new(1), U32::new(2), U32::new(3), &dense_command_list(count), false);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("commands", count), &packet[..], |b, bytes| {
+            b.iter(|| {
+                let packet = MidiPacket::ref_from_bytes(black_box(bytes)).unwrap();
+                packet.commands().map(|event| event.delta_time()).sum::<u32>()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode, bench_decode);
+criterion_main!(benches);
@@ -0,0 +1,4 @@
+//! The C API of `rtpmidi::capi` as a shared library, declared in `include/rtpmidi.h`. It lives in a crate of its
+//! own so `rtpmidi` stays a plain library, one that still builds without `std`.
+
+pub use rtpmidi::capi::*;
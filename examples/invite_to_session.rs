@@ -11,13 +11,13 @@ async fn main() {
         5004,
         "My Session",
         54321,
-        InviteResponder::new(|_packet, name, _addr| name.to_str().unwrap() == "Bob's jam session"),
+        InviteResponder::new(|_packet, name, _addr| name == "Bob's jam session"),
     )
     .await
     .expect("Failed to start RTP MIDI session");
 
     let addr = SocketAddr::new("192.168.0.28".parse().unwrap(), 5006);
-    session.invite_participant(addr).await;
+    session.invite_participant(addr).await.expect("Failed to invite participant");
 
     tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl+C");
     session.stop_gracefully().await;
@@ -0,0 +1,53 @@
+/*
+ * C API for rtpmidi, built as target/release/librtpmidi.so (or .dylib, or rtpmidi.dll) with
+ * `cargo build --release -p rtpmidi-capi`.
+ * See src/capi.rs for the details of each function.
+ */
+#ifndef RTPMIDI_H
+#define RTPMIDI_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RTPMIDI_OK 0
+/* A pointer was null, a string wasn't valid, or the bytes to send weren't complete MIDI messages. */
+#define RTPMIDI_ERR_INVALID_ARGUMENT (-1)
+/* The session couldn't do what was asked, for instance because a socket failed. */
+#define RTPMIDI_ERR_SESSION (-2)
+
+typedef struct rtpmidi_session rtpmidi_session;
+
+/*
+ * Called with each message received, from one of the session's own threads. `data` holds the message's bytes,
+ * status byte first, with SysEx messages between their F0 and F7; it is only valid during the call.
+ */
+typedef void (*rtpmidi_callback)(void *user_data, const uint8_t *data, size_t len);
+
+/* Starts a session on `port` (its MIDI port is `port + 1`) that accepts every invitation. Returns NULL on failure. */
+rtpmidi_session *rtpmidi_session_create(uint16_t port, const char *name, uint32_t ssrc);
+
+/*
+ * Sets the function called with each message received, replacing any set before. NULL stops delivery. Returns once
+ * calls to the callback it replaces have returned, so its user data can be freed then. Not to be called from the
+ * callback.
+ */
+int rtpmidi_session_set_callback(rtpmidi_session *session, rtpmidi_callback callback, void *user_data);
+
+/* Invites the peer whose control port is at `addr`, such as "192.168.0.10:5004". */
+int rtpmidi_session_invite(rtpmidi_session *session, const char *addr);
+
+/* Sends `len` bytes of whole MIDI messages to every participant in one packet. */
+int rtpmidi_session_send(rtpmidi_session *session, const uint8_t *data, size_t len);
+
+/* Says goodbye to every participant, stops the session and frees it. Not to be called from the callback. */
+void rtpmidi_session_destroy(rtpmidi_session *session);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RTPMIDI_H */
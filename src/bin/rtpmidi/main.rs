@@ -0,0 +1,283 @@
+//! `rtpmidi`, a command line tool for poking at RTP-MIDI peers without writing any code.
+
+mod message_args;
+
+use std::error::Error;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Arg, ArgMatches, Command, value_parser};
+use rtpmidi::sessions::events::event_handling::{MidiMessageEvent, ParticipantJoinedEvent, ParticipantLeftEvent, SysExPacketEvent};
+use rtpmidi::sessions::invite_responder::InviteResponder;
+use rtpmidi::sessions::rtp_midi_session::RtpMidiSession;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::{Notify, mpsc};
+use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+use message_args::{MESSAGE_SYNTAX, OutgoingMessage, parse_message};
+
+type CliResult = Result<(), Box<dyn Error>>;
+
+/// How long `send` waits for the peer to accept the invitation.
+const JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn cli() -> Command {
+    let port = Arg::new("port")
+        .long("port")
+        .short('p')
+        .value_parser(value_parser!(u16))
+        .default_value("5004")
+        .help("Control port to listen on; the MIDI port is the one after it");
+    let name = Arg::new("name")
+        .long("name")
+        .short('n')
+        .default_value("rtpmidi")
+        .help("Session name shown to peers");
+    let peer = |id: &'static str| {
+        Arg::new(id)
+            .value_parser(value_parser!(SocketAddr))
+            .help("Control port address of the peer, e.g. 192.168.0.10:5004")
+    };
+
+    let command = Command::new("rtpmidi")
+        .about("Talk to RTP-MIDI (AppleMIDI) peers from the command line")
+        .version(env!("CARGO_PKG_VERSION"))
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("listen")
+                .about("Accept invitations and print the traffic that arrives")
+                .args([port.clone(), name.clone()]),
+        )
+        .subcommand(Command::new("invite").about("Invite a peer and print the traffic that arrives").args([
+            port.clone(),
+            name.clone(),
+            peer("peer").required(true),
+        ]))
+        .subcommand(
+            Command::new("send")
+                .about("Invite a peer, send it some messages and leave")
+                .args([
+                    port.clone(),
+                    name.clone(),
+                    peer("peer").required(true),
+                    Arg::new("messages")
+                        .num_args(1..)
+                        .help("Messages to send, each quoted as one argument; read from stdin, one per line, if none are given"),
+                ])
+                .after_help(MESSAGE_SYNTAX),
+        )
+        .subcommand(
+            Command::new("bridge")
+                .about("Run two sessions and pass everything each receives on through the other")
+                .args([
+                    port.help("Control port of the first session; the second uses the one two above it"),
+                    name,
+                    peer("a").long("a").help("Peer for the first session to invite"),
+                    peer("b").long("b").help("Peer for the second session to invite"),
+                ]),
+        );
+
+    #[cfg(feature = "mdns")]
+    let command = command.subcommand(
+        Command::new("discover")
+            .about("List the RTP-MIDI sessions advertised on the local network")
+            .arg(
+                Arg::new("timeout")
+                    .long("timeout")
+                    .short('t')
+                    .value_parser(value_parser!(u64))
+                    .default_value("3")
+                    .help("Seconds to listen for advertisements"),
+            ),
+    );
+
+    command
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::registry().with(fmt::layer()).with(EnvFilter::from_default_env()).init();
+
+    let matches = cli().get_matches();
+    let result = match matches.subcommand() {
+        Some(("listen", args)) => listen(args).await,
+        Some(("invite", args)) => invite(args).await,
+        Some(("send", args)) => send(args).await,
+        Some(("bridge", args)) => bridge(args).await,
+        #[cfg(feature = "mdns")]
+        Some(("discover", args)) => discover(args),
+        _ => unreachable!("a subcommand is required"),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("rtpmidi: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn start_session(port: u16, name: &str) -> Result<Arc<RtpMidiSession>, Box<dyn Error>> {
+    Ok(RtpMidiSession::start(port, name, rand::random(), InviteResponder::Accept).await?)
+}
+
+async fn start_from_args(args: &ArgMatches) -> Result<Arc<RtpMidiSession>, Box<dyn Error>> {
+    start_session(*args.get_one("port").unwrap(), args.get_one::<String>("name").unwrap()).await
+}
+
+async fn print_traffic(session: &RtpMidiSession) {
+    session
+        .add_listener(ParticipantJoinedEvent, |participant| {
+            println!("joined: {} ({})", participant.name(), participant.addr());
+        })
+        .await;
+    session
+        .add_listener(ParticipantLeftEvent, |participant| {
+            println!("left: {} ({})", participant.name(), participant.addr());
+        })
+        .await;
+    session
+        .add_listener(MidiMessageEvent, |(message, delta_time)| {
+            println!("{message:?} (delta time {delta_time})");
+        })
+        .await;
+    session
+        .add_listener(SysExPacketEvent, |sysex| {
+            let hex: Vec<String> = sysex.iter().map(|byte| format!("{byte:02X}")).collect();
+            println!("SysEx F0 {} F7", hex.join(" "));
+        })
+        .await;
+}
+
+async fn wait_for_ctrl_c(sessions: &[&RtpMidiSession]) -> CliResult {
+    tokio::signal::ctrl_c().await?;
+    for session in sessions {
+        session.stop_gracefully().await;
+    }
+    Ok(())
+}
+
+async fn listen(args: &ArgMatches) -> CliResult {
+    let session = start_from_args(args).await?;
+    print_traffic(&session).await;
+    eprintln!("Listening on port {}, press Ctrl+C to stop", args.get_one::<u16>("port").unwrap());
+    wait_for_ctrl_c(&[&session]).await
+}
+
+async fn invite(args: &ArgMatches) -> CliResult {
+    let session = start_from_args(args).await?;
+    print_traffic(&session).await;
+    session.invite_participant(*args.get_one("peer").unwrap()).await?;
+    wait_for_ctrl_c(&[&session]).await
+}
+
+async fn send(args: &ArgMatches) -> CliResult {
+    // Check the messages before getting the peer involved
+    let messages = args
+        .get_many::<String>("messages")
+        .map(|messages| messages.map(|message| parse_message(message)).collect::<Result<Vec<_>, _>>())
+        .transpose()
+        .map_err(|e| format!("{e}\n\n{MESSAGE_SYNTAX}"))?;
+
+    let session = start_from_args(args).await?;
+    let joined = Arc::new(Notify::new());
+    let joined_listener = Arc::clone(&joined);
+    session.add_listener(ParticipantJoinedEvent, move |_| joined_listener.notify_one()).await;
+    session.invite_participant(*args.get_one("peer").unwrap()).await?;
+    tokio::time::timeout(JOIN_TIMEOUT, joined.notified())
+        .await
+        .map_err(|_| "the peer didn't accept the invitation")?;
+
+    let result = match messages {
+        Some(messages) => send_all(&session, &messages).await,
+        None => send_stdin(&session).await,
+    };
+    session.stop_gracefully().await;
+    result
+}
+
+async fn send_all(session: &RtpMidiSession, messages: &[OutgoingMessage]) -> CliResult {
+    for message in messages {
+        session.send_midi(&message.as_rtp()).await?;
+    }
+    Ok(())
+}
+
+async fn send_stdin(session: &RtpMidiSession) -> CliResult {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // A typo shouldn't end an interactive session, so report it and carry on
+        match parse_message(line) {
+            Ok(message) => session.send_midi(&message.as_rtp()).await?,
+            Err(e) => eprintln!("rtpmidi: {e}"),
+        }
+    }
+    Ok(())
+}
+
+async fn bridge(args: &ArgMatches) -> CliResult {
+    let port = *args.get_one::<u16>("port").unwrap();
+    let name = args.get_one::<String>("name").unwrap();
+    let a = start_session(port, &format!("{name} A")).await?;
+    let b = start_session(port + 2, &format!("{name} B")).await?;
+    forward(&a, &b).await;
+    forward(&b, &a).await;
+
+    for (session, peer) in [(&a, "a"), (&b, "b")] {
+        if let Some(&peer) = args.get_one::<SocketAddr>(peer) {
+            session.invite_participant(peer).await?;
+        }
+    }
+    eprintln!("Bridging ports {port} and {}, press Ctrl+C to stop", port + 2);
+    wait_for_ctrl_c(&[&a, &b]).await
+}
+
+/// Sends everything `from` receives on through `to`, in the order it arrived.
+async fn forward(from: &RtpMidiSession, to: &Arc<RtpMidiSession>) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let sysex_sender = sender.clone();
+    from.add_listener(MidiMessageEvent, move |(message, _)| {
+        let _ = sender.send(OutgoingMessage::Midi(message));
+    })
+    .await;
+    from.add_listener(SysExPacketEvent, move |sysex| {
+        let _ = sysex_sender.send(OutgoingMessage::SysEx(sysex.to_vec()));
+    })
+    .await;
+
+    let to = Arc::clone(to);
+    tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            if let Err(e) = to.send_midi(&message.as_rtp()).await {
+                eprintln!("rtpmidi: failed to forward {message:?}: {e}");
+            }
+        }
+    });
+}
+
+#[cfg(feature = "mdns")]
+fn discover(args: &ArgMatches) -> CliResult {
+    use mdns_sd::{ServiceDaemon, ServiceEvent};
+    use std::time::Instant;
+
+    let deadline = Instant::now() + Duration::from_secs(*args.get_one("timeout").unwrap());
+    let mdns = ServiceDaemon::new()?;
+    let events = mdns.browse("_apple-midi._udp.local.")?;
+    while let Ok(event) = events.recv_deadline(deadline) {
+        if let ServiceEvent::ServiceResolved(info) = event {
+            let name = info.get_fullname().trim_end_matches("._apple-midi._udp.local.");
+            for addr in info.get_addresses() {
+                println!("{name}\t{}", SocketAddr::new(*addr, info.get_port()));
+            }
+        }
+    }
+    let _ = mdns.shutdown();
+    Ok(())
+}
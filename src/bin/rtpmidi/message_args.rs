@@ -0,0 +1,172 @@
+use std::iter::Peekable;
+use std::str::SplitWhitespace;
+
+use midi_types::{Channel, Control, MidiMessage, Note, Program, Value7, Value14};
+use rtpmidi::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+
+pub const MESSAGE_SYNTAX: &str = "\
+Messages are written as a name followed by its numbers, with channels counted from 1 to 16:
+  note-on CHANNEL KEY [VELOCITY]     velocity defaults to 100
+  note-off CHANNEL KEY [VELOCITY]    velocity defaults to 0
+  cc CHANNEL CONTROL VALUE
+  program CHANNEL PROGRAM
+  pitch-bend CHANNEL VALUE           0 to 16383, centred on 8192
+  pressure CHANNEL VALUE
+  start | continue | stop | clock
+  sysex HEX...                       the bytes inside F0 ... F7, e.g. `sysex 7e 7f 06 01`";
+
+/// A message read from the command line, owning its SysEx bytes so it can outlive the line it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutgoingMessage {
+    Midi(MidiMessage),
+    SysEx(Vec<u8>),
+}
+
+impl OutgoingMessage {
+    pub fn as_rtp(&self) -> RtpMidiMessage<'_> {
+        match self {
+            OutgoingMessage::Midi(message) => RtpMidiMessage::MidiMessage(*message),
+            OutgoingMessage::SysEx(sysex) => RtpMidiMessage::SysEx(sysex),
+        }
+    }
+}
+
+/// Reads one message in the syntax of [`MESSAGE_SYNTAX`].
+pub fn parse_message(text: &str) -> Result<OutgoingMessage, String> {
+    let mut words = text.split_whitespace();
+    let name = words.next().ok_or("empty message")?;
+    let mut numbers = Numbers { name, words: words.peekable() };
+
+    let message = match name {
+        "note-on" => MidiMessage::NoteOn(numbers.channel()?, numbers.note()?, numbers.value7_or(100)?),
+        "note-off" => MidiMessage::NoteOff(numbers.channel()?, numbers.note()?, numbers.value7_or(0)?),
+        "cc" => MidiMessage::ControlChange(numbers.channel()?, numbers.control()?, numbers.value7()?),
+        "program" => MidiMessage::ProgramChange(numbers.channel()?, numbers.program()?),
+        "pitch-bend" => MidiMessage::PitchBendChange(numbers.channel()?, Value14::from(numbers.number(0, 16383)?)),
+        "pressure" => MidiMessage::ChannelPressure(numbers.channel()?, numbers.value7()?),
+        "start" => MidiMessage::Start,
+        "continue" => MidiMessage::Continue,
+        "stop" => MidiMessage::Stop,
+        "clock" => MidiMessage::TimingClock,
+        "sysex" => return numbers.sysex().map(OutgoingMessage::SysEx),
+        _ => return Err(format!("unknown message `{name}`")),
+    };
+    numbers.finish()?;
+    Ok(OutgoingMessage::Midi(message))
+}
+
+/// The words after a message's name, read as the numbers it needs.
+struct Numbers<'a> {
+    name: &'a str,
+    words: Peekable<SplitWhitespace<'a>>,
+}
+
+impl Numbers<'_> {
+    fn number(&mut self, min: u16, max: u16) -> Result<u16, String> {
+        let word = self.words.next().ok_or_else(|| format!("`{}` needs more numbers", self.name))?;
+        word.parse()
+            .ok()
+            .filter(|number| (min..=max).contains(number))
+            .ok_or_else(|| format!("`{word}` isn't a number from {min} to {max}"))
+    }
+
+    fn channel(&mut self) -> Result<Channel, String> {
+        Ok(Channel::from(self.number(1, 16)? as u8 - 1))
+    }
+
+    fn note(&mut self) -> Result<Note, String> {
+        Ok(Note::from(self.number(0, 127)? as u8))
+    }
+
+    // midi-types only takes controls and programs below 127
+    fn control(&mut self) -> Result<Control, String> {
+        Ok(Control::from(self.number(0, 126)? as u8))
+    }
+
+    fn program(&mut self) -> Result<Program, String> {
+        Ok(Program::from(self.number(0, 126)? as u8))
+    }
+
+    fn value7(&mut self) -> Result<Value7, String> {
+        Ok(Value7::from(self.number(0, 127)? as u8))
+    }
+
+    fn value7_or(&mut self, default: u8) -> Result<Value7, String> {
+        if self.words.peek().is_none() {
+            return Ok(Value7::from(default));
+        }
+        self.value7()
+    }
+
+    fn sysex(&mut self) -> Result<Vec<u8>, String> {
+        let hex: String = self.words.by_ref().collect();
+        if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+            return Err(format!("`{hex}` isn't an even number of hex digits"));
+        }
+        let mut bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("`{hex}` isn't hex")))
+            .collect::<Result<Vec<_>, _>>()?;
+        // The start and end bytes are added when sending, so take them off if they were given
+        if bytes.first() == Some(&0xF0) {
+            bytes.remove(0);
+        }
+        if bytes.last() == Some(&0xF7) {
+            bytes.pop();
+        }
+        if let Some(byte) = bytes.iter().find(|&&byte| byte > 0x7F) {
+            return Err(format!("SysEx data byte {byte:02X} has its top bit set"));
+        }
+        Ok(bytes)
+    }
+
+    fn finish(mut self) -> Result<(), String> {
+        match self.words.next() {
+            Some(word) => Err(format!("unexpected `{word}` after `{}`", self.name)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_messages() {
+        assert_eq!(
+            parse_message("note-on 1 60"),
+            Ok(OutgoingMessage::Midi(MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100))))
+        );
+        assert_eq!(
+            parse_message("  cc 16 7 127 "),
+            Ok(OutgoingMessage::Midi(MidiMessage::ControlChange(
+                Channel::C16,
+                Control::from(7),
+                Value7::from(127)
+            )))
+        );
+        assert_eq!(
+            parse_message("pitch-bend 2 8192"),
+            Ok(OutgoingMessage::Midi(MidiMessage::PitchBendChange(Channel::C2, Value14::from(8192u16))))
+        );
+    }
+
+    #[test]
+    fn test_sysex_start_and_end_bytes_are_optional() {
+        assert_eq!(parse_message("sysex 7e 7f 06 01"), Ok(OutgoingMessage::SysEx(vec![0x7E, 0x7F, 0x06, 0x01])));
+        assert_eq!(parse_message("sysex F07E7F0601F7"), Ok(OutgoingMessage::SysEx(vec![0x7E, 0x7F, 0x06, 0x01])));
+        assert!(parse_message("sysex 7e 80").is_err());
+        assert!(parse_message("sysex 7e 0").is_err());
+    }
+
+    #[test]
+    fn test_bad_messages_are_rejected() {
+        assert!(parse_message("").is_err());
+        assert!(parse_message("note-on 0 60").is_err());
+        assert!(parse_message("note-on 1 128").is_err());
+        assert!(parse_message("cc 1 7").is_err());
+        assert!(parse_message("stop 1").is_err());
+        assert!(parse_message("hello").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! A flat C API for embedding a session in C and C++ applications and plugin hosts, declared in
+//! `include/rtpmidi.h`. Build it as a shared library with `cargo build --release -p rtpmidi-capi`.
+//!
+//! Each session runs on a tokio runtime of its own, so the host doesn't need to know about async Rust. Received
+//! MIDI is handed to a callback as raw bytes, one message at a time, and MIDI to send is taken the same way.
+
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::net::SocketAddr;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::{Arc, PoisonError, RwLock};
+
+use tokio::runtime::Runtime;
+
+use crate::packets::midi_packets::midi_event::MidiEvent;
+use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use crate::packets::midi_packets::util::next_running_status;
+use crate::sessions::events::event_handling::{MidiMessageEvent, SysExPacketEvent};
+use crate::sessions::invite_responder::InviteResponder;
+use crate::sessions::rtp_midi_session::RtpMidiSession;
+
+pub const RTPMIDI_OK: c_int = 0;
+/// A pointer was null, a string wasn't valid, or the bytes to send weren't complete MIDI messages.
+pub const RTPMIDI_ERR_INVALID_ARGUMENT: c_int = -1;
+/// The session couldn't do what was asked, for instance because a socket failed.
+pub const RTPMIDI_ERR_SESSION: c_int = -2;
+
+/// Called with each message received, from one of the session's own threads. `data` holds the message's bytes,
+/// status byte first, with SysEx messages between their F0 and F7; it is only valid during the call.
+pub type RtpMidiCallback = unsafe extern "C" fn(user_data: *mut c_void, data: *const u8, len: usize);
+
+#[derive(Clone, Copy)]
+struct Callback {
+    function: RtpMidiCallback,
+    user_data: *mut c_void,
+}
+
+// The host promises the user data can be used from the session's threads when it registers the callback
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+/// A session and the runtime it runs on, `rtpmidi_session` to C.
+pub struct CSession {
+    runtime: Runtime,
+    session: Arc<RtpMidiSession>,
+    /// Read locked for as long as the callback runs, so replacing it waits for the calls under way.
+    callback: Arc<RwLock<Option<Callback>>>,
+}
+
+impl CSession {
+    /// Runs `future` to completion, also when called from the callback, on one of the runtime's own threads.
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let handle = self.runtime.handle();
+        tokio::task::block_in_place(|| handle.block_on(future))
+    }
+}
+
+/// Runs `f`, turning a panic into an error rather than letting it unwind into C.
+fn guard(f: impl FnOnce() -> c_int) -> c_int {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or(RTPMIDI_ERR_SESSION)
+}
+
+fn deliver(callback: &RwLock<Option<Callback>>, message: &RtpMidiMessage) {
+    let current = callback.read().unwrap_or_else(PoisonError::into_inner);
+    let Some(callback) = *current else {
+        return;
+    };
+    let mut bytes = Vec::with_capacity(message.len());
+    message.write(&mut bytes, None);
+    // SAFETY: the host registered the callback along with the user data it expects
+    unsafe { (callback.function)(callback.user_data, bytes.as_ptr(), bytes.len()) };
+}
+
+/// Starts a session on `port` (its MIDI port is `port + 1`) that accepts every invitation. Returns null if the
+/// session couldn't be started.
+///
+/// # Safety
+/// `name` must be null or point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtpmidi_session_create(port: u16, name: *const c_char, ssrc: u32) -> *mut CSession {
+    if name.is_null() {
+        return std::ptr::null_mut();
+    }
+    // SAFETY: checked for null above, and the caller promises it is NUL-terminated
+    let Ok(name) = unsafe { CStr::from_ptr(name) }.to_str() else {
+        return std::ptr::null_mut();
+    };
+
+    catch_unwind(|| {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().ok()?;
+        let callback: Arc<RwLock<Option<Callback>>> = Arc::default();
+        let session = runtime.block_on(async {
+            let session = RtpMidiSession::start(port, name, ssrc, InviteResponder::Accept).await.ok()?;
+            let midi_callback = Arc::clone(&callback);
+            session
+                .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+                    deliver(&midi_callback, &RtpMidiMessage::MidiMessage(message));
+                })
+                .await;
+            let sysex_callback = Arc::clone(&callback);
+            session
+                .add_listener(SysExPacketEvent, move |sysex| deliver(&sysex_callback, &RtpMidiMessage::SysEx(sysex)))
+                .await;
+            Some(session)
+        })?;
+        Some(Box::into_raw(Box::new(CSession { runtime, session, callback })))
+    })
+    .ok()
+    .flatten()
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Sets the function called with each message received, replacing any set before. A null `callback` stops
+/// messages being delivered. Returns once calls to the callback it replaces have returned, so its user data can be
+/// freed then.
+///
+/// # Safety
+/// `session` must be null or come from [`rtpmidi_session_create`], and `user_data` must be usable from any thread
+/// for as long as the callback is set. It mustn't be called from the callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtpmidi_session_set_callback(session: *mut CSession, callback: Option<RtpMidiCallback>, user_data: *mut c_void) -> c_int {
+    // SAFETY: the caller promises the pointer is null or valid
+    let Some(session) = (unsafe { session.as_ref() }) else {
+        return RTPMIDI_ERR_INVALID_ARGUMENT;
+    };
+    *session.callback.write().unwrap_or_else(PoisonError::into_inner) = callback.map(|function| Callback { function, user_data });
+    RTPMIDI_OK
+}
+
+/// Invites the peer whose control port is at `addr`, written like `"192.168.0.10:5004"`. Returns as soon as the
+/// invitation is sent; the peer joins once it accepts.
+///
+/// # Safety
+/// `session` must be null or come from [`rtpmidi_session_create`], and `addr` must be null or point to a
+/// NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtpmidi_session_invite(session: *mut CSession, addr: *const c_char) -> c_int {
+    // SAFETY: the caller promises the pointers are null or valid
+    let (Some(session), false) = (unsafe { session.as_ref() }, addr.is_null()) else {
+        return RTPMIDI_ERR_INVALID_ARGUMENT;
+    };
+    let Some(addr) = unsafe { CStr::from_ptr(addr) }.to_str().ok().and_then(|addr| addr.parse::<SocketAddr>().ok()) else {
+        return RTPMIDI_ERR_INVALID_ARGUMENT;
+    };
+    guard(|| match session.block_on(session.session.invite_participant(addr)) {
+        Ok(()) => RTPMIDI_OK,
+        Err(_) => RTPMIDI_ERR_SESSION,
+    })
+}
+
+/// Sends `len` bytes of MIDI to every participant in one packet. The bytes must be whole messages; running status
+/// may be used between them, and SysEx messages go between their F0 and F7.
+///
+/// # Safety
+/// `session` must be null or come from [`rtpmidi_session_create`], and `data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtpmidi_session_send(session: *mut CSession, data: *const u8, len: usize) -> c_int {
+    // SAFETY: the caller promises the pointers are null or valid
+    let (Some(session), false) = (unsafe { session.as_ref() }, data.is_null()) else {
+        return RTPMIDI_ERR_INVALID_ARGUMENT;
+    };
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+
+    guard(|| {
+        let mut events = Vec::new();
+        let mut rest = data;
+        let mut running_status = None;
+        while !rest.is_empty() {
+            let Ok((event, remaining)) = MidiEvent::from_be_bytes(rest, false, running_status) else {
+                return RTPMIDI_ERR_INVALID_ARGUMENT;
+            };
+            running_status = next_running_status(running_status, event.command().status());
+            events.push(event);
+            rest = remaining;
+        }
+        match session.block_on(session.session.send_midi_batch(&events)) {
+            Ok(()) => RTPMIDI_OK,
+            Err(_) => RTPMIDI_ERR_SESSION,
+        }
+    })
+}
+
+/// Says goodbye to every participant, stops the session and frees it.
+///
+/// # Safety
+/// `session` must be null or come from [`rtpmidi_session_create`], and must not be used afterwards. It mustn't be
+/// called from the callback.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rtpmidi_session_destroy(session: *mut CSession) {
+    if session.is_null() {
+        return;
+    }
+    // SAFETY: the caller promises the pointer came from rtpmidi_session_create and hands it back here
+    let session = unsafe { Box::from_raw(session) };
+    let _ = catch_unwind(AssertUnwindSafe(|| session.runtime.block_on(session.session.stop_gracefully())));
+}
@@ -0,0 +1,35 @@
+use alloc::string::String;
+
+use thiserror::Error;
+
+use crate::packets::error::PacketParseError;
+
+/// The error type returned by sessions and by packet serialization.
+#[derive(Debug, Error)]
+pub enum RtpMidiError {
+    /// A received packet could not be decoded.
+    #[error("Failed to parse packet: {0}")]
+    Parse(#[from] PacketParseError),
+    /// A peer answered a session handshake in a way that doesn't match what we sent.
+    #[error("Session handshake failed: {0}")]
+    Handshake(&'static str),
+    /// The underlying socket failed.
+    #[cfg(feature = "std")]
+    #[error("Transport error: {0}")]
+    Transport(#[from] std::io::Error),
+    /// A peer didn't answer in time.
+    #[error("Timed out waiting for {0}")]
+    Timeout(&'static str),
+    /// The operation doesn't make sense for the current state of the session or participant.
+    #[error("Invalid state: {0}")]
+    InvalidState(&'static str),
+    /// A caller-provided value can't be used, e.g. a session name containing a NUL byte.
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    /// A command can't be written as it is, e.g. a SysEx message with a status byte in its data.
+    #[error("Invalid command: {0}")]
+    InvalidCommand(&'static str),
+    /// A caller-provided buffer is too small to hold the packet being written into it.
+    #[error("Packet needs {needed} bytes but the buffer only holds {available}")]
+    BufferTooSmall { needed: usize, available: usize },
+}
@@ -8,10 +8,33 @@
 //! - **Invitation Handling**: Can send and receive invitations to join RTP MIDI sessions.
 //!   Users can control the logic for accepting or rejecting invitations.
 //! - **SysEx Support**: Supports sending and receiving System Exclusive (SysEx) messages.
+//! - **Serde**: With the `serde` feature, parsed packets, participants and event payloads can be serialized,
+//!   e.g. to log them as JSON.
+//! - **`no_std`**: With default features disabled, only the [`packets`] module is built, as a `no_std` + `alloc`
+//!   library. Firmware with its own UDP stack can use it to parse and build packets without tokio.
+//! - **OSC**: With the `osc` feature, `sessions::osc` bridges a session's MIDI to and from OSC peers.
+//! - **Metrics**: With the `metrics` feature, `sessions::metrics` renders session statistics for Prometheus to scrape.
+//! - **Logging**: Through `tracing` by default. Applications standardized on `log` can disable default features and
+//!   enable `std` and `log` instead, leaving the tracing stack out. With default features off, `std` alone logs
+//!   nothing; add `tracing` to keep logging as before.
+//! - **C API**: With the `capi` feature, the `capi` module offers a flat C API for embedding sessions in C and C++ hosts.
 //!
 //! ## Unsupported Features
 //! - **Recovery Journal**: The library does not implement the recovery journal feature of RTP MIDI.
 //!   This means that if a packet is lost, it cannot be recovered.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod error;
+#[cfg(feature = "std")]
+mod logging;
 pub mod packets;
+#[cfg(feature = "std")]
 mod participant;
+#[cfg(feature = "serde")]
+pub mod serde_helpers;
+#[cfg(feature = "std")]
 pub mod sessions;
@@ -0,0 +1,88 @@
+//! The logging backend, picked by feature: `tracing` (the default), or `log` for applications standardized on it. With
+//! both, `tracing` is used; its own `log` feature forwards its events to `log` too. With neither, nothing is logged.
+//!
+//! Events are written the `tracing` way, `event!(Level::INFO, field = value, "message {}", arg)`. The `log` backend
+//! keeps the message and drops the fields. Spans only exist with `tracing`, so `#[instrument]` is applied with
+//! `cfg_attr` and spans are only recorded into behind `#[cfg(feature = "tracing")]`.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{Level, event};
+
+/// The levels, under the names `tracing` gives them.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Level;
+
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+impl Level {
+    pub const ERROR: log::Level = log::Level::Error;
+    pub const WARN: log::Level = log::Level::Warn;
+    pub const INFO: log::Level = log::Level::Info;
+    pub const DEBUG: log::Level = log::Level::Debug;
+    pub const TRACE: log::Level = log::Level::Trace;
+}
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+impl Level {
+    pub const ERROR: () = ();
+    pub const WARN: () = ();
+    pub const INFO: () = ();
+    pub const DEBUG: () = ();
+    pub const TRACE: () = ();
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:expr, $($rest:tt)+) => {
+        $crate::logging::event_fields!(($level) [] $($rest)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use event;
+
+/// Collects the values of the fields up to the message, so they still count as used.
+#[cfg(not(feature = "tracing"))]
+macro_rules! event_fields {
+    (($level:expr) [$($value:expr),*] $message:literal $($args:tt)*) => {{
+        if false {
+            $(let _ = &$value;)*
+        }
+        $crate::logging::write_log!($level, $message $($args)*)
+    }};
+    (($level:expr) [$($value:expr),*] $key:ident = %$field:expr, $($rest:tt)+) => {
+        $crate::logging::event_fields!(($level) [$($value,)* $field] $($rest)+)
+    };
+    (($level:expr) [$($value:expr),*] $key:ident = ?$field:expr, $($rest:tt)+) => {
+        $crate::logging::event_fields!(($level) [$($value,)* $field] $($rest)+)
+    };
+    (($level:expr) [$($value:expr),*] $key:ident = $field:expr, $($rest:tt)+) => {
+        $crate::logging::event_fields!(($level) [$($value,)* $field] $($rest)+)
+    };
+    (($level:expr) [$($value:expr),*] $field:ident, $($rest:tt)+) => {
+        $crate::logging::event_fields!(($level) [$($value,)* $field] $($rest)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use event_fields;
+
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+macro_rules! write_log {
+    ($level:expr, $($message:tt)+) => {
+        ::log::log!($level, $($message)+)
+    };
+}
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! write_log {
+    // Still type checked, so the arguments count as used
+    ($level:expr, $($message:tt)+) => {
+        if false {
+            let _ = $level;
+            let _ = format_args!($($message)+);
+        }
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use write_log;
@@ -0,0 +1,478 @@
+//! Construction of AppleMIDI control packets and RTP-MIDI data packets from plain values, for tools that need to
+//! put packets on the wire without running a session (testers, analyzers, proxies).
+//!
+//! ```
+//! use midi_types::{Channel, MidiMessage, Note, Value7};
+//! use rtpmidi::packets::builder::{ControlPacketBuilder, MidiPacketBuilder};
+//!
+//! let invitation = ControlPacketBuilder::invitation(0x1234, 0xCAFE, c"My Session").build();
+//! assert_eq!(&invitation[..4], b"\xFF\xFFIN");
+//!
+//! let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+//! let packet = MidiPacketBuilder::new(0xCAFE).sequence_number(7).timestamp(1000).message(note_on.into()).build();
+//!
+//! let mut buffer = [0u8; 64];
+//! let len = MidiPacketBuilder::new(0xCAFE).sequence_number(7).timestamp(1000).message(note_on.into()).write_into(&mut buffer)?;
+//! assert_eq!(&buffer[..len], &packet[..]);
+//! # Ok::<(), rtpmidi::error::RtpMidiError>(())
+//! ```
+//!
+//! [`MidiBatchBuilder`] lays out a timed batch of commands, to send with a session or put in a packet:
+//!
+//! ```
+//! use midi_types::{Channel, Note, Value7};
+//! use rtpmidi::packets::builder::MidiBatchBuilder;
+//!
+//! let batch = MidiBatchBuilder::new()
+//!     .note_on(Channel::C1, Note::C4, Value7::from(100))
+//!     .at(480)
+//!     .cc(Channel::C1, 64.into(), Value7::from(127))
+//!     .at(480)
+//!     .note_off(Channel::C1, Note::C4, Value7::from(0))
+//!     .build()?;
+//! assert_eq!(batch[2].delta_time(), 480);
+//! # Ok::<(), rtpmidi::error::RtpMidiError>(())
+//! ```
+
+use alloc::format;
+use alloc::vec::Vec;
+use core::ffi::CStr;
+
+use bytes::Bytes;
+use midi_types::{Channel, Control, MidiMessage, Note, Program, Value7, Value14};
+use zerocopy::network_endian::{U16, U32, U64};
+
+use crate::error::RtpMidiError;
+use crate::packets::control_packets::control_packet::ControlPacket;
+use crate::packets::midi_packets::controller_change::ControllerChange;
+use crate::packets::midi_packets::delta_time::MAX_DELTA_TIME;
+use crate::packets::midi_packets::midi_event::MidiEvent;
+use crate::packets::midi_packets::midi_packet::{MidiPacket, PacketOptions};
+use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+
+/// An AppleMIDI session control packet, ready to be serialized.
+#[derive(Debug, Clone)]
+pub struct ControlPacketBuilder<'a> {
+    kind: ControlPacketKind<'a>,
+}
+
+#[derive(Debug, Clone)]
+enum ControlPacketKind<'a> {
+    Invitation { initiator_token: u32, ssrc: u32, name: &'a CStr },
+    Acceptance { initiator_token: u32, ssrc: u32, name: &'a CStr },
+    Rejection { initiator_token: u32, ssrc: u32 },
+    Termination { initiator_token: u32, ssrc: u32 },
+    ClockSync { count: u8, timestamps: [u64; 3], ssrc: u32 },
+    BitrateLimit { limit: u32, ssrc: u32 },
+}
+
+impl<'a> ControlPacketBuilder<'a> {
+    /// An `IN` packet inviting the receiver into the session called `name`.
+    pub fn invitation(initiator_token: u32, ssrc: u32, name: &'a CStr) -> Self {
+        Self::from_kind(ControlPacketKind::Invitation { initiator_token, ssrc, name })
+    }
+
+    /// An `OK` packet accepting the invitation carrying `initiator_token`.
+    pub fn acceptance(initiator_token: u32, ssrc: u32, name: &'a CStr) -> Self {
+        Self::from_kind(ControlPacketKind::Acceptance { initiator_token, ssrc, name })
+    }
+
+    /// A `NO` packet declining the invitation carrying `initiator_token`.
+    pub fn rejection(initiator_token: u32, ssrc: u32) -> Self {
+        Self::from_kind(ControlPacketKind::Rejection { initiator_token, ssrc })
+    }
+
+    /// A `BY` packet ending the session that was set up with `initiator_token`.
+    pub fn termination(initiator_token: u32, ssrc: u32) -> Self {
+        Self::from_kind(ControlPacketKind::Termination { initiator_token, ssrc })
+    }
+
+    /// A `CK` packet. `count` is the step of the exchange (0 to 2) and `timestamps` holds one timestamp per step,
+    /// in units of 100 microseconds; only those up to `count` are meaningful.
+    pub fn clock_sync(count: u8, timestamps: [u64; 3], ssrc: u32) -> Self {
+        Self::from_kind(ControlPacketKind::ClockSync { count, timestamps, ssrc })
+    }
+
+    /// An `RL` packet asking the receiver to send no more than `limit` bits per second.
+    pub fn bitrate_limit(limit: u32, ssrc: u32) -> Self {
+        Self::from_kind(ControlPacketKind::BitrateLimit { limit, ssrc })
+    }
+
+    fn from_kind(kind: ControlPacketKind<'a>) -> Self {
+        ControlPacketBuilder { kind }
+    }
+
+    /// Serializes the packet into a new buffer.
+    pub fn build(&self) -> Bytes {
+        match self.kind {
+            ControlPacketKind::Invitation { initiator_token, ssrc, name } => {
+                ControlPacket::new_invitation_as_bytes(U32::new(initiator_token), U32::new(ssrc), name)
+            }
+            ControlPacketKind::Acceptance { initiator_token, ssrc, name } => {
+                ControlPacket::new_acceptance_as_bytes(U32::new(initiator_token), U32::new(ssrc), name)
+            }
+            ControlPacketKind::Rejection { initiator_token, ssrc } => ControlPacket::new_rejection_as_bytes(U32::new(initiator_token), U32::new(ssrc)),
+            ControlPacketKind::Termination { initiator_token, ssrc } => ControlPacket::new_termination_as_bytes(U32::new(initiator_token), U32::new(ssrc)),
+            ControlPacketKind::ClockSync { count, timestamps, ssrc } => ControlPacket::new_clock_sync_as_bytes(count, timestamps.map(U64::new), U32::new(ssrc)),
+            ControlPacketKind::BitrateLimit { limit, ssrc } => ControlPacket::new_bitrate_limit_as_bytes(U32::new(limit), U32::new(ssrc)),
+        }
+    }
+
+    /// Serializes the packet into the start of `buffer`, returning the number of bytes written.
+    /// Fails without writing anything if `buffer` is too small.
+    pub fn write_into(&self, buffer: &mut [u8]) -> Result<usize, RtpMidiError> {
+        match self.kind {
+            ControlPacketKind::Invitation { initiator_token, ssrc, name } => {
+                ControlPacket::write_invitation_into(buffer, U32::new(initiator_token), U32::new(ssrc), name)
+            }
+            ControlPacketKind::Acceptance { initiator_token, ssrc, name } => {
+                ControlPacket::write_acceptance_into(buffer, U32::new(initiator_token), U32::new(ssrc), name)
+            }
+            ControlPacketKind::Rejection { initiator_token, ssrc } => ControlPacket::write_rejection_into(buffer, U32::new(initiator_token), U32::new(ssrc)),
+            ControlPacketKind::Termination { initiator_token, ssrc } => {
+                ControlPacket::write_termination_into(buffer, U32::new(initiator_token), U32::new(ssrc))
+            }
+            ControlPacketKind::ClockSync { count, timestamps, ssrc } => {
+                ControlPacket::write_clock_sync_into(buffer, count, timestamps.map(U64::new), U32::new(ssrc))
+            }
+            ControlPacketKind::BitrateLimit { limit, ssrc } => ControlPacket::write_bitrate_limit_into(buffer, U32::new(limit), U32::new(ssrc)),
+        }
+    }
+}
+
+/// An RTP-MIDI data packet, assembled one command at a time.
+///
+/// The sequence number and timestamp start at zero.
+#[derive(Debug, Clone)]
+pub struct MidiPacketBuilder<'a> {
+    sequence_number: u16,
+    timestamp: u32,
+    ssrc: u32,
+    commands: Vec<MidiEvent<'a>>,
+    options: PacketOptions<'a>,
+}
+
+impl<'a> MidiPacketBuilder<'a> {
+    pub fn new(ssrc: u32) -> Self {
+        MidiPacketBuilder {
+            sequence_number: 0,
+            timestamp: 0,
+            ssrc,
+            commands: Vec::new(),
+            options: PacketOptions::default(),
+        }
+    }
+
+    pub fn sequence_number(mut self, sequence_number: u16) -> Self {
+        self.sequence_number = sequence_number;
+        self
+    }
+
+    /// The RTP timestamp of the first command, in the session's clock units.
+    pub fn timestamp(mut self, timestamp: u32) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Whether the first command carries a delta time of its own.
+    pub fn z_flag(mut self, z_flag: bool) -> Self {
+        self.options.z_flag = z_flag;
+        self
+    }
+
+    /// Whether the first command's status byte is a phantom, left out of the MIDI stream the commands came from under
+    /// running status. It's sent regardless, with the P flag set, see
+    /// [`MidiPacket::phantom_status`](crate::packets::midi_packets::midi_packet::MidiPacket::phantom_status). Only
+    /// counts if the first command is a channel message.
+    pub fn phantom_status(mut self, phantom_status: bool) -> Self {
+        self.options.phantom_status = phantom_status;
+        self
+    }
+
+    /// An encoded recovery journal to send after the commands, with the J flag set. The packet can do without
+    /// commands then, as a journal-only packet, see
+    /// [`MidiPacket::journal`](crate::packets::midi_packets::midi_packet::MidiPacket::journal).
+    pub fn journal(mut self, journal: &'a [u8]) -> Self {
+        self.options.journal = Some(journal);
+        self
+    }
+
+    /// Appends a command played at the same time as the one before it.
+    pub fn message(self, message: RtpMidiMessage<'a>) -> Self {
+        let delta_time = if self.commands.is_empty() { None } else { Some(0) };
+        self.command(MidiEvent::new(delta_time, message))
+    }
+
+    /// Appends a command with an explicit delta time.
+    pub fn command(mut self, command: MidiEvent<'a>) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Appends the Control Change messages carrying a high-resolution controller operation, played at the same time
+    /// as the command before them. An operation that fails [`ControllerChange::check`] has none to append;
+    /// [`MidiBatchBuilder`] reports it instead.
+    pub fn controller_change(self, change: ControllerChange) -> Self {
+        change.messages().into_iter().fold(self, |builder, message| builder.message(message.into()))
+    }
+
+    pub fn commands(mut self, commands: impl IntoIterator<Item = MidiEvent<'a>>) -> Self {
+        self.commands.extend(commands);
+        self
+    }
+
+    /// Serializes the packet into a new buffer.
+    pub fn build(&self) -> Bytes {
+        MidiPacket::new_with_options_as_bytes(
+            U16::new(self.sequence_number),
+            U32::new(self.timestamp),
+            U32::new(self.ssrc),
+            &self.commands,
+            &self.options,
+        )
+    }
+
+    /// Serializes the packet into the start of `buffer`, returning the number of bytes written.
+    /// Fails without writing anything if `buffer` is too small.
+    pub fn write_into(&self, buffer: &mut [u8]) -> Result<usize, RtpMidiError> {
+        MidiPacket::write_with_options_into(
+            buffer,
+            U16::new(self.sequence_number),
+            U32::new(self.timestamp),
+            U32::new(self.ssrc),
+            &self.commands,
+            &self.options,
+        )
+    }
+}
+
+/// A batch of commands, each played a delta time after the one before it, assembled one message at a time.
+///
+/// [`at`](Self::at) sets how long after the previous message the next one is played, in the session's clock units;
+/// messages without one are played at the same time as the previous message. Delta times too large to be sent are
+/// reported by [`build`](Self::build), rather than by each call.
+#[derive(Debug, Clone, Default)]
+pub struct MidiBatchBuilder<'a> {
+    events: Vec<MidiEvent<'a>>,
+    delta_time: u32,
+    invalid_delta_time: Option<u64>,
+    invalid_change: Option<ControllerChange>,
+}
+
+impl<'a> MidiBatchBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Plays the next message `delta_time` after the previous one. Delta times set before the same message add up.
+    ///
+    /// Sessions play the first command of a batch as soon as it arrives, so a delta time before the first message
+    /// only counts in a packet built with [`MidiPacketBuilder::z_flag`].
+    pub fn at(mut self, delta_time: u32) -> Self {
+        let total = u64::from(self.delta_time) + u64::from(delta_time);
+        match u32::try_from(total) {
+            Ok(total) if total <= MAX_DELTA_TIME => self.delta_time = total,
+            _ => self.invalid_delta_time = self.invalid_delta_time.or(Some(total)),
+        }
+        self
+    }
+
+    pub fn message(mut self, message: RtpMidiMessage<'a>) -> Self {
+        let delta_time = match (self.events.is_empty(), self.delta_time) {
+            (true, 0) => None,
+            (_, delta_time) => Some(delta_time),
+        };
+        self.events.push(MidiEvent::new(delta_time, message));
+        self.delta_time = 0;
+        self
+    }
+
+    pub fn note_on(self, channel: Channel, note: Note, velocity: Value7) -> Self {
+        self.message(MidiMessage::NoteOn(channel, note, velocity).into())
+    }
+
+    pub fn note_off(self, channel: Channel, note: Note, velocity: Value7) -> Self {
+        self.message(MidiMessage::NoteOff(channel, note, velocity).into())
+    }
+
+    /// Appends a Control Change.
+    pub fn cc(self, channel: Channel, control: Control, value: Value7) -> Self {
+        self.message(MidiMessage::ControlChange(channel, control, value).into())
+    }
+
+    pub fn program_change(self, channel: Channel, program: Program) -> Self {
+        self.message(MidiMessage::ProgramChange(channel, program).into())
+    }
+
+    pub fn pitch_bend(self, channel: Channel, value: Value14) -> Self {
+        self.message(MidiMessage::PitchBendChange(channel, value).into())
+    }
+
+    /// Appends the Control Change messages carrying a high-resolution controller operation, all played at once.
+    /// One that fails [`ControllerChange::check`] is reported by [`build`](Self::build).
+    pub fn controller_change(mut self, change: ControllerChange) -> Self {
+        if change.check().is_err() {
+            self.invalid_change = self.invalid_change.or(Some(change));
+            return self;
+        }
+        change.messages().into_iter().fold(self, |builder, message| builder.message(message.into()))
+    }
+
+    /// The batch, or [`RtpMidiError::InvalidArgument`] if a delta time was more than the 28 bits a command can
+    /// carry, or a controller operation can't be sent, and [`RtpMidiError::InvalidCommand`] if a SysEx message has
+    /// a status byte in its data.
+    pub fn build(self) -> Result<Vec<MidiEvent<'a>>, RtpMidiError> {
+        if let Some(delta_time) = self.invalid_delta_time {
+            return Err(RtpMidiError::InvalidArgument(format!(
+                "delta time {delta_time} is more than the {MAX_DELTA_TIME} a command can carry"
+            )));
+        }
+        if let Some(change) = self.invalid_change {
+            change.check()?;
+        }
+        if self.events.iter().any(|event| event.command().has_status_byte_in_sysex()) {
+            return Err(RtpMidiError::InvalidCommand("SysEx data can't hold status bytes"));
+        }
+        Ok(self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi_types::{Channel, MidiMessage, Note, Value7};
+    use zerocopy::FromBytes;
+
+    use super::*;
+    use crate::packets::parse_mode::ParseMode;
+
+    #[test]
+    fn test_control_packets_parse_back() {
+        let packet = ControlPacketBuilder::acceptance(1, 2, c"Name").build();
+        let ControlPacket::Acceptance { body, name } = ControlPacket::try_from_bytes(&packet, ParseMode::Strict).unwrap() else {
+            panic!("Expected an acceptance");
+        };
+        assert_eq!(body.initiator_token, 1);
+        assert_eq!(body.sender_ssrc, 2);
+        assert_eq!(name, "Name");
+
+        let packet = ControlPacketBuilder::clock_sync(1, [10, 20, 0], 2).build();
+        let ControlPacket::ClockSync(clock_sync) = ControlPacket::try_from_bytes(&packet, ParseMode::Strict).unwrap() else {
+            panic!("Expected a clock sync");
+        };
+        assert_eq!(clock_sync.count, 1);
+        assert_eq!({ clock_sync.timestamps }[1], 20);
+    }
+
+    #[test]
+    fn test_midi_packet_parses_back() {
+        let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+        let packet = MidiPacketBuilder::new(3)
+            .sequence_number(1)
+            .timestamp(2)
+            .message(note_on.into())
+            .command(MidiEvent::new(Some(10), MidiMessage::TimingClock.into()))
+            .build();
+
+        let packet = MidiPacket::ref_from_bytes(&packet).unwrap();
+        assert_eq!(packet.sequence_number(), 1);
+        assert_eq!(packet.timestamp(), 2);
+        assert_eq!(packet.ssrc(), 3);
+        let commands = packet.commands().collect::<Vec<_>>();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].command(), &RtpMidiMessage::MidiMessage(note_on));
+        assert_eq!(commands[1].delta_time(), 10);
+    }
+
+    #[test]
+    fn test_z_flag_and_phantom_status_parse_back() {
+        let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+        let builder = MidiPacketBuilder::new(3)
+            .z_flag(true)
+            .phantom_status(true)
+            .command(MidiEvent::new(Some(7), note_on.into()));
+        let packet = builder.build();
+        let mut buffer = [0u8; 32];
+        let len = builder.write_into(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], &packet[..]);
+
+        let packet = MidiPacket::ref_from_bytes(&packet).unwrap();
+        assert!(packet.phantom_status());
+        assert_eq!(packet.commands().next().unwrap().delta_time(), 7);
+    }
+
+    #[test]
+    fn test_journal_only_packet_parses_back() {
+        let journal = [0x00, 0x00, 0x01];
+        let packet = MidiPacketBuilder::new(3).sequence_number(2).journal(&journal).build();
+        let packet = MidiPacket::ref_from_bytes(&packet).unwrap();
+        assert_eq!(packet.commands().count(), 0);
+        assert_eq!(packet.journal(), Some(&journal[..]));
+    }
+
+    #[test]
+    fn test_controller_change_is_expanded() {
+        let change = ControllerChange::pitch_bend_range(Channel::C1, 2, 0);
+        let packet = MidiPacketBuilder::new(3).controller_change(change).build();
+
+        let packet = MidiPacket::ref_from_bytes(&packet).unwrap();
+        let commands = packet.commands().collect::<Vec<_>>();
+        let messages = change.messages();
+        assert_eq!(commands.len(), messages.len());
+        for (command, message) in commands.iter().zip(messages) {
+            assert_eq!(command.command(), &RtpMidiMessage::MidiMessage(message));
+            assert_eq!(command.delta_time(), 0);
+        }
+    }
+
+    #[test]
+    fn test_batch_delta_times() {
+        let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+        let batch = MidiBatchBuilder::new()
+            .message(note_on.into())
+            .cc(Channel::C1, 64.into(), Value7::from(127))
+            .at(10)
+            .at(5)
+            .message(note_on.into())
+            .build()
+            .unwrap();
+        assert_eq!(
+            batch,
+            [
+                MidiEvent::new(None, note_on.into()),
+                MidiEvent::new(Some(0), MidiMessage::ControlChange(Channel::C1, 64.into(), Value7::from(127)).into()),
+                MidiEvent::new(Some(15), note_on.into()),
+            ]
+        );
+
+        let packet = MidiPacketBuilder::new(3).commands(batch).build();
+        let packet = MidiPacket::ref_from_bytes(&packet).unwrap();
+        assert_eq!(packet.commands().map(|command| command.delta_time()).collect::<Vec<_>>(), [0, 0, 15]);
+    }
+
+    #[test]
+    fn test_batch_rejects_delta_times_too_large_to_send() {
+        let builder = MidiBatchBuilder::new().at(MAX_DELTA_TIME).message(MidiMessage::TimingClock.into());
+        assert_eq!(builder.clone().build().unwrap()[0].delta_time(), MAX_DELTA_TIME);
+        assert!(matches!(builder.clone().at(MAX_DELTA_TIME + 1).build(), Err(RtpMidiError::InvalidArgument(_))));
+        assert!(matches!(builder.at(MAX_DELTA_TIME).at(1).build(), Err(RtpMidiError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_batch_rejects_control_change_14_above_controller_31() {
+        let change = ControllerChange::ControlChange14 {
+            channel: Channel::C1,
+            control: 40.into(),
+            value: Value14::from(0u16),
+        };
+        let builder = MidiBatchBuilder::new().controller_change(change);
+        assert!(matches!(builder.build(), Err(RtpMidiError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_batch_rejects_status_bytes_in_sysex() {
+        let builder = MidiBatchBuilder::new().message(RtpMidiMessage::SysEx(&[0x7D, 0x01]));
+        assert!(builder.clone().build().is_ok());
+        let builder = builder.message(RtpMidiMessage::SysEx(&[0x7D, 0xF7, 0x01]));
+        assert!(matches!(builder.build(), Err(RtpMidiError::InvalidCommand(_))));
+    }
+}
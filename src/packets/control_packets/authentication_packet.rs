@@ -0,0 +1,18 @@
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned, network_endian::U32};
+
+/// The start of an `AC` or `AR` packet, an extension of ours rather than part of AppleMIDI. An `AC` packet challenges
+/// a peer with a nonce, and the `AR` packet it answers with carries its proof of the shared secret; both follow this
+/// body.
+#[derive(Debug, KnownLayout, IntoBytes, Immutable, FromBytes, Unaligned)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C, packed)]
+pub struct AuthenticationPacketBody {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
+    pub sender_ssrc: U32,
+}
+
+impl AuthenticationPacketBody {
+    pub fn new(sender_ssrc: U32) -> Self {
+        AuthenticationPacketBody { sender_ssrc }
+    }
+}
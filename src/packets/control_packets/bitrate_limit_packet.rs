@@ -0,0 +1,34 @@
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, network_endian::U32};
+
+/// The body of an `RL` packet, in which a peer asks to be sent no more than `limit` bits per second.
+#[derive(Debug, KnownLayout, IntoBytes, Immutable, FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C, packed)]
+pub struct BitrateLimitPacket {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
+    pub sender_ssrc: U32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
+    pub limit: U32,
+}
+
+impl BitrateLimitPacket {
+    pub fn new(limit: U32, sender_ssrc: U32) -> Self {
+        BitrateLimitPacket { sender_ssrc, limit }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bitrate_limit_packet() {
+        let buffer = [
+            0xF5, 0x19, 0xAE, 0xB9, //sender ssrc
+            0x00, 0x01, 0xF4, 0x00, //limit
+        ];
+        let packet = BitrateLimitPacket::ref_from_bytes(&buffer).unwrap();
+        assert_eq!(packet.sender_ssrc, 0xF519AEB9);
+        assert_eq!(packet.limit, 128000);
+    }
+}
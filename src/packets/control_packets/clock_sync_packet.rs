@@ -4,11 +4,15 @@ use zerocopy::{
 };
 
 #[derive(Debug, KnownLayout, IntoBytes, Immutable, FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C, packed)]
 pub struct ClockSyncPacket {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
     pub sender_ssrc: U32,
     pub count: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
     _reserved: [u8; 3], // Reserved bytes
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::timestamps"))]
     pub timestamps: [U64; 3],
 }
 
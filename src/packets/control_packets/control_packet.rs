@@ -1,36 +1,53 @@
-use std::ffi::CStr;
+use alloc::borrow::Cow;
+use alloc::string::String;
+use core::ffi::CStr;
 
-use anyhow::{Context, Result};
-use bytes::{Bytes, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
 use zerocopy::{
-    FromBytes, Immutable, IntoBytes, KnownLayout, TryFromBytes, Unaligned,
-    network_endian::{U32, U64},
+    FromBytes, IntoBytes,
+    network_endian::{U16, U32, U64},
 };
 
-use crate::packets::{control_packets::session_initiation_packet::SessionInitiationPacketBody, error::PacketParseError};
+use crate::error::RtpMidiError;
+use crate::packets::describe::Annotator;
+use crate::packets::slice_writer::write_into_slice;
+use crate::packets::{control_packets::session_initiation_packet::SessionInitiationPacketBody, error::PacketParseError, parse_mode::ParseMode};
 
+use super::authentication_packet::AuthenticationPacketBody;
+use super::bitrate_limit_packet::BitrateLimitPacket;
 use super::clock_sync_packet::ClockSyncPacket;
+use super::retransmission_request_packet::RetransmissionRequestPacket;
 
 const CONTROL_PACKET_MARKER_VALUE: [u8; 2] = [255, 255];
 
-#[derive(TryFromBytes, Unaligned, KnownLayout, Immutable, Debug, Default, IntoBytes, Clone, Copy)]
-#[repr(u8)]
-enum ControlPacketMarkerEnum {
-    #[default]
-    AllOn = 0xFFu8,
-}
-
-#[derive(TryFromBytes, Unaligned, KnownLayout, Immutable, Debug, Default, IntoBytes, Clone, Copy)]
-#[repr(C)]
-struct ControlPacketMarker(ControlPacketMarkerEnum, ControlPacketMarkerEnum);
-
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ControlPacket<'a> {
     ClockSync(&'a ClockSyncPacket),
-    Invitation { body: &'a SessionInitiationPacketBody, name: &'a CStr },
-    Acceptance { body: &'a SessionInitiationPacketBody, name: &'a CStr },
+    Invitation {
+        body: &'a SessionInitiationPacketBody,
+        name: Cow<'a, str>,
+    },
+    Acceptance {
+        body: &'a SessionInitiationPacketBody,
+        name: Cow<'a, str>,
+    },
     Rejection(&'a SessionInitiationPacketBody),
     Termination(&'a SessionInitiationPacketBody),
+    BitrateLimit(&'a BitrateLimitPacket),
+    /// Asks the peer to prove it knows the session's shared secret, see
+    /// [`Authenticator`](crate::sessions::authenticator::Authenticator).
+    AuthenticationChallenge {
+        body: &'a AuthenticationPacketBody,
+        nonce: &'a [u8],
+    },
+    AuthenticationResponse {
+        body: &'a AuthenticationPacketBody,
+        proof: &'a [u8],
+    },
+    /// Asks the peer to send MIDI packets we missed again, see
+    /// [`SessionConfig::retransmission`](crate::sessions::session_config::SessionConfig::retransmission).
+    RetransmissionRequest(&'a RetransmissionRequestPacket),
 }
 
 impl<'a> ControlPacket<'a> {
@@ -38,14 +55,16 @@ impl<'a> ControlPacket<'a> {
         buffer.starts_with(&CONTROL_PACKET_MARKER_VALUE)
     }
 
-    pub fn try_from_bytes(buffer: &'a [u8]) -> Result<Self> {
+    /// Parses an AppleMIDI control packet. Like [`RtpMidiPacket::parse`](crate::packets::packet::RtpMidiPacket::parse),
+    /// never panics.
+    pub fn try_from_bytes(buffer: &'a [u8], mode: ParseMode) -> Result<Self, PacketParseError> {
         if buffer.len() < 4 {
-            return Err(anyhow::Error::new(PacketParseError::NotEnoughData));
+            return Err(PacketParseError::NotEnoughData);
         }
 
         // Validate marker (2 bytes)
         if !buffer.starts_with(&CONTROL_PACKET_MARKER_VALUE) {
-            return Err(anyhow::Error::new(PacketParseError::InvalidData));
+            return Err(PacketParseError::InvalidData);
         }
 
         // Parse command type (2 bytes)
@@ -55,105 +74,245 @@ impl<'a> ControlPacket<'a> {
         // Parse body based on command type
         let result = match command {
             b"CK" => {
-                let clock_sync = ClockSyncPacket::ref_from_bytes(remaining)
-                    .map_err(|_| PacketParseError::InvalidData)
-                    .context("Failed to parse Clock Sync Packet")?;
+                let clock_sync = ClockSyncPacket::ref_from_bytes(remaining).map_err(|_| PacketParseError::Malformed("clock sync packet"))?;
                 ControlPacket::ClockSync(clock_sync)
             }
             b"IN" => {
-                let (session_body, name_bytes) = SessionInitiationPacketBody::ref_from_prefix(remaining)
-                    .map_err(|_| PacketParseError::InvalidData)
-                    .context("Failed to parse Session Invitation Packet")?;
-                let name = CStr::from_bytes_with_nul(name_bytes).context("Failed to parse Session name from Session Invitation Packet")?;
+                let (session_body, name_bytes) =
+                    SessionInitiationPacketBody::ref_from_prefix(remaining).map_err(|_| PacketParseError::Malformed("session invitation packet"))?;
+                let name = parse_name(name_bytes, mode)?;
                 ControlPacket::Invitation { body: session_body, name }
             }
             b"OK" => {
-                let (session_body, name_bytes) = SessionInitiationPacketBody::ref_from_prefix(remaining)
-                    .map_err(|_| PacketParseError::InvalidData)
-                    .context("Failed to parse Session Acceptance Packet")?;
-                let name = CStr::from_bytes_with_nul(name_bytes).context("Failed to parse Session name from Session Acceptance Packet")?;
+                let (session_body, name_bytes) =
+                    SessionInitiationPacketBody::ref_from_prefix(remaining).map_err(|_| PacketParseError::Malformed("session acceptance packet"))?;
+                let name = parse_name(name_bytes, mode)?;
                 ControlPacket::Acceptance { body: session_body, name }
             }
             b"NO" => {
-                let session_body = SessionInitiationPacketBody::ref_from_bytes(remaining)
-                    .map_err(|_| PacketParseError::InvalidData)
-                    .context("Failed to parse Session Rejection Packet")?;
+                let session_body =
+                    SessionInitiationPacketBody::ref_from_bytes(remaining).map_err(|_| PacketParseError::Malformed("session rejection packet"))?;
                 ControlPacket::Rejection(session_body)
             }
             b"BY" => {
-                let session_body = SessionInitiationPacketBody::ref_from_bytes(remaining)
-                    .map_err(|_| PacketParseError::InvalidData)
-                    .context("Failed to parse Session Termination Packet")?;
+                let session_body =
+                    SessionInitiationPacketBody::ref_from_bytes(remaining).map_err(|_| PacketParseError::Malformed("session termination packet"))?;
                 ControlPacket::Termination(session_body)
             }
-            _ => return Err(anyhow::Error::new(PacketParseError::InvalidData).context(format!("Unknown control packet command: {command:?}"))),
+            b"RL" => {
+                let bitrate_limit = BitrateLimitPacket::ref_from_bytes(remaining).map_err(|_| PacketParseError::Malformed("bitrate limit packet"))?;
+                ControlPacket::BitrateLimit(bitrate_limit)
+            }
+            b"AC" => {
+                let (body, nonce) =
+                    AuthenticationPacketBody::ref_from_prefix(remaining).map_err(|_| PacketParseError::Malformed("authentication challenge packet"))?;
+                ControlPacket::AuthenticationChallenge { body, nonce }
+            }
+            b"AR" => {
+                let (body, proof) =
+                    AuthenticationPacketBody::ref_from_prefix(remaining).map_err(|_| PacketParseError::Malformed("authentication response packet"))?;
+                ControlPacket::AuthenticationResponse { body, proof }
+            }
+            b"RT" => {
+                let request =
+                    RetransmissionRequestPacket::ref_from_bytes(remaining).map_err(|_| PacketParseError::Malformed("retransmission request packet"))?;
+                ControlPacket::RetransmissionRequest(request)
+            }
+            _ => return Err(PacketParseError::UnknownCommand([command[0], command[1]])),
         };
         Ok(result)
     }
 
     pub fn new_invitation_as_bytes(initiator_token: U32, ssrc: U32, name: &CStr) -> Bytes {
         let body = SessionInitiationPacketBody::new(initiator_token, ssrc);
-        let name_bytes = name.to_bytes_with_nul();
-        let header = CONTROL_PACKET_MARKER_VALUE;
-        let command = b"IN";
+        parts_to_bytes(&[b"IN", body.as_bytes(), name.to_bytes_with_nul()])
+    }
 
-        let mut packet = BytesMut::with_capacity(header.len() + command.len() + body.as_bytes().len() + name_bytes.len());
-        packet.extend_from_slice(&header);
-        packet.extend_from_slice(command);
-        packet.extend_from_slice(body.as_bytes());
-        packet.extend_from_slice(name_bytes);
-        packet.freeze()
+    /// Serializes an invitation into the start of `buffer`, returning the number of bytes written.
+    pub fn write_invitation_into(buffer: &mut [u8], initiator_token: U32, ssrc: U32, name: &CStr) -> Result<usize, RtpMidiError> {
+        let body = SessionInitiationPacketBody::new(initiator_token, ssrc);
+        write_parts_into(buffer, &[b"IN", body.as_bytes(), name.to_bytes_with_nul()])
     }
 
     pub fn new_acceptance_as_bytes(initiator_token: U32, ssrc: U32, name: &CStr) -> Bytes {
         let body = SessionInitiationPacketBody::new(initiator_token, ssrc);
-        let name_bytes = name.to_bytes_with_nul();
-        let header = CONTROL_PACKET_MARKER_VALUE;
-        let command = b"OK";
+        parts_to_bytes(&[b"OK", body.as_bytes(), name.to_bytes_with_nul()])
+    }
 
-        let mut packet = BytesMut::with_capacity(header.len() + command.len() + body.as_bytes().len() + name_bytes.len());
-        packet.extend_from_slice(&header);
-        packet.extend_from_slice(command);
-        packet.extend_from_slice(body.as_bytes());
-        packet.extend_from_slice(name_bytes);
-        packet.freeze()
+    /// Serializes an invitation acceptance into the start of `buffer`, returning the number of bytes written.
+    pub fn write_acceptance_into(buffer: &mut [u8], initiator_token: U32, ssrc: U32, name: &CStr) -> Result<usize, RtpMidiError> {
+        let body = SessionInitiationPacketBody::new(initiator_token, ssrc);
+        write_parts_into(buffer, &[b"OK", body.as_bytes(), name.to_bytes_with_nul()])
     }
 
     pub fn new_rejection_as_bytes(initiator_token: U32, ssrc: U32) -> Bytes {
         let body = SessionInitiationPacketBody::new(initiator_token, ssrc);
-        let header = CONTROL_PACKET_MARKER_VALUE;
-        let command = b"NO";
+        parts_to_bytes(&[b"NO", body.as_bytes()])
+    }
 
-        let mut packet = BytesMut::with_capacity(header.len() + command.len() + body.as_bytes().len());
-        packet.extend_from_slice(&header);
-        packet.extend_from_slice(command);
-        packet.extend_from_slice(body.as_bytes());
-        packet.freeze()
+    /// Serializes an invitation rejection into the start of `buffer`, returning the number of bytes written.
+    pub fn write_rejection_into(buffer: &mut [u8], initiator_token: U32, ssrc: U32) -> Result<usize, RtpMidiError> {
+        let body = SessionInitiationPacketBody::new(initiator_token, ssrc);
+        write_parts_into(buffer, &[b"NO", body.as_bytes()])
     }
 
     pub fn new_termination_as_bytes(initiator_token: U32, ssrc: U32) -> Bytes {
         let body = SessionInitiationPacketBody::new(initiator_token, ssrc);
-        let header = CONTROL_PACKET_MARKER_VALUE;
-        let command = b"BY";
+        parts_to_bytes(&[b"BY", body.as_bytes()])
+    }
 
-        let mut packet = BytesMut::with_capacity(header.len() + command.len() + body.as_bytes().len());
-        packet.extend_from_slice(&header);
-        packet.extend_from_slice(command);
-        packet.extend_from_slice(body.as_bytes());
-        packet.freeze()
+    /// Serializes a session termination into the start of `buffer`, returning the number of bytes written.
+    pub fn write_termination_into(buffer: &mut [u8], initiator_token: U32, ssrc: U32) -> Result<usize, RtpMidiError> {
+        let body = SessionInitiationPacketBody::new(initiator_token, ssrc);
+        write_parts_into(buffer, &[b"BY", body.as_bytes()])
     }
 
     pub fn new_clock_sync_as_bytes(count: u8, timestamps: [U64; 3], sender_ssrc: U32) -> Bytes {
         let clock_sync_packet = ClockSyncPacket::new(count, timestamps, sender_ssrc);
-        let packet_bytes = clock_sync_packet.as_bytes();
-        let header = CONTROL_PACKET_MARKER_VALUE;
-        let command = b"CK";
+        parts_to_bytes(&[b"CK", clock_sync_packet.as_bytes()])
+    }
+
+    /// Serializes a clock sync into the start of `buffer`, returning the number of bytes written.
+    pub fn write_clock_sync_into(buffer: &mut [u8], count: u8, timestamps: [U64; 3], sender_ssrc: U32) -> Result<usize, RtpMidiError> {
+        let clock_sync_packet = ClockSyncPacket::new(count, timestamps, sender_ssrc);
+        write_parts_into(buffer, &[b"CK", clock_sync_packet.as_bytes()])
+    }
+
+    /// An `RL` packet asking peers to send us no more than `limit` bits per second.
+    pub fn new_bitrate_limit_as_bytes(limit: U32, sender_ssrc: U32) -> Bytes {
+        let bitrate_limit_packet = BitrateLimitPacket::new(limit, sender_ssrc);
+        parts_to_bytes(&[b"RL", bitrate_limit_packet.as_bytes()])
+    }
+
+    /// Serializes a bitrate limit into the start of `buffer`, returning the number of bytes written.
+    pub fn write_bitrate_limit_into(buffer: &mut [u8], limit: U32, sender_ssrc: U32) -> Result<usize, RtpMidiError> {
+        let bitrate_limit_packet = BitrateLimitPacket::new(limit, sender_ssrc);
+        write_parts_into(buffer, &[b"RL", bitrate_limit_packet.as_bytes()])
+    }
+
+    pub fn new_authentication_challenge_as_bytes(nonce: &[u8], sender_ssrc: U32) -> Bytes {
+        let body = AuthenticationPacketBody::new(sender_ssrc);
+        parts_to_bytes(&[b"AC", body.as_bytes(), nonce])
+    }
+
+    pub fn new_authentication_response_as_bytes(proof: &[u8], sender_ssrc: U32) -> Bytes {
+        let body = AuthenticationPacketBody::new(sender_ssrc);
+        parts_to_bytes(&[b"AR", body.as_bytes(), proof])
+    }
 
-        let mut packet = BytesMut::with_capacity(header.len() + command.len() + packet_bytes.len());
-        packet.extend_from_slice(&header);
-        packet.extend_from_slice(command);
-        packet.extend_from_slice(packet_bytes);
-        packet.freeze()
+    /// An `RT` packet asking for the `count` MIDI packets from `sequence_number` on to be sent again.
+    pub fn new_retransmission_request_as_bytes(sequence_number: U16, count: U16, sender_ssrc: U32) -> Bytes {
+        let request = RetransmissionRequestPacket::new(sequence_number, count, sender_ssrc);
+        parts_to_bytes(&[b"RT", request.as_bytes()])
+    }
+}
+
+impl ControlPacket<'_> {
+    /// An annotated hex dump of the packet. Session names are shown as they were decoded, so a name that was
+    /// repaired in lenient mode appears in its repaired form.
+    pub fn describe(&self) -> String {
+        let (command, title): (&[u8; 2], _) = match self {
+            ControlPacket::ClockSync(_) => (b"CK", "AppleMIDI clock sync"),
+            ControlPacket::Invitation { .. } => (b"IN", "AppleMIDI invitation"),
+            ControlPacket::Acceptance { .. } => (b"OK", "AppleMIDI invitation acceptance"),
+            ControlPacket::Rejection(_) => (b"NO", "AppleMIDI invitation rejection"),
+            ControlPacket::Termination(_) => (b"BY", "AppleMIDI session termination"),
+            ControlPacket::BitrateLimit(_) => (b"RL", "AppleMIDI bitrate receive limit"),
+            ControlPacket::AuthenticationChallenge { .. } => (b"AC", "Authentication challenge"),
+            ControlPacket::AuthenticationResponse { .. } => (b"AR", "Authentication response"),
+            ControlPacket::RetransmissionRequest(_) => (b"RT", "Retransmission request"),
+        };
+        let mut annotator = Annotator::new(title);
+        annotator.field(&CONTROL_PACKET_MARKER_VALUE, "Signature");
+        annotator.field(command, format_args!("Command: {}", String::from_utf8_lossy(command)));
+
+        match self {
+            ControlPacket::ClockSync(packet) => {
+                annotator.field(packet.sender_ssrc.as_bytes(), format_args!("Sender SSRC: {:#010X}", packet.sender_ssrc.get()));
+                annotator.field(&[packet.count], format_args!("Count: {}", packet.count));
+                // Unused, and not necessarily zero from every peer, so shown as sent
+                annotator.field(&packet.as_bytes()[5..8], "Padding");
+                for (i, timestamp) in { packet.timestamps }.iter().enumerate() {
+                    annotator.field(timestamp.as_bytes(), format_args!("Timestamp {}: {}", i + 1, timestamp.get()));
+                }
+            }
+            ControlPacket::Invitation { body, name } | ControlPacket::Acceptance { body, name } => {
+                describe_session_body(&mut annotator, body);
+                let mut name_bytes = name.as_bytes().to_vec();
+                name_bytes.push(0);
+                annotator.field(&name_bytes, format_args!("Name: {name:?}"));
+            }
+            ControlPacket::Rejection(body) | ControlPacket::Termination(body) => describe_session_body(&mut annotator, body),
+            ControlPacket::BitrateLimit(packet) => {
+                annotator.field(packet.sender_ssrc.as_bytes(), format_args!("Sender SSRC: {:#010X}", packet.sender_ssrc.get()));
+                annotator.field(packet.limit.as_bytes(), format_args!("Limit: {} bits per second", packet.limit.get()));
+            }
+            ControlPacket::AuthenticationChallenge { body, nonce: bytes } | ControlPacket::AuthenticationResponse { body, proof: bytes } => {
+                annotator.field(body.sender_ssrc.as_bytes(), format_args!("Sender SSRC: {:#010X}", body.sender_ssrc.get()));
+                let label = if matches!(self, ControlPacket::AuthenticationChallenge { .. }) {
+                    "Nonce"
+                } else {
+                    "Proof"
+                };
+                annotator.field(bytes, format_args!("{label}: {} bytes", bytes.len()));
+            }
+            ControlPacket::RetransmissionRequest(packet) => {
+                annotator.field(packet.sender_ssrc.as_bytes(), format_args!("Sender SSRC: {:#010X}", packet.sender_ssrc.get()));
+                annotator.field(
+                    packet.sequence_number.as_bytes(),
+                    format_args!("Sequence number: {}", packet.sequence_number.get()),
+                );
+                annotator.field(packet.count.as_bytes(), format_args!("Count: {}", packet.count.get()));
+            }
+        }
+        annotator.finish()
+    }
+}
+
+fn describe_session_body(annotator: &mut Annotator, body: &SessionInitiationPacketBody) {
+    annotator.field(
+        body.protocol_version.as_bytes(),
+        format_args!("Protocol version: {}", body.protocol_version.get()),
+    );
+    annotator.field(
+        body.initiator_token.as_bytes(),
+        format_args!("Initiator token: {:#010X}", body.initiator_token.get()),
+    );
+    annotator.field(body.sender_ssrc.as_bytes(), format_args!("Sender SSRC: {:#010X}", body.sender_ssrc.get()));
+}
+
+/// Builds a control packet from its command and the sections that follow it.
+fn parts_to_bytes(parts: &[&[u8]]) -> Bytes {
+    let mut packet = BytesMut::with_capacity(packet_len(parts));
+    packet.put_slice(&CONTROL_PACKET_MARKER_VALUE);
+    parts.iter().for_each(|part| packet.put_slice(part));
+    packet.freeze()
+}
+
+fn write_parts_into(buffer: &mut [u8], parts: &[&[u8]]) -> Result<usize, RtpMidiError> {
+    write_into_slice(buffer, packet_len(parts), |buffer| {
+        buffer.put_slice(&CONTROL_PACKET_MARKER_VALUE);
+        parts.iter().for_each(|part| buffer.put_slice(part));
+    })
+}
+
+fn packet_len(parts: &[&[u8]]) -> usize {
+    CONTROL_PACKET_MARKER_VALUE.len() + parts.iter().map(|part| part.len()).sum::<usize>()
+}
+
+/// Reads a session name. Strict mode requires a NUL-terminated UTF-8 string; lenient mode makes the
+/// terminator optional, ignores anything after it and replaces invalid UTF-8 rather than failing the handshake.
+fn parse_name(bytes: &[u8], mode: ParseMode) -> Result<Cow<'_, str>, PacketParseError> {
+    match mode {
+        ParseMode::Strict => CStr::from_bytes_with_nul(bytes)
+            .ok()
+            .and_then(|name| name.to_str().ok())
+            .map(Cow::Borrowed)
+            .ok_or(PacketParseError::Malformed("session name")),
+        ParseMode::Lenient => {
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            Ok(String::from_utf8_lossy(&bytes[..end]))
+        }
     }
 }
 
@@ -164,14 +323,14 @@ mod tests {
     #[test]
     fn test_parse_invalid_control_packet() {
         let data = vec![0, 0, 0, 0];
-        let result = ControlPacket::try_from_bytes(&data);
+        let result = ControlPacket::try_from_bytes(&data, ParseMode::Lenient);
         assert!(result.is_err());
     }
 
     #[test]
     fn test_parse_too_short_control_packet() {
         let data = vec![255, 255, 67];
-        let result = ControlPacket::try_from_bytes(&data);
+        let result = ControlPacket::try_from_bytes(&data, ParseMode::Lenient);
         assert!(result.is_err());
     }
 
@@ -186,8 +345,31 @@ mod tests {
     #[test]
     fn test_parse_unknown_control_packet() {
         let data = vec![255, 255, 0, 0];
-        let result = ControlPacket::try_from_bytes(&data);
+        let result = ControlPacket::try_from_bytes(&data, ParseMode::Lenient);
         assert!(result.is_err());
+        assert_eq!(result.unwrap_err(), PacketParseError::UnknownCommand([0, 0]));
+    }
+
+    #[test]
+    fn test_authentication_packets_round_trip() {
+        let challenge = ControlPacket::new_authentication_challenge_as_bytes(&[1, 2, 3], U32::new(0x11223344));
+        assert_eq!(&challenge[..], &[0xFF, 0xFF, b'A', b'C', 0x11, 0x22, 0x33, 0x44, 1, 2, 3]);
+        match ControlPacket::try_from_bytes(&challenge, ParseMode::Strict).unwrap() {
+            ControlPacket::AuthenticationChallenge { body, nonce } => {
+                assert_eq!(body.sender_ssrc, 0x11223344);
+                assert_eq!(nonce, &[1, 2, 3]);
+            }
+            packet => panic!("Expected an authentication challenge, got {packet:?}"),
+        }
+
+        let response = ControlPacket::new_authentication_response_as_bytes(&[4, 5], U32::new(7));
+        match ControlPacket::try_from_bytes(&response, ParseMode::Strict).unwrap() {
+            ControlPacket::AuthenticationResponse { body, proof } => {
+                assert_eq!(body.sender_ssrc, 7);
+                assert_eq!(proof, &[4, 5]);
+            }
+            packet => panic!("Expected an authentication response, got {packet:?}"),
+        }
     }
 
     #[test]
@@ -202,7 +384,7 @@ mod tests {
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, // timestamp 3
         ]; // Example buffer for a ClockSync packet
 
-        let result = ControlPacket::try_from_bytes(&buffer);
+        let result = ControlPacket::try_from_bytes(&buffer, ParseMode::Lenient);
         if let Err(e) = result {
             panic!("Failed to parse control packet: {e}");
         }
@@ -228,16 +410,119 @@ mod tests {
             0x4C, 0x6F, 0x76, 0x65, 0x6C, 0x79, 0x20, 0x53, 0x65, 0x73, 0x73, 0x69, 0x6F, 0x6E, 0x00, //name
         ];
 
-        let result = ControlPacket::try_from_bytes(&buffer);
+        let result = ControlPacket::try_from_bytes(&buffer, ParseMode::Lenient);
         if let Err(e) = result {
             panic!("Failed to parse control packet: {e}");
         }
 
         assert!(result.is_ok());
         if let ControlPacket::Invitation { body: _body, name } = &result.unwrap() {
-            assert_eq!(name.to_bytes(), b"Lovely Session");
+            assert_eq!(name, "Lovely Session");
         } else {
             panic!("Expected Invitation packet");
         }
     }
+
+    fn parse_invitation_name(name_bytes: &[u8]) -> String {
+        let mut buffer = vec![
+            0xFF, 0xFF, b'I', b'N', //header
+            0x00, 0x00, 0x00, 0x02, //version
+            0xF8, 0xD1, 0x80, 0xE6, //initiator token
+            0xF5, 0x19, 0xAE, 0xB9, //sender ssrc
+        ];
+        buffer.extend_from_slice(name_bytes);
+
+        match ControlPacket::try_from_bytes(&buffer, ParseMode::Lenient) {
+            Ok(ControlPacket::Invitation { name, .. }) => name.into_owned(),
+            other => panic!("Expected Invitation packet, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_strict_mode_requires_clean_name() {
+        let mut buffer = vec![
+            0xFF, 0xFF, b'I', b'N', //header
+            0x00, 0x00, 0x00, 0x02, //version
+            0xF8, 0xD1, 0x80, 0xE6, //initiator token
+            0xF5, 0x19, 0xAE, 0xB9, //sender ssrc
+        ];
+        buffer.extend_from_slice(b"Session");
+        assert!(ControlPacket::try_from_bytes(&buffer, ParseMode::Strict).is_err());
+
+        buffer.push(0);
+        assert!(ControlPacket::try_from_bytes(&buffer, ParseMode::Strict).is_ok());
+    }
+
+    #[test]
+    fn test_read_unterminated_name() {
+        assert_eq!(parse_invitation_name(b"Session"), "Session");
+    }
+
+    #[test]
+    fn test_read_missing_name() {
+        assert_eq!(parse_invitation_name(b""), "");
+    }
+
+    #[test]
+    fn test_read_name_with_trailing_data() {
+        assert_eq!(parse_invitation_name(b"Session\0\x01\x02"), "Session");
+    }
+
+    #[test]
+    fn test_read_name_with_invalid_utf8() {
+        assert_eq!(parse_invitation_name(b"Caf\xE9\0"), "Caf\u{FFFD}");
+    }
+
+    #[test]
+    fn test_write_into_matches_as_bytes() {
+        let name = c"Session";
+        let mut buffer = [0u8; 64];
+
+        let len = ControlPacket::write_invitation_into(&mut buffer, U32::new(1), U32::new(2), name).unwrap();
+        assert_eq!(&buffer[..len], &ControlPacket::new_invitation_as_bytes(U32::new(1), U32::new(2), name)[..]);
+
+        let len = ControlPacket::write_termination_into(&mut buffer, U32::new(1), U32::new(2)).unwrap();
+        assert_eq!(&buffer[..len], &ControlPacket::new_termination_as_bytes(U32::new(1), U32::new(2))[..]);
+
+        let timestamps = [U64::new(1), U64::new(2), U64::new(3)];
+        let len = ControlPacket::write_clock_sync_into(&mut buffer, 2, timestamps, U32::new(2)).unwrap();
+        assert_eq!(&buffer[..len], &ControlPacket::new_clock_sync_as_bytes(2, timestamps, U32::new(2))[..]);
+
+        let len = ControlPacket::write_bitrate_limit_into(&mut buffer, U32::new(64000), U32::new(2)).unwrap();
+        assert_eq!(&buffer[..len], &ControlPacket::new_bitrate_limit_as_bytes(U32::new(64000), U32::new(2))[..]);
+    }
+
+    #[test]
+    fn test_bitrate_limit_round_trip() {
+        let packet = ControlPacket::new_bitrate_limit_as_bytes(U32::new(64000), U32::new(0xF519AEB9));
+        assert_eq!(&packet[..], &[0xFF, 0xFF, b'R', b'L', 0xF5, 0x19, 0xAE, 0xB9, 0x00, 0x00, 0xFA, 0x00]);
+
+        let Ok(ControlPacket::BitrateLimit(parsed)) = ControlPacket::try_from_bytes(&packet, ParseMode::Strict) else {
+            panic!("Expected a bitrate limit");
+        };
+        assert_eq!(parsed.sender_ssrc, 0xF519AEB9);
+        assert_eq!(parsed.limit, 64000);
+        assert!(ControlPacket::try_from_bytes(&packet[..10], ParseMode::Lenient).is_err());
+    }
+
+    #[test]
+    fn test_retransmission_request_round_trip() {
+        let packet = ControlPacket::new_retransmission_request_as_bytes(U16::new(0x0102), U16::new(3), U32::new(0xF519AEB9));
+        assert_eq!(&packet[..], &[0xFF, 0xFF, b'R', b'T', 0xF5, 0x19, 0xAE, 0xB9, 0x01, 0x02, 0x00, 0x03]);
+
+        let Ok(ControlPacket::RetransmissionRequest(parsed)) = ControlPacket::try_from_bytes(&packet, ParseMode::Strict) else {
+            panic!("Expected a retransmission request");
+        };
+        assert_eq!(parsed.sender_ssrc, 0xF519AEB9);
+        assert_eq!((parsed.sequence_number.get(), parsed.count.get()), (0x0102, 3));
+        assert!(ControlPacket::try_from_bytes(&packet[..10], ParseMode::Lenient).is_err());
+    }
+
+    #[test]
+    fn test_write_into_too_small_buffer() {
+        let mut buffer = [0u8; 8];
+        let result = ControlPacket::write_rejection_into(&mut buffer, U32::new(1), U32::new(2));
+        assert!(matches!(result, Err(RtpMidiError::BufferTooSmall { needed: 16, available: 8 })));
+        assert_eq!(buffer, [0u8; 8], "nothing should be written when the packet doesn't fit");
+    }
 }
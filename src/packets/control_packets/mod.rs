@@ -1,3 +1,6 @@
+pub mod authentication_packet;
+pub mod bitrate_limit_packet;
 pub mod clock_sync_packet;
 pub mod control_packet;
+pub mod retransmission_request_packet;
 pub mod session_initiation_packet;
@@ -0,0 +1,47 @@
+use zerocopy::{
+    FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned,
+    network_endian::{U16, U32},
+};
+
+/// The body of an `RT` packet, an extension of ours rather than part of AppleMIDI, in which a peer that has missed
+/// `count` MIDI packets from `sequence_number` on asks for them to be sent again.
+#[derive(Debug, KnownLayout, IntoBytes, Immutable, FromBytes, Unaligned)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(C, packed)]
+pub struct RetransmissionRequestPacket {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
+    pub sender_ssrc: U32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u16"))]
+    pub sequence_number: U16,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u16"))]
+    pub count: U16,
+}
+
+impl RetransmissionRequestPacket {
+    pub fn new(sequence_number: U16, count: U16, sender_ssrc: U32) -> Self {
+        RetransmissionRequestPacket {
+            sender_ssrc,
+            sequence_number,
+            count,
+        }
+    }
+
+    /// Whether `sequence_number` is among the packets asked for, counting on from the first past the wrap.
+    pub fn includes(&self, sequence_number: u16) -> bool {
+        sequence_number.wrapping_sub(self.sequence_number.get()) < self.count.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_includes_wraps_around() {
+        let packet = RetransmissionRequestPacket::new(U16::new(0xFFFE), U16::new(3), U32::new(1));
+        assert!(packet.includes(0xFFFE));
+        assert!(packet.includes(0));
+        assert!(!packet.includes(1));
+        assert!(!packet.includes(0xFFFD));
+    }
+}
@@ -1,23 +1,33 @@
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, network_endian::U32};
 
 #[derive(Debug, KnownLayout, IntoBytes, Immutable, FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct SessionInitiationPacketBody {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
     pub protocol_version: U32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
     pub initiator_token: U32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
     pub sender_ssrc: U32,
 }
 
 impl SessionInitiationPacketBody {
     pub const SIZE: usize = 12;
+    /// The only AppleMIDI protocol version this library speaks.
+    pub const PROTOCOL_VERSION: u32 = 2;
 
     pub fn new(initiator_token: U32, sender_ssrc: U32) -> SessionInitiationPacketBody {
         SessionInitiationPacketBody {
-            protocol_version: U32::new(2),
+            protocol_version: U32::new(Self::PROTOCOL_VERSION),
             initiator_token,
             sender_ssrc,
         }
     }
+
+    pub fn has_supported_version(&self) -> bool {
+        self.protocol_version.get() == Self::PROTOCOL_VERSION
+    }
 }
 
 #[cfg(test)]
@@ -48,6 +58,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_supported_version() {
+        let mut body = get_test_body();
+        assert!(SessionInitiationPacketBody::ref_from_bytes(&body).unwrap().has_supported_version());
+
+        body[3] = 0x03;
+        assert!(!SessionInitiationPacketBody::ref_from_bytes(&body).unwrap().has_supported_version());
+    }
+
     #[test]
     fn test_write() {
         let initiator_token = U32::new(0xF8D180E6);
@@ -0,0 +1,59 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{Display, Write};
+
+const BYTES_PER_LINE: usize = 8;
+
+/// Builds the annotated hex dump behind the `describe()` methods: one line per field, giving its offset in the
+/// packet, its bytes and what they mean. Fields longer than a line carry on over unannotated lines.
+pub(crate) struct Annotator {
+    out: String,
+    offset: usize,
+}
+
+impl Annotator {
+    pub fn new(title: impl Display) -> Self {
+        Annotator {
+            out: format!("{title}\n"),
+            offset: 0,
+        }
+    }
+
+    pub fn field(&mut self, bytes: &[u8], description: impl Display) {
+        let mut description = Some(description);
+        for chunk in bytes.chunks(BYTES_PER_LINE) {
+            let hex = chunk.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<_>>().join(" ");
+            let mut line = format!("{:04X}  {hex:width$}", self.offset, width = BYTES_PER_LINE * 3 - 1);
+            if let Some(description) = description.take() {
+                let _ = write!(line, "  {description}");
+            }
+            self.out.push_str(line.trim_end());
+            self.out.push('\n');
+            self.offset += chunk.len();
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_fields_wrap() {
+        let mut annotator = Annotator::new("Title");
+        annotator.field(&[0xFF, 0xFF], "Marker");
+        annotator.field(&[0; 10], "Data");
+        assert_eq!(
+            annotator.finish(),
+            "Title\n\
+             0000  FF FF                    Marker\n\
+             0002  00 00 00 00 00 00 00 00  Data\n\
+             000A  00 00\n"
+        );
+    }
+}
@@ -1,9 +1,22 @@
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+/// Why a packet, or a part of one, could not be decoded.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
 pub enum PacketParseError {
     #[error("Not enough data")]
     NotEnoughData,
     #[error("Invalid data")]
     InvalidData,
+    /// The named part of the packet ends before it should.
+    #[error("{0} is truncated")]
+    Truncated(&'static str),
+    /// The named part of the packet is present but can't be decoded.
+    #[error("Malformed {0}")]
+    Malformed(&'static str),
+    #[error("Unsupported RTP version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Unsupported MIDI status byte: {0:#04X}")]
+    UnsupportedStatus(u8),
+    #[error("Unknown control packet command: {0:?}")]
+    UnknownCommand([u8; 2]),
 }
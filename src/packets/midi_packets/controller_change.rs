@@ -0,0 +1,294 @@
+//! High-resolution controller operations, which MIDI 1.0 spreads over several Control Change messages: 14-bit
+//! values for controllers 0 to 31 (their MSB, then the LSB on the controller 32 above), and registered (RPN) and
+//! non-registered (NRPN) parameters set through Data Entry.
+//!
+//! [`ControllerChange::events`] expands an operation into the messages to send in one batch, and
+//! [`ControllerCombiner`] puts received messages back together.
+//!
+//! ```
+//! use midi_types::Channel;
+//! use rtpmidi::packets::midi_packets::controller_change::{ControllerChange, ControllerCombiner};
+//!
+//! let pitch_bend_range = ControllerChange::pitch_bend_range(Channel::C1, 12, 0);
+//! let mut combiner = ControllerCombiner::default();
+//! let combined: Vec<_> = pitch_bend_range.messages().iter().filter_map(|message| combiner.push(message)).collect();
+//! assert_eq!(combined, [pitch_bend_range]);
+//! ```
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use midi_types::{Channel, Control, MidiMessage, Value7, Value14};
+
+use crate::error::RtpMidiError;
+use crate::packets::midi_packets::midi_event::MidiEvent;
+
+const DATA_ENTRY_MSB: u8 = 6;
+const DATA_ENTRY_LSB: u8 = 38;
+const LSB_OFFSET: u8 = 32;
+const NRPN_LSB: u8 = 98;
+const NRPN_MSB: u8 = 99;
+const RPN_LSB: u8 = 100;
+const RPN_MSB: u8 = 101;
+/// Selecting this parameter deselects the current one, so stray Data Entry messages change nothing.
+const NULL_PARAMETER: u8 = 127;
+
+const PITCH_BEND_SENSITIVITY: u8 = 0;
+const FINE_TUNING: u8 = 1;
+
+/// One high-resolution controller operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerChange {
+    /// A 14-bit value for one of controllers 0 to 31. Build it with [`control_change_14`](Self::control_change_14) to
+    /// have other controllers rejected.
+    ControlChange14 { channel: Channel, control: Control, value: Value14 },
+    /// A registered parameter, such as the pitch bend range.
+    Rpn { channel: Channel, parameter: Value14, value: Value14 },
+    /// A parameter whose meaning is up to the receiving device.
+    Nrpn { channel: Channel, parameter: Value14, value: Value14 },
+}
+
+impl ControllerChange {
+    /// A 14-bit value for `control`, or [`RtpMidiError::InvalidArgument`] if it is above 31, as only controllers 0 to
+    /// 31 have an LSB controller.
+    pub fn control_change_14(channel: Channel, control: Control, value: Value14) -> Result<Self, RtpMidiError> {
+        let change = ControllerChange::ControlChange14 { channel, control, value };
+        change.check().map(|()| change)
+    }
+
+    /// Fails with [`RtpMidiError::InvalidArgument`] for a [`ControlChange14`](Self::ControlChange14) above controller
+    /// 31, which can't be sent.
+    pub fn check(&self) -> Result<(), RtpMidiError> {
+        match *self {
+            ControllerChange::ControlChange14 { control, .. } if u8::from(control) >= LSB_OFFSET => Err(RtpMidiError::InvalidArgument(format!(
+                "controller {} has no LSB controller, only 0 to 31 do",
+                u8::from(control)
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets how far a full pitch bend goes, in semitones and cents.
+    pub fn pitch_bend_range(channel: Channel, semitones: u8, cents: u8) -> Self {
+        ControllerChange::Rpn {
+            channel,
+            parameter: Value14::new(0, PITCH_BEND_SENSITIVITY),
+            value: Value14::new(semitones, cents),
+        }
+    }
+
+    /// Detunes the channel from A440 by up to a semitone either way: `Value14::from(0i16)` leaves it in tune, and
+    /// each step is 100/8192 of a cent.
+    pub fn fine_tuning(channel: Channel, value: Value14) -> Self {
+        ControllerChange::Rpn {
+            channel,
+            parameter: Value14::new(0, FINE_TUNING),
+            value,
+        }
+    }
+
+    /// The messages carrying the operation, in the order they have to be sent. Parameters are deselected again
+    /// afterwards, so that later Data Entry messages can't change them by accident. There are none for an operation
+    /// that fails [`check`](Self::check).
+    pub fn messages(&self) -> Vec<MidiMessage> {
+        if self.check().is_err() {
+            return Vec::new();
+        }
+        let control_change = |channel, control: u8, value: u8| MidiMessage::ControlChange(channel, Control::from(control), Value7::from(value));
+        match *self {
+            ControllerChange::ControlChange14 { channel, control, value } => {
+                let (msb, lsb) = value.into();
+                let control = u8::from(control);
+                alloc::vec![control_change(channel, control, msb), control_change(channel, control + LSB_OFFSET, lsb)]
+            }
+            ControllerChange::Rpn { channel, parameter, value } | ControllerChange::Nrpn { channel, parameter, value } => {
+                let (select_msb, select_lsb) = match self {
+                    ControllerChange::Rpn { .. } => (RPN_MSB, RPN_LSB),
+                    _ => (NRPN_MSB, NRPN_LSB),
+                };
+                let (parameter_msb, parameter_lsb) = parameter.into();
+                let (value_msb, value_lsb) = value.into();
+                alloc::vec![
+                    control_change(channel, select_msb, parameter_msb),
+                    control_change(channel, select_lsb, parameter_lsb),
+                    control_change(channel, DATA_ENTRY_MSB, value_msb),
+                    control_change(channel, DATA_ENTRY_LSB, value_lsb),
+                    control_change(channel, RPN_MSB, NULL_PARAMETER),
+                    control_change(channel, RPN_LSB, NULL_PARAMETER),
+                ]
+            }
+        }
+    }
+
+    /// The messages as commands played at once, to send as a single batch.
+    pub fn events(&self) -> Vec<MidiEvent<'static>> {
+        let messages = self.messages().into_iter().enumerate();
+        messages
+            .map(|(i, message)| MidiEvent::new(if i == 0 { None } else { Some(0) }, message.into()))
+            .collect()
+    }
+}
+
+/// Reassembles the high-resolution controller operations in one sender's stream of messages.
+///
+/// An operation is complete when its LSB arrives. A later LSB on its own changes the value again, keeping the MSB,
+/// and a sender that only ever sends MSBs produces no operations.
+#[derive(Debug, Clone, Default)]
+pub struct ControllerCombiner {
+    channels: [ChannelState; 16],
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    msbs: [Option<u8>; LSB_OFFSET as usize],
+    parameter: Option<SelectedParameter>,
+    data_entry_msb: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SelectedParameter {
+    registered: bool,
+    msb: Option<u8>,
+    lsb: Option<u8>,
+}
+
+impl ControllerCombiner {
+    /// Takes the next received message, returning the operation it completes, if any.
+    pub fn push(&mut self, message: &MidiMessage) -> Option<ControllerChange> {
+        let MidiMessage::ControlChange(channel, control, value) = *message else {
+            return None;
+        };
+        let state = &mut self.channels[u8::from(channel) as usize];
+        let value = u8::from(value);
+        match u8::from(control) {
+            DATA_ENTRY_MSB => state.data_entry_msb = Some(value),
+            DATA_ENTRY_LSB => {
+                let selected = state.parameter?;
+                let parameter = Value14::new(selected.msb?, selected.lsb?);
+                let value = Value14::new(state.data_entry_msb?, value);
+                return Some(if selected.registered {
+                    ControllerChange::Rpn { channel, parameter, value }
+                } else {
+                    ControllerChange::Nrpn { channel, parameter, value }
+                });
+            }
+            control @ (NRPN_LSB | NRPN_MSB | RPN_LSB | RPN_MSB) => state.select_parameter(control, value),
+            control @ 0..LSB_OFFSET => state.msbs[control as usize] = Some(value),
+            control @ LSB_OFFSET..64 => {
+                let control = control - LSB_OFFSET;
+                let msb = state.msbs[control as usize]?;
+                return Some(ControllerChange::ControlChange14 {
+                    channel,
+                    control: Control::from(control),
+                    value: Value14::new(msb, value),
+                });
+            }
+            _ => {}
+        }
+        None
+    }
+}
+
+impl ChannelState {
+    fn select_parameter(&mut self, control: u8, value: u8) {
+        let registered = matches!(control, RPN_LSB | RPN_MSB);
+        let selected = match &mut self.parameter {
+            Some(selected) if selected.registered == registered => selected,
+            parameter => parameter.insert(SelectedParameter {
+                registered,
+                msb: None,
+                lsb: None,
+            }),
+        };
+        match control {
+            RPN_MSB | NRPN_MSB => selected.msb = Some(value),
+            _ => selected.lsb = Some(value),
+        }
+        if selected.msb == Some(NULL_PARAMETER) && selected.lsb == Some(NULL_PARAMETER) {
+            self.parameter = None;
+        }
+        self.data_entry_msb = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control_change(control: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange(Channel::C2, Control::from(control), Value7::from(value))
+    }
+
+    fn combine(combiner: &mut ControllerCombiner, messages: &[MidiMessage]) -> Vec<ControllerChange> {
+        messages.iter().filter_map(|message| combiner.push(message)).collect()
+    }
+
+    #[test]
+    fn test_control_change_14_is_split_into_msb_and_lsb() {
+        let change = ControllerChange::ControlChange14 {
+            channel: Channel::C2,
+            control: Control::from(7),
+            value: Value14::from(0x1234u16),
+        };
+        assert_eq!(change.messages(), [control_change(7, 0x24), control_change(39, 0x34)]);
+        assert_eq!(combine(&mut ControllerCombiner::default(), &change.messages()), [change]);
+    }
+
+    #[test]
+    fn test_control_change_14_above_controller_31_is_rejected() {
+        let value = Value14::from(0x1234u16);
+        assert!(ControllerChange::control_change_14(Channel::C2, Control::from(31), value).is_ok());
+        assert!(matches!(
+            ControllerChange::control_change_14(Channel::C2, Control::from(32), value),
+            Err(RtpMidiError::InvalidArgument(_))
+        ));
+        let change = ControllerChange::ControlChange14 {
+            channel: Channel::C2,
+            control: Control::from(100),
+            value,
+        };
+        assert!(change.check().is_err());
+        assert!(change.messages().is_empty());
+    }
+
+    #[test]
+    fn test_rpn_is_deselected_after_data_entry() {
+        let change = ControllerChange::pitch_bend_range(Channel::C2, 12, 50);
+        assert_eq!(
+            change.messages(),
+            [
+                control_change(RPN_MSB, 0),
+                control_change(RPN_LSB, 0),
+                control_change(DATA_ENTRY_MSB, 12),
+                control_change(DATA_ENTRY_LSB, 50),
+                control_change(RPN_MSB, NULL_PARAMETER),
+                control_change(RPN_LSB, NULL_PARAMETER),
+            ]
+        );
+
+        let mut combiner = ControllerCombiner::default();
+        assert_eq!(combine(&mut combiner, &change.messages()), [change]);
+        // The null parameter is selected now, so further Data Entry isn't an operation
+        assert!(combine(&mut combiner, &[control_change(DATA_ENTRY_MSB, 1), control_change(DATA_ENTRY_LSB, 2)]).is_empty());
+    }
+
+    #[test]
+    fn test_nrpn_round_trip() {
+        let change = ControllerChange::Nrpn {
+            channel: Channel::C2,
+            parameter: Value14::new(1, 2),
+            value: Value14::new(3, 4),
+        };
+        assert_eq!(change.messages()[..2], [control_change(NRPN_MSB, 1), control_change(NRPN_LSB, 2)]);
+        assert_eq!(combine(&mut ControllerCombiner::default(), &change.messages()), [change]);
+    }
+
+    #[test]
+    fn test_lsb_without_msb_is_ignored() {
+        let mut combiner = ControllerCombiner::default();
+        assert!(combine(&mut combiner, &[control_change(39, 1)]).is_empty());
+        // Messages on another channel don't provide the MSB either
+        let msb_on_other_channel = MidiMessage::ControlChange(Channel::C1, Control::from(7), Value7::from(1));
+        assert!(combine(&mut combiner, &[msb_on_other_channel, control_change(39, 1)]).is_empty());
+    }
+}
@@ -1,26 +1,22 @@
-use bytes::{BufMut, BytesMut};
+use bytes::BufMut;
 
-pub(crate) fn delta_time_size(delta_time: u32) -> usize {
-    let mut size = 0;
-    let mut value = delta_time;
-
-    while value > 0 {
-        size += 1;
-        value >>= 7;
-    }
+use crate::packets::error::PacketParseError;
 
-    if size == 0 {
-        size = 1; // At least one byte for zero
-    }
+const MAX_DELTA_TIME_SIZE: usize = 4;
+/// The largest delta time that fits in [`MAX_DELTA_TIME_SIZE`] bytes.
+pub(crate) const MAX_DELTA_TIME: u32 = (1 << (7 * MAX_DELTA_TIME_SIZE)) - 1;
 
-    size
+pub(crate) fn delta_time_size(delta_time: u32) -> usize {
+    // 7 bits per byte, and at least one byte for zero
+    let significant_bits = u32::BITS - (delta_time | 1).leading_zeros();
+    significant_bits.div_ceil(7) as usize
 }
 
 pub(crate) trait WriteDeltaTimeExt {
     fn write_delta_time(&mut self, delta_time: u32);
 }
 
-impl WriteDeltaTimeExt for BytesMut {
+impl<B: BufMut> WriteDeltaTimeExt for B {
     fn write_delta_time(&mut self, delta_time: u32) {
         let num_bytes = delta_time_size(delta_time);
         let value_to_write = delta_time;
@@ -35,23 +31,28 @@ impl WriteDeltaTimeExt for BytesMut {
     }
 }
 
-pub fn read_delta_time(bytes: &[u8]) -> std::io::Result<(u32, &[u8])> {
+/// Reads a delta time, which is sent most significant group of 7 bits first in at most 4 bytes.
+pub fn read_delta_time(bytes: &[u8]) -> Result<(u32, &[u8]), PacketParseError> {
     let mut value: u32 = 0;
-    let mut shift: u8 = 0;
 
-    for (bytes_read, &byte) in bytes.iter().enumerate() {
-        value |= ((byte & 0x7F) as u32) << shift;
+    for (bytes_read, &byte) in bytes.iter().take(MAX_DELTA_TIME_SIZE).enumerate() {
+        value = (value << 7) | (byte & 0x7F) as u32;
         if byte & 0x80 == 0 {
             return Ok((value, &bytes[(bytes_read + 1)..]));
         }
-        shift += 7;
     }
 
-    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid delta time encoding"))
+    if bytes.len() >= MAX_DELTA_TIME_SIZE {
+        Err(PacketParseError::Malformed("delta time"))
+    } else {
+        Err(PacketParseError::Truncated("Delta time"))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use bytes::BytesMut;
+
     use super::*;
 
     fn test_delta_time_rw(delta_time: u32, expected_bytes: &[u8]) {
@@ -60,6 +61,11 @@ mod tests {
         buffer.write_delta_time(delta_time);
         assert_eq!(buffer.len(), expected_bytes.len());
         assert_eq!(buffer, expected_bytes);
+
+        // Test reading
+        let (read, remaining) = read_delta_time(expected_bytes).unwrap();
+        assert_eq!(read, delta_time);
+        assert!(remaining.is_empty());
     }
 
     #[test]
@@ -104,6 +110,12 @@ mod tests {
         test_delta_time_rw(0x08000000, &[0xC0, 0x80, 0x80, 0x00]);
     }
 
+    #[test]
+    fn test_read_invalid_delta_time() {
+        assert_eq!(read_delta_time(&[0x81, 0x80]), Err(PacketParseError::Truncated("Delta time")));
+        assert_eq!(read_delta_time(&[0x81, 0x80, 0x80, 0x80, 0x00]), Err(PacketParseError::Malformed("delta time")));
+    }
+
     #[test]
     fn test_size_calculation() {
         assert_eq!(delta_time_size(0), 1);
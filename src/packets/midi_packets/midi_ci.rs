@@ -0,0 +1,448 @@
+//! MIDI Capability Inquiry (MIDI-CI): the universal SysEx messages devices use to discover each other and exchange
+//! properties. Each device picks a random 28-bit MUID to tell its messages apart from those of other devices on the
+//! same connection.
+//!
+//! Messages are built and read as SysEx payloads without their start and end bytes, the way
+//! [`RtpMidiMessage::SysEx`](crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage::SysEx) carries them.
+//! They are built in the MIDI-CI 1.1 layout; anything a later version adds to the end of a message is skipped when
+//! reading it.
+//!
+//! ```
+//! use rtpmidi::packets::midi_packets::midi_ci::{CiBody, CiMessage, DeviceDetails, Muid, TO_FUNCTION_BLOCK};
+//! use rtpmidi::packets::midi_packets::sysex::ManufacturerId;
+//!
+//! let discovery = CiMessage {
+//!     device_id: TO_FUNCTION_BLOCK,
+//!     source: Muid::new(0x0123_4567),
+//!     destination: Muid::BROADCAST,
+//!     body: CiBody::Discovery(DeviceDetails {
+//!         manufacturer: ManufacturerId::Standard(0x7D),
+//!         family: 1,
+//!         model: 2,
+//!         software_revision: [0, 1, 0, 0],
+//!         categories: DeviceDetails::PROPERTY_EXCHANGE,
+//!         max_sysex_size: 512,
+//!     }),
+//! };
+//! let sysex = discovery.to_sysex()?;
+//! assert_eq!(CiMessage::from_sysex(&sysex), Some(discovery));
+//! # Ok::<(), rtpmidi::error::RtpMidiError>(())
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::error::RtpMidiError;
+use crate::packets::midi_packets::sysex::{ManufacturerId, write_7bit, write_bytes};
+
+/// Addresses the whole function block or port, rather than a single channel.
+pub const TO_FUNCTION_BLOCK: u8 = 0x7F;
+
+const UNIVERSAL_NON_REAL_TIME: u8 = 0x7E;
+const MIDI_CI: u8 = 0x0D;
+const VERSION: u8 = 0x01;
+
+const DISCOVERY: u8 = 0x70;
+const DISCOVERY_REPLY: u8 = 0x71;
+const INVALIDATE_MUID: u8 = 0x7E;
+const NAK: u8 = 0x7F;
+const PROPERTY_EXCHANGE_CAPABILITIES: u8 = 0x30;
+const PROPERTY_EXCHANGE_CAPABILITIES_REPLY: u8 = 0x31;
+
+/// A MIDI Unique Identifier, the 28-bit address of a MIDI-CI device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Muid(u32);
+
+impl Muid {
+    /// Addresses every device.
+    pub const BROADCAST: Muid = Muid(0x0FFF_FFFF);
+
+    /// Keeps the low 28 bits of `value`.
+    pub const fn new(value: u32) -> Self {
+        Muid(value & 0x0FFF_FFFF)
+    }
+
+    pub fn value(self) -> u32 {
+        self.0
+    }
+}
+
+/// Who a device is and what it can do, as given in Discovery and its reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceDetails {
+    pub manufacturer: ManufacturerId,
+    pub family: u16,
+    pub model: u16,
+    pub software_revision: [u8; 4],
+    /// Which of the [`PROFILE_CONFIGURATION`](Self::PROFILE_CONFIGURATION),
+    /// [`PROPERTY_EXCHANGE`](Self::PROPERTY_EXCHANGE) and [`PROCESS_INQUIRY`](Self::PROCESS_INQUIRY) categories the
+    /// device supports.
+    pub categories: u8,
+    /// The largest SysEx message the device can take, in bytes.
+    pub max_sysex_size: u32,
+}
+
+impl DeviceDetails {
+    pub const PROFILE_CONFIGURATION: u8 = 0b0000_0100;
+    pub const PROPERTY_EXCHANGE: u8 = 0b0000_1000;
+    pub const PROCESS_INQUIRY: u8 = 0b0001_0000;
+}
+
+/// The Property Exchange messages that carry property data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PropertyExchangeKind {
+    Get,
+    GetReply,
+    Set,
+    SetReply,
+    Subscription,
+    SubscriptionReply,
+    Notify,
+}
+
+impl PropertyExchangeKind {
+    const ALL: [PropertyExchangeKind; 7] = [
+        PropertyExchangeKind::Get,
+        PropertyExchangeKind::GetReply,
+        PropertyExchangeKind::Set,
+        PropertyExchangeKind::SetReply,
+        PropertyExchangeKind::Subscription,
+        PropertyExchangeKind::SubscriptionReply,
+        PropertyExchangeKind::Notify,
+    ];
+
+    fn sub_id(self) -> u8 {
+        match self {
+            PropertyExchangeKind::Get => 0x34,
+            PropertyExchangeKind::GetReply => 0x35,
+            PropertyExchangeKind::Set => 0x36,
+            PropertyExchangeKind::SetReply => 0x37,
+            PropertyExchangeKind::Subscription => 0x38,
+            PropertyExchangeKind::SubscriptionReply => 0x39,
+            PropertyExchangeKind::Notify => 0x3F,
+        }
+    }
+}
+
+/// One chunk of a Property Exchange transaction. The header is JSON naming the resource, such as
+/// `{"resource":"DeviceInfo"}`; large property data is split over several chunks numbered from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PropertyData<'a> {
+    /// Ties replies to their inquiry.
+    pub request_id: u8,
+    pub header: &'a [u8],
+    pub chunk_count: u16,
+    pub chunk: u16,
+    pub data: &'a [u8],
+}
+
+impl<'a> PropertyData<'a> {
+    /// A transaction with no property data, just a header, such as an inquiry for a resource.
+    pub fn header_only(request_id: u8, header: &'a [u8]) -> Self {
+        PropertyData {
+            request_id,
+            header,
+            chunk_count: 1,
+            chunk: 1,
+            data: &[],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiBody<'a> {
+    /// Asks the devices on the connection to reply with their details; sent to [`Muid::BROADCAST`].
+    Discovery(DeviceDetails),
+    DiscoveryReply(DeviceDetails),
+    /// Tells every device to forget the MUID, for instance when it turns out two devices picked the same one.
+    InvalidateMuid(Muid),
+    /// Refuses an inquiry the device can't handle.
+    Nak,
+    /// Asks how many Property Exchange transactions the device can handle at once, giving the sender's own limit.
+    PropertyExchangeCapabilities {
+        simultaneous_requests: u8,
+    },
+    PropertyExchangeCapabilitiesReply {
+        simultaneous_requests: u8,
+    },
+    PropertyExchange(PropertyExchangeKind, PropertyData<'a>),
+    /// Any other MIDI-CI message, with the bytes after the MUIDs left as they are.
+    Other {
+        sub_id: u8,
+        data: &'a [u8],
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CiMessage<'a> {
+    /// The channel the message is about, from 0x00 to 0x0F, or [`TO_FUNCTION_BLOCK`].
+    pub device_id: u8,
+    pub source: Muid,
+    pub destination: Muid,
+    pub body: CiBody<'a>,
+}
+
+impl<'a> CiMessage<'a> {
+    /// The SysEx payload carrying the message, without its start and end bytes, or
+    /// [`RtpMidiError::InvalidArgument`] if a value doesn't fit the 7-bit bytes it is sent in: a byte field above
+    /// 0x7F, a family or model above 16383, or property data longer than 16383 bytes.
+    pub fn to_sysex(&self) -> Result<Vec<u8>, RtpMidiError> {
+        let sub_id = match self.body {
+            CiBody::Discovery(_) => DISCOVERY,
+            CiBody::DiscoveryReply(_) => DISCOVERY_REPLY,
+            CiBody::InvalidateMuid(_) => INVALIDATE_MUID,
+            CiBody::Nak => NAK,
+            CiBody::PropertyExchangeCapabilities { .. } => PROPERTY_EXCHANGE_CAPABILITIES,
+            CiBody::PropertyExchangeCapabilitiesReply { .. } => PROPERTY_EXCHANGE_CAPABILITIES_REPLY,
+            CiBody::PropertyExchange(kind, _) => kind.sub_id(),
+            CiBody::Other { sub_id, .. } => sub_id,
+        };
+        let mut sysex = Vec::from([UNIVERSAL_NON_REAL_TIME]);
+        write_bytes(&mut sysex, "device ID", &[self.device_id])?;
+        sysex.push(MIDI_CI);
+        write_bytes(&mut sysex, "sub-ID", &[sub_id])?;
+        sysex.push(VERSION);
+        write_7bit(&mut sysex, "source MUID", self.source.0, 4)?;
+        write_7bit(&mut sysex, "destination MUID", self.destination.0, 4)?;
+        match self.body {
+            CiBody::Discovery(details) | CiBody::DiscoveryReply(details) => {
+                write_manufacturer(&mut sysex, details.manufacturer)?;
+                write_7bit(&mut sysex, "family", details.family.into(), 2)?;
+                write_7bit(&mut sysex, "model", details.model.into(), 2)?;
+                write_bytes(&mut sysex, "software revision", &details.software_revision)?;
+                write_bytes(&mut sysex, "categories", &[details.categories])?;
+                write_7bit(&mut sysex, "largest SysEx size", details.max_sysex_size, 4)?;
+            }
+            CiBody::InvalidateMuid(target) => write_7bit(&mut sysex, "target MUID", target.0, 4)?,
+            CiBody::Nak => {}
+            CiBody::PropertyExchangeCapabilities { simultaneous_requests } | CiBody::PropertyExchangeCapabilitiesReply { simultaneous_requests } => {
+                write_bytes(&mut sysex, "simultaneous requests", &[simultaneous_requests])?
+            }
+            CiBody::PropertyExchange(_, property) => {
+                write_bytes(&mut sysex, "request ID", &[property.request_id])?;
+                write_7bit(&mut sysex, "header length", length(property.header), 2)?;
+                write_bytes(&mut sysex, "header", property.header)?;
+                write_7bit(&mut sysex, "chunk count", property.chunk_count.into(), 2)?;
+                write_7bit(&mut sysex, "chunk", property.chunk.into(), 2)?;
+                write_7bit(&mut sysex, "data length", length(property.data), 2)?;
+                write_bytes(&mut sysex, "property data", property.data)?;
+            }
+            CiBody::Other { data, .. } => write_bytes(&mut sysex, "data", data)?,
+        }
+        Ok(sysex)
+    }
+
+    /// Reads a SysEx payload, as received without its start and end bytes. Returns `None` for any other SysEx
+    /// message, or a MIDI-CI message cut short.
+    pub fn from_sysex(sysex: &'a [u8]) -> Option<Self> {
+        let [UNIVERSAL_NON_REAL_TIME, device_id, MIDI_CI, sub_id, _version, ref rest @ ..] = *sysex else {
+            return None;
+        };
+        let mut reader = Reader(rest);
+        let source = Muid(reader.read_7bit(4)?);
+        let destination = Muid(reader.read_7bit(4)?);
+        let body = match sub_id {
+            DISCOVERY => CiBody::Discovery(reader.read_details()?),
+            DISCOVERY_REPLY => CiBody::DiscoveryReply(reader.read_details()?),
+            INVALIDATE_MUID => CiBody::InvalidateMuid(Muid(reader.read_7bit(4)?)),
+            NAK => CiBody::Nak,
+            PROPERTY_EXCHANGE_CAPABILITIES => CiBody::PropertyExchangeCapabilities {
+                simultaneous_requests: reader.read_byte()?,
+            },
+            PROPERTY_EXCHANGE_CAPABILITIES_REPLY => CiBody::PropertyExchangeCapabilitiesReply {
+                simultaneous_requests: reader.read_byte()?,
+            },
+            sub_id => match PropertyExchangeKind::ALL.into_iter().find(|kind| kind.sub_id() == sub_id) {
+                Some(kind) => CiBody::PropertyExchange(kind, reader.read_property_data()?),
+                None => CiBody::Other { sub_id, data: reader.0 },
+            },
+        };
+        Some(CiMessage {
+            device_id,
+            source,
+            destination,
+            body,
+        })
+    }
+}
+
+/// MIDI-CI always gives the manufacturer three bytes: a one-byte ID is followed by two zeros, and an extended one
+/// keeps the zero it starts with.
+fn write_manufacturer(sysex: &mut Vec<u8>, manufacturer: ManufacturerId) -> Result<(), RtpMidiError> {
+    let bytes = match manufacturer {
+        ManufacturerId::Standard(id) => [id, 0, 0],
+        ManufacturerId::Extended([a, b]) => [0, a, b],
+    };
+    write_bytes(sysex, "manufacturer", &bytes)
+}
+
+/// The length of `bytes`, as large as it gets if it doesn't fit in a `u32`, for [`write_7bit`] to reject.
+fn length(bytes: &[u8]) -> u32 {
+    u32::try_from(bytes.len()).unwrap_or(u32::MAX)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (bytes, rest) = self.0.split_at_checked(len)?;
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        Some(self.read_bytes(1)?[0])
+    }
+
+    fn read_7bit(&mut self, len: usize) -> Option<u32> {
+        let bytes = self.read_bytes(len)?;
+        Some(bytes.iter().rev().fold(0, |value, &byte| value << 7 | u32::from(byte & 0x7F)))
+    }
+
+    fn read_manufacturer(&mut self) -> Option<ManufacturerId> {
+        match *self.read_bytes(3)? {
+            [0, a, b] => Some(ManufacturerId::Extended([a, b])),
+            [id, _, _] => Some(ManufacturerId::Standard(id)),
+            _ => None,
+        }
+    }
+
+    fn read_details(&mut self) -> Option<DeviceDetails> {
+        Some(DeviceDetails {
+            manufacturer: self.read_manufacturer()?,
+            family: self.read_7bit(2)? as u16,
+            model: self.read_7bit(2)? as u16,
+            software_revision: self.read_bytes(4)?.try_into().ok()?,
+            categories: self.read_byte()?,
+            max_sysex_size: self.read_7bit(4)?,
+        })
+    }
+
+    fn read_property_data(&mut self) -> Option<PropertyData<'a>> {
+        let request_id = self.read_byte()?;
+        let header_len = self.read_7bit(2)?;
+        let header = self.read_bytes(header_len as usize)?;
+        let chunk_count = self.read_7bit(2)? as u16;
+        let chunk = self.read_7bit(2)? as u16;
+        let data_len = self.read_7bit(2)?;
+        let data = self.read_bytes(data_len as usize)?;
+        Some(PropertyData {
+            request_id,
+            header,
+            chunk_count,
+            chunk,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(body: CiBody<'_>) -> CiMessage<'_> {
+        CiMessage {
+            device_id: TO_FUNCTION_BLOCK,
+            source: Muid::new(0x0ABC_DEF0),
+            destination: Muid::new(0x0000_0081),
+            body,
+        }
+    }
+
+    #[test]
+    fn test_muids_are_sent_least_significant_first() {
+        let sysex = message(CiBody::Nak).to_sysex().unwrap();
+        assert_eq!(sysex, [0x7E, 0x7F, 0x0D, 0x7F, 0x01, 0x70, 0x3D, 0x73, 0x55, 0x01, 0x01, 0x00, 0x00]);
+        assert_eq!(Muid::new(u32::MAX), Muid::BROADCAST);
+    }
+
+    #[test]
+    fn test_property_exchange_round_trip() {
+        let header = br#"{"resource":"DeviceInfo"}"#;
+        let bodies = [
+            CiBody::PropertyExchange(PropertyExchangeKind::Get, PropertyData::header_only(3, header)),
+            CiBody::PropertyExchange(
+                PropertyExchangeKind::GetReply,
+                PropertyData {
+                    request_id: 3,
+                    header: br#"{"status":200}"#,
+                    chunk_count: 2,
+                    chunk: 1,
+                    data: &[b'x'; 200],
+                },
+            ),
+            CiBody::PropertyExchangeCapabilities { simultaneous_requests: 4 },
+            CiBody::InvalidateMuid(Muid::new(5)),
+            CiBody::Other { sub_id: 0x20, data: &[1, 2] },
+        ];
+        for body in bodies {
+            let sysex = message(body).to_sysex().unwrap();
+            assert_eq!(CiMessage::from_sysex(&sysex), Some(message(body)));
+        }
+    }
+
+    #[test]
+    fn test_messages_cut_short_are_rejected() {
+        let sysex = message(CiBody::PropertyExchange(PropertyExchangeKind::Notify, PropertyData::header_only(1, b"{}")))
+            .to_sysex()
+            .unwrap();
+        assert_eq!(CiMessage::from_sysex(&sysex[..sysex.len() - 1]), None);
+        // Universal real time, not non-real time
+        assert_eq!(CiMessage::from_sysex(&[0x7F, 0x7F, 0x0D, 0x7F, 0x01]), None);
+    }
+
+    #[test]
+    fn test_later_versions_extra_bytes_are_skipped() {
+        let details = DeviceDetails {
+            manufacturer: ManufacturerId::Extended([0x21, 0x09]),
+            family: 0x1234,
+            model: 0x0101,
+            software_revision: [1, 2, 3, 4],
+            categories: DeviceDetails::PROFILE_CONFIGURATION | DeviceDetails::PROPERTY_EXCHANGE,
+            max_sysex_size: 0x0FFF_FFFF,
+        };
+        let mut sysex = message(CiBody::DiscoveryReply(details)).to_sysex().unwrap();
+        // MIDI-CI 1.2 adds the output path and function block
+        sysex[4] = 0x02;
+        sysex.extend_from_slice(&[0x00, 0x7F]);
+        assert_eq!(CiMessage::from_sysex(&sysex).map(|message| message.body), Some(CiBody::DiscoveryReply(details)));
+    }
+
+    #[test]
+    fn test_values_too_wide_for_their_fields_are_rejected() {
+        let details = DeviceDetails {
+            manufacturer: ManufacturerId::Standard(0x7D),
+            family: 0x3FFF,
+            model: 0,
+            software_revision: [0; 4],
+            categories: 0,
+            max_sysex_size: 512,
+        };
+        assert!(message(CiBody::Discovery(details)).to_sysex().is_ok());
+        let too_wide = [
+            message(CiBody::Discovery(DeviceDetails { family: 0x4000, ..details })),
+            message(CiBody::Discovery(DeviceDetails {
+                manufacturer: ManufacturerId::Standard(0x80),
+                ..details
+            })),
+            message(CiBody::Discovery(DeviceDetails {
+                max_sysex_size: 0x1000_0000,
+                ..details
+            })),
+            message(CiBody::PropertyExchangeCapabilities { simultaneous_requests: 0x80 }),
+            message(CiBody::PropertyExchange(PropertyExchangeKind::Get, PropertyData::header_only(1, &[0xFF]))),
+            CiMessage {
+                device_id: 0x80,
+                ..message(CiBody::Nak)
+            },
+        ];
+        for message in too_wide {
+            assert!(matches!(message.to_sysex(), Err(RtpMidiError::InvalidArgument(_))), "{message:?}");
+        }
+        let data = [0; 0x4000];
+        let property = PropertyData {
+            data: &data,
+            ..PropertyData::header_only(1, b"{}")
+        };
+        assert!(message(CiBody::PropertyExchange(PropertyExchangeKind::SetReply, property)).to_sysex().is_err());
+    }
+}
@@ -1,26 +1,99 @@
+use crate::packets::error::PacketParseError;
 use crate::packets::midi_packets::midi_event::MidiEvent;
+use crate::packets::midi_packets::midi_packet::FirstDeltaTime;
+use crate::packets::midi_packets::util::{StatusBit, next_running_status};
+use crate::packets::parse_mode::ParseMode;
 
 use super::midi_command_list_header::MidiCommandListHeader;
 
 #[derive(Debug)]
-pub(crate) struct MidiCommandIterator<'a> {
+pub struct MidiCommandIterator<'a> {
     data: &'a [u8],
     running_status: Option<u8>,
     read_delta_time: bool,
+    mode: ParseMode,
 }
 
 impl<'a> MidiCommandIterator<'a> {
-    pub fn new(data: &'a [u8]) -> Self {
-        let command_list_header = MidiCommandListHeader::from_slice(data);
+    /// Creates an iterator over the command list at the start of `data`, yielding nothing if the header is unusable.
+    pub(crate) fn new(data: &'a [u8], mode: ParseMode) -> Self {
+        Self::try_new(data, mode).unwrap_or(MidiCommandIterator {
+            data: &[],
+            running_status: None,
+            read_delta_time: false,
+            mode,
+        })
+    }
+
+    pub(crate) fn try_new(data: &'a [u8], mode: ParseMode) -> Result<Self, PacketParseError> {
+        if data.is_empty() {
+            return Ok(MidiCommandIterator {
+                data,
+                running_status: None,
+                read_delta_time: false,
+                mode,
+            });
+        }
+
+        let command_list_header = MidiCommandListHeader::from_slice(data).ok_or(PacketParseError::Truncated("Command list header"))?;
         let read_delta_time = command_list_header.flags().z_flag();
-        let offset = command_list_header.size();
-        let length = command_list_header.length();
-        let slice = &data[offset..length + offset];
-        MidiCommandIterator {
+        let available = &data[command_list_header.size()..];
+        let slice = match available.get(..command_list_header.length()) {
+            Some(slice) => slice,
+            None if mode == ParseMode::Lenient => available,
+            None => return Err(PacketParseError::Truncated("Command list")),
+        };
+        Ok(MidiCommandIterator {
             data: slice,
             running_status: None,
             read_delta_time,
+            mode,
+        })
+    }
+
+    /// Reads a delta time before the first command whatever the Z flag says, if `first_delta_time` is
+    /// [`FirstDeltaTime::Always`]. Only called before iterating.
+    pub(crate) fn with_first_delta_time(mut self, first_delta_time: FirstDeltaTime) -> Self {
+        self.read_delta_time |= first_delta_time == FirstDeltaTime::Always;
+        self
+    }
+
+    /// The number of bytes not yet consumed.
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Parses every remaining command, failing on the first one that can't be decoded.
+    pub(crate) fn validate(mut self) -> Result<(), PacketParseError> {
+        while let Some(result) = self.next_event() {
+            result?;
         }
+        Ok(())
+    }
+
+    fn next_event(&mut self) -> Option<Result<MidiEvent<'a>, PacketParseError>> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let result = MidiEvent::from_be_bytes(self.data, self.read_delta_time, self.running_status).map(|(event, remaining)| {
+            self.running_status = next_running_status(self.running_status, event.command().status());
+            self.data = remaining;
+            self.read_delta_time = true;
+            event
+        });
+        Some(result)
+    }
+
+    /// Skips ahead to the next byte that can begin a command, discarding whatever could not be decoded.
+    fn resync(&mut self) {
+        let can_start_command = |byte: &u8| byte.status_bit() && !matches!(byte, 0xF4 | 0xF5 | 0xF9 | 0xFD);
+        self.data = match self.data.iter().skip(1).position(can_start_command) {
+            Some(index) => &self.data[index + 1..],
+            None => &[],
+        };
+        self.running_status = None;
+        self.read_delta_time = false;
     }
 }
 
@@ -28,18 +101,15 @@ impl<'a> Iterator for MidiCommandIterator<'a> {
     type Item = MidiEvent<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if !self.data.is_empty() {
-            match MidiEvent::from_be_bytes(self.data, self.read_delta_time, self.running_status) {
-                Ok((event, new_offset)) => {
-                    self.running_status = Some(event.command().status());
-                    self.data = new_offset;
-                    self.read_delta_time = true;
-                    Some(event)
+        loop {
+            match self.next_event()? {
+                Ok(event) => return Some(event),
+                Err(_) if self.mode == ParseMode::Lenient => self.resync(),
+                Err(_) => {
+                    self.data = &[];
+                    return None;
                 }
-                Err(_) => None, // Handle error appropriately, e.g., log or return None
             }
-        } else {
-            None
         }
     }
 }
@@ -55,7 +125,7 @@ mod tests {
     #[test]
     fn test_midi_command_iterator() {
         let data = &[70, 145, 65, 0, 11, 62, 0, 32, 126, 37, 8, 12, 8, 131, 136, 62, 83, 193, 93, 197, 83, 144];
-        let iterator = MidiCommandIterator::new(data);
+        let iterator = MidiCommandIterator::new(data, ParseMode::Lenient);
         let events = iterator.collect::<Vec<_>>();
         assert_eq!(events.len(), 2);
         assert_eq!(events[0].delta_time(), 0);
@@ -75,4 +145,27 @@ mod tests {
         assert_eq!(*key, Note::from(62));
         assert_eq!(*velocity, Into::into(0));
     }
+
+    #[test]
+    fn test_lenient_skips_undecodable_command() {
+        // Note On, an undefined status byte, then Note Off
+        let data = &[0x09, 0x90, 0x3C, 0x7F, 0x00, 0xF4, 0x00, 0x80, 0x3C, 0x00];
+        let events = MidiCommandIterator::new(data, ParseMode::Lenient).collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[1].command(), RtpMidiMessage::MidiMessage(MidiMessage::NoteOff(..))));
+    }
+
+    #[test]
+    fn test_strict_stops_at_undecodable_command() {
+        let data = &[0x09, 0x90, 0x3C, 0x7F, 0x00, 0xF4, 0x00, 0x80, 0x3C, 0x00];
+        assert_eq!(MidiCommandIterator::new(data, ParseMode::Strict).count(), 1);
+        assert!(MidiCommandIterator::try_new(data, ParseMode::Strict).unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn test_command_list_longer_than_packet() {
+        let data = &[0x05, 0x90, 0x3C, 0x7F];
+        assert_eq!(MidiCommandIterator::new(data, ParseMode::Lenient).count(), 1);
+        assert!(MidiCommandIterator::try_new(data, ParseMode::Strict).is_err());
+    }
 }
@@ -1,22 +1,26 @@
-use bytes::BytesMut;
+use bytes::BufMut;
 
 use crate::packets::midi_packets::delta_time::delta_time_size;
+use crate::packets::midi_packets::util::next_running_status;
 
 use super::midi_event::MidiEvent;
 
+/// The longest command list the 12-bit length in its header can describe.
+pub(crate) const MAX_COMMAND_LIST_LENGTH: usize = 0x0FFF;
+
 pub(super) trait MidiEventList {
-    fn write(&self, buffer: &mut BytesMut, z_flag: bool);
+    fn write<B: BufMut>(&self, buffer: &mut B, z_flag: bool);
     fn size(&self, z_flag: bool) -> usize;
 }
 
 // Specific implementation for slices to avoid lifetime issues
 impl<'a> MidiEventList for [MidiEvent<'a>] {
-    fn write(&self, buffer: &mut BytesMut, z_flag: bool) {
+    fn write<B: BufMut>(&self, buffer: &mut B, z_flag: bool) {
         let mut write_delta_time = z_flag;
         let mut running_status: Option<u8> = None;
         for command in self.iter() {
             command.write(buffer, running_status, write_delta_time);
-            running_status = Some(command.command().status());
+            running_status = next_running_status(running_status, command.command().status());
             write_delta_time = true;
         }
     }
@@ -25,17 +29,101 @@ impl<'a> MidiEventList for [MidiEvent<'a>] {
         let mut length: usize = 0;
         let mut running_status: Option<u8> = None;
         for (i, command) in self.iter().enumerate() {
-            if i > 0 || z_flag {
-                length += delta_time_size(command.delta_time())
-            }
-            if Some(command.command().status()) != running_status {
-                length += command.command().len();
-            } else {
-                length += command.command().len() - 1;
-            }
-            running_status = Some(command.command().status());
+            length += command_size(command, running_status, i > 0 || z_flag);
+            running_status = next_running_status(running_status, command.command().status());
         }
 
         length
     }
 }
+
+/// The bytes `command` takes in a command list after others left `running_status`, with its delta time or not.
+pub(super) fn command_size(command: &MidiEvent, running_status: Option<u8>, with_delta_time: bool) -> usize {
+    let delta_time_length = if with_delta_time { delta_time_size(command.delta_time()) } else { 0 };
+    match Some(command.command().status()) == running_status {
+        true => delta_time_length + command.command().len() - 1,
+        false => delta_time_length + command.command().len(),
+    }
+}
+
+/// Splits `commands` into runs that each fit in a command list of at most `max_length` bytes, as written with the
+/// Z flag given, without allocating. A command too long for any list gets one to itself, and no commands at all make
+/// one empty list.
+#[cfg(feature = "std")]
+pub(crate) fn split_into_command_lists<'b, 'a>(commands: &'b [MidiEvent<'a>], max_length: usize, z_flag: bool) -> CommandLists<'b, 'a> {
+    CommandLists {
+        commands: Some(commands),
+        max_length,
+        z_flag,
+    }
+}
+
+/// The command lists [`split_into_command_lists`] makes, one at a time.
+#[cfg(feature = "std")]
+pub(crate) struct CommandLists<'b, 'a> {
+    /// What's left to split, until the last list has been handed out.
+    commands: Option<&'b [MidiEvent<'a>]>,
+    max_length: usize,
+    z_flag: bool,
+}
+
+#[cfg(feature = "std")]
+impl<'b, 'a> Iterator for CommandLists<'b, 'a> {
+    type Item = &'b [MidiEvent<'a>];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let commands = self.commands?;
+        let mut length = 0;
+        let mut running_status: Option<u8> = None;
+        let mut end = 0;
+        for (i, command) in commands.iter().enumerate() {
+            // The first command of a list only has a delta time with the Z flag
+            let command_length = command_size(command, running_status, i > 0 || self.z_flag);
+            if i > 0 && length + command_length > self.max_length {
+                break;
+            }
+            length += command_length;
+            running_status = next_running_status(running_status, command.command().status());
+            end = i + 1;
+        }
+        let (list, rest) = commands.split_at(end);
+        self.commands = (!rest.is_empty()).then_some(rest);
+        Some(list)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use midi_types::{Channel, MidiMessage, Note, Value7};
+
+    use super::*;
+    use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+
+    #[test]
+    fn test_split_into_command_lists() {
+        let note_on = |key: u8| {
+            MidiEvent::new(
+                Some(1),
+                RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::from(key), Value7::from(100))),
+            )
+        };
+        let commands: Vec<_> = (0..10).map(note_on).collect();
+        // 3 bytes for the first, then a delta time and 2 data bytes under running status for each one after it
+        assert_eq!(commands.size(false), 3 + 9 * 3);
+        let lists: Vec<_> = split_into_command_lists(&commands, 9, false).collect();
+        assert_eq!(lists.iter().map(|list| list.len()).collect::<Vec<_>>(), [3, 3, 3, 1]);
+        assert!(lists.iter().all(|list| list.size(false) <= 9));
+        assert_eq!(
+            split_into_command_lists(&commands, MAX_COMMAND_LIST_LENGTH, false).collect::<Vec<_>>(),
+            [&commands[..]]
+        );
+        // With the Z flag, the first command's delta time takes a byte too
+        let lists: Vec<_> = split_into_command_lists(&commands, 9, true).collect();
+        assert_eq!(lists.iter().map(|list| list.len()).collect::<Vec<_>>(), [2, 2, 2, 2, 2]);
+        assert!(lists.iter().all(|list| list.size(true) <= 9));
+
+        let sysex = [MidiEvent::new(None, RtpMidiMessage::SysEx(&[0x7D; 16]))];
+        assert_eq!(split_into_command_lists(&sysex, 9, false).collect::<Vec<_>>(), [&sysex[..]]);
+        assert_eq!(split_into_command_lists(&[], 9, false).collect::<Vec<_>>(), [&[][..]]);
+    }
+}
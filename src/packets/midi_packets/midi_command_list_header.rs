@@ -1,4 +1,4 @@
-use bytes::{BufMut, BytesMut};
+use bytes::BufMut;
 
 use crate::packets::midi_packets::{midi_command_list_body::MidiEventList, midi_event::MidiEvent};
 
@@ -43,9 +43,13 @@ impl MidiCommandListFlags {
         }
     }
 
-    // pub fn j_flag(&self) -> bool {
-    //     self.get_flag(MidiCommandSectionFlagMasks::J)
-    // }
+    pub fn j_flag(&self) -> bool {
+        self.get_flag(MidiCommandSectionFlagMasks::J)
+    }
+
+    pub fn p_flag(&self) -> bool {
+        self.get_flag(MidiCommandSectionFlagMasks::P)
+    }
 
     pub fn b_flag(&self) -> bool {
         self.get_flag(MidiCommandSectionFlagMasks::B)
@@ -69,10 +73,13 @@ impl MidiCommandListHeader {
         MidiCommandListHeader { flags, length }
     }
 
-    pub fn build_for(events: &[MidiEvent], z_flag: bool) -> Self {
+    /// The header for `events`, with a journal after them if `j_flag` is set. The P flag is only set if the first
+    /// command is a channel message, the only kind with a running status to leave out.
+    pub fn build_for(events: &[MidiEvent], z_flag: bool, phantom_status: bool, j_flag: bool) -> Self {
         let length = events.size(z_flag);
         let b_flag = MidiCommandListFlags::needs_b_flag(length);
-        let flags = MidiCommandListFlags::new(b_flag, false, false, z_flag);
+        let is_channel_message = events.first().is_some_and(|event| event.command().status() < 0xF0);
+        let flags = MidiCommandListFlags::new(b_flag, j_flag, z_flag, phantom_status && is_channel_message);
         Self::new(flags, length)
     }
 
@@ -88,23 +95,23 @@ impl MidiCommandListHeader {
         if self.flags.b_flag() { 2 } else { 1 }
     }
 
-    pub fn from_slice(data: &[u8]) -> Self {
-        let first_byte = data[0];
+    pub fn from_slice(data: &[u8]) -> Option<Self> {
+        let first_byte = *data.first()?;
         let flags = MidiCommandListFlags::from_u8(first_byte);
         if flags.b_flag() {
-            let length_lsb = data[1];
+            let length_lsb = *data.get(1)?;
             let length = (((first_byte & 0x0F) as u16) << 8) | (length_lsb as u16);
-            Self {
+            Some(Self {
                 flags,
                 length: length as usize,
-            }
+            })
         } else {
             let length = (first_byte & 0x0F) as usize;
-            Self { flags, length }
+            Some(Self { flags, length })
         }
     }
 
-    pub fn write(&self, buffer: &mut BytesMut) {
+    pub fn write<B: BufMut>(&self, buffer: &mut B) {
         if self.flags.b_flag() {
             // For large lengths: first byte has flags + upper 4 bits of length
             let first_byte = self.flags.flags | ((self.length >> 8) as u8 & 0x0F);
@@ -1,6 +1,7 @@
-use bytes::BytesMut;
+use bytes::BufMut;
 use midi_types::MidiMessage;
 
+use crate::packets::error::PacketParseError;
 use crate::packets::midi_packets::delta_time::read_delta_time;
 use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
 
@@ -8,6 +9,7 @@ use super::delta_time::WriteDeltaTimeExt;
 use super::midi_message_ext::ReadWriteExt;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct MidiEvent<'a> {
     delta_time: Option<u32>,
     command: RtpMidiMessage<'a>,
@@ -26,7 +28,15 @@ impl<'a> MidiEvent<'a> {
         &self.command
     }
 
-    pub fn from_be_bytes(bytes: &'a [u8], include_delta_time: bool, running_status: Option<u8>) -> std::io::Result<(Self, &'a [u8])> {
+    /// The same event, with its delta time, carrying `command` instead.
+    pub fn with_command<'b>(&self, command: RtpMidiMessage<'b>) -> MidiEvent<'b> {
+        MidiEvent {
+            delta_time: self.delta_time,
+            command,
+        }
+    }
+
+    pub fn from_be_bytes(bytes: &'a [u8], include_delta_time: bool, running_status: Option<u8>) -> Result<(Self, &'a [u8]), PacketParseError> {
         let mut delta_time = None;
 
         let mut bytes = bytes;
@@ -40,7 +50,7 @@ impl<'a> MidiEvent<'a> {
         Ok((MidiEvent { delta_time, command }, offset))
     }
 
-    pub(super) fn write(&self, bytes: &mut BytesMut, running_status: Option<u8>, include_delta_time: bool) {
+    pub(super) fn write<B: BufMut>(&self, bytes: &mut B, running_status: Option<u8>, include_delta_time: bool) {
         if include_delta_time {
             match self.delta_time {
                 Some(dt) => bytes.write_delta_time(dt),
@@ -54,6 +64,7 @@ impl<'a> MidiEvent<'a> {
 
 #[cfg(test)]
 mod tests {
+    use bytes::BytesMut;
     use midi_types::{Channel, Note, Value7};
 
     use super::*;
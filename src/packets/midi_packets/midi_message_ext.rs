@@ -1,27 +1,25 @@
-use bytes::{BufMut, BytesMut};
+use bytes::BufMut;
 use midi_types::{
     Channel, Control, MidiMessage, Note, Program, QuarterFrame, Value7, Value14,
     status::{self},
 };
 
+use crate::packets::error::PacketParseError;
 use crate::packets::midi_packets::{rtp_midi_message::RtpMidiMessage, util::StatusBit};
-use std::io::Result;
 
 pub(super) trait ReadWriteExt {
-    fn write(&self, writer: &mut BytesMut, running_status: Option<u8>);
+    fn write<B: BufMut>(&self, writer: &mut B, running_status: Option<u8>);
     fn status(&self) -> u8;
-    fn from_status_byte(status_byte: u8, channel: u8, bytes: &[u8]) -> std::io::Result<(RtpMidiMessage, &[u8])>;
-    fn from_be_bytes(bytes: &[u8], running_status: Option<u8>) -> std::io::Result<(RtpMidiMessage, &[u8])>;
+    fn from_status_byte(status_byte: u8, channel: u8, bytes: &[u8]) -> Result<(RtpMidiMessage<'_>, &[u8]), PacketParseError>;
+    fn from_be_bytes(bytes: &[u8], running_status: Option<u8>) -> Result<(RtpMidiMessage<'_>, &[u8]), PacketParseError>;
 }
 
 impl ReadWriteExt for MidiMessage {
-    fn write(&self, bytes: &mut BytesMut, running_status: Option<u8>) {
-        if let Some(status_byte) = running_status {
-            if status_byte != self.status() {
-                bytes.put_u8(self.status());
-            }
-        } else {
-            bytes.put_u8(self.status());
+    fn write<B: BufMut>(&self, bytes: &mut B, running_status: Option<u8>) {
+        // Only channel messages may omit their status byte
+        let status = self.status();
+        if status >= 0xF0 || running_status != Some(status) {
+            bytes.put_u8(status);
         }
 
         match self {
@@ -48,10 +46,25 @@ impl ReadWriteExt for MidiMessage {
                 bytes.put_u8((raw >> 7) as u8);
                 bytes.put_u8((raw & 0x7F) as u8);
             }
-            _ => {
-                // Handle other MIDI messages or SysEx messages here
-                // For now, we will panic if an unsupported message is encountered
-                panic!("Unsupported MIDI message type: {self:?}");
+            MidiMessage::QuarterFrame(frame) => {
+                bytes.put_u8(Into::into(*frame));
+            }
+            MidiMessage::SongPositionPointer(position) => {
+                let (first, second): (u8, u8) = Into::into(*position);
+                bytes.put_u8(first);
+                bytes.put_u8(second);
+            }
+            MidiMessage::SongSelect(song) => {
+                bytes.put_u8(Into::into(*song));
+            }
+            MidiMessage::TuneRequest
+            | MidiMessage::TimingClock
+            | MidiMessage::Start
+            | MidiMessage::Continue
+            | MidiMessage::Stop
+            | MidiMessage::ActiveSensing
+            | MidiMessage::Reset => {
+                // Status byte only
             }
         }
     }
@@ -78,56 +91,90 @@ impl ReadWriteExt for MidiMessage {
         }
     }
 
-    fn from_status_byte(status_byte: u8, channel: u8, bytes: &[u8]) -> Result<(RtpMidiMessage, &[u8])> {
+    fn from_status_byte(status_byte: u8, channel: u8, bytes: &[u8]) -> Result<(RtpMidiMessage<'_>, &[u8]), PacketParseError> {
+        if let 0xF0 | 0xF7 = status_byte {
+            return RtpMidiMessage::sysex_from_be_bytes(status_byte, bytes);
+        }
+        let length = data_length(status_byte).ok_or(PacketParseError::UnsupportedStatus(status_byte))?;
+        if bytes.len() < length {
+            return Err(PacketParseError::Truncated("MIDI command"));
+        }
+        // A status byte where data should be; midi-types would panic on a value out of range
+        if bytes[..length].iter().any(|byte| byte.status_bit()) {
+            return Err(PacketParseError::Malformed("MIDI command data byte"));
+        }
+
         let command = match status_byte {
             0x80..0x90 => RtpMidiMessage::MidiMessage(MidiMessage::NoteOff(Channel::from(channel), Note::from(bytes[0]), Value7::from(bytes[1]))),
             0x90..0xA0 => RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::from(channel), Note::from(bytes[0]), Value7::from(bytes[1]))),
             0xA0..0xB0 => RtpMidiMessage::MidiMessage(MidiMessage::KeyPressure(Channel::from(channel), Note::from(bytes[0]), Value7::from(bytes[1]))),
-            0xB0..0xC0 => RtpMidiMessage::MidiMessage(MidiMessage::ControlChange(
-                Channel::from(channel),
-                Control::from(bytes[0]),
-                Value7::from(bytes[1]),
-            )),
-            0xC0..0xD0 => RtpMidiMessage::MidiMessage(MidiMessage::ProgramChange(Channel::from(channel), Program::from(bytes[0]))),
+            0xB0..0xC0 => RtpMidiMessage::MidiMessage(MidiMessage::ControlChange(Channel::from(channel), control(bytes[0]), Value7::from(bytes[1]))),
+            0xC0..0xD0 => RtpMidiMessage::MidiMessage(MidiMessage::ProgramChange(Channel::from(channel), program(bytes[0]))),
             0xD0..0xE0 => RtpMidiMessage::MidiMessage(MidiMessage::ChannelPressure(Channel::from(channel), Value7::from(bytes[0]))),
-            0xE0..0xF0 => RtpMidiMessage::MidiMessage(MidiMessage::PitchBendChange(Channel::from(channel), Value14::from((bytes[0], bytes[1])))),
-            0xF0 => {
-                let end_index = bytes.iter().position(|&b| b == 0xF7).unwrap_or(bytes.len());
-                RtpMidiMessage::SysEx(&bytes[1..end_index])
-            }
+            0xE0..=0xEF => RtpMidiMessage::MidiMessage(MidiMessage::PitchBendChange(Channel::from(channel), Value14::from((bytes[0], bytes[1])))),
             0xF1 => RtpMidiMessage::MidiMessage(MidiMessage::QuarterFrame(QuarterFrame::from(bytes[0]))),
             0xF2 => RtpMidiMessage::MidiMessage(MidiMessage::SongPositionPointer(Value14::from((bytes[0], bytes[1])))),
             0xF3 => RtpMidiMessage::MidiMessage(MidiMessage::SongSelect(Value7::from(bytes[0]))),
             0xF6 => RtpMidiMessage::MidiMessage(MidiMessage::TuneRequest),
             0xF8 => RtpMidiMessage::MidiMessage(MidiMessage::TimingClock),
-            _ => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Unsupported MIDI status byte: {status_byte:#02X}"),
-                ));
-            }
+            0xFA => RtpMidiMessage::MidiMessage(MidiMessage::Start),
+            0xFB => RtpMidiMessage::MidiMessage(MidiMessage::Continue),
+            0xFC => RtpMidiMessage::MidiMessage(MidiMessage::Stop),
+            0xFE => RtpMidiMessage::MidiMessage(MidiMessage::ActiveSensing),
+            0xFF => RtpMidiMessage::MidiMessage(MidiMessage::Reset),
+            _ => return Err(PacketParseError::UnsupportedStatus(status_byte)),
         };
 
-        let remaining = &bytes[command.len() - 1..];
-        Ok((command, remaining))
+        Ok((command, &bytes[length..]))
     }
 
-    fn from_be_bytes(bytes: &[u8], running_status: Option<u8>) -> std::io::Result<(RtpMidiMessage, &[u8])> {
-        let (status_byte, bytes) = if bytes[0].status_bit() {
-            (bytes[0], &bytes[1..])
+    fn from_be_bytes(bytes: &[u8], running_status: Option<u8>) -> Result<(RtpMidiMessage<'_>, &[u8]), PacketParseError> {
+        let first_byte = *bytes.first().ok_or(PacketParseError::NotEnoughData)?;
+        let (status_byte, bytes) = if first_byte.status_bit() {
+            (first_byte, &bytes[1..])
         } else {
-            (
-                running_status.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Running status not set"))?,
-                bytes,
-            )
+            (running_status.ok_or(PacketParseError::Malformed("command without a running status"))?, bytes)
         };
         let channel = status_byte & 0x0F;
         Self::from_status_byte(status_byte, channel, bytes)
     }
 }
 
+// midi-types debug asserts controller and program numbers are below 127, though 127 is a valid one, so `From` would
+// panic on some of what arrives off the wire in debug builds. The numbers given here are data bytes, so at most 127.
+pub(crate) fn control(number: u8) -> Control {
+    if number < 127 {
+        return Control::new(number);
+    }
+    const { assert!(size_of::<Control>() == 1) };
+    // SAFETY: midi-types is pinned to 0.2.1 in Cargo.toml, where Control is `struct Control(u8)` and nothing but `new`
+    // builds one, so it is a lone u8 and 127 is a number it can hold
+    unsafe { core::mem::transmute::<u8, Control>(number) }
+}
+
+pub(crate) fn program(number: u8) -> Program {
+    if number < 127 {
+        return Program::new(number);
+    }
+    const { assert!(size_of::<Program>() == 1) };
+    // SAFETY: as for control(), Program is `struct Program(u8)` in the pinned version
+    unsafe { core::mem::transmute::<u8, Program>(number) }
+}
+
+/// The number of data bytes that follow a (non-SysEx) status byte, or `None` if the status byte is undefined.
+pub(super) fn data_length(status_byte: u8) -> Option<usize> {
+    match status_byte {
+        0x80..=0xBF | 0xE0..=0xEF | 0xF2 => Some(2),
+        0xC0..=0xDF | 0xF1 | 0xF3 => Some(1),
+        0xF6 | 0xF8 | 0xFA..=0xFC | 0xFE | 0xFF => Some(0),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use bytes::BytesMut;
+
     use super::*;
     use ReadWriteExt;
 
@@ -208,6 +255,50 @@ mod tests {
         test_command_write_type(command, &expected_bytes);
     }
 
+    #[test]
+    fn test_command_write_system_messages() {
+        test_command_write_type(MidiMessage::QuarterFrame(From::from(0x35)), &[0xF1, 0x35]);
+        test_command_write_type(MidiMessage::SongPositionPointer(From::from((0x01, 0x02))), &[0xF2, 0x01, 0x02]);
+        test_command_write_type(MidiMessage::SongSelect(From::from(0x05)), &[0xF3, 0x05]);
+        test_command_write_type(MidiMessage::TuneRequest, &[0xF6]);
+        test_command_write_type(MidiMessage::TimingClock, &[0xF8]);
+        test_command_write_type(MidiMessage::Start, &[0xFA]);
+        test_command_write_type(MidiMessage::Continue, &[0xFB]);
+        test_command_write_type(MidiMessage::Stop, &[0xFC]);
+        test_command_write_type(MidiMessage::ActiveSensing, &[0xFE]);
+        test_command_write_type(MidiMessage::Reset, &[0xFF]);
+    }
+
+    #[test]
+    fn test_system_message_never_uses_running_status() {
+        let mut bytes = BytesMut::new();
+        MidiMessage::TimingClock.write(&mut bytes, Some(0xF8));
+        assert_eq!(&bytes[..], &[0xF8]);
+    }
+
+    #[test]
+    fn test_system_messages_round_trip() {
+        let messages = [
+            MidiMessage::QuarterFrame(From::from(0x35)),
+            MidiMessage::SongPositionPointer(From::from((0x01, 0x02))),
+            MidiMessage::SongSelect(From::from(0x05)),
+            MidiMessage::TuneRequest,
+            MidiMessage::TimingClock,
+            MidiMessage::Start,
+            MidiMessage::Continue,
+            MidiMessage::Stop,
+            MidiMessage::ActiveSensing,
+            MidiMessage::Reset,
+        ];
+        for message in messages {
+            let mut bytes = BytesMut::new();
+            message.write(&mut bytes, None);
+            let (parsed, remaining) = MidiMessage::from_be_bytes(&bytes, None).unwrap();
+            assert_eq!(parsed, RtpMidiMessage::MidiMessage(message));
+            assert!(remaining.is_empty());
+        }
+    }
+
     #[test]
     fn test_command_write_invalid() {
         let command = MidiMessage::NoteOn(From::from(4), From::from(0x40), From::from(0x7F));
@@ -215,4 +306,43 @@ mod tests {
         command.write(&mut bytes, None);
         assert_eq!(&bytes[..], &[0x94u8, 0x40, 0x7F]);
     }
+
+    #[test]
+    fn test_parse_truncated_command() {
+        assert_eq!(
+            MidiMessage::from_be_bytes(&[0x94, 0x40], None).unwrap_err(),
+            PacketParseError::Truncated("MIDI command")
+        );
+        assert!(MidiMessage::from_be_bytes(&[0x40], Some(0xE4)).is_err());
+        assert!(MidiMessage::from_be_bytes(&[], None).is_err());
+    }
+
+    #[test]
+    fn test_parse_data_byte_out_of_range() {
+        for bytes in [&[0xB1, 0x07, 0xD4][..], &[0x90, 0xC8, 0x64], &[0xE0, 0x00, 0x80], &[0xC0, 0xFF]] {
+            assert_eq!(
+                MidiMessage::from_be_bytes(bytes, None).unwrap_err(),
+                PacketParseError::Malformed("MIDI command data byte")
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_highest_control_and_program() {
+        let (command, _) = MidiMessage::from_be_bytes(&[0xB0, 0x7F, 0x00], None).unwrap();
+        assert_eq!(
+            command,
+            RtpMidiMessage::MidiMessage(MidiMessage::ControlChange(Channel::C1, control(127), Value7::from(0)))
+        );
+        let RtpMidiMessage::MidiMessage(MidiMessage::ControlChange(_, number, _)) = command else {
+            unreachable!()
+        };
+        assert_eq!(u8::from(number), 127);
+
+        let (command, _) = MidiMessage::from_be_bytes(&[0xC0, 0x7F], None).unwrap();
+        let RtpMidiMessage::MidiMessage(MidiMessage::ProgramChange(_, number)) = command else {
+            panic!("Not a ProgramChange: {command:?}");
+        };
+        assert_eq!(u8::from(number), 127);
+    }
 }
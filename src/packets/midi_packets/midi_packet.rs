@@ -1,3 +1,6 @@
+use alloc::format;
+use alloc::string::String;
+
 use bytes::{BufMut, Bytes, BytesMut};
 use zerocopy::{
     FromBytes, Immutable, IntoBytes, KnownLayout,
@@ -6,52 +9,356 @@ use zerocopy::{
 
 use super::midi_command_iterator::MidiCommandIterator;
 use super::midi_command_list_body::MidiEventList;
+use super::rtp_midi_message::RtpMidiMessage;
+use crate::error::RtpMidiError;
+use crate::packets::describe::Annotator;
+use crate::packets::error::PacketParseError;
 use crate::packets::midi_packets::{midi_command_list_header::MidiCommandListHeader, midi_event::MidiEvent, midi_packet_header::MidiPacketHeader};
+use crate::packets::parse_mode::ParseMode;
+use crate::packets::slice_writer::write_into_slice;
+
+/// Whether the first command of a command list has a delta time before it. RFC 6295 has the Z flag say so, the
+/// command falling on the packet's timestamp without one, but implementations disagree and some write a delta time
+/// for it regardless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FirstDeltaTime {
+    /// As the Z flag says.
+    #[default]
+    Flagged,
+    /// Always, whatever the Z flag says.
+    Always,
+}
+
+/// What a packet is written with besides its commands, as a [`MidiPacketBuilder`](crate::packets::builder::MidiPacketBuilder)
+/// gives it.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct PacketOptions<'a> {
+    pub z_flag: bool,
+    /// Sets the P flag, see [`MidiPacket::phantom_status`].
+    pub phantom_status: bool,
+    /// An encoded recovery journal to follow the command list, setting the J flag.
+    pub journal: Option<&'a [u8]>,
+}
 
 #[derive(FromBytes, KnownLayout, Immutable, Debug)]
 #[repr(C)]
-pub(crate) struct MidiPacket {
+pub struct MidiPacket {
     header: MidiPacketHeader,
     body: [u8],
 }
 
 impl MidiPacket {
-    pub(crate) fn new_as_bytes<'a>(sequence_number: U16, timestamp: U32, ssrc: U32, commands: &'a [MidiEvent<'a>], z_flag: bool) -> Bytes {
+    pub fn new_as_bytes<'a>(sequence_number: U16, timestamp: U32, ssrc: U32, commands: &'a [MidiEvent<'a>], z_flag: bool) -> Bytes {
+        let mut buffer = BytesMut::new();
+        Self::write_to(&mut buffer, sequence_number, timestamp, ssrc, commands, z_flag);
+        buffer.freeze()
+    }
+
+    /// Like [`new_as_bytes`](Self::new_as_bytes), with the command list flags and journal `options` give it.
+    pub(crate) fn new_with_options_as_bytes<'a>(
+        sequence_number: U16,
+        timestamp: U32,
+        ssrc: U32,
+        commands: &'a [MidiEvent<'a>],
+        options: &PacketOptions,
+    ) -> Bytes {
+        let mut buffer = BytesMut::new();
+        Self::append(&mut buffer, sequence_number, timestamp, ssrc, commands, options);
+        buffer.freeze()
+    }
+
+    /// Appends a packet to `buffer`, growing it only if it doesn't already have the capacity.
+    pub(crate) fn write_to<'a>(buffer: &mut BytesMut, sequence_number: U16, timestamp: U32, ssrc: U32, commands: &'a [MidiEvent<'a>], z_flag: bool) {
+        let options = PacketOptions { z_flag, ..Default::default() };
+        Self::append(buffer, sequence_number, timestamp, ssrc, commands, &options);
+    }
+
+    fn append(buffer: &mut BytesMut, sequence_number: U16, timestamp: U32, ssrc: U32, commands: &[MidiEvent], options: &PacketOptions) {
+        let packet_header = MidiPacketHeader::new(sequence_number, timestamp, ssrc);
+        let command_list_header = MidiCommandListHeader::build_for(commands, options.z_flag, options.phantom_status, options.journal.is_some());
+        buffer.reserve(Self::packet_len(&command_list_header, options));
+        Self::write_parts(buffer, &packet_header, &command_list_header, commands, options);
+    }
+
+    /// Serializes a packet into the start of `buffer` without allocating, returning the number of bytes written.
+    /// Fails without writing anything if `buffer` is too small to hold the packet.
+    pub fn write_into<'a>(
+        buffer: &mut [u8],
+        sequence_number: U16,
+        timestamp: U32,
+        ssrc: U32,
+        commands: &'a [MidiEvent<'a>],
+        z_flag: bool,
+    ) -> Result<usize, RtpMidiError> {
+        let options = PacketOptions { z_flag, ..Default::default() };
+        Self::write_with_options_into(buffer, sequence_number, timestamp, ssrc, commands, &options)
+    }
+
+    /// Like [`write_into`](Self::write_into), with the command list flags and journal `options` give it.
+    pub(crate) fn write_with_options_into<'a>(
+        buffer: &mut [u8],
+        sequence_number: U16,
+        timestamp: U32,
+        ssrc: U32,
+        commands: &'a [MidiEvent<'a>],
+        options: &PacketOptions,
+    ) -> Result<usize, RtpMidiError> {
         let packet_header = MidiPacketHeader::new(sequence_number, timestamp, ssrc);
-        let command_list_header = MidiCommandListHeader::build_for(commands, z_flag);
+        let command_list_header = MidiCommandListHeader::build_for(commands, options.z_flag, options.phantom_status, options.journal.is_some());
+        write_into_slice(buffer, Self::packet_len(&command_list_header, options), |buffer| {
+            Self::write_parts(buffer, &packet_header, &command_list_header, commands, options)
+        })
+    }
 
+    fn packet_len(command_list_header: &MidiCommandListHeader, options: &PacketOptions) -> usize {
         // Get the size of the body from the header as it's already calculated
-        let mut buffer = BytesMut::with_capacity(std::mem::size_of::<MidiPacketHeader>() + command_list_header.size() + command_list_header.length());
+        core::mem::size_of::<MidiPacketHeader>() + command_list_header.size() + command_list_header.length() + options.journal.map_or(0, <[u8]>::len)
+    }
+
+    fn write_parts<B: BufMut>(
+        buffer: &mut B,
+        packet_header: &MidiPacketHeader,
+        command_list_header: &MidiCommandListHeader,
+        commands: &[MidiEvent],
+        options: &PacketOptions,
+    ) {
         buffer.put_slice(packet_header.as_bytes());
-        command_list_header.write(&mut buffer);
-        commands.write(&mut buffer, z_flag);
-        buffer.freeze()
+        command_list_header.write(buffer);
+        commands.write(buffer, options.z_flag);
+        if let Some(journal) = options.journal {
+            buffer.put_slice(journal);
+        }
+    }
+
+    pub fn commands(&self) -> MidiCommandIterator<'_> {
+        self.commands_with_mode(ParseMode::Lenient)
+    }
+
+    pub fn commands_with_mode(&self, mode: ParseMode) -> MidiCommandIterator<'_> {
+        self.commands_with_first_delta_time(mode, FirstDeltaTime::Flagged)
+    }
+
+    /// Like [`commands_with_mode`](Self::commands_with_mode), for a sender that treats the first command's delta time
+    /// as `first_delta_time` says.
+    pub fn commands_with_first_delta_time(&self, mode: ParseMode, first_delta_time: FirstDeltaTime) -> MidiCommandIterator<'_> {
+        MidiCommandIterator::new(self.payload().unwrap_or_default(), mode).with_first_delta_time(first_delta_time)
+    }
+
+    /// Checks that the packet can be processed in the given mode. Strict mode requires RTP version 2
+    /// and a command list that decodes completely, reading the first command's delta time as `first_delta_time` says.
+    pub(crate) fn validate(&self, mode: ParseMode, first_delta_time: FirstDeltaTime) -> Result<(), PacketParseError> {
+        let payload = self.payload()?;
+        if mode == ParseMode::Strict {
+            let version = self.header.flags.get_version();
+            if version != 2 {
+                return Err(PacketParseError::UnsupportedVersion(version));
+            }
+            MidiCommandIterator::try_new(payload, mode)?
+                .with_first_delta_time(first_delta_time)
+                .validate()?;
+        }
+        Ok(())
+    }
+
+    /// The MIDI command section of the packet, with any RTP header extension and padding removed.
+    pub(crate) fn payload(&self) -> Result<&[u8], PacketParseError> {
+        let layout = self.layout()?;
+        Ok(&self.body[layout.extension_end..layout.payload_end])
+    }
+
+    /// Where the optional RTP sections end within the body.
+    fn layout(&self) -> Result<BodyLayout, PacketParseError> {
+        let csrc_end = self.header.flags.cc() as usize * 4;
+        let mut payload = self.body.get(csrc_end..).ok_or(PacketParseError::Truncated("RTP CSRC list"))?;
+
+        if self.header.flags.p_flag() {
+            // The last octet holds the number of padding octets, including itself
+            let padding = *payload.last().ok_or(PacketParseError::Truncated("RTP padding"))? as usize;
+            if padding == 0 || padding > payload.len() {
+                return Err(PacketParseError::Malformed("RTP padding length"));
+            }
+            payload = &payload[..payload.len() - padding];
+        }
+
+        let mut extension_length = 0;
+        if self.header.flags.x_flag() {
+            // 16 bits of profile-defined data followed by the extension length in 32-bit words
+            if payload.len() < 4 {
+                return Err(PacketParseError::Truncated("RTP header extension"));
+            }
+            extension_length = 4 + u16::from_be_bytes([payload[2], payload[3]]) as usize * 4;
+            if extension_length > payload.len() {
+                return Err(PacketParseError::Truncated("RTP header extension"));
+            }
+        }
+
+        Ok(BodyLayout {
+            csrc_end,
+            extension_end: csrc_end + extension_length,
+            payload_end: csrc_end + payload.len(),
+        })
     }
 
-    pub fn commands(&self) -> MidiCommandIterator {
-        MidiCommandIterator::new(&self.body)
+    /// An annotated hex dump of the packet: the RTP header, the command list and each command in it.
+    pub fn describe(&self) -> String {
+        let flags = &self.header.flags;
+        let mut annotator = Annotator::new(format!("RTP-MIDI packet, {} bytes", self.header.as_bytes().len() + self.body.len()));
+        annotator.field(
+            self.header.flags.as_bytes(),
+            format_args!(
+                "Version {}, P={}, X={}, CC={}, M={}, payload type {}",
+                flags.get_version(),
+                flags.p_flag() as u8,
+                flags.x_flag() as u8,
+                flags.cc(),
+                flags.m_flag() as u8,
+                flags.pt()
+            ),
+        );
+        annotator.field(
+            self.header.sequence_number.as_bytes(),
+            format_args!("Sequence number: {}", self.sequence_number()),
+        );
+        annotator.field(self.header.timestamp.as_bytes(), format_args!("Timestamp: {}", self.timestamp()));
+        annotator.field(self.header.ssrc.as_bytes(), format_args!("SSRC: {:#010X}", self.ssrc().get()));
+
+        let layout = match self.layout() {
+            Ok(layout) => layout,
+            Err(e) => {
+                annotator.field(&self.body, format_args!("Undecodable: {e}"));
+                return annotator.finish();
+            }
+        };
+        for csrc in self.csrcs() {
+            annotator.field(csrc.as_bytes(), format_args!("CSRC: {:#010X}", csrc.get()));
+        }
+        if layout.extension_end > layout.csrc_end {
+            annotator.field(&self.body[layout.csrc_end..layout.extension_end], "RTP header extension");
+        }
+
+        let payload = &self.body[layout.extension_end..layout.payload_end];
+        let mut journal = &[][..];
+        match MidiCommandListHeader::from_slice(payload) {
+            Some(header) => {
+                let flags = header.flags();
+                annotator.field(
+                    &payload[..header.size()],
+                    format_args!(
+                        "Command list: B={}, J={}, Z={}, P={}, length {}",
+                        flags.b_flag() as u8,
+                        flags.j_flag() as u8,
+                        flags.z_flag() as u8,
+                        flags.p_flag() as u8,
+                        header.length()
+                    ),
+                );
+                let list_end = (header.size() + header.length()).min(payload.len());
+                describe_commands(&mut annotator, &payload[..list_end]);
+                if flags.j_flag() {
+                    journal = &payload[list_end..];
+                } else if list_end < payload.len() {
+                    annotator.field(&payload[list_end..], "Unexpected data after the command list");
+                }
+            }
+            None if payload.is_empty() => {}
+            None => annotator.field(payload, "Truncated command list header"),
+        }
+        if !journal.is_empty() {
+            annotator.field(journal, "Recovery journal (not decoded)");
+        }
+        annotator.field(&self.body[layout.payload_end..], "RTP padding");
+        annotator.finish()
+    }
+
+    /// The contributing source identifiers added by any mixers this packet passed through.
+    pub fn csrcs(&self) -> &[U32] {
+        let csrc_length = self.header.flags.cc() as usize * 4;
+        self.body
+            .get(..csrc_length)
+            .and_then(|bytes| <[U32]>::ref_from_bytes(bytes).ok())
+            .unwrap_or_default()
     }
 
     pub fn sequence_number(&self) -> U16 {
         self.header.sequence_number
     }
 
-    #[allow(dead_code)]
+    /// Whether the command list's P flag is set: the first command's status byte is in the packet, as it has to be,
+    /// but the MIDI stream the commands came from left it out under running status. Passing the commands on to a
+    /// MIDI stream that carries on from the same running status, the byte can be left out again.
+    pub fn phantom_status(&self) -> bool {
+        self.payload()
+            .ok()
+            .and_then(MidiCommandListHeader::from_slice)
+            .is_some_and(|header| header.flags().p_flag())
+    }
+
+    /// The recovery journal after the command list, still encoded, if the J flag says there is one. A packet can be
+    /// sent with no commands and only a journal, which some senders do to keep a session alive.
+    pub fn journal(&self) -> Option<&[u8]> {
+        let payload = self.payload().ok()?;
+        let header = MidiCommandListHeader::from_slice(payload).filter(|header| header.flags().j_flag())?;
+        payload.get(header.size() + header.length()..)
+    }
+
     pub fn timestamp(&self) -> U32 {
         self.header.timestamp
     }
 
-    #[allow(dead_code)]
     pub fn ssrc(&self) -> U32 {
         self.header.ssrc
     }
 }
 
+/// Serializes the decoded packet: the RTP header fields and the commands, parsed leniently.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MidiPacket {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use alloc::vec::Vec;
+        use serde::ser::SerializeStruct;
+
+        let mut packet = serializer.serialize_struct("MidiPacket", 5)?;
+        packet.serialize_field("sequence_number", &self.sequence_number().get())?;
+        packet.serialize_field("timestamp", &self.timestamp().get())?;
+        packet.serialize_field("ssrc", &self.ssrc().get())?;
+        packet.serialize_field("csrcs", &self.csrcs().iter().map(|csrc| csrc.get()).collect::<Vec<_>>())?;
+        packet.serialize_field("commands", &self.commands().collect::<Vec<_>>())?;
+        packet.end()
+    }
+}
+
+/// Offsets within the body of a [`MidiPacket`] at which each optional RTP section ends.
+struct BodyLayout {
+    csrc_end: usize,
+    extension_end: usize,
+    payload_end: usize,
+}
+
+fn describe_commands(annotator: &mut Annotator, command_list: &[u8]) {
+    let mut commands = MidiCommandIterator::new(command_list, ParseMode::Lenient);
+    let mut offset = command_list.len() - commands.remaining();
+    while let Some(event) = commands.next() {
+        let end = command_list.len() - commands.remaining();
+        let command = match event.command() {
+            RtpMidiMessage::MidiMessage(message) => format!("{message:?}"),
+            RtpMidiMessage::SysEx(data) => format!("SysEx, {} bytes", data.len()),
+            RtpMidiMessage::SysExSegment(segment, data) => format!("SysEx segment {segment:?}, {} bytes", data.len()),
+        };
+        annotator.field(&command_list[offset..end], format_args!("Command at delta {}: {command}", event.delta_time()));
+        offset = end;
+    }
+    if offset < command_list.len() {
+        annotator.field(&command_list[offset..], "Undecodable commands");
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use midi_types::{Channel, MidiMessage, Note, Value7};
 
     use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+    use crate::packets::packet::RtpMidiPacket;
 
     use super::*;
 
@@ -82,4 +389,235 @@ mod tests {
         assert_eq!(packet.len(), expected.len());
         assert_eq!(&packet[..], &expected);
     }
+
+    #[test]
+    fn test_z_and_p_flags_are_kept_apart() {
+        let note_on = RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(127)));
+        let commands = [MidiEvent::new(Some(5), note_on.clone())];
+        let bytes = MidiPacket::new_with_options_as_bytes(
+            U16::new(1),
+            U32::new(2),
+            U32::new(3),
+            &commands,
+            &PacketOptions {
+                z_flag: true,
+                phantom_status: true,
+                journal: None,
+            },
+        );
+        assert_eq!(&bytes[12..], &[0x34, 0x05, 0x90, 0x48, 0x7F]);
+        let packet = MidiPacket::ref_from_bytes(&bytes).unwrap();
+        assert!(packet.phantom_status());
+        let parsed: Vec<_> = packet.commands().collect();
+        assert_eq!((parsed[0].delta_time(), parsed[0].command()), (5, &note_on));
+
+        let bytes = MidiPacket::new_as_bytes(U16::new(1), U32::new(2), U32::new(3), &commands, true);
+        assert_eq!(bytes[12], 0x24);
+        assert!(!MidiPacket::ref_from_bytes(&bytes).unwrap().phantom_status());
+
+        // Only channel messages have a running status to leave out
+        let commands = [MidiEvent::new(None, RtpMidiMessage::MidiMessage(MidiMessage::TimingClock))];
+        let bytes = MidiPacket::new_with_options_as_bytes(
+            U16::new(1),
+            U32::new(2),
+            U32::new(3),
+            &commands,
+            &PacketOptions {
+                phantom_status: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(bytes[12], 0x01);
+    }
+
+    #[test]
+    fn test_midi_packet_round_trip_with_system_messages() {
+        let commands = vec![
+            MidiEvent::new(None, RtpMidiMessage::MidiMessage(MidiMessage::TimingClock)),
+            MidiEvent::new(Some(1), RtpMidiMessage::MidiMessage(MidiMessage::TimingClock)),
+            MidiEvent::new(
+                Some(2),
+                RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(127))),
+            ),
+            MidiEvent::new(Some(3), RtpMidiMessage::MidiMessage(MidiMessage::TimingClock)),
+            MidiEvent::new(
+                Some(4),
+                RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::D4, Value7::from(127))),
+            ),
+            MidiEvent::new(Some(5), RtpMidiMessage::MidiMessage(MidiMessage::Stop)),
+        ];
+
+        let bytes = MidiPacket::new_as_bytes(U16::new(1), U32::new(2), U32::new(3), &commands, false);
+        let packet = MidiPacket::ref_from_bytes(&bytes).unwrap();
+        let parsed = packet.commands().map(|event| event.command().clone()).collect::<Vec<_>>();
+        let expected = commands.iter().map(|event| event.command().clone()).collect::<Vec<_>>();
+        assert_eq!(parsed, expected);
+    }
+
+    fn first_command(bytes: &[u8]) -> RtpMidiMessage<'_> {
+        let packet = MidiPacket::ref_from_bytes(bytes).unwrap();
+        packet.commands().next().unwrap().command().clone()
+    }
+
+    #[test]
+    fn test_midi_packet_with_padding() {
+        let bytes = [
+            0xA0, 0x61, // flags, P bit set
+            0x00, 0x01, // sequence number
+            0x00, 0x00, 0x00, 0x02, // timestamp
+            0x00, 0x00, 0x00, 0x03, // ssrc
+            0x03, // command list flags and length
+            0x90, 0x48, 0x7F, // Note On
+            0x00, 0x00, 0x03, // padding
+        ];
+
+        let expected = RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(127)));
+        assert_eq!(first_command(&bytes), expected);
+    }
+
+    #[test]
+    fn test_midi_packet_with_header_extension() {
+        let bytes = [
+            0x90, 0x61, // flags, X bit set
+            0x00, 0x01, // sequence number
+            0x00, 0x00, 0x00, 0x02, // timestamp
+            0x00, 0x00, 0x00, 0x03, // ssrc
+            0xBE, 0xDE, 0x00, 0x01, // extension profile and length in words
+            0x01, 0x02, 0x03, 0x04, // extension data
+            0x03, // command list flags and length
+            0x90, 0x48, 0x7F, // Note On
+        ];
+
+        let expected = RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(127)));
+        assert_eq!(first_command(&bytes), expected);
+    }
+
+    #[test]
+    fn test_midi_packet_with_csrcs() {
+        let bytes = [
+            0x82, 0x61, // flags, CC = 2
+            0x00, 0x01, // sequence number
+            0x00, 0x00, 0x00, 0x02, // timestamp
+            0x00, 0x00, 0x00, 0x03, // ssrc
+            0x00, 0x00, 0x00, 0x04, // csrc 1
+            0x00, 0x00, 0x00, 0x05, // csrc 2
+            0x03, // command list flags and length
+            0x90, 0x48, 0x7F, // Note On
+        ];
+
+        let packet = MidiPacket::ref_from_bytes(&bytes).unwrap();
+        assert_eq!(packet.csrcs(), &[U32::new(4), U32::new(5)]);
+        let expected = RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(127)));
+        assert_eq!(first_command(&bytes), expected);
+    }
+
+    #[test]
+    fn test_midi_packet_with_truncated_csrcs() {
+        let bytes = [
+            0x82, 0x61, // flags, CC = 2
+            0x00, 0x01, // sequence number
+            0x00, 0x00, 0x00, 0x02, // timestamp
+            0x00, 0x00, 0x00, 0x03, // ssrc
+            0x00, 0x00, 0x00, 0x04, // csrc 1
+        ];
+
+        let packet = MidiPacket::ref_from_bytes(&bytes).unwrap();
+        assert!(packet.csrcs().is_empty());
+        assert!(packet.payload().is_err());
+    }
+
+    #[test]
+    fn test_midi_packet_with_invalid_padding() {
+        let bytes = [
+            0xA0, 0x61, // flags, P bit set
+            0x00, 0x01, // sequence number
+            0x00, 0x00, 0x00, 0x02, // timestamp
+            0x00, 0x00, 0x00, 0x03, // ssrc
+            0x03, // command list flags and length
+            0x90, 0x48, 0x7F, // Note On
+            0x00, 0x00, 0x10, // padding longer than the payload
+        ];
+
+        let packet = MidiPacket::ref_from_bytes(&bytes).unwrap();
+        assert!(packet.payload().is_err());
+        assert_eq!(packet.commands().count(), 0);
+    }
+
+    #[test]
+    fn test_journal_only_packet() {
+        let bytes = [
+            0x80, 0x61, // flags
+            0x00, 0x01, // sequence number
+            0x00, 0x00, 0x00, 0x02, // timestamp
+            0x00, 0x00, 0x00, 0x03, // ssrc
+            0x40, // command list flags, J bit set, and length
+            0x00, 0x00, 0x01, // journal header, with an empty journal since packet 1
+        ];
+
+        for mode in [ParseMode::Lenient, ParseMode::Strict] {
+            let RtpMidiPacket::Midi(packet) = RtpMidiPacket::parse(&bytes, mode).unwrap() else {
+                panic!("Expected a MIDI packet");
+            };
+            assert_eq!(packet.commands_with_mode(mode).count(), 0);
+            assert_eq!(packet.journal(), Some(&bytes[13..]));
+        }
+        let built = MidiPacket::new_with_options_as_bytes(
+            U16::new(1),
+            U32::new(2),
+            U32::new(3),
+            &[],
+            &PacketOptions {
+                journal: Some(&bytes[13..]),
+                ..Default::default()
+            },
+        );
+        assert_eq!(&built[..], &bytes);
+
+        // Without the J bit, there's no journal to go looking for
+        let commands = [MidiEvent::new(None, RtpMidiMessage::MidiMessage(MidiMessage::TimingClock))];
+        let bytes = MidiPacket::new_as_bytes(U16::new(1), U32::new(2), U32::new(3), &commands, false);
+        assert_eq!(MidiPacket::ref_from_bytes(&bytes).unwrap().journal(), None);
+    }
+
+    #[test]
+    fn test_first_delta_time_without_the_z_flag() {
+        let bytes = [
+            0x80, 0x61, // flags
+            0x00, 0x01, // sequence number
+            0x00, 0x00, 0x00, 0x02, // timestamp
+            0x00, 0x00, 0x00, 0x03, // ssrc
+            0x04, // command list flags, Z bit clear, and length
+            0x05, 0x90, 0x48, 0x7F, // Note On, with a delta time regardless
+        ];
+
+        assert!(RtpMidiPacket::parse(&bytes, ParseMode::Strict).is_err());
+        let RtpMidiPacket::Midi(packet) = RtpMidiPacket::parse_with_first_delta_time(&bytes, ParseMode::Strict, FirstDeltaTime::Always).unwrap() else {
+            panic!("Expected a MIDI packet");
+        };
+        let commands: Vec<_> = packet.commands_with_first_delta_time(ParseMode::Strict, FirstDeltaTime::Always).collect();
+        let note_on = RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(127)));
+        assert_eq!(commands.len(), 1);
+        assert_eq!((commands[0].delta_time(), commands[0].command()), (5, &note_on));
+        // Read as the Z flag says, the delta time is skipped over as a stray byte
+        assert_eq!(packet.commands().map(|command| command.delta_time()).collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    fn test_write_into_matches_as_bytes() {
+        let commands = vec![
+            MidiEvent::new(None, RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(127)))),
+            MidiEvent::new(
+                Some(10),
+                RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::D4, Value7::from(127))),
+            ),
+        ];
+        let expected = MidiPacket::new_as_bytes(U16::from(1), U32::from(2), U32::from(3), &commands, false);
+
+        let mut buffer = [0u8; 64];
+        let len = MidiPacket::write_into(&mut buffer, U16::from(1), U32::from(2), U32::from(3), &commands, false).unwrap();
+        assert_eq!(&buffer[..len], &expected[..]);
+
+        let mut too_small = vec![0u8; expected.len() - 1];
+        assert!(MidiPacket::write_into(&mut too_small, U16::from(1), U32::from(2), U32::from(3), &commands, false).is_err());
+    }
 }
@@ -40,6 +40,18 @@ impl MidiPacketHeaderFlags {
         flags
     }
 
+    pub(super) fn p_flag(&self) -> bool {
+        self.get_flag(FlagMasks::P)
+    }
+
+    pub(super) fn x_flag(&self) -> bool {
+        self.get_flag(FlagMasks::X)
+    }
+
+    pub(super) fn m_flag(&self) -> bool {
+        self.get_flag(FlagMasks::M)
+    }
+
     fn get_flag(&self, flag: FlagMasks) -> bool {
         self.flags & flag as u16 != 0
     }
@@ -52,7 +64,7 @@ impl MidiPacketHeaderFlags {
         }
     }
 
-    fn get_version(&self) -> u8 {
+    pub(super) fn get_version(&self) -> u8 {
         ((self.flags.get() & FlagMasks::Version as u16) >> 14) as u8
     }
 
@@ -60,7 +72,7 @@ impl MidiPacketHeaderFlags {
         self.flags.set((self.flags.get() & !(FlagMasks::Version as u16)) | ((version as u16) << 14));
     }
 
-    fn cc(&self) -> u8 {
+    pub(super) fn cc(&self) -> u8 {
         ((self.flags.get() & FlagMasks::CC as u16) >> 8) as u8
     }
 
@@ -68,7 +80,7 @@ impl MidiPacketHeaderFlags {
         self.flags.set((self.flags.get() & !(FlagMasks::CC as u16)) | ((cc as u16) << 8));
     }
 
-    fn pt(&self) -> u8 {
+    pub(super) fn pt(&self) -> u8 {
         (self.flags.get() & FlagMasks::PT as u16) as u8
     }
 
@@ -103,8 +115,8 @@ impl MidiPacketHeader {
     }
 }
 
-impl std::fmt::Debug for MidiPacketHeader {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Debug for MidiPacketHeader {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("MidiPacketHeader")
             .field(
                 "flags",
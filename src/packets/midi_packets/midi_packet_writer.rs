@@ -0,0 +1,237 @@
+use zerocopy::IntoBytes;
+use zerocopy::network_endian::{U16, U32};
+
+use super::delta_time::MAX_DELTA_TIME;
+use super::midi_command_list_body::{MAX_COMMAND_LIST_LENGTH, command_size};
+use super::midi_command_list_header::{MidiCommandListFlags, MidiCommandListHeader};
+use super::midi_event::MidiEvent;
+use super::midi_packet_header::MidiPacketHeader;
+use super::rtp_midi_message::RtpMidiMessage;
+use super::util::next_running_status;
+use crate::error::RtpMidiError;
+
+const PACKET_HEADER_LENGTH: usize = core::mem::size_of::<MidiPacketHeader>();
+/// Room for the longer of the two command list headers, until the list's length is known.
+const COMMANDS_START: usize = PACKET_HEADER_LENGTH + 2;
+
+/// An RTP-MIDI data packet written straight into a caller's buffer, a command at a time, for sending with no heap
+/// allocation at all on small devices. It lays out the same bytes as
+/// [`MidiPacketBuilder`](crate::packets::builder::MidiPacketBuilder), without needing the commands in a slice first.
+/// A command that doesn't fit, or can't be sent as it is, is refused, leaving what was written before it alone.
+///
+/// It writes one packet, for sending it yourself. Sessions write their own packets, splitting each batch over as many
+/// as it needs, and don't use it.
+///
+/// ```
+/// use midi_types::{Channel, MidiMessage, Note, Value7};
+/// use rtpmidi::packets::builder::MidiPacketBuilder;
+/// use rtpmidi::packets::midi_packets::midi_event::MidiEvent;
+/// use rtpmidi::packets::midi_packets::midi_packet_writer::MidiPacketWriter;
+///
+/// let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+/// let note_off = MidiMessage::NoteOff(Channel::C1, Note::C4, Value7::from(0));
+///
+/// let mut buffer = [0u8; 32];
+/// let mut writer = MidiPacketWriter::new(&mut buffer, 0xCAFE).sequence_number(7).timestamp(1000);
+/// writer.message(note_on.into())?;
+/// writer.command(&MidiEvent::new(Some(480), note_off.into()))?;
+/// let len = writer.finish()?;
+///
+/// let packet = MidiPacketBuilder::new(0xCAFE)
+///     .sequence_number(7)
+///     .timestamp(1000)
+///     .message(note_on.into())
+///     .command(MidiEvent::new(Some(480), note_off.into()))
+///     .build();
+/// assert_eq!(&buffer[..len], &packet[..]);
+/// # Ok::<(), rtpmidi::error::RtpMidiError>(())
+/// ```
+#[derive(Debug)]
+pub struct MidiPacketWriter<'b> {
+    buffer: &'b mut [u8],
+    sequence_number: u16,
+    timestamp: u32,
+    ssrc: u32,
+    z_flag: bool,
+    /// Bytes of commands written so far, after the room for the headers.
+    length: usize,
+    commands: usize,
+    running_status: Option<u8>,
+}
+
+impl<'b> MidiPacketWriter<'b> {
+    /// A packet from `ssrc` with sequence number and timestamp 0 and no commands yet, to be written into `buffer`.
+    pub fn new(buffer: &'b mut [u8], ssrc: u32) -> Self {
+        MidiPacketWriter {
+            buffer,
+            sequence_number: 0,
+            timestamp: 0,
+            ssrc,
+            z_flag: false,
+            length: 0,
+            commands: 0,
+            running_status: None,
+        }
+    }
+
+    pub fn sequence_number(mut self, sequence_number: u16) -> Self {
+        self.sequence_number = sequence_number;
+        self
+    }
+
+    /// The RTP timestamp, in the session's clock units (100 microseconds).
+    pub fn timestamp(mut self, timestamp: u32) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Sets the Z flag, so the first command's delta time is written too. Only has an effect before the first command.
+    pub fn z_flag(mut self, z_flag: bool) -> Self {
+        if self.commands == 0 {
+            self.z_flag = z_flag;
+        }
+        self
+    }
+
+    /// Appends a command. Fails without writing anything with [`RtpMidiError::BufferTooSmall`] if it doesn't fit in
+    /// the buffer, or [`RtpMidiError::InvalidCommand`] if it would make the command list longer than its header can
+    /// describe, its delta time is more than the 28 bits a command can carry, or it's SysEx with a status byte in it.
+    pub fn command(&mut self, command: &MidiEvent) -> Result<(), RtpMidiError> {
+        if command.delta_time() > MAX_DELTA_TIME {
+            return Err(RtpMidiError::InvalidCommand("a delta time can't be more than 28 bits"));
+        }
+        if command.command().has_status_byte_in_sysex() {
+            return Err(RtpMidiError::InvalidCommand("SysEx data can't hold status bytes"));
+        }
+        let size = command_size(command, self.running_status, self.commands > 0 || self.z_flag);
+        if self.length + size > MAX_COMMAND_LIST_LENGTH {
+            return Err(RtpMidiError::InvalidCommand("a command list can't be longer than 4095 bytes"));
+        }
+        let start = COMMANDS_START + self.length;
+        if start + size > self.buffer.len() {
+            return Err(RtpMidiError::BufferTooSmall {
+                needed: start + size,
+                available: self.buffer.len(),
+            });
+        }
+        command.write(
+            &mut &mut self.buffer[start..start + size],
+            self.running_status,
+            self.commands > 0 || self.z_flag,
+        );
+        self.length += size;
+        self.commands += 1;
+        self.running_status = next_running_status(self.running_status, command.command().status());
+        Ok(())
+    }
+
+    /// Appends a message right after the previous command, or at the packet's timestamp if it's the first.
+    pub fn message(&mut self, message: RtpMidiMessage) -> Result<(), RtpMidiError> {
+        let delta_time = if self.commands == 0 { None } else { Some(0) };
+        self.command(&MidiEvent::new(delta_time, message))
+    }
+
+    /// Writes the headers in front of the commands, returning the length of the packet at the start of the buffer.
+    /// Fails with [`RtpMidiError::BufferTooSmall`] if the buffer can't even hold the headers.
+    pub fn finish(self) -> Result<usize, RtpMidiError> {
+        let b_flag = MidiCommandListFlags::needs_b_flag(self.length);
+        let command_list_header = MidiCommandListHeader::new(MidiCommandListFlags::new(b_flag, false, self.z_flag, false), self.length);
+        let commands_start = PACKET_HEADER_LENGTH + command_list_header.size();
+        if self.buffer.len() < commands_start {
+            return Err(RtpMidiError::BufferTooSmall {
+                needed: commands_start,
+                available: self.buffer.len(),
+            });
+        }
+        // The commands were written after room for the long header, so move them up against a short one
+        if self.length > 0 {
+            self.buffer.copy_within(COMMANDS_START..COMMANDS_START + self.length, commands_start);
+        }
+        let packet_header = MidiPacketHeader::new(U16::new(self.sequence_number), U32::new(self.timestamp), U32::new(self.ssrc));
+        self.buffer[..PACKET_HEADER_LENGTH].copy_from_slice(packet_header.as_bytes());
+        command_list_header.write(&mut &mut self.buffer[PACKET_HEADER_LENGTH..commands_start]);
+        Ok(commands_start + self.length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use midi_types::{Channel, MidiMessage, Note, Value7};
+
+    use super::*;
+    use crate::packets::midi_packets::midi_packet::MidiPacket;
+
+    fn note_on(key: u8) -> RtpMidiMessage<'static> {
+        RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::from(key), Value7::from(100)))
+    }
+
+    #[test]
+    fn test_writes_what_a_packet_built_from_a_slice_has() {
+        for (count, z_flag) in [(0, false), (1, true), (4, false), (4, true), (40, false)] {
+            let commands: Vec<_> = (0..count).map(|i| MidiEvent::new(Some(i as u32 * 100), note_on(i as u8))).collect();
+            let mut buffer = [0u8; 256];
+            let mut writer = MidiPacketWriter::new(&mut buffer, 0xCAFE).sequence_number(3).timestamp(1000).z_flag(z_flag);
+            for command in &commands {
+                writer.command(command).unwrap();
+            }
+            let len = writer.finish().unwrap();
+            let packet = MidiPacket::new_as_bytes(U16::new(3), U32::new(1000), U32::new(0xCAFE), &commands, z_flag);
+            assert_eq!(&buffer[..len], &packet[..], "{count} commands, Z flag {z_flag}");
+        }
+    }
+
+    #[test]
+    fn test_refuses_commands_that_dont_fit() {
+        // The headers and one note on fit, a second one's delta time and data bytes don't
+        let mut buffer = vec![0u8; COMMANDS_START + 3 + 2];
+        let mut writer = MidiPacketWriter::new(&mut buffer, 0xCAFE);
+        writer.message(note_on(60)).unwrap();
+        assert!(matches!(
+            writer.message(note_on(62)),
+            Err(RtpMidiError::BufferTooSmall { needed: 20, available: 19 })
+        ));
+        let len = writer.finish().unwrap();
+        let packet = MidiPacket::new_as_bytes(U16::new(0), U32::new(0), U32::new(0xCAFE), &[MidiEvent::new(None, note_on(60))], false);
+        assert_eq!(&buffer[..len], &packet[..]);
+
+        let sysex = [0x7D; MAX_COMMAND_LIST_LENGTH];
+        let mut buffer = vec![0u8; 2 * MAX_COMMAND_LIST_LENGTH];
+        let mut writer = MidiPacketWriter::new(&mut buffer, 0xCAFE);
+        assert!(matches!(writer.message(RtpMidiMessage::SysEx(&sysex)), Err(RtpMidiError::InvalidCommand(_))));
+        writer.message(RtpMidiMessage::SysEx(&sysex[..MAX_COMMAND_LIST_LENGTH - 2])).unwrap();
+        assert!(matches!(writer.message(note_on(60)), Err(RtpMidiError::InvalidCommand(_))));
+    }
+
+    #[test]
+    fn test_refuses_commands_that_cant_be_sent() {
+        let mut buffer = [0u8; 64];
+        let mut writer = MidiPacketWriter::new(&mut buffer, 0xCAFE);
+        assert!(matches!(
+            writer.command(&MidiEvent::new(Some(MAX_DELTA_TIME + 1), note_on(60))),
+            Err(RtpMidiError::InvalidCommand(_))
+        ));
+        assert!(matches!(
+            writer.message(RtpMidiMessage::SysEx(&[0x7D, 0x90, 0x01])),
+            Err(RtpMidiError::InvalidCommand(_))
+        ));
+        writer.command(&MidiEvent::new(Some(MAX_DELTA_TIME), note_on(60))).unwrap();
+        let len = writer.finish().unwrap();
+        let packet = MidiPacket::new_as_bytes(U16::new(0), U32::new(0), U32::new(0xCAFE), &[MidiEvent::new(None, note_on(60))], false);
+        assert_eq!(&buffer[..len], &packet[..]);
+    }
+
+    #[test]
+    fn test_refuses_buffers_too_small_for_the_headers() {
+        assert!(matches!(
+            MidiPacketWriter::new(&mut [0u8; 8], 1).finish(),
+            Err(RtpMidiError::BufferTooSmall { needed: 13, available: 8 })
+        ));
+        // An empty command list only takes the short header
+        let mut buffer = [0u8; PACKET_HEADER_LENGTH + 1];
+        let len = MidiPacketWriter::new(&mut buffer, 1).finish().unwrap();
+        assert_eq!(&buffer[..len], &MidiPacket::new_as_bytes(U16::new(0), U32::new(0), U32::new(1), &[], false)[..]);
+    }
+}
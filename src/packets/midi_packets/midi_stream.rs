@@ -0,0 +1,244 @@
+//! Parsing a raw MIDI 1.0 byte stream, as it comes from a serial MIDI port or another library, into messages that can
+//! be sent on with a session.
+
+use midi_types::MidiMessage;
+
+use crate::packets::error::PacketParseError;
+use crate::packets::midi_packets::midi_message_ext::{ReadWriteExt, data_length};
+use crate::packets::midi_packets::rtp_midi_message::{RtpMidiMessage, SysExSegment};
+use crate::packets::midi_packets::util::{StatusBit, next_running_status};
+
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_END: u8 = 0xF7;
+
+/// Where a [`MidiStream`] left off, to carry on from with the next bytes read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MidiStreamState {
+    running_status: Option<u8>,
+    /// The status of the message being read, and the data bytes read for it so far.
+    status: Option<u8>,
+    data: [u8; 2],
+    data_len: usize,
+    /// Inside a SysEx message, and whether part of it has been handed out already.
+    sysex: Option<bool>,
+}
+
+/// Parses a raw MIDI stream into messages, following running status and the real-time messages that can turn up
+/// between any two bytes.
+///
+/// Messages are handed out as soon as they are complete, borrowing SysEx data from the stream. A SysEx message that
+/// a real-time message interrupts, or that the bytes end in the middle of, is handed out in
+/// [`SysExSegment`]s, which sessions send as such. A message the bytes end in the middle of is finished off with the
+/// next bytes read, through [`state`](Self::state) and [`resume`](Self::resume):
+///
+/// ```
+/// use rtpmidi::packets::midi_packets::midi_stream::MidiStream;
+///
+/// let mut stream = MidiStream::new(&[0x90, 0x3C]);
+/// assert!(stream.next().is_none());
+/// let mut stream = MidiStream::resume(&[0x64, 0x3E, 0x64], stream.state());
+/// assert_eq!(stream.by_ref().filter_map(Result::ok).count(), 2); // running status
+/// ```
+///
+/// Bytes that can't be part of a message are skipped with an error, and parsing carries on after them.
+#[derive(Debug, Clone)]
+pub struct MidiStream<'a> {
+    bytes: &'a [u8],
+    state: MidiStreamState,
+}
+
+impl<'a> MidiStream<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self::resume(bytes, MidiStreamState::default())
+    }
+
+    /// Carries on parsing where an earlier stream, left in `state`, stopped.
+    pub fn resume(bytes: &'a [u8], state: MidiStreamState) -> Self {
+        MidiStream { bytes, state }
+    }
+
+    pub fn state(&self) -> MidiStreamState {
+        self.state
+    }
+
+    /// Hands out what has been read of the SysEx message under way, up to the next status byte or the end of the
+    /// bytes.
+    fn next_sysex(&mut self, emitted: bool) -> Option<Result<RtpMidiMessage<'a>, PacketParseError>> {
+        let end = self.bytes.iter().position(StatusBit::status_bit);
+        let data = &self.bytes[..end.unwrap_or(self.bytes.len())];
+        self.bytes = &self.bytes[data.len()..];
+        let segment = if emitted { SysExSegment::Middle } else { SysExSegment::First };
+
+        match end.map(|_| self.bytes[0]) {
+            Some(SYSEX_END) => {
+                self.bytes = &self.bytes[1..];
+                self.state.sysex = None;
+                Some(Ok(match emitted {
+                    false => RtpMidiMessage::SysEx(data),
+                    true => RtpMidiMessage::SysExSegment(SysExSegment::Last, data),
+                }))
+            }
+            // Interrupted by a real-time message, or cut off by the end of the bytes
+            Some(0xF8..) | None if data.is_empty() && emitted => None,
+            Some(0xF8..) | None => {
+                self.state.sysex = Some(true);
+                Some(Ok(RtpMidiMessage::SysExSegment(segment, data)))
+            }
+            // Any other status byte ends it without an end byte
+            Some(_) => {
+                self.state.sysex = None;
+                Some(match emitted {
+                    false => Err(PacketParseError::Malformed("SysEx message without an end")),
+                    true => Ok(RtpMidiMessage::SysExSegment(SysExSegment::Cancel, data)),
+                })
+            }
+        }
+    }
+
+    fn status_byte(&mut self, status: u8) -> Option<Result<RtpMidiMessage<'a>, PacketParseError>> {
+        if status >= 0xF8 {
+            // Real-time messages leave whatever they interrupt alone
+            return Some(match data_length(status) {
+                Some(_) => midi_message(status, &[]),
+                None => Err(PacketParseError::UnsupportedStatus(status)),
+            });
+        }
+        self.state.running_status = next_running_status(self.state.running_status, status);
+        self.state.status = None;
+        self.state.data_len = 0;
+        match status {
+            SYSEX_START => {
+                self.state.sysex = Some(false);
+                None
+            }
+            SYSEX_END => Some(Err(PacketParseError::Malformed("SysEx end without a start"))),
+            _ => match data_length(status) {
+                Some(0) => Some(midi_message(status, &[])),
+                Some(_) => {
+                    self.state.status = Some(status);
+                    None
+                }
+                None => Some(Err(PacketParseError::UnsupportedStatus(status))),
+            },
+        }
+    }
+
+    fn data_byte(&mut self, byte: u8) -> Option<Result<RtpMidiMessage<'a>, PacketParseError>> {
+        let Some(status) = self.state.status.or(self.state.running_status) else {
+            return Some(Err(PacketParseError::Malformed("data byte without a running status")));
+        };
+        self.state.status = Some(status);
+        self.state.data[self.state.data_len] = byte;
+        self.state.data_len += 1;
+        if Some(self.state.data_len) != data_length(status) {
+            return None;
+        }
+        self.state.status = None;
+        self.state.data_len = 0;
+        Some(midi_message(status, &self.state.data))
+    }
+}
+
+/// Parses a message that isn't SysEx, which doesn't borrow the bytes it is parsed from.
+fn midi_message<'a>(status: u8, data: &[u8]) -> Result<RtpMidiMessage<'a>, PacketParseError> {
+    match MidiMessage::from_status_byte(status, status & 0x0F, data)? {
+        (RtpMidiMessage::MidiMessage(message), _) => Ok(RtpMidiMessage::MidiMessage(message)),
+        _ => Err(PacketParseError::UnsupportedStatus(status)),
+    }
+}
+
+impl<'a> Iterator for MidiStream<'a> {
+    type Item = Result<RtpMidiMessage<'a>, PacketParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let &byte = self.bytes.first()?;
+            let message = match self.state.sysex {
+                Some(emitted) if byte < 0xF8 => match self.next_sysex(emitted) {
+                    None if self.bytes.is_empty() => return None,
+                    None => continue,
+                    message => message,
+                },
+                _ => {
+                    self.bytes = &self.bytes[1..];
+                    match byte.status_bit() {
+                        true => self.status_byte(byte),
+                        false => self.data_byte(byte),
+                    }
+                }
+            };
+            if message.is_some() {
+                return message;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use midi_types::{Channel, Note, Value7};
+
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<Result<RtpMidiMessage<'_>, PacketParseError>> {
+        MidiStream::new(bytes).collect()
+    }
+
+    fn note_on(key: u8) -> Result<RtpMidiMessage<'static>, PacketParseError> {
+        Ok(RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::from(key), Value7::new(100))))
+    }
+
+    #[test]
+    fn test_running_status_and_real_time_messages() {
+        let clock = Ok(RtpMidiMessage::MidiMessage(MidiMessage::TimingClock));
+        assert_eq!(parse(&[0x90, 0x3C, 0x64, 0x3E, 0x64]), [note_on(0x3C), note_on(0x3E)]);
+        // Real-time messages can come between the bytes of another, which carries on after them
+        assert_eq!(parse(&[0x90, 0xF8, 0x3C, 0x64]), [clock.clone(), note_on(0x3C)]);
+        // System common messages cancel running status
+        assert_eq!(
+            parse(&[0x90, 0x3C, 0x64, 0xF6, 0x3E, 0x64]),
+            [
+                note_on(0x3C),
+                Ok(RtpMidiMessage::MidiMessage(MidiMessage::TuneRequest)),
+                Err(PacketParseError::Malformed("data byte without a running status")),
+                Err(PacketParseError::Malformed("data byte without a running status")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sysex() {
+        assert_eq!(
+            parse(&[0xF0, 0x7E, 0x7F, 0xF7, 0xFA]),
+            [Ok(RtpMidiMessage::SysEx(&[0x7E, 0x7F])), Ok(RtpMidiMessage::MidiMessage(MidiMessage::Start))]
+        );
+        assert_eq!(
+            parse(&[0xF0, 0x01, 0xF8, 0x02, 0xF7]),
+            [
+                Ok(RtpMidiMessage::SysExSegment(SysExSegment::First, &[0x01])),
+                Ok(RtpMidiMessage::MidiMessage(MidiMessage::TimingClock)),
+                Ok(RtpMidiMessage::SysExSegment(SysExSegment::Last, &[0x02])),
+            ]
+        );
+        assert_eq!(
+            parse(&[0xF0, 0x01, 0x90, 0x3C, 0x64]),
+            [Err(PacketParseError::Malformed("SysEx message without an end")), note_on(0x3C)]
+        );
+    }
+
+    #[test]
+    fn test_resuming_with_the_next_bytes() {
+        let mut stream = MidiStream::new(&[0xF0, 0x01, 0x02]);
+        assert_eq!(stream.next(), Some(Ok(RtpMidiMessage::SysExSegment(SysExSegment::First, &[0x01, 0x02]))));
+        assert_eq!(stream.next(), None);
+        let mut stream = MidiStream::resume(&[0x03, 0xF7, 0x90], stream.state());
+        assert_eq!(stream.next(), Some(Ok(RtpMidiMessage::SysExSegment(SysExSegment::Last, &[0x03]))));
+        assert_eq!(stream.next(), None);
+        let mut stream = MidiStream::resume(&[0x3C], stream.state());
+        assert_eq!(stream.next(), None);
+        let collected: Vec<_> = MidiStream::resume(&[0x64], stream.state()).collect();
+        assert_eq!(collected, [note_on(0x3C)]);
+    }
+}
@@ -1,11 +1,20 @@
-mod delta_time;
-pub(crate) mod midi_command_iterator;
-mod midi_command_list_body;
+pub mod controller_change;
+pub(crate) mod delta_time;
+pub mod midi_ci;
+pub mod midi_command_iterator;
+pub(crate) mod midi_command_list_body;
 mod midi_command_list_header;
 pub mod midi_event;
 pub mod midi_message_ext;
-pub(crate) mod midi_packet;
+pub mod midi_packet;
 mod midi_packet_header;
+pub mod midi_packet_writer;
+pub mod midi_stream;
+pub mod mpe;
 pub mod rtp_midi_message;
+pub mod show_control;
+pub mod sysex;
+pub mod timecode;
+pub mod transport;
 pub(crate) mod util;
 //pub mod recovery_journal;
@@ -0,0 +1,185 @@
+//! MIDI Polyphonic Expression (MPE). An MPE controller plays each note on a channel of its own, the member channels
+//! of a zone, so that pitch bend and pressure apply per note. Messages for the whole zone go on its manager channel:
+//! the first channel for the lower zone, the last one for the upper zone.
+//!
+//! Zones are set up with MPE Configuration Messages, registered parameter 6 on the manager channel.
+//! [`MpeZone::configuration`] builds one to send, and [`MpeZones`] follows the ones received to work out what each
+//! channel is for.
+//!
+//! ```
+//! use midi_types::Channel;
+//! use rtpmidi::packets::midi_packets::controller_change::ControllerCombiner;
+//! use rtpmidi::packets::midi_packets::mpe::{ChannelRole, MpeZone, MpeZones};
+//!
+//! let mut combiner = ControllerCombiner::default();
+//! let mut zones = MpeZones::default();
+//! for message in MpeZone::Lower.configuration(5).messages() {
+//!     if let Some(change) = combiner.push(&message) {
+//!         zones.apply(&change);
+//!     }
+//! }
+//! assert_eq!(zones.role(Channel::C1), ChannelRole::Manager(MpeZone::Lower));
+//! assert_eq!(zones.role(Channel::C6), ChannelRole::Member(MpeZone::Lower));
+//! assert_eq!(zones.role(Channel::C7), ChannelRole::Outside);
+//! ```
+
+use midi_types::{Channel, Value14};
+
+use crate::packets::midi_packets::controller_change::ControllerChange;
+
+const MPE_CONFIGURATION: u8 = 6;
+/// Channels left for members once both manager channels are taken.
+const MAX_MEMBER_CHANNELS: u8 = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MpeZone {
+    /// Managed on the first channel, with member channels counting up from the second.
+    Lower,
+    /// Managed on the last channel, with member channels counting down from the second to last.
+    Upper,
+}
+
+/// What a channel is used for under the current zone layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelRole {
+    /// Carries messages that apply to every note in the zone.
+    Manager(MpeZone),
+    /// Carries one note at a time of the zone.
+    Member(MpeZone),
+    /// Not part of any zone, so it works as an ordinary MIDI channel.
+    Outside,
+}
+
+impl MpeZone {
+    pub fn manager_channel(self) -> Channel {
+        match self {
+            MpeZone::Lower => Channel::C1,
+            MpeZone::Upper => Channel::C16,
+        }
+    }
+
+    /// The MPE Configuration Message giving the zone `member_channels` member channels, at most 14. With none, the
+    /// zone is switched off.
+    pub fn configuration(self, member_channels: u8) -> ControllerChange {
+        ControllerChange::Rpn {
+            channel: self.manager_channel(),
+            parameter: Value14::new(0, MPE_CONFIGURATION),
+            value: Value14::new(member_channels.min(MAX_MEMBER_CHANNELS), 0),
+        }
+    }
+}
+
+/// The zone layout, as set up by the MPE Configuration Messages received so far. Both zones start out switched off.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MpeZones {
+    lower_members: u8,
+    upper_members: u8,
+}
+
+impl MpeZones {
+    /// Follows a received controller operation, returning the zone it reconfigured, if it was an MPE Configuration
+    /// Message. A zone that grows into the other one shrinks it, as the MPE specification asks.
+    pub fn apply(&mut self, change: &ControllerChange) -> Option<MpeZone> {
+        let ControllerChange::Rpn { channel, parameter, value } = *change else {
+            return None;
+        };
+        if parameter != Value14::new(0, MPE_CONFIGURATION) {
+            return None;
+        }
+        let zone = [MpeZone::Lower, MpeZone::Upper].into_iter().find(|zone| zone.manager_channel() == channel)?;
+        let (members, _) = value.into();
+        let members = u8::min(members, MAX_MEMBER_CHANNELS);
+        match zone {
+            MpeZone::Lower => {
+                self.lower_members = members;
+                self.upper_members = self.upper_members.min(MAX_MEMBER_CHANNELS - members);
+            }
+            MpeZone::Upper => {
+                self.upper_members = members;
+                self.lower_members = self.lower_members.min(MAX_MEMBER_CHANNELS - members);
+            }
+        }
+        Some(zone)
+    }
+
+    /// How many member channels the zone has, none if it's switched off.
+    pub fn member_channels(&self, zone: MpeZone) -> u8 {
+        match zone {
+            MpeZone::Lower => self.lower_members,
+            MpeZone::Upper => self.upper_members,
+        }
+    }
+
+    pub fn role(&self, channel: Channel) -> ChannelRole {
+        let channel = u8::from(channel);
+        let lower_manager = u8::from(MpeZone::Lower.manager_channel());
+        let upper_manager = u8::from(MpeZone::Upper.manager_channel());
+        if self.lower_members > 0 && channel == lower_manager {
+            ChannelRole::Manager(MpeZone::Lower)
+        } else if self.upper_members > 0 && channel == upper_manager {
+            ChannelRole::Manager(MpeZone::Upper)
+        } else if channel > lower_manager && channel <= lower_manager + self.lower_members {
+            ChannelRole::Member(MpeZone::Lower)
+        } else if channel < upper_manager && channel >= upper_manager - self.upper_members {
+            ChannelRole::Member(MpeZone::Upper)
+        } else {
+            ChannelRole::Outside
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configure(zones: &mut MpeZones, zone: MpeZone, member_channels: u8) {
+        assert_eq!(zones.apply(&zone.configuration(member_channels)), Some(zone));
+    }
+
+    #[test]
+    fn test_both_zones_share_the_channels() {
+        let mut zones = MpeZones::default();
+        configure(&mut zones, MpeZone::Lower, 7);
+        configure(&mut zones, MpeZone::Upper, 7);
+        assert_eq!(zones.role(Channel::C8), ChannelRole::Member(MpeZone::Lower));
+        assert_eq!(zones.role(Channel::C9), ChannelRole::Member(MpeZone::Upper));
+        assert_eq!(zones.role(Channel::C16), ChannelRole::Manager(MpeZone::Upper));
+    }
+
+    #[test]
+    fn test_growing_zone_shrinks_the_other() {
+        let mut zones = MpeZones::default();
+        configure(&mut zones, MpeZone::Upper, 10);
+        configure(&mut zones, MpeZone::Lower, 10);
+        assert_eq!(zones.member_channels(MpeZone::Upper), 4);
+        assert_eq!(zones.role(Channel::C11), ChannelRole::Member(MpeZone::Lower));
+        assert_eq!(zones.role(Channel::C12), ChannelRole::Member(MpeZone::Upper));
+
+        configure(&mut zones, MpeZone::Lower, 15);
+        assert_eq!(zones.member_channels(MpeZone::Lower), 14);
+        assert_eq!(zones.member_channels(MpeZone::Upper), 0);
+        assert_eq!(zones.role(Channel::C16), ChannelRole::Outside);
+    }
+
+    #[test]
+    fn test_switched_off_zone_has_no_manager() {
+        let mut zones = MpeZones::default();
+        configure(&mut zones, MpeZone::Lower, 3);
+        configure(&mut zones, MpeZone::Lower, 0);
+        assert_eq!(zones.role(Channel::C1), ChannelRole::Outside);
+        assert_eq!(zones.role(Channel::C2), ChannelRole::Outside);
+    }
+
+    #[test]
+    fn test_other_parameters_are_ignored() {
+        let mut zones = MpeZones::default();
+        assert_eq!(zones.apply(&ControllerChange::pitch_bend_range(Channel::C1, 48, 0)), None);
+        let configuration_on_member_channel = ControllerChange::Rpn {
+            channel: Channel::C2,
+            parameter: Value14::new(0, MPE_CONFIGURATION),
+            value: Value14::new(5, 0),
+        };
+        assert_eq!(zones.apply(&configuration_on_member_channel), None);
+        assert_eq!(zones, MpeZones::default());
+    }
+}
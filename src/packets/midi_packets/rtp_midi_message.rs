@@ -1,12 +1,58 @@
 use bytes::BufMut;
 use midi_types::MidiMessage;
 
+use crate::packets::error::PacketParseError;
 use crate::packets::midi_packets::midi_message_ext::ReadWriteExt;
+use crate::packets::midi_packets::util::StatusBit;
+
+const SYSEX_START: u8 = 0xF0;
+const SYSEX_END: u8 = 0xF7;
+const SYSEX_CANCEL: u8 = 0xF4;
+
+/// The largest SysEx payload sent in a single command before it is split into segments.
+pub const MAX_SYSEX_SEGMENT_SIZE: usize = 1024;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum RtpMidiMessage<'a> {
-    MidiMessage(MidiMessage),
+    MidiMessage(#[cfg_attr(feature = "serde", serde(serialize_with = "crate::serde_helpers::midi_message::serialize"))] MidiMessage),
     SysEx(&'a [u8]),
+    /// Part of a SysEx message that has been split across several command lists (RFC 6295 section 3.2).
+    SysExSegment(SysExSegment, &'a [u8]),
+}
+
+/// Position of a segment within a segmented SysEx message.
+///
+/// | Segment  | Framing       |
+/// |----------|---------------|
+/// | `First`  | `F0 ... F0`   |
+/// | `Middle` | `F7 ... F0`   |
+/// | `Last`   | `F7 ... F7`   |
+/// | `Cancel` | `F7 ... F4`   |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SysExSegment {
+    First,
+    Middle,
+    Last,
+    Cancel,
+}
+
+impl SysExSegment {
+    fn start_byte(&self) -> u8 {
+        match self {
+            SysExSegment::First => SYSEX_START,
+            SysExSegment::Middle | SysExSegment::Last | SysExSegment::Cancel => SYSEX_END,
+        }
+    }
+
+    fn end_byte(&self) -> u8 {
+        match self {
+            SysExSegment::First | SysExSegment::Middle => SYSEX_START,
+            SysExSegment::Last => SYSEX_END,
+            SysExSegment::Cancel => SYSEX_CANCEL,
+        }
+    }
 }
 
 impl From<MidiMessage> for RtpMidiMessage<'_> {
@@ -15,11 +61,32 @@ impl From<MidiMessage> for RtpMidiMessage<'_> {
     }
 }
 
-impl RtpMidiMessage<'_> {
+/// Parses one complete message in its raw MIDI form: a status byte and its data bytes, or a SysEx message from `F0`
+/// to `F7`. For a stream of them, see [`MidiStream`](super::midi_stream::MidiStream).
+impl<'a> TryFrom<&'a [u8]> for RtpMidiMessage<'a> {
+    type Error = PacketParseError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        let (&status_byte, data) = bytes.split_first().ok_or(PacketParseError::NotEnoughData)?;
+        let (message, remaining) = match status_byte {
+            SYSEX_START => Self::sysex_from_be_bytes(status_byte, data)?,
+            _ if status_byte.status_bit() && status_byte != SYSEX_END => MidiMessage::from_status_byte(status_byte, status_byte & 0x0F, data)?,
+            _ => return Err(PacketParseError::Malformed("MIDI message without a status byte")),
+        };
+        match message {
+            RtpMidiMessage::SysEx(data) if data.iter().any(StatusBit::status_bit) => Err(PacketParseError::Malformed("SysEx data byte")),
+            RtpMidiMessage::SysExSegment(..) => Err(PacketParseError::Malformed("SysEx message without an end")),
+            _ if !remaining.is_empty() => Err(PacketParseError::Malformed("MIDI message with bytes after it")),
+            message => Ok(message),
+        }
+    }
+}
+
+impl<'a> RtpMidiMessage<'a> {
     pub fn len(&self) -> usize {
         match self {
             RtpMidiMessage::MidiMessage(msg) => msg.len(),
-            RtpMidiMessage::SysEx(data) => data.len() + 2, // +1 for the SysEx start byte
+            RtpMidiMessage::SysEx(data) | RtpMidiMessage::SysExSegment(_, data) => data.len() + 2, // start and end bytes
         }
     }
 
@@ -28,13 +95,18 @@ impl RtpMidiMessage<'_> {
         self.len() == 0
     }
 
-    pub fn write(&self, bytes: &mut bytes::BytesMut, running_status: Option<u8>) {
+    pub fn write<B: BufMut>(&self, bytes: &mut B, running_status: Option<u8>) {
         match self {
             RtpMidiMessage::MidiMessage(msg) => msg.write(bytes, running_status),
             RtpMidiMessage::SysEx(data) => {
-                bytes.put_u8(0xF0); // SysEx start byte
-                bytes.extend_from_slice(data);
-                bytes.put_u8(0xF7); // SysEx end byte
+                bytes.put_u8(SYSEX_START);
+                bytes.put_slice(data);
+                bytes.put_u8(SYSEX_END);
+            }
+            RtpMidiMessage::SysExSegment(segment, data) => {
+                bytes.put_u8(segment.start_byte());
+                bytes.put_slice(data);
+                bytes.put_u8(segment.end_byte());
             }
         }
     }
@@ -42,7 +114,138 @@ impl RtpMidiMessage<'_> {
     pub(crate) fn status(&self) -> u8 {
         match self {
             RtpMidiMessage::MidiMessage(msg) => msg.status(),
-            RtpMidiMessage::SysEx(_) => 0xF0, // SysEx messages have a special status byte
+            RtpMidiMessage::SysEx(_) => SYSEX_START, // SysEx messages have a special status byte
+            RtpMidiMessage::SysExSegment(segment, _) => segment.start_byte(),
         }
     }
+
+    /// Whether the data of a SysEx message or segment holds a status byte, which would end it early once written.
+    pub(crate) fn has_status_byte_in_sysex(&self) -> bool {
+        match self {
+            RtpMidiMessage::MidiMessage(_) => false,
+            RtpMidiMessage::SysEx(data) | RtpMidiMessage::SysExSegment(_, data) => data.iter().any(StatusBit::status_bit),
+        }
+    }
+
+    /// Splits a SysEx payload into messages of at most `max_segment_size` data bytes each.
+    ///
+    /// Payloads that already fit are returned as a single [`RtpMidiMessage::SysEx`].
+    pub fn sysex_segments(data: &'a [u8], max_segment_size: usize) -> impl Iterator<Item = RtpMidiMessage<'a>> {
+        let max_segment_size = max_segment_size.max(1);
+        let segment_count = data.len().div_ceil(max_segment_size).max(1);
+
+        (0..segment_count).map(move |i| {
+            let start = i * max_segment_size;
+            let chunk = &data[start..(start + max_segment_size).min(data.len())];
+            match i {
+                _ if segment_count == 1 => RtpMidiMessage::SysEx(chunk),
+                0 => RtpMidiMessage::SysExSegment(SysExSegment::First, chunk),
+                _ if i == segment_count - 1 => RtpMidiMessage::SysExSegment(SysExSegment::Last, chunk),
+                _ => RtpMidiMessage::SysExSegment(SysExSegment::Middle, chunk),
+            }
+        })
+    }
+
+    /// Parses a SysEx command (complete or segmented). `bytes` starts immediately after the status byte.
+    pub(crate) fn sysex_from_be_bytes(status_byte: u8, bytes: &'a [u8]) -> Result<(Self, &'a [u8]), PacketParseError> {
+        let end_index = bytes
+            .iter()
+            .position(|&b| b == SYSEX_START || b == SYSEX_END || b == SYSEX_CANCEL)
+            .ok_or(PacketParseError::Truncated("SysEx command"))?;
+        let data = &bytes[..end_index];
+        let remaining = &bytes[end_index + 1..];
+
+        let message = match (status_byte, bytes[end_index]) {
+            (SYSEX_START, SYSEX_END) => RtpMidiMessage::SysEx(data),
+            (SYSEX_START, SYSEX_START) => RtpMidiMessage::SysExSegment(SysExSegment::First, data),
+            (SYSEX_END, SYSEX_START) => RtpMidiMessage::SysExSegment(SysExSegment::Middle, data),
+            (SYSEX_END, SYSEX_END) => RtpMidiMessage::SysExSegment(SysExSegment::Last, data),
+            (_, SYSEX_CANCEL) => RtpMidiMessage::SysExSegment(SysExSegment::Cancel, data),
+            _ => return Err(PacketParseError::UnsupportedStatus(status_byte)),
+        };
+        Ok((message, remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+
+    fn round_trip(message: RtpMidiMessage) {
+        let mut bytes = BytesMut::new();
+        message.write(&mut bytes, None);
+        assert_eq!(bytes.len(), message.len());
+
+        let (parsed, remaining) = RtpMidiMessage::sysex_from_be_bytes(bytes[0], &bytes[1..]).unwrap();
+        assert_eq!(parsed, message);
+        assert!(remaining.is_empty());
+    }
+
+    #[test]
+    fn test_sysex_round_trip() {
+        round_trip(RtpMidiMessage::SysEx(&[0x7E, 0x01, 0x02]));
+        round_trip(RtpMidiMessage::SysExSegment(SysExSegment::First, &[0x01, 0x02]));
+        round_trip(RtpMidiMessage::SysExSegment(SysExSegment::Middle, &[0x03]));
+        round_trip(RtpMidiMessage::SysExSegment(SysExSegment::Last, &[0x04, 0x05]));
+        round_trip(RtpMidiMessage::SysExSegment(SysExSegment::Cancel, &[]));
+    }
+
+    #[test]
+    fn test_parse_sysex_leaves_following_bytes() {
+        let bytes = [0x01, 0x02, 0xF7, 0x00, 0x90];
+        let (parsed, remaining) = RtpMidiMessage::sysex_from_be_bytes(0xF0, &bytes).unwrap();
+        assert_eq!(parsed, RtpMidiMessage::SysEx(&[0x01, 0x02]));
+        assert_eq!(remaining, &[0x00, 0x90]);
+    }
+
+    #[test]
+    fn test_parse_unterminated_sysex() {
+        let result = RtpMidiMessage::sysex_from_be_bytes(0xF0, &[0x01, 0x02]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sysex_segments_small_payload() {
+        let data = [0x01, 0x02, 0x03];
+        let segments = RtpMidiMessage::sysex_segments(&data, 4).collect::<Vec<_>>();
+        assert_eq!(segments, vec![RtpMidiMessage::SysEx(&data)]);
+    }
+
+    #[test]
+    fn test_sysex_segments_empty_payload() {
+        let segments = RtpMidiMessage::sysex_segments(&[], 4).collect::<Vec<_>>();
+        assert_eq!(segments, vec![RtpMidiMessage::SysEx(&[])]);
+    }
+
+    #[test]
+    fn test_sysex_segments_split() {
+        let data = [0x01, 0x02, 0x03, 0x04, 0x05];
+        let segments = RtpMidiMessage::sysex_segments(&data, 2).collect::<Vec<_>>();
+        assert_eq!(
+            segments,
+            vec![
+                RtpMidiMessage::SysExSegment(SysExSegment::First, &[0x01, 0x02]),
+                RtpMidiMessage::SysExSegment(SysExSegment::Middle, &[0x03, 0x04]),
+                RtpMidiMessage::SysExSegment(SysExSegment::Last, &[0x05]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_try_from_raw_bytes() {
+        let note_on = RtpMidiMessage::try_from(&[0x91, 0x3C, 0x64][..]).unwrap();
+        assert_eq!(note_on, RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(1.into(), 0x3C.into(), 0x64.into())));
+        assert_eq!(
+            RtpMidiMessage::try_from(&[0xF0, 0x7E, 0x01, 0xF7][..]),
+            Ok(RtpMidiMessage::SysEx(&[0x7E, 0x01]))
+        );
+
+        assert_eq!(RtpMidiMessage::try_from(&[][..]), Err(PacketParseError::NotEnoughData));
+        assert!(RtpMidiMessage::try_from(&[0x3C, 0x64][..]).is_err());
+        assert!(RtpMidiMessage::try_from(&[0x91, 0x3C][..]).is_err());
+        assert!(RtpMidiMessage::try_from(&[0x91, 0x3C, 0x64, 0x64][..]).is_err());
+        assert!(RtpMidiMessage::try_from(&[0xF0, 0x7E, 0x01][..]).is_err());
+    }
 }
@@ -0,0 +1,221 @@
+//! MIDI Show Control (MSC), the SysEx messages lighting desks, sound and playback machines use to run cues.
+//!
+//! Messages are built and read as SysEx payloads without their start and end bytes, the way
+//! [`RtpMidiMessage::SysEx`](crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage::SysEx) carries them.
+//!
+//! ```
+//! use rtpmidi::packets::midi_packets::show_control::{ALL_CALL, Cue, ShowControlCommand, ShowControlMessage, command_format};
+//!
+//! let go = ShowControlMessage {
+//!     device_id: ALL_CALL,
+//!     command_format: command_format::LIGHTING,
+//!     command: ShowControlCommand::Go(Some(Cue { number: "15.5", list: Some("1"), path: None })),
+//! };
+//! let sysex = go.to_sysex();
+//! assert_eq!(ShowControlMessage::from_sysex(&sysex), Some(go));
+//! ```
+
+use alloc::vec::Vec;
+
+/// Addresses every device, whatever its own device ID.
+pub const ALL_CALL: u8 = 0x7F;
+
+const UNIVERSAL_REAL_TIME: u8 = 0x7F;
+const SHOW_CONTROL: u8 = 0x02;
+const CUE_SEPARATOR: u8 = 0x00;
+
+/// The kinds of equipment a message is meant for.
+pub mod command_format {
+    pub const LIGHTING: u8 = 0x01;
+    pub const MOVING_LIGHTS: u8 = 0x02;
+    pub const SOUND: u8 = 0x10;
+    pub const MACHINERY: u8 = 0x20;
+    pub const VIDEO: u8 = 0x30;
+    pub const PROJECTION: u8 = 0x40;
+    pub const PROCESS_CONTROL: u8 = 0x50;
+    pub const PYRO: u8 = 0x60;
+    pub const ALL_TYPES: u8 = 0x7F;
+}
+
+const GO: u8 = 0x01;
+const STOP: u8 = 0x02;
+const RESUME: u8 = 0x03;
+const LOAD: u8 = 0x05;
+const ALL_OFF: u8 = 0x08;
+const RESTORE: u8 = 0x09;
+const RESET: u8 = 0x0A;
+const GO_OFF: u8 = 0x0B;
+
+/// Identifies a cue: its number, and optionally the cue list and path it is in. Each is written in ASCII digits
+/// with `.` separating the parts of a number, such as `"15.5"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cue<'a> {
+    pub number: &'a str,
+    pub list: Option<&'a str>,
+    pub path: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShowControlCommand<'a> {
+    /// Runs the cue, or the next one in sequence.
+    Go(Option<Cue<'a>>),
+    /// Halts the cue, or every running cue.
+    Stop(Option<Cue<'a>>),
+    /// Carries on with a stopped cue, or every stopped cue.
+    Resume(Option<Cue<'a>>),
+    /// Gets a cue ready to go.
+    Load(Cue<'a>),
+    /// Ends the cue, or every running cue, as if it had run to completion.
+    GoOff(Option<Cue<'a>>),
+    /// Turns every output off, remembering the state to restore.
+    AllOff,
+    /// Puts back the state from before `AllOff`.
+    Restore,
+    /// Stops everything and goes back to the top of the cue lists.
+    Reset,
+    /// Any other command, with its data bytes left as they are.
+    Other { command: u8, data: &'a [u8] },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShowControlMessage<'a> {
+    /// The device the message is for, from 0x00 to 0x6F, a group from 0x70 to 0x7E, or [`ALL_CALL`].
+    pub device_id: u8,
+    /// One of the [`command_format`] constants.
+    pub command_format: u8,
+    pub command: ShowControlCommand<'a>,
+}
+
+impl<'a> ShowControlMessage<'a> {
+    /// The SysEx payload carrying the message, without its start and end bytes.
+    pub fn to_sysex(&self) -> Vec<u8> {
+        let mut sysex = Vec::from([UNIVERSAL_REAL_TIME, self.device_id, SHOW_CONTROL, self.command_format]);
+        let (command, cue) = match self.command {
+            ShowControlCommand::Go(cue) => (GO, cue),
+            ShowControlCommand::Stop(cue) => (STOP, cue),
+            ShowControlCommand::Resume(cue) => (RESUME, cue),
+            ShowControlCommand::Load(cue) => (LOAD, Some(cue)),
+            ShowControlCommand::GoOff(cue) => (GO_OFF, cue),
+            ShowControlCommand::AllOff => (ALL_OFF, None),
+            ShowControlCommand::Restore => (RESTORE, None),
+            ShowControlCommand::Reset => (RESET, None),
+            ShowControlCommand::Other { command, data } => {
+                sysex.push(command);
+                sysex.extend_from_slice(data);
+                return sysex;
+            }
+        };
+        sysex.push(command);
+        if let Some(cue) = cue {
+            sysex.extend_from_slice(cue.number.as_bytes());
+            // Each part goes after a separator, and a path is only written along with its list
+            for part in [cue.list, cue.path].into_iter().map_while(|part| part) {
+                sysex.push(CUE_SEPARATOR);
+                sysex.extend_from_slice(part.as_bytes());
+            }
+        }
+        sysex
+    }
+
+    /// Reads a SysEx payload, as received without its start and end bytes. Returns `None` for any other SysEx
+    /// message, or a cue that isn't written in ASCII.
+    pub fn from_sysex(sysex: &'a [u8]) -> Option<Self> {
+        let [UNIVERSAL_REAL_TIME, device_id, SHOW_CONTROL, command_format, command, ref data @ ..] = *sysex else {
+            return None;
+        };
+        let command = match command {
+            GO => ShowControlCommand::Go(read_cue(data)?),
+            STOP => ShowControlCommand::Stop(read_cue(data)?),
+            RESUME => ShowControlCommand::Resume(read_cue(data)?),
+            LOAD => ShowControlCommand::Load(read_cue(data)??),
+            GO_OFF => ShowControlCommand::GoOff(read_cue(data)?),
+            ALL_OFF => ShowControlCommand::AllOff,
+            RESTORE => ShowControlCommand::Restore,
+            RESET => ShowControlCommand::Reset,
+            command => ShowControlCommand::Other { command, data },
+        };
+        Some(ShowControlMessage {
+            device_id,
+            command_format,
+            command,
+        })
+    }
+}
+
+/// Reads the cue a command's data gives, `Some(None)` if it gives none and `None` if it isn't ASCII.
+fn read_cue(data: &[u8]) -> Option<Option<Cue<'_>>> {
+    if data.is_empty() {
+        return Some(None);
+    }
+    if !data.is_ascii() {
+        return None;
+    }
+    let mut parts = data.split(|&byte| byte == CUE_SEPARATOR).map(|part| core::str::from_utf8(part).ok());
+    let number = parts.next()??;
+    let list = parts.next().flatten();
+    let path = parts.next().flatten();
+    Some(Some(Cue { number, list, path }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(command: ShowControlCommand<'_>) -> ShowControlMessage<'_> {
+        ShowControlMessage {
+            device_id: 0x01,
+            command_format: command_format::SOUND,
+            command,
+        }
+    }
+
+    #[test]
+    fn test_go_layout() {
+        let cue = Cue {
+            number: "2.5",
+            list: Some("3"),
+            path: None,
+        };
+        assert_eq!(
+            message(ShowControlCommand::Go(Some(cue))).to_sysex(),
+            [0x7F, 0x01, 0x02, 0x10, 0x01, b'2', b'.', b'5', 0x00, b'3']
+        );
+        assert_eq!(message(ShowControlCommand::Stop(None)).to_sysex(), [0x7F, 0x01, 0x02, 0x10, 0x02]);
+    }
+
+    #[test]
+    fn test_commands_round_trip() {
+        let cue = Cue {
+            number: "1",
+            list: Some("2"),
+            path: Some("3"),
+        };
+        let commands = [
+            ShowControlCommand::Go(None),
+            ShowControlCommand::Resume(Some(cue)),
+            ShowControlCommand::Load(cue),
+            ShowControlCommand::GoOff(Some(Cue {
+                number: "7",
+                list: None,
+                path: None,
+            })),
+            ShowControlCommand::AllOff,
+            ShowControlCommand::Restore,
+            ShowControlCommand::Reset,
+            ShowControlCommand::Other { command: 0x06, data: &[1, 2] },
+        ];
+        for command in commands {
+            let sysex = message(command).to_sysex();
+            assert_eq!(ShowControlMessage::from_sysex(&sysex), Some(message(command)));
+        }
+    }
+
+    #[test]
+    fn test_other_sysex_is_not_show_control() {
+        assert_eq!(ShowControlMessage::from_sysex(&[0x7F, 0x7F, 0x01, 0x01, 0, 0, 0, 0]), None);
+        assert_eq!(ShowControlMessage::from_sysex(&[0x7F, 0x01, 0x02, 0x10]), None);
+        // A load needs a cue to load, and cues are ASCII
+        assert_eq!(ShowControlMessage::from_sysex(&[0x7F, 0x01, 0x02, 0x10, LOAD]), None);
+        assert_eq!(ShowControlMessage::from_sysex(&[0x7F, 0x01, 0x02, 0x10, GO, 0x80]), None);
+    }
+}
@@ -0,0 +1,510 @@
+//! A typed reading of SysEx payloads: the universal messages for identifying devices, moving samples and locating
+//! timecode, and the manufacturer a manufacturer-specific message is from. Anything else is left as raw bytes.
+//!
+//! Messages are built and read as SysEx payloads without their start and end bytes, the way
+//! [`RtpMidiMessage::SysEx`](crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage::SysEx) carries them.
+//!
+//! ```
+//! use rtpmidi::packets::midi_packets::sysex::{ALL_DEVICES, ManufacturerId, SysExMessage};
+//!
+//! let inquiry = SysExMessage::DeviceInquiry { device_id: ALL_DEVICES };
+//! assert_eq!(inquiry.to_sysex()?, [0x7E, 0x7F, 0x06, 0x01]);
+//!
+//! let roland = SysExMessage::parse(&[0x41, 0x10, 0x42, 0x12]);
+//! assert_eq!(roland, SysExMessage::Manufacturer { id: ManufacturerId::Standard(0x41), data: &[0x10, 0x42, 0x12] });
+//! # Ok::<(), rtpmidi::error::RtpMidiError>(())
+//! ```
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use crate::error::RtpMidiError;
+use crate::packets::midi_packets::timecode::TimecodePosition;
+
+/// Addresses every device, whatever its own device ID.
+pub const ALL_DEVICES: u8 = 0x7F;
+
+const UNIVERSAL_NON_REAL_TIME: u8 = 0x7E;
+const UNIVERSAL_REAL_TIME: u8 = 0x7F;
+/// Starts a manufacturer ID that takes the two bytes after it.
+const EXTENDED_MANUFACTURER: u8 = 0x00;
+
+const SAMPLE_DUMP_HEADER: u8 = 0x01;
+const SAMPLE_DUMP_PACKET: u8 = 0x02;
+const SAMPLE_DUMP_REQUEST: u8 = 0x03;
+const GENERAL_INFORMATION: u8 = 0x06;
+const DEVICE_INQUIRY: u8 = 0x01;
+const DEVICE_IDENTITY: u8 = 0x02;
+const END_OF_FILE: u8 = 0x7B;
+const WAIT: u8 = 0x7C;
+const CANCEL: u8 = 0x7D;
+const NAK: u8 = 0x7E;
+const ACK: u8 = 0x7F;
+
+/// Who a manufacturer-specific message is from, as registered with the MIDI Manufacturers Association.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ManufacturerId {
+    /// One of the original IDs, from 0x01 to 0x7C.
+    Standard(u8),
+    /// An ID sent as 0x00 and then these two bytes.
+    Extended([u8; 2]),
+}
+
+impl ManufacturerId {
+    fn read(bytes: &[u8]) -> Option<(Self, &[u8])> {
+        match *bytes {
+            [EXTENDED_MANUFACTURER, a, b, ref rest @ ..] => Some((ManufacturerId::Extended([a, b]), rest)),
+            [EXTENDED_MANUFACTURER, ..] => None,
+            [id @ 0x01..=0x7C, ref rest @ ..] => Some((ManufacturerId::Standard(id), rest)),
+            _ => None,
+        }
+    }
+
+    fn write(&self, sysex: &mut Vec<u8>) -> Result<(), RtpMidiError> {
+        match *self {
+            ManufacturerId::Standard(id) => write_bytes(sysex, "manufacturer", &[id]),
+            ManufacturerId::Extended([a, b]) => write_bytes(sysex, "manufacturer", &[EXTENDED_MANUFACTURER, a, b]),
+        }
+    }
+}
+
+/// What a device says it is, in reply to a device inquiry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub manufacturer: ManufacturerId,
+    /// The product family, and the member of it, as the manufacturer numbers them, from 0 to 16383.
+    pub family: u16,
+    pub member: u16,
+    /// The software revision, in a format up to the manufacturer.
+    pub revision: [u8; 4],
+}
+
+/// Describes a sample about to be sent in [`SysExMessage::SampleDumpPacket`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleDumpHeader {
+    /// From 0 to 16383.
+    pub sample: u16,
+    /// Bits per sample word, from 8 to 28.
+    pub bits: u8,
+    /// The time between sample words, in nanoseconds. This and the rest below are up to 21 bits.
+    pub period_ns: u32,
+    /// The length of the sample, and where its sustain loop starts and ends, in sample words.
+    pub length: u32,
+    pub loop_start: u32,
+    pub loop_end: u32,
+    /// 0 loops forwards only, 1 forwards and backwards, and 0x7F doesn't loop.
+    pub loop_type: u8,
+}
+
+/// The replies that pace a sample dump, each about the data packet it gives the number of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handshake {
+    /// The packet arrived; send the next.
+    Ack,
+    /// The packet arrived damaged; send it again.
+    Nak,
+    /// Hold off until the next handshake.
+    Wait,
+    /// Stop the dump.
+    Cancel,
+    /// The dump is over.
+    EndOfFile,
+}
+
+impl Handshake {
+    fn code(self) -> u8 {
+        match self {
+            Handshake::Ack => ACK,
+            Handshake::Nak => NAK,
+            Handshake::Wait => WAIT,
+            Handshake::Cancel => CANCEL,
+            Handshake::EndOfFile => END_OF_FILE,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            ACK => Some(Handshake::Ack),
+            NAK => Some(Handshake::Nak),
+            WAIT => Some(Handshake::Wait),
+            CANCEL => Some(Handshake::Cancel),
+            END_OF_FILE => Some(Handshake::EndOfFile),
+            _ => None,
+        }
+    }
+}
+
+/// A SysEx message, read as far as this library knows how. Each universal message is for the device with
+/// `device_id`, or for every device with [`ALL_DEVICES`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SysExMessage<'a> {
+    /// Asks devices to reply with a [`SysExMessage::DeviceIdentity`].
+    DeviceInquiry {
+        device_id: u8,
+    },
+    DeviceIdentity {
+        device_id: u8,
+        identity: DeviceIdentity,
+    },
+    SampleDumpHeader {
+        device_id: u8,
+        header: SampleDumpHeader,
+    },
+    /// One of the packets a sample is sent in, numbered from 0 to 127 and then from 0 again. `data` is 120 bytes,
+    /// bar in the last packet, which may carry less.
+    SampleDumpPacket {
+        device_id: u8,
+        packet: u8,
+        data: &'a [u8],
+        checksum: u8,
+    },
+    /// Asks for a sample to be dumped.
+    SampleDumpRequest {
+        device_id: u8,
+        sample: u16,
+    },
+    SampleDumpHandshake {
+        device_id: u8,
+        handshake: Handshake,
+        packet: u8,
+    },
+    /// An MTC full frame, as sent after locating somewhere else.
+    FullFrame {
+        device_id: u8,
+        position: TimecodePosition,
+    },
+    /// A manufacturer-specific message, with the bytes after the manufacturer ID left as they are.
+    Manufacturer {
+        id: ManufacturerId,
+        data: &'a [u8],
+    },
+    /// Any other message, universal ones included, as it was received.
+    Raw(&'a [u8]),
+}
+
+impl<'a> SysExMessage<'a> {
+    /// Reads a SysEx payload, as received without its start and end bytes. Universal messages that are cut short,
+    /// or that it doesn't know, are left [`Raw`](SysExMessage::Raw).
+    pub fn parse(sysex: &'a [u8]) -> Self {
+        let message = match *sysex {
+            [UNIVERSAL_NON_REAL_TIME, device_id, ref rest @ ..] => read_non_real_time(device_id, rest),
+            [UNIVERSAL_REAL_TIME, device_id, ..] => TimecodePosition::from_full_frame(sysex).map(|position| SysExMessage::FullFrame { device_id, position }),
+            _ => ManufacturerId::read(sysex).map(|(id, data)| SysExMessage::Manufacturer { id, data }),
+        };
+        message.unwrap_or(SysExMessage::Raw(sysex))
+    }
+
+    /// The SysEx payload carrying the message, without its start and end bytes, or
+    /// [`RtpMidiError::InvalidArgument`] if a value doesn't fit the 7-bit bytes it is sent in: a byte above 0x7F,
+    /// a family, member or sample number above 16383, or a sample dump header field above 21 bits.
+    pub fn to_sysex(&self) -> Result<Vec<u8>, RtpMidiError> {
+        let mut sysex = Vec::new();
+        match *self {
+            SysExMessage::DeviceInquiry { device_id } => {
+                write_universal(&mut sysex, UNIVERSAL_NON_REAL_TIME, device_id, &[GENERAL_INFORMATION, DEVICE_INQUIRY])?;
+            }
+            SysExMessage::DeviceIdentity { device_id, identity } => {
+                write_universal(&mut sysex, UNIVERSAL_NON_REAL_TIME, device_id, &[GENERAL_INFORMATION, DEVICE_IDENTITY])?;
+                identity.manufacturer.write(&mut sysex)?;
+                write_7bit(&mut sysex, "family", identity.family.into(), 2)?;
+                write_7bit(&mut sysex, "member", identity.member.into(), 2)?;
+                write_bytes(&mut sysex, "revision", &identity.revision)?;
+            }
+            SysExMessage::SampleDumpHeader { device_id, header } => {
+                write_universal(&mut sysex, UNIVERSAL_NON_REAL_TIME, device_id, &[SAMPLE_DUMP_HEADER])?;
+                write_7bit(&mut sysex, "sample", header.sample.into(), 2)?;
+                write_bytes(&mut sysex, "bits", &[header.bits])?;
+                write_7bit(&mut sysex, "period", header.period_ns, 3)?;
+                write_7bit(&mut sysex, "length", header.length, 3)?;
+                write_7bit(&mut sysex, "loop start", header.loop_start, 3)?;
+                write_7bit(&mut sysex, "loop end", header.loop_end, 3)?;
+                write_bytes(&mut sysex, "loop type", &[header.loop_type])?;
+            }
+            SysExMessage::SampleDumpPacket {
+                device_id,
+                packet,
+                data,
+                checksum,
+            } => {
+                write_universal(&mut sysex, UNIVERSAL_NON_REAL_TIME, device_id, &[SAMPLE_DUMP_PACKET])?;
+                write_bytes(&mut sysex, "packet", &[packet])?;
+                write_bytes(&mut sysex, "sample data", data)?;
+                write_bytes(&mut sysex, "checksum", &[checksum])?;
+            }
+            SysExMessage::SampleDumpRequest { device_id, sample } => {
+                write_universal(&mut sysex, UNIVERSAL_NON_REAL_TIME, device_id, &[SAMPLE_DUMP_REQUEST])?;
+                write_7bit(&mut sysex, "sample", sample.into(), 2)?;
+            }
+            SysExMessage::SampleDumpHandshake { device_id, handshake, packet } => {
+                write_universal(&mut sysex, UNIVERSAL_NON_REAL_TIME, device_id, &[handshake.code()])?;
+                write_bytes(&mut sysex, "packet", &[packet])?;
+            }
+            SysExMessage::FullFrame { device_id, position } => {
+                let [_, _, ref rest @ ..] = position.full_frame();
+                write_universal(&mut sysex, UNIVERSAL_REAL_TIME, device_id, rest)?;
+            }
+            SysExMessage::Manufacturer { id, data } => {
+                id.write(&mut sysex)?;
+                write_bytes(&mut sysex, "data", data)?;
+            }
+            SysExMessage::Raw(data) => write_bytes(&mut sysex, "data", data)?,
+        }
+        Ok(sysex)
+    }
+
+    /// A sample dump data packet, with the checksum worked out for its contents.
+    pub fn sample_dump_packet(device_id: u8, packet: u8, data: &'a [u8]) -> Self {
+        SysExMessage::SampleDumpPacket {
+            device_id,
+            packet,
+            data,
+            checksum: packet_checksum(device_id, packet, data),
+        }
+    }
+
+    /// Whether a sample dump data packet arrived as it was sent, going by its checksum. Always true for any other
+    /// message.
+    pub fn checksum_matches(&self) -> bool {
+        match *self {
+            SysExMessage::SampleDumpPacket {
+                device_id,
+                packet,
+                data,
+                checksum,
+            } => packet_checksum(device_id, packet, data) == checksum,
+            _ => true,
+        }
+    }
+}
+
+fn read_non_real_time(device_id: u8, message: &[u8]) -> Option<SysExMessage<'_>> {
+    let message = match *message {
+        [GENERAL_INFORMATION, DEVICE_INQUIRY] => SysExMessage::DeviceInquiry { device_id },
+        [GENERAL_INFORMATION, DEVICE_IDENTITY, ref rest @ ..] => {
+            let (manufacturer, rest) = ManufacturerId::read(rest)?;
+            let [f0, f1, m0, m1, r0, r1, r2, r3] = *rest else {
+                return None;
+            };
+            let identity = DeviceIdentity {
+                manufacturer,
+                family: read_7bit(&[f0, f1]) as u16,
+                member: read_7bit(&[m0, m1]) as u16,
+                revision: [r0, r1, r2, r3],
+            };
+            SysExMessage::DeviceIdentity { device_id, identity }
+        }
+        [SAMPLE_DUMP_HEADER, s0, s1, bits, ref rest @ ..] => {
+            let [p0, p1, p2, l0, l1, l2, a0, a1, a2, b0, b1, b2, loop_type] = *rest else {
+                return None;
+            };
+            let header = SampleDumpHeader {
+                sample: read_7bit(&[s0, s1]) as u16,
+                bits,
+                period_ns: read_7bit(&[p0, p1, p2]),
+                length: read_7bit(&[l0, l1, l2]),
+                loop_start: read_7bit(&[a0, a1, a2]),
+                loop_end: read_7bit(&[b0, b1, b2]),
+                loop_type,
+            };
+            SysExMessage::SampleDumpHeader { device_id, header }
+        }
+        [SAMPLE_DUMP_PACKET, packet, ref rest @ .., checksum] => SysExMessage::SampleDumpPacket {
+            device_id,
+            packet,
+            data: rest,
+            checksum,
+        },
+        [SAMPLE_DUMP_REQUEST, s0, s1] => SysExMessage::SampleDumpRequest {
+            device_id,
+            sample: read_7bit(&[s0, s1]) as u16,
+        },
+        [code, packet] => SysExMessage::SampleDumpHandshake {
+            device_id,
+            handshake: Handshake::from_code(code)?,
+            packet,
+        },
+        _ => return None,
+    };
+    Some(message)
+}
+
+/// The XOR of every byte of a data packet before the checksum, from the universal ID on.
+fn packet_checksum(device_id: u8, packet: u8, data: &[u8]) -> u8 {
+    let header = UNIVERSAL_NON_REAL_TIME ^ device_id ^ SAMPLE_DUMP_PACKET ^ packet;
+    data.iter().fold(header, |checksum, byte| checksum ^ byte) & 0x7F
+}
+
+/// Writes the universal ID, `device_id` and the rest of the header of a universal message.
+fn write_universal(sysex: &mut Vec<u8>, universal: u8, device_id: u8, header: &[u8]) -> Result<(), RtpMidiError> {
+    sysex.push(universal);
+    write_bytes(sysex, "device ID", &[device_id])?;
+    sysex.extend_from_slice(header);
+    Ok(())
+}
+
+/// Writes `value` as `len` groups of 7 bits, least significant first, as universal messages, MIDI-CI among them,
+/// send their numbers. Fails rather than cut off what doesn't fit.
+pub(crate) fn write_7bit(sysex: &mut Vec<u8>, field: &str, value: u32, len: usize) -> Result<(), RtpMidiError> {
+    if u64::from(value) >= 1 << (7 * len) {
+        return Err(RtpMidiError::InvalidArgument(format!("{field} {value} doesn't fit in {} bits", 7 * len)));
+    }
+    sysex.extend((0..len).map(|i| (value >> (7 * i)) as u8 & 0x7F));
+    Ok(())
+}
+
+/// Writes bytes that have to be data bytes, failing on any with the top bit set.
+pub(crate) fn write_bytes(sysex: &mut Vec<u8>, field: &str, bytes: &[u8]) -> Result<(), RtpMidiError> {
+    if let Some(byte) = bytes.iter().find(|&&byte| byte > 0x7F) {
+        return Err(RtpMidiError::InvalidArgument(format!("{field} holds {byte:#04X}, which isn't a 7-bit value")));
+    }
+    sysex.extend_from_slice(bytes);
+    Ok(())
+}
+
+fn read_7bit(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |value, &byte| value << 7 | u32::from(byte & 0x7F))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::midi_packets::timecode::FrameRate;
+
+    fn round_trip(message: SysExMessage<'_>) {
+        assert_eq!(SysExMessage::parse(&message.to_sysex().unwrap()), message);
+    }
+
+    #[test]
+    fn test_universal_messages_round_trip() {
+        round_trip(SysExMessage::DeviceInquiry { device_id: ALL_DEVICES });
+        for manufacturer in [ManufacturerId::Standard(0x43), ManufacturerId::Extended([0x20, 0x29])] {
+            let identity = DeviceIdentity {
+                manufacturer,
+                family: 0x1234,
+                member: 3,
+                revision: [1, 2, 3, 4],
+            };
+            round_trip(SysExMessage::DeviceIdentity { device_id: 0x10, identity });
+        }
+        let header = SampleDumpHeader {
+            sample: 200,
+            bits: 16,
+            period_ns: 22_675,
+            length: 0x1F_FFFF,
+            loop_start: 0,
+            loop_end: 1000,
+            loop_type: 0x7F,
+        };
+        round_trip(SysExMessage::SampleDumpHeader { device_id: 0, header });
+        round_trip(SysExMessage::sample_dump_packet(0, 5, &[0x11; 120]));
+        round_trip(SysExMessage::SampleDumpRequest { device_id: 0, sample: 200 });
+        round_trip(SysExMessage::SampleDumpHandshake {
+            device_id: 0,
+            handshake: Handshake::Wait,
+            packet: 5,
+        });
+        let position = TimecodePosition {
+            hours: 1,
+            minutes: 2,
+            seconds: 3,
+            frames: 4,
+            rate: FrameRate::Fps30,
+        };
+        round_trip(SysExMessage::FullFrame { device_id: 0x7F, position });
+    }
+
+    #[test]
+    fn test_device_identity_layout() {
+        let sysex = [0x7E, 0x7F, 0x06, 0x02, 0x00, 0x20, 0x29, 0x34, 0x24, 0x03, 0x00, 0x01, 0x02, 0x03, 0x04];
+        let SysExMessage::DeviceIdentity { identity, .. } = SysExMessage::parse(&sysex) else {
+            panic!("Expected a device identity");
+        };
+        assert_eq!(identity.manufacturer, ManufacturerId::Extended([0x20, 0x29]));
+        assert_eq!(identity.family, 0x1234);
+        assert_eq!(identity.member, 3);
+    }
+
+    #[test]
+    fn test_sample_dump_checksum() {
+        let packet = SysExMessage::sample_dump_packet(0, 1, &[0x40, 0x01]);
+        assert_eq!(
+            packet.to_sysex().unwrap(),
+            [0x7E, 0x00, 0x02, 0x01, 0x40, 0x01, 0x7E ^ 0x02 ^ 0x01 ^ 0x40 ^ 0x01]
+        );
+        assert!(packet.checksum_matches());
+        let mut damaged = packet.to_sysex().unwrap();
+        damaged[4] = 0x41;
+        assert!(!SysExMessage::parse(&damaged).checksum_matches());
+    }
+
+    #[test]
+    fn test_unknown_messages_stay_raw() {
+        for sysex in [
+            &[0x7E, 0x00, 0x06][..],
+            &[0x7E, 0x00, 0x09, 0x01],
+            &[0x7F, 0x7F, 0x02, 0x10, 0x01],
+            &[0x7D, 0x01],
+            &[0x00, 0x01],
+            &[],
+        ] {
+            assert_eq!(SysExMessage::parse(sysex), SysExMessage::Raw(sysex));
+        }
+        assert_eq!(SysExMessage::Raw(&[0x7D, 0x01]).to_sysex().unwrap(), [0x7D, 0x01]);
+    }
+
+    #[test]
+    fn test_values_too_wide_for_their_fields_are_rejected() {
+        let identity = DeviceIdentity {
+            manufacturer: ManufacturerId::Standard(0x43),
+            family: 0x3FFF,
+            member: 0,
+            revision: [0; 4],
+        };
+        let header = SampleDumpHeader {
+            sample: 0x3FFF,
+            bits: 16,
+            period_ns: 0x1F_FFFF,
+            length: 0,
+            loop_start: 0,
+            loop_end: 0,
+            loop_type: 0,
+        };
+        assert!(SysExMessage::DeviceIdentity { device_id: 0, identity }.to_sysex().is_ok());
+        assert!(SysExMessage::SampleDumpHeader { device_id: 0, header }.to_sysex().is_ok());
+        let too_wide = [
+            SysExMessage::DeviceIdentity {
+                device_id: 0,
+                identity: DeviceIdentity { family: 0x4000, ..identity },
+            },
+            SysExMessage::DeviceIdentity {
+                device_id: 0,
+                identity: DeviceIdentity { member: 0x4000, ..identity },
+            },
+            SysExMessage::DeviceIdentity {
+                device_id: 0,
+                identity: DeviceIdentity {
+                    manufacturer: ManufacturerId::Extended([0x80, 0]),
+                    ..identity
+                },
+            },
+            SysExMessage::SampleDumpHeader {
+                device_id: 0,
+                header: SampleDumpHeader {
+                    period_ns: 0x20_0000,
+                    ..header
+                },
+            },
+            SysExMessage::SampleDumpHeader {
+                device_id: 0,
+                header: SampleDumpHeader { loop_end: 0x20_0000, ..header },
+            },
+            SysExMessage::SampleDumpRequest { device_id: 0, sample: 0x4000 },
+            SysExMessage::DeviceInquiry { device_id: 0x80 },
+            SysExMessage::sample_dump_packet(0, 1, &[0x80]),
+            SysExMessage::Raw(&[0xF7]),
+        ];
+        for message in too_wide {
+            assert!(matches!(message.to_sysex(), Err(RtpMidiError::InvalidArgument(_))), "{message:?}");
+        }
+    }
+}
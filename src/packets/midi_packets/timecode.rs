@@ -0,0 +1,301 @@
+//! MIDI Time Code (MTC). While running, a timecode sender spreads each position over eight quarter-frame messages,
+//! four per frame; when it jumps somewhere else, it sends the whole position at once in a full-frame SysEx message.
+//!
+//! ```
+//! use rtpmidi::packets::midi_packets::timecode::{FrameRate, QuarterFrameAssembler, TimecodePosition};
+//!
+//! let position = TimecodePosition { hours: 1, minutes: 2, seconds: 3, frames: 4, rate: FrameRate::Fps25 };
+//! assert_eq!(TimecodePosition::from_full_frame(&position.full_frame()), Some(position));
+//!
+//! let mut assembler = QuarterFrameAssembler::default();
+//! let assembled = position.quarter_frames().iter().filter_map(|message| assembler.push(message)).last();
+//! assert_eq!(assembled, Some(position));
+//! ```
+
+use midi_types::{MidiMessage, QuarterFrame};
+
+/// The start of a full-frame message, after the SysEx start byte: universal real time, to all devices, MTC full frame.
+const FULL_FRAME_HEADER: [u8; 4] = [0x7F, 0x7F, 0x01, 0x01];
+const QUARTER_FRAME_PIECES: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameRate {
+    Fps24,
+    Fps25,
+    /// 29.97 frames per second, counted in drop-frame timecode: frames 0 and 1 are skipped at the start of every
+    /// minute except each tenth one.
+    Fps2997DropFrame,
+    Fps30,
+}
+
+impl FrameRate {
+    fn code(self) -> u8 {
+        match self {
+            FrameRate::Fps24 => 0,
+            FrameRate::Fps25 => 1,
+            FrameRate::Fps2997DropFrame => 2,
+            FrameRate::Fps30 => 3,
+        }
+    }
+
+    fn from_code(code: u8) -> Self {
+        match code & 0b11 {
+            0 => FrameRate::Fps24,
+            1 => FrameRate::Fps25,
+            2 => FrameRate::Fps2997DropFrame,
+            _ => FrameRate::Fps30,
+        }
+    }
+
+    /// How many frames are counted in each second.
+    pub fn frames_per_second(self) -> u8 {
+        match self {
+            FrameRate::Fps24 => 24,
+            FrameRate::Fps25 => 25,
+            FrameRate::Fps2997DropFrame | FrameRate::Fps30 => 30,
+        }
+    }
+}
+
+/// A position in SMPTE hours, minutes, seconds and frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimecodePosition {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    pub rate: FrameRate,
+}
+
+impl TimecodePosition {
+    /// The position one frame later, wrapping around after 23:59:59 and skipping the frames drop-frame timecode
+    /// leaves out. Hours past 23, which the 5 bits timecode gives them can carry, are taken mod 24.
+    pub fn next_frame(&self) -> Self {
+        let mut next = *self;
+        next.frames += 1;
+        if next.frames < self.rate.frames_per_second() {
+            return next;
+        }
+        next.frames = 0;
+        next.seconds += 1;
+        if next.seconds < 60 {
+            return next;
+        }
+        next.seconds = 0;
+        next.minutes += 1;
+        if next.minutes == 60 {
+            next.minutes = 0;
+            next.hours = (next.hours % 24 + 1) % 24;
+        }
+        if next.rate == FrameRate::Fps2997DropFrame && !next.minutes.is_multiple_of(10) {
+            next.frames = 2;
+        }
+        next
+    }
+
+    /// The payload of the full-frame SysEx message, without its start and end bytes, ready for
+    /// [`RtpMidiMessage::SysEx`](crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage::SysEx).
+    pub fn full_frame(&self) -> [u8; 8] {
+        let [a, b, c, d] = FULL_FRAME_HEADER;
+        let hours = self.rate.code() << 5 | (self.hours & 0x1F);
+        [a, b, c, d, hours, self.minutes & 0x3F, self.seconds & 0x3F, self.frames & 0x1F]
+    }
+
+    /// Reads a full-frame SysEx payload, as received without its start and end bytes. Returns `None` for any other
+    /// SysEx message. Messages addressed to a single device are accepted as well as those to all devices.
+    pub fn from_full_frame(sysex: &[u8]) -> Option<Self> {
+        let [0x7F, _device, 0x01, 0x01, hours, minutes, seconds, frames] = *sysex else {
+            return None;
+        };
+        Some(TimecodePosition {
+            hours: hours & 0x1F,
+            minutes: minutes & 0x3F,
+            seconds: seconds & 0x3F,
+            frames: frames & 0x1F,
+            rate: FrameRate::from_code(hours >> 5),
+        })
+    }
+
+    /// The eight quarter-frame messages carrying the position, in the order they are sent while running forwards.
+    /// A sender sends one every quarter of a frame, so the position has moved on by two frames by the time the last
+    /// one goes out; the next eight carry the position two frames later.
+    pub fn quarter_frames(&self) -> [MidiMessage; QUARTER_FRAME_PIECES as usize] {
+        let nibbles = [
+            self.frames & 0x0F,
+            self.frames >> 4 & 0x01,
+            self.seconds & 0x0F,
+            self.seconds >> 4 & 0x03,
+            self.minutes & 0x0F,
+            self.minutes >> 4 & 0x03,
+            self.hours & 0x0F,
+            self.rate.code() << 1 | (self.hours >> 4 & 0x01),
+        ];
+        let mut piece = 0;
+        nibbles.map(|nibble| {
+            let message = MidiMessage::QuarterFrame(QuarterFrame::from(piece << 4 | nibble));
+            piece += 1;
+            message
+        })
+    }
+}
+
+/// Puts together the positions carried by one sender's quarter-frame messages.
+///
+/// A position is complete once all eight pieces have arrived in order; a sender running backwards sends them in
+/// reverse, and those are ignored. The position returned is the one the sender started sending eight quarter frames
+/// ago, so the sender itself is two frames further on.
+#[derive(Debug, Clone, Default)]
+pub struct QuarterFrameAssembler {
+    nibbles: [u8; QUARTER_FRAME_PIECES as usize],
+    next_piece: u8,
+}
+
+impl QuarterFrameAssembler {
+    /// Takes the next received message, returning the position it completes, if any.
+    pub fn push(&mut self, message: &MidiMessage) -> Option<TimecodePosition> {
+        let MidiMessage::QuarterFrame(quarter_frame) = *message else {
+            return None;
+        };
+        let value = u8::from(quarter_frame);
+        let piece = value >> 4 & 0x07;
+        if piece != 0 && piece != self.next_piece {
+            // A piece was lost or the sender is running backwards; wait for the start of the next position
+            self.next_piece = 0;
+            return None;
+        }
+        self.nibbles[piece as usize] = value & 0x0F;
+        self.next_piece = piece + 1;
+        if self.next_piece < QUARTER_FRAME_PIECES {
+            return None;
+        }
+
+        self.next_piece = 0;
+        let [
+            frames_low,
+            frames_high,
+            seconds_low,
+            seconds_high,
+            minutes_low,
+            minutes_high,
+            hours_low,
+            hours_high,
+        ] = self.nibbles;
+        Some(TimecodePosition {
+            hours: (hours_high & 0x01) << 4 | hours_low,
+            minutes: (minutes_high & 0x03) << 4 | minutes_low,
+            seconds: (seconds_high & 0x03) << 4 | seconds_low,
+            frames: (frames_high & 0x01) << 4 | frames_low,
+            rate: FrameRate::from_code(hours_high >> 1),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITION: TimecodePosition = TimecodePosition {
+        hours: 23,
+        minutes: 59,
+        seconds: 58,
+        frames: 29,
+        rate: FrameRate::Fps30,
+    };
+
+    #[test]
+    fn test_full_frame_layout() {
+        assert_eq!(POSITION.full_frame(), [0x7F, 0x7F, 0x01, 0x01, 0x60 | 23, 59, 58, 29]);
+        assert_eq!(TimecodePosition::from_full_frame(&[0x7F, 0x7F, 0x01, 0x02, 0, 0, 0, 0]), None);
+        assert_eq!(TimecodePosition::from_full_frame(&POSITION.full_frame()[..7]), None);
+    }
+
+    #[test]
+    fn test_quarter_frame_layout() {
+        let values = POSITION.quarter_frames().map(|message| match message {
+            MidiMessage::QuarterFrame(value) => u8::from(value),
+            _ => unreachable!(),
+        });
+        assert_eq!(values, [0x0D, 0x11, 0x2A, 0x33, 0x4B, 0x53, 0x67, 0x77]);
+    }
+
+    #[test]
+    fn test_assembly_restarts_after_a_lost_piece() {
+        let mut assembler = QuarterFrameAssembler::default();
+        let quarter_frames = POSITION.quarter_frames();
+        let with_gap = quarter_frames[..3].iter().chain(&quarter_frames[4..]);
+        assert_eq!(with_gap.filter_map(|message| assembler.push(message)).last(), None);
+        assert_eq!(quarter_frames.iter().filter_map(|message| assembler.push(message)).last(), Some(POSITION));
+    }
+
+    #[test]
+    fn test_backwards_quarter_frames_are_ignored() {
+        let mut assembler = QuarterFrameAssembler::default();
+        let backwards = POSITION.quarter_frames().into_iter().rev();
+        assert_eq!(backwards.filter_map(|message| assembler.push(&message)).last(), None);
+    }
+
+    #[test]
+    fn test_next_frame_wraps() {
+        let next = TimecodePosition { seconds: 59, ..POSITION }.next_frame();
+        assert_eq!((next.hours, next.minutes, next.seconds, next.frames), (0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_next_frame_wraps_hours_mod_24() {
+        for rate in [FrameRate::Fps24, FrameRate::Fps25, FrameRate::Fps2997DropFrame, FrameRate::Fps30] {
+            let last = TimecodePosition {
+                seconds: 59,
+                frames: rate.frames_per_second() - 1,
+                rate,
+                ..POSITION
+            };
+            assert_eq!(
+                last.next_frame(),
+                TimecodePosition {
+                    hours: 0,
+                    minutes: 0,
+                    seconds: 0,
+                    frames: 0,
+                    rate
+                },
+                "{rate:?}"
+            );
+        }
+        let next = TimecodePosition {
+            hours: 22,
+            seconds: 59,
+            ..POSITION
+        }
+        .next_frame();
+        assert_eq!((next.hours, next.minutes), (23, 0));
+        // Out of range, as a full frame can carry, and not a reason to overflow
+        for (hours, wrapped) in [(24, 1), (31, 8), (u8::MAX, 16)] {
+            let next = TimecodePosition {
+                hours,
+                seconds: 59,
+                ..POSITION
+            }
+            .next_frame();
+            assert_eq!(next.hours, wrapped, "{hours}");
+        }
+    }
+
+    #[test]
+    fn test_drop_frame_skips_the_first_frames_of_most_minutes() {
+        let position = TimecodePosition {
+            hours: 0,
+            minutes: 0,
+            seconds: 59,
+            frames: 29,
+            rate: FrameRate::Fps2997DropFrame,
+        };
+        let next = position.next_frame();
+        assert_eq!((next.minutes, next.seconds, next.frames), (1, 0, 2));
+
+        let position = TimecodePosition { minutes: 9, ..position };
+        let next = position.next_frame();
+        assert_eq!((next.minutes, next.seconds, next.frames), (10, 0, 0));
+    }
+}
@@ -0,0 +1,128 @@
+//! Sequencer transport: Start, Continue and Stop, and the Song Position Pointer (SPP) that says where playback
+//! carries on from. Song positions count sixteenth notes from the start of the song, six MIDI clocks each.
+//!
+//! ```
+//! use midi_types::MidiMessage;
+//! use rtpmidi::packets::midi_packets::transport::TransportFollower;
+//!
+//! let mut follower = TransportFollower::default();
+//! for message in [MidiMessage::Start, MidiMessage::TimingClock, MidiMessage::TimingClock] {
+//!     follower.push(&message);
+//! }
+//! assert!(follower.is_playing());
+//! assert_eq!(follower.clocks(), 2);
+//! ```
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use midi_types::{MidiMessage, Value14};
+
+/// MIDI clocks in each sixteenth note of song position.
+pub const CLOCKS_PER_SIXTEENTH: u32 = 6;
+/// The furthest song position a Song Position Pointer can carry.
+pub const MAX_SONG_POSITION: u16 = 0x3FFF;
+
+/// The messages that move playback to `song_position`. A receiver only takes a Song Position Pointer while it's
+/// stopped, so when `playing` it's stopped first and carries on from the new position afterwards.
+pub fn locate_messages(song_position: u16, playing: bool) -> Vec<MidiMessage> {
+    let pointer = MidiMessage::SongPositionPointer(Value14::from(song_position.min(MAX_SONG_POSITION)));
+    if playing {
+        vec![MidiMessage::Stop, pointer, MidiMessage::Continue]
+    } else {
+        vec![pointer]
+    }
+}
+
+/// Follows the transport of one sender from the messages it sends.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransportFollower {
+    playing: bool,
+    clocks: u32,
+}
+
+impl TransportFollower {
+    /// Takes the next received message, returning whether it was a transport message. MIDI clocks only move the
+    /// position along while playing.
+    pub fn push(&mut self, message: &MidiMessage) -> bool {
+        match *message {
+            MidiMessage::Start => {
+                self.playing = true;
+                self.clocks = 0;
+            }
+            MidiMessage::Continue => self.playing = true,
+            MidiMessage::Stop => self.playing = false,
+            MidiMessage::SongPositionPointer(position) => self.clocks = u32::from(u16::from(position)) * CLOCKS_PER_SIXTEENTH,
+            MidiMessage::TimingClock => {
+                if self.playing {
+                    self.clocks = self.clocks.saturating_add(1);
+                }
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// MIDI clocks since the start of the song.
+    pub fn clocks(&self) -> u32 {
+        self.clocks
+    }
+
+    /// The sixteenth note playback is in, as a Song Position Pointer would give it.
+    pub fn song_position(&self) -> u16 {
+        (self.clocks / CLOCKS_PER_SIXTEENTH).min(u32::from(MAX_SONG_POSITION)) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn follow(follower: &mut TransportFollower, messages: &[MidiMessage]) {
+        for message in messages {
+            follower.push(message);
+        }
+    }
+
+    #[test]
+    fn test_position_follows_pointer_and_clocks() {
+        let mut follower = TransportFollower::default();
+        follow(&mut follower, &locate_messages(4, false));
+        assert!(!follower.is_playing());
+        assert_eq!(follower.clocks(), 24);
+
+        // Clocks sent while stopped don't move playback along
+        follow(&mut follower, &[MidiMessage::TimingClock, MidiMessage::Continue]);
+        follow(&mut follower, &[MidiMessage::TimingClock; 6]);
+        assert!(follower.is_playing());
+        assert_eq!(follower.song_position(), 5);
+    }
+
+    #[test]
+    fn test_locate_while_playing_stops_first() {
+        let messages = locate_messages(16, true);
+        assert_eq!(messages[0], MidiMessage::Stop);
+        assert_eq!(messages[2], MidiMessage::Continue);
+
+        let mut follower = TransportFollower::default();
+        follow(&mut follower, &[MidiMessage::Start, MidiMessage::TimingClock]);
+        assert!(messages.iter().all(|message| follower.push(message)));
+        assert!(follower.is_playing());
+        assert_eq!(follower.song_position(), 16);
+    }
+
+    #[test]
+    fn test_start_goes_back_to_the_top() {
+        let mut follower = TransportFollower::default();
+        follow(&mut follower, &locate_messages(MAX_SONG_POSITION, false));
+        assert_eq!(follower.song_position(), MAX_SONG_POSITION);
+        assert!(follower.push(&MidiMessage::Start));
+        assert_eq!(follower.clocks(), 0);
+        assert!(!follower.push(&MidiMessage::TimingClock));
+    }
+}
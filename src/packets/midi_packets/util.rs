@@ -7,3 +7,13 @@ impl StatusBit for u8 {
         self & 0x80 != 0
     }
 }
+
+/// The running status in effect after a command with `status`: channel messages set it,
+/// system common and SysEx messages cancel it and system real-time messages leave it untouched.
+pub(crate) fn next_running_status(running_status: Option<u8>, status: u8) -> Option<u8> {
+    match status {
+        0x80..=0xEF => Some(status),
+        0xF0..=0xF7 => None,
+        _ => running_status,
+    }
+}
@@ -1,4 +1,8 @@
-pub(crate) mod control_packets;
+pub mod builder;
+pub mod control_packets;
+mod describe;
 pub mod error;
 pub mod midi_packets;
-pub(crate) mod packet;
+pub mod packet;
+pub mod parse_mode;
+pub(crate) mod slice_writer;
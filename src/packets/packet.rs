@@ -1,32 +1,56 @@
+use alloc::string::String;
+
 use zerocopy::FromBytes;
 
-use super::{control_packets::control_packet::ControlPacket, midi_packets::midi_packet::MidiPacket};
+use super::{
+    control_packets::control_packet::ControlPacket,
+    error::PacketParseError,
+    midi_packets::midi_packet::{FirstDeltaTime, MidiPacket},
+    parse_mode::ParseMode,
+};
 
 #[derive(Debug)]
-pub(crate) enum RtpMidiPacket<'a> {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum RtpMidiPacket<'a> {
     Midi(&'a MidiPacket),
     Control(ControlPacket<'a>),
 }
 
 impl<'a> RtpMidiPacket<'a> {
-    pub fn parse(bytes: &'a [u8]) -> Result<Self, std::io::Error> {
+    /// Parses a datagram from either port. Never panics, whatever the bytes, nor does anything done with the packet
+    /// it returns; `tests/fuzz.rs` holds it to that.
+    pub fn parse(bytes: &'a [u8], mode: ParseMode) -> Result<Self, PacketParseError> {
+        Self::parse_with_first_delta_time(bytes, mode, FirstDeltaTime::Flagged)
+    }
+
+    /// Like [`parse`](Self::parse), validating MIDI packets in strict mode as from a sender that treats the first
+    /// command's delta time as `first_delta_time` says.
+    pub fn parse_with_first_delta_time(bytes: &'a [u8], mode: ParseMode, first_delta_time: FirstDeltaTime) -> Result<Self, PacketParseError> {
         if ControlPacket::is_control_packet(bytes) {
-            let packet =
-                ControlPacket::try_from_bytes(bytes).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to parse Control packet"))?;
+            let packet = ControlPacket::try_from_bytes(bytes, mode)?;
             Ok(RtpMidiPacket::Control(packet))
         } else {
-            let (packet, _remaining) =
-                MidiPacket::ref_from_prefix(bytes).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to parse MIDI packet"))?;
+            let (packet, _remaining) = MidiPacket::ref_from_prefix(bytes).map_err(|_| PacketParseError::Truncated("MIDI packet header"))?;
+            packet.validate(mode, first_delta_time)?;
             Ok(RtpMidiPacket::Midi(packet))
         }
     }
+
+    /// An annotated hex dump of the packet, in the spirit of Wireshark's packet details pane, for comparing what
+    /// this library sees against another implementation.
+    pub fn describe(&self) -> String {
+        match self {
+            RtpMidiPacket::Midi(packet) => packet.describe(),
+            RtpMidiPacket::Control(packet) => packet.describe(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use midi_types::{Channel, MidiMessage, Note, Value7};
     use zerocopy::U16;
-    use zerocopy::network_endian::U32;
+    use zerocopy::network_endian::{U32, U64};
 
     use super::*;
     use crate::packets::midi_packets::midi_event::MidiEvent;
@@ -40,7 +64,7 @@ mod tests {
         )];
         let packet = MidiPacket::new_as_bytes(U16::new(1), U32::new(2), U32::new(3), &commands, false);
 
-        let parsed_packet = RtpMidiPacket::parse(&packet).unwrap();
+        let parsed_packet = RtpMidiPacket::parse(&packet, ParseMode::Lenient).unwrap();
         if let RtpMidiPacket::Midi(parsed_midi_packet) = parsed_packet {
             assert_eq!(parsed_midi_packet.sequence_number(), 1);
             assert_eq!(parsed_midi_packet.timestamp(), 2);
@@ -56,6 +80,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_describe_midi_packet() {
+        let commands = [
+            MidiEvent::new(None, RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100)))),
+            MidiEvent::new(Some(300), RtpMidiMessage::SysEx(&[1, 2, 3, 4, 5, 6, 7, 8, 9])),
+        ];
+        let packet = MidiPacket::new_as_bytes(U16::new(1), U32::new(2), U32::new(0xCAFE), &commands, false);
+        let description = RtpMidiPacket::parse(&packet, ParseMode::Strict).unwrap().describe();
+        assert_eq!(
+            description,
+            "RTP-MIDI packet, 30 bytes\n\
+             0000  80 61                    Version 2, P=0, X=0, CC=0, M=0, payload type 97\n\
+             0002  00 01                    Sequence number: 1\n\
+             0004  00 00 00 02              Timestamp: 2\n\
+             0008  00 00 CA FE              SSRC: 0x0000CAFE\n\
+             000C  80 10                    Command list: B=1, J=0, Z=0, P=0, length 16\n\
+             000E  90 48 64                 Command at delta 0: NoteOn(Channel(0), Note(72), Value7(100))\n\
+             0011  82 2C F0 01 02 03 04 05  Command at delta 300: SysEx, 9 bytes\n\
+             0019  06 07 08 09 F7\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_control_packet() {
+        let packet = ControlPacket::new_invitation_as_bytes(U32::new(1), U32::new(2), c"Session");
+        let description = RtpMidiPacket::parse(&packet, ParseMode::Strict).unwrap().describe();
+        assert_eq!(
+            description,
+            "AppleMIDI invitation\n\
+             0000  FF FF                    Signature\n\
+             0002  49 4E                    Command: IN\n\
+             0004  00 00 00 02              Protocol version: 2\n\
+             0008  00 00 00 01              Initiator token: 0x00000001\n\
+             000C  00 00 00 02              Sender SSRC: 0x00000002\n\
+             0010  53 65 73 73 69 6F 6E 00  Name: \"Session\"\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_clock_sync_packet() {
+        let packet = ControlPacket::new_clock_sync_as_bytes(1, [U64::new(3), U64::new(4), U64::new(0)], U32::new(2));
+        let description = RtpMidiPacket::parse(&packet, ParseMode::Strict).unwrap().describe();
+        assert!(description.contains("0009  00 00 00                 Padding\n"), "{description}");
+    }
+
     // #[test]
     // fn test_parse_control_packet() {
     //     let packet = ControlPacket::new_acceptance(U32::new(1), U32::new(1), c"Test Name");
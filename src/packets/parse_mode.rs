@@ -0,0 +1,11 @@
+/// How tolerant parsing is of packets that don't quite follow RFC 6295 / AppleMIDI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseMode {
+    /// Reject any packet that is not well formed.
+    Strict,
+    /// Recover whatever can be recovered: skip commands that can't be decoded, accept odd session names
+    /// and truncate command lists that claim more data than arrived.
+    #[default]
+    Lenient,
+}
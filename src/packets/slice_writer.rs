@@ -0,0 +1,14 @@
+use crate::error::RtpMidiError;
+
+/// Runs `write` over the start of `buffer` once it is known to hold `len` bytes, returning `len`.
+pub(crate) fn write_into_slice(buffer: &mut [u8], len: usize, write: impl FnOnce(&mut &mut [u8])) -> Result<usize, RtpMidiError> {
+    if buffer.len() < len {
+        return Err(RtpMidiError::BufferTooSmall {
+            needed: len,
+            available: buffer.len(),
+        });
+    }
+    let mut remaining = &mut buffer[..len];
+    write(&mut remaining);
+    Ok(len)
+}
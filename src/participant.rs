@@ -1,31 +1,169 @@
 use std::{
-    ffi::{CStr, CString},
-    fmt::Display,
+    fmt::{Debug, Display},
     net::SocketAddr,
-    time::Instant,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
 };
 
 use zerocopy::network_endian::U32;
 
+use crate::sessions::active_notes::ActiveNotes;
+use crate::sessions::retransmission::RetransmissionHistory;
+use crate::sessions::stats::{LatencyHistory, LatencySummary, LossWindow, MIN_LOSS_SAMPLES};
+
+/// How many of the most recent sequence numbers are remembered to recognise duplicates.
+pub(crate) const SEEN_SEQUENCE_NUMBERS: u32 = u64::BITS;
+
+/// A remote session connected to ours.
+///
+/// With the `serde` feature, the time of the last clock sync isn't serialized; a deserialized participant counts as
+/// having just synced.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Participant {
     ctrl_addr: SocketAddr,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::option_u32"))]
     initiator_token: Option<U32>,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Instant::now"))]
     last_clock_sync: Instant,
-    name: CString,
+    clock_synced: bool,
+    name: String,
     invited_by_us: bool,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_helpers::network_endian::u32"))]
     ssrc: U32,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    reception: Locked<Reception>,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    transmission: Locked<Transmission>,
+    latency: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    latency_history: LatencyHistory,
+    bitrate_limit: Option<u32>,
+    muted: bool,
+}
+
+/// What we send the participant, and keep of it: the sequence numbers, counted for the participant alone so sending
+/// to some of the participants leaves no gaps for the others, the notes left sounding on it, and the retransmission
+/// history. Locked on its own, so sending only needs the participants read.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct Transmission {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sequence_number: u16,
+    packets_sent: u64,
+    bytes_sent: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) active_notes: ActiveNotes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) retransmission: RetransmissionHistory,
+}
+
+impl Transmission {
+    /// The sequence number for the next packet sent to the participant.
+    pub(crate) fn next_sequence_number(&mut self) -> u16 {
+        let sequence_number = self.sequence_number;
+        self.sequence_number = sequence_number.wrapping_add(1);
+        sequence_number
+    }
+
+    pub(crate) fn sent_packet(&mut self, bytes: usize) {
+        self.packets_sent += 1;
+        self.bytes_sent += bytes as u64;
+    }
+}
+
+/// What we keep of the MIDI packets the participant sends us: their sequence numbers and how many were lost, late or
+/// received twice. Locked on its own, like [`Transmission`], so receiving only needs the participants read.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Reception {
+    highest_sequence_number: Option<u16>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    seen_sequence_numbers: u64, // bit n is set once highest_sequence_number - n has been received
+    lost_packets: u64,
+    late_packets: u64,
+    duplicate_packets: u64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    loss_window: LossWindow,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    loss_alerted: bool,
+    packets_received: u64,
+    bytes_received: u64,
+}
+
+/// A mutex that clones, compares, prints and serializes as what it holds, so a [`Participant`] still can.
+#[derive(Default)]
+struct Locked<T>(Mutex<T>);
+
+impl<T> Locked<T> {
+    fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: Clone> Clone for Locked<T> {
+    fn clone(&self) -> Self {
+        Self(Mutex::new(self.lock().clone()))
+    }
+}
+
+impl<T: PartialEq> PartialEq for Locked<T> {
+    fn eq(&self, other: &Self) -> bool {
+        // Locking the same mutex twice would never return
+        std::ptr::eq(self, other) || *self.lock() == *other.lock()
+    }
+}
+
+impl<T: Debug> Debug for Locked<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.lock().fmt(f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Locked<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.lock().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Locked<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(|value| Self(Mutex::new(value)))
+    }
+}
+
+/// How a received packet's sequence number relates to the packets received before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SequenceStatus {
+    /// The first packet from the participant, or the one directly after the newest so far.
+    InOrder,
+    /// Newer than expected, this many packets are missing.
+    Gap(u16),
+    /// Older than the newest so far, so it was reordered on the way.
+    Late,
+    /// Already received, because the sender retransmitted it or the network duplicated it.
+    Duplicate,
 }
 
 impl Participant {
-    pub fn new(ctrl_addr: SocketAddr, invited_by_us: bool, initiator_token: Option<U32>, name: &CStr, ssrc: U32) -> Self {
+    pub fn new(ctrl_addr: SocketAddr, invited_by_us: bool, initiator_token: Option<U32>, name: &str, ssrc: U32) -> Self {
         Participant {
             ctrl_addr,
             initiator_token,
             name: name.to_owned(),
             last_clock_sync: Instant::now(),
+            clock_synced: false,
             invited_by_us,
             ssrc,
+            reception: Locked::default(),
+            transmission: Locked::default(),
+            latency: None,
+            latency_history: LatencyHistory::default(),
+            bitrate_limit: None,
+            muted: false,
         }
     }
 
@@ -41,6 +179,181 @@ impl Participant {
         self.last_clock_sync = Instant::now();
     }
 
+    pub(super) fn completed_clock_sync(&mut self) {
+        self.clock_synced = true;
+    }
+
+    /// Whether a clock sync exchange with the participant has gone all the way round, the last step of joining.
+    pub fn is_clock_synced(&self) -> bool {
+        self.clock_synced
+    }
+
+    /// Tracks the RTP sequence numbers of the MIDI packets this participant sends us. The last
+    /// [`SEEN_SEQUENCE_NUMBERS`] are remembered, so a packet received twice within that window is recognised.
+    pub(super) fn received_sequence_number(&self, sequence_number: u16) -> SequenceStatus {
+        let reception = &mut *self.reception.lock();
+        let Some(highest) = reception.highest_sequence_number else {
+            reception.highest_sequence_number = Some(sequence_number);
+            reception.seen_sequence_numbers = 1;
+            reception.loss_window.received(0);
+            return SequenceStatus::InOrder;
+        };
+
+        // Sequence numbers wrap, so anything up to half the range ahead counts as newer
+        match sequence_number.wrapping_sub(highest) {
+            ahead @ 1..0x8000 => {
+                reception.highest_sequence_number = Some(sequence_number);
+                let still_seen = if (ahead as u32) < SEEN_SEQUENCE_NUMBERS {
+                    reception.seen_sequence_numbers << ahead
+                } else {
+                    0
+                };
+                reception.seen_sequence_numbers = still_seen | 1;
+                reception.loss_window.received(ahead - 1);
+                if ahead == 1 {
+                    return SequenceStatus::InOrder;
+                }
+                reception.lost_packets += ahead as u64 - 1;
+                SequenceStatus::Gap(ahead - 1)
+            }
+            _ => {
+                let behind = highest.wrapping_sub(sequence_number) as u32;
+                let seen = if behind < SEEN_SEQUENCE_NUMBERS { 1 << behind } else { 0 };
+                if reception.seen_sequence_numbers & seen != 0 {
+                    reception.duplicate_packets += 1;
+                    return SequenceStatus::Duplicate;
+                }
+                reception.seen_sequence_numbers |= seen;
+                reception.late_packets += 1;
+                reception.loss_window.received_late();
+                SequenceStatus::Late
+            }
+        }
+    }
+
+    /// The number of MIDI packets from this participant that were skipped over, including any that turned up late.
+    pub fn lost_packets(&self) -> u64 {
+        self.reception.lock().lost_packets
+    }
+
+    /// The number of MIDI packets from this participant that arrived after a newer one.
+    pub fn late_packets(&self) -> u64 {
+        self.reception.lock().late_packets
+    }
+
+    /// The number of MIDI packets from this participant that were received more than once and dropped.
+    pub fn duplicate_packets(&self) -> u64 {
+        self.reception.lock().duplicate_packets
+    }
+
+    /// The percentage of the most recent MIDI packets from this participant that were lost, over the last
+    /// [`LOSS_WINDOW`](crate::sessions::stats::LOSS_WINDOW) received.
+    pub fn loss_rate(&self) -> f64 {
+        self.reception.lock().loss_window.rate()
+    }
+
+    /// Compares the loss rate with `threshold`, returning whether it is now above it if it has crossed it since the
+    /// last check. Nothing is compared until enough packets have been received for the rate to mean something.
+    pub(super) fn check_loss_threshold(&self, threshold: f64) -> Option<bool> {
+        let mut reception = self.reception.lock();
+        if reception.loss_window.samples() < MIN_LOSS_SAMPLES {
+            return None;
+        }
+        let above = reception.loss_window.rate() > threshold;
+        if above == reception.loss_alerted {
+            return None;
+        }
+        reception.loss_alerted = above;
+        Some(above)
+    }
+
+    /// The number of MIDI packets sent to this participant.
+    pub fn packets_sent(&self) -> u64 {
+        self.transmission.lock().packets_sent
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.transmission.lock().bytes_sent
+    }
+
+    /// The number of MIDI packets received from this participant, duplicates included.
+    pub fn packets_received(&self) -> u64 {
+        self.reception.lock().packets_received
+    }
+
+    pub fn bytes_received(&self) -> u64 {
+        self.reception.lock().bytes_received
+    }
+
+    /// The round trip time to the participant measured by the last clock sync, if one has completed.
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    /// The spread of the round trip times measured by the most recent clock syncs, if one has completed.
+    pub fn latency_summary(&self) -> Option<LatencySummary> {
+        self.latency_history.summary()
+    }
+
+    pub(super) fn received_packet(&self, bytes: usize) {
+        let mut reception = self.reception.lock();
+        reception.packets_received += 1;
+        reception.bytes_received += bytes as u64;
+    }
+
+    pub(super) fn set_latency(&mut self, latency: Duration) {
+        self.latency = Some(latency);
+        self.latency_history.push(latency);
+    }
+
+    /// The most bits per second the participant has asked to be sent, if it has sent an `RL` packet.
+    pub fn bitrate_limit(&self) -> Option<u32> {
+        self.bitrate_limit
+    }
+
+    /// Whether MIDI sent to the whole session skips this participant. It stays connected, and is still sent clock
+    /// syncs.
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub(super) fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Takes on the details of a fresh invitation from the same peer. Its sequence numbers start over too.
+    pub(super) fn reinvited(&mut self, initiator_token: U32, name: &str, ssrc: U32) {
+        self.initiator_token = Some(initiator_token);
+        name.clone_into(&mut self.name);
+        self.ssrc = ssrc;
+        let reception = &mut *self.reception.lock();
+        reception.highest_sequence_number = None;
+        reception.seen_sequence_numbers = 0;
+        reception.loss_window = LossWindow::default();
+        reception.loss_alerted = false;
+        self.transmission.lock().retransmission.forget_missing();
+    }
+
+    /// Follows the participant to the control port it has moved to, with its MIDI port the one after.
+    pub(super) fn moved_to(&mut self, ctrl_addr: SocketAddr) {
+        self.ctrl_addr = ctrl_addr;
+    }
+
+    pub(super) fn set_bitrate_limit(&mut self, limit: u32) {
+        self.bitrate_limit = Some(limit);
+    }
+
+    /// What we send the participant, locked until the guard is dropped. Not to be held across an `.await`.
+    pub(super) fn transmission(&self) -> MutexGuard<'_, Transmission> {
+        self.transmission.lock()
+    }
+
+    /// The bytes the participant's name and statistics take up outside of it, not counting its retransmission
+    /// history.
+    pub(super) fn heap_size(&self) -> usize {
+        self.name.capacity() + self.reception.lock().loss_window.heap_size() + self.latency_history.heap_size()
+    }
+
     pub(super) fn is_invited_by_us(&self) -> bool {
         self.invited_by_us
     }
@@ -49,7 +362,11 @@ impl Participant {
         self.initiator_token
     }
 
-    pub fn name(&self) -> &CStr {
+    pub(super) fn set_initiator_token(&mut self, initiator_token: U32) {
+        self.initiator_token = Some(initiator_token);
+    }
+
+    pub fn name(&self) -> &str {
         &self.name
     }
 
@@ -62,14 +379,120 @@ impl Participant {
     }
 }
 
+/// Written as `Participant { name: Studio Mac, addr: 192.168.1.20:5004, ssrc: 305419896 }`, a format that is kept
+/// from release to release so logs and tools can rely on it.
 impl Display for Participant {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Participant {{ name: {}, addr: {}, ssrc: {} }}",
-            self.name.to_str().unwrap_or("Unknown"),
-            self.ctrl_addr,
-            self.ssrc.get()
-        )
+        write!(f, "Participant {{ name: {}, addr: {}, ssrc: {} }}", self.name, self.ctrl_addr, self.ssrc.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn participant() -> Participant {
+        Participant::new("127.0.0.1:5004".parse().unwrap(), false, None, "Peer", U32::new(1))
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(participant().to_string(), "Participant { name: Peer, addr: 127.0.0.1:5004, ssrc: 1 }");
+    }
+
+    #[test]
+    fn test_sequence_numbers_sent_are_the_participants_own() {
+        let (first, second) = (participant(), participant());
+        assert_eq!(first.transmission().next_sequence_number(), 0);
+        assert_eq!(first.transmission().next_sequence_number(), 1);
+        assert_eq!(second.transmission().next_sequence_number(), 0);
+        let copy = first.clone();
+        assert_eq!(copy, first);
+        assert_eq!(copy.transmission().next_sequence_number(), 2);
+        assert_eq!(first.transmission().next_sequence_number(), 2, "clones shouldn't share the count");
+    }
+
+    #[test]
+    fn test_sequence_numbers_in_order() {
+        let participant = participant();
+        assert_eq!(participant.received_sequence_number(10), SequenceStatus::InOrder);
+        assert_eq!(participant.received_sequence_number(11), SequenceStatus::InOrder);
+        assert_eq!(participant.lost_packets(), 0);
+    }
+
+    #[test]
+    fn test_sequence_number_gap_and_late_packet() {
+        let participant = participant();
+        participant.received_sequence_number(10);
+        assert_eq!(participant.received_sequence_number(13), SequenceStatus::Gap(2));
+        assert_eq!(participant.received_sequence_number(12), SequenceStatus::Late);
+        assert_eq!(participant.received_sequence_number(11), SequenceStatus::Late);
+        assert_eq!(participant.received_sequence_number(14), SequenceStatus::InOrder);
+        assert_eq!(participant.lost_packets(), 2);
+        assert_eq!(participant.late_packets(), 2);
+    }
+
+    #[test]
+    fn test_duplicate_sequence_numbers() {
+        let participant = participant();
+        participant.received_sequence_number(10);
+        assert_eq!(participant.received_sequence_number(10), SequenceStatus::Duplicate);
+        assert_eq!(participant.received_sequence_number(12), SequenceStatus::Gap(1));
+        assert_eq!(participant.received_sequence_number(11), SequenceStatus::Late);
+        assert_eq!(participant.received_sequence_number(11), SequenceStatus::Duplicate);
+        assert_eq!(participant.received_sequence_number(12), SequenceStatus::Duplicate);
+        assert_eq!(participant.duplicate_packets(), 3);
+        assert_eq!(participant.late_packets(), 1);
+    }
+
+    #[test]
+    fn test_duplicates_outside_the_window_count_as_late() {
+        let participant = participant();
+        participant.received_sequence_number(0);
+        participant.received_sequence_number(SEEN_SEQUENCE_NUMBERS as u16);
+        assert_eq!(participant.received_sequence_number(0), SequenceStatus::Late);
+    }
+
+    #[test]
+    fn test_sequence_number_wraps() {
+        let participant = participant();
+        participant.received_sequence_number(u16::MAX);
+        assert_eq!(participant.received_sequence_number(0), SequenceStatus::InOrder);
+        assert_eq!(participant.received_sequence_number(2), SequenceStatus::Gap(1));
+    }
+
+    #[test]
+    fn test_reinvitation_restarts_sequence_numbers() {
+        let mut participant = participant();
+        participant.received_sequence_number(100);
+        participant.reinvited(U32::new(5), "Renamed", U32::new(2));
+        assert_eq!(participant.name(), "Renamed");
+        assert_eq!(participant.ssrc(), 2);
+        assert_eq!(participant.initiator_token(), Some(U32::new(5)));
+        assert_eq!(participant.received_sequence_number(0), SequenceStatus::InOrder);
+        assert_eq!(participant.late_packets(), 0);
+    }
+
+    #[test]
+    fn test_loss_threshold_is_reported_once_each_way() {
+        let participant = participant();
+        // Every other packet goes missing
+        let mut sequence_number = 0;
+        for _ in 0..MIN_LOSS_SAMPLES {
+            assert_eq!(participant.check_loss_threshold(5.0), None);
+            participant.received_sequence_number(sequence_number);
+            sequence_number += 2;
+        }
+        assert_eq!(participant.loss_rate(), 19.0 * 100.0 / 39.0);
+        assert_eq!(participant.check_loss_threshold(5.0), Some(true));
+        assert_eq!(participant.check_loss_threshold(5.0), None);
+
+        sequence_number -= 2;
+        for _ in 0..crate::sessions::stats::LOSS_WINDOW {
+            sequence_number += 1;
+            participant.received_sequence_number(sequence_number);
+        }
+        assert_eq!(participant.loss_rate(), 0.0);
+        assert_eq!(participant.check_loss_threshold(5.0), Some(false));
     }
 }
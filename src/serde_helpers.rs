@@ -0,0 +1,319 @@
+//! Serde support for the types this crate borrows from elsewhere, for use with `#[serde(with = "...")]`.
+//!
+//! [`midi_message`] is public so applications can serialize the [`MidiMessage`](midi_types::MidiMessage) handed to
+//! [`MidiMessageEvent`](crate::sessions::events::event_handling::MidiMessageEvent) listeners:
+//!
+//! ```
+//! use midi_types::{Channel, MidiMessage, Note, Value7};
+//!
+//! #[derive(serde::Serialize)]
+//! struct LoggedMessage {
+//!     #[serde(with = "rtpmidi::serde_helpers::midi_message")]
+//!     message: MidiMessage,
+//!     timestamp: u32,
+//! }
+//!
+//! let logged = LoggedMessage {
+//!     message: MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100)),
+//!     timestamp: 1000,
+//! };
+//! assert_eq!(
+//!     serde_json::to_string(&logged).unwrap(),
+//!     r#"{"message":{"type":"note_on","channel":0,"note":60,"velocity":100},"timestamp":1000}"#
+//! );
+//! ```
+
+/// (De)serializes a [`MidiMessage`] as a map tagged with the message `type`, with every value a plain number.
+/// Deserialization rejects out-of-range values rather than clamping them.
+pub mod midi_message {
+    use midi_types::{Channel, MidiMessage, Note, QuarterFrame, Value7, Value14};
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::packets::midi_packets::midi_message_ext;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum MidiMessageRepr {
+        NoteOff { channel: u8, note: u8, velocity: u8 },
+        NoteOn { channel: u8, note: u8, velocity: u8 },
+        KeyPressure { channel: u8, note: u8, value: u8 },
+        ControlChange { channel: u8, control: u8, value: u8 },
+        ProgramChange { channel: u8, program: u8 },
+        ChannelPressure { channel: u8, value: u8 },
+        PitchBendChange { channel: u8, value: u16 },
+        QuarterFrame { value: u8 },
+        SongPositionPointer { value: u16 },
+        SongSelect { value: u8 },
+        TuneRequest,
+        TimingClock,
+        Start,
+        Continue,
+        Stop,
+        ActiveSensing,
+        Reset,
+    }
+
+    pub fn serialize<S: Serializer>(message: &MidiMessage, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match *message {
+            MidiMessage::NoteOff(channel, note, velocity) => MidiMessageRepr::NoteOff {
+                channel: channel.into(),
+                note: note.into(),
+                velocity: velocity.into(),
+            },
+            MidiMessage::NoteOn(channel, note, velocity) => MidiMessageRepr::NoteOn {
+                channel: channel.into(),
+                note: note.into(),
+                velocity: velocity.into(),
+            },
+            MidiMessage::KeyPressure(channel, note, value) => MidiMessageRepr::KeyPressure {
+                channel: channel.into(),
+                note: note.into(),
+                value: value.into(),
+            },
+            MidiMessage::ControlChange(channel, control, value) => MidiMessageRepr::ControlChange {
+                channel: channel.into(),
+                control: control.into(),
+                value: value.into(),
+            },
+            MidiMessage::ProgramChange(channel, program) => MidiMessageRepr::ProgramChange {
+                channel: channel.into(),
+                program: program.into(),
+            },
+            MidiMessage::ChannelPressure(channel, value) => MidiMessageRepr::ChannelPressure {
+                channel: channel.into(),
+                value: value.into(),
+            },
+            MidiMessage::PitchBendChange(channel, value) => MidiMessageRepr::PitchBendChange {
+                channel: channel.into(),
+                value: value.into(),
+            },
+            MidiMessage::QuarterFrame(value) => MidiMessageRepr::QuarterFrame { value: value.into() },
+            MidiMessage::SongPositionPointer(value) => MidiMessageRepr::SongPositionPointer { value: value.into() },
+            MidiMessage::SongSelect(value) => MidiMessageRepr::SongSelect { value: value.into() },
+            MidiMessage::TuneRequest => MidiMessageRepr::TuneRequest,
+            MidiMessage::TimingClock => MidiMessageRepr::TimingClock,
+            MidiMessage::Start => MidiMessageRepr::Start,
+            MidiMessage::Continue => MidiMessageRepr::Continue,
+            MidiMessage::Stop => MidiMessageRepr::Stop,
+            MidiMessage::ActiveSensing => MidiMessageRepr::ActiveSensing,
+            MidiMessage::Reset => MidiMessageRepr::Reset,
+        };
+        repr.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MidiMessage, D::Error> {
+        let message = match MidiMessageRepr::deserialize(deserializer)? {
+            MidiMessageRepr::NoteOff { channel, note, velocity } => {
+                MidiMessage::NoteOff(checked_channel(channel)?, Note::from(seven_bit(note)?), Value7::from(seven_bit(velocity)?))
+            }
+            MidiMessageRepr::NoteOn { channel, note, velocity } => {
+                MidiMessage::NoteOn(checked_channel(channel)?, Note::from(seven_bit(note)?), Value7::from(seven_bit(velocity)?))
+            }
+            MidiMessageRepr::KeyPressure { channel, note, value } => {
+                MidiMessage::KeyPressure(checked_channel(channel)?, Note::from(seven_bit(note)?), Value7::from(seven_bit(value)?))
+            }
+            MidiMessageRepr::ControlChange { channel, control, value } => MidiMessage::ControlChange(
+                checked_channel(channel)?,
+                midi_message_ext::control(seven_bit(control)?),
+                Value7::from(seven_bit(value)?),
+            ),
+            MidiMessageRepr::ProgramChange { channel, program } => {
+                MidiMessage::ProgramChange(checked_channel(channel)?, midi_message_ext::program(seven_bit(program)?))
+            }
+            MidiMessageRepr::ChannelPressure { channel, value } => MidiMessage::ChannelPressure(checked_channel(channel)?, Value7::from(seven_bit(value)?)),
+            MidiMessageRepr::PitchBendChange { channel, value } => MidiMessage::PitchBendChange(checked_channel(channel)?, fourteen_bit(value)?),
+            MidiMessageRepr::QuarterFrame { value } => MidiMessage::QuarterFrame(QuarterFrame::from(seven_bit(value)?)),
+            MidiMessageRepr::SongPositionPointer { value } => MidiMessage::SongPositionPointer(fourteen_bit(value)?),
+            MidiMessageRepr::SongSelect { value } => MidiMessage::SongSelect(Value7::from(seven_bit(value)?)),
+            MidiMessageRepr::TuneRequest => MidiMessage::TuneRequest,
+            MidiMessageRepr::TimingClock => MidiMessage::TimingClock,
+            MidiMessageRepr::Start => MidiMessage::Start,
+            MidiMessageRepr::Continue => MidiMessage::Continue,
+            MidiMessageRepr::Stop => MidiMessage::Stop,
+            MidiMessageRepr::ActiveSensing => MidiMessage::ActiveSensing,
+            MidiMessageRepr::Reset => MidiMessage::Reset,
+        };
+        Ok(message)
+    }
+
+    fn checked_channel<E: Error>(channel: u8) -> Result<Channel, E> {
+        match channel {
+            0..=15 => Ok(Channel::from(channel)),
+            _ => Err(E::custom(format_args!("MIDI channel {channel} is out of range"))),
+        }
+    }
+
+    fn seven_bit<E: Error>(value: u8) -> Result<u8, E> {
+        match value {
+            0..=127 => Ok(value),
+            _ => Err(E::custom(format_args!("{value} doesn't fit in 7 bits"))),
+        }
+    }
+
+    fn fourteen_bit<E: Error>(value: u16) -> Result<Value14, E> {
+        match value {
+            0..=0x3FFF => Ok(Value14::from(value)),
+            _ => Err(E::custom(format_args!("{value} doesn't fit in 14 bits"))),
+        }
+    }
+}
+
+/// (De)serializes the network-endian integers in wire structs as plain numbers.
+pub(crate) mod network_endian {
+    pub(crate) mod u32 {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use zerocopy::network_endian::U32;
+
+        pub(crate) fn serialize<S: Serializer>(value: &U32, serializer: S) -> Result<S::Ok, S::Error> {
+            value.get().serialize(serializer)
+        }
+
+        pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U32, D::Error> {
+            u32::deserialize(deserializer).map(U32::new)
+        }
+    }
+
+    pub(crate) mod u16 {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use zerocopy::network_endian::U16;
+
+        pub(crate) fn serialize<S: Serializer>(value: &U16, serializer: S) -> Result<S::Ok, S::Error> {
+            value.get().serialize(serializer)
+        }
+
+        pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<U16, D::Error> {
+            u16::deserialize(deserializer).map(U16::new)
+        }
+    }
+
+    /// An optional network-endian `u32`, as a number or `null`.
+    #[cfg(feature = "std")]
+    pub(crate) mod option_u32 {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use zerocopy::network_endian::U32;
+
+        pub(crate) fn serialize<S: Serializer>(value: &Option<U32>, serializer: S) -> Result<S::Ok, S::Error> {
+            value.map(U32::get).serialize(serializer)
+        }
+
+        pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<U32>, D::Error> {
+            Option::<u32>::deserialize(deserializer).map(|value| value.map(U32::new))
+        }
+    }
+
+    /// The three network-endian `u64` timestamps of a clock sync, as an array of numbers.
+    pub(crate) mod timestamps {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+        use zerocopy::network_endian::U64;
+
+        pub(crate) fn serialize<S: Serializer>(value: &[U64; 3], serializer: S) -> Result<S::Ok, S::Error> {
+            value.map(U64::get).serialize(serializer)
+        }
+
+        pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[U64; 3], D::Error> {
+            <[u64; 3]>::deserialize(deserializer).map(|value| value.map(U64::new))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi_types::{Channel, Control, MidiMessage, Note, Value7, Value14};
+    use serde::{Deserialize, Serialize};
+    use zerocopy::network_endian::{U16, U32};
+
+    use crate::packets::control_packets::control_packet::ControlPacket;
+    use crate::packets::midi_packets::midi_event::MidiEvent;
+    use crate::packets::midi_packets::midi_packet::MidiPacket;
+    use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+    use crate::packets::packet::RtpMidiPacket;
+    use crate::packets::parse_mode::ParseMode;
+    #[cfg(feature = "std")]
+    use crate::participant::Participant;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Wrapper(#[serde(with = "super::midi_message")] MidiMessage);
+
+    #[test]
+    fn test_midi_message_round_trip() {
+        let messages = [
+            MidiMessage::NoteOff(Channel::C16, Note::from(0), Value7::from(127)),
+            MidiMessage::ControlChange(Channel::C2, Control::from(7), Value7::from(64)),
+            MidiMessage::PitchBendChange(Channel::C1, Value14::from(0x2000u16)),
+            MidiMessage::SongPositionPointer(Value14::from(0x3FFFu16)),
+            MidiMessage::TimingClock,
+        ];
+        for message in messages {
+            let json = serde_json::to_string(&Wrapper(message)).unwrap();
+            assert_eq!(serde_json::from_str::<Wrapper>(&json).unwrap(), Wrapper(message), "{json}");
+        }
+    }
+
+    #[test]
+    fn test_highest_controller_and_program_round_trip() {
+        let control = serde_json::from_str::<Wrapper>(r#"{"type":"control_change","channel":0,"control":127,"value":0}"#).unwrap();
+        let program = serde_json::from_str::<Wrapper>(r#"{"type":"program_change","channel":0,"program":127}"#).unwrap();
+        for message in [control, program] {
+            let json = serde_json::to_string(&message).unwrap();
+            assert!(json.contains("127"), "{json}");
+            assert_eq!(serde_json::from_str::<Wrapper>(&json).unwrap(), message, "{json}");
+        }
+    }
+
+    #[test]
+    fn test_serialize_parsed_packets() {
+        let commands = [
+            MidiEvent::new(
+                None,
+                RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100))),
+            ),
+            MidiEvent::new(Some(10), RtpMidiMessage::SysEx(&[0x7E])),
+        ];
+        let bytes = MidiPacket::new_as_bytes(U16::new(1), U32::new(2), U32::new(3), &commands, false);
+        let packet = RtpMidiPacket::parse(&bytes, ParseMode::Strict).unwrap();
+        assert_eq!(
+            serde_json::to_string(&packet).unwrap(),
+            r#"{"Midi":{"sequence_number":1,"timestamp":2,"ssrc":3,"csrcs":[],"commands":["#.to_owned()
+                + r#"{"delta_time":null,"command":{"MidiMessage":{"type":"note_on","channel":0,"note":60,"velocity":100}}},"#
+                + r#"{"delta_time":10,"command":{"SysEx":[126]}}]}}"#
+        );
+
+        let bytes = ControlPacket::new_termination_as_bytes(U32::new(1), U32::new(2));
+        let packet = RtpMidiPacket::parse(&bytes, ParseMode::Strict).unwrap();
+        assert_eq!(
+            serde_json::to_string(&packet).unwrap(),
+            r#"{"Control":{"Termination":{"protocol_version":2,"initiator_token":1,"sender_ssrc":2}}}"#
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_participant_round_trip() {
+        let participant = Participant::new("127.0.0.1:5004".parse().unwrap(), true, Some(U32::new(7)), "Peer", U32::new(9));
+        let json = serde_json::to_string(&participant).unwrap();
+        let restored: Participant = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.addr(), participant.addr());
+        assert_eq!(restored.initiator_token(), Some(U32::new(7)));
+        assert_eq!(restored.name(), "Peer");
+        assert_eq!(restored.ssrc(), U32::new(9));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_known_peer_round_trip() {
+        use crate::sessions::known_peer::KnownPeer;
+
+        let participant = Participant::new("127.0.0.1:5004".parse().unwrap(), false, None, "Peer", U32::new(9));
+        let json = serde_json::to_string(&KnownPeer::from(&participant)).unwrap();
+        assert_eq!(json, r#"{"addr":"127.0.0.1:5004","name":"Peer"}"#);
+        assert_eq!(serde_json::from_str::<KnownPeer>(&json).unwrap(), KnownPeer::from(&participant));
+    }
+
+    #[test]
+    fn test_out_of_range_values_are_rejected() {
+        assert!(serde_json::from_str::<Wrapper>(r#"{"type":"note_on","channel":16,"note":60,"velocity":100}"#).is_err());
+        assert!(serde_json::from_str::<Wrapper>(r#"{"type":"note_on","channel":0,"note":128,"velocity":100}"#).is_err());
+        assert!(serde_json::from_str::<Wrapper>(r#"{"type":"song_position_pointer","value":16384}"#).is_err());
+    }
+}
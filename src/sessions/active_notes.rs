@@ -0,0 +1,139 @@
+use midi_types::{Channel, MidiMessage, Note, Value7};
+
+use crate::packets::midi_packets::midi_event::MidiEvent;
+use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+
+const SUSTAIN: u8 = 64;
+const ALL_SOUND_OFF: u8 = 120;
+const ALL_NOTES_OFF: u8 = 123;
+const POLY_MODE_ON: u8 = 127;
+
+/// The notes we have sent a NoteOn for and not yet ended, so they can be released if the session to the
+/// participant goes away before the NoteOffs are sent. Received notes are followed the same way, to conceal loss with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ActiveNotes {
+    channels: [u128; 16], // bit n is set while note n is sounding
+    sustained: u16,       // bit n is set while the sustain pedal is down on channel n
+}
+
+impl ActiveNotes {
+    /// Follows the notes started and ended by commands that have been sent.
+    pub fn track(&mut self, commands: &[MidiEvent]) {
+        for command in commands {
+            if let RtpMidiMessage::MidiMessage(message) = command.command() {
+                self.track_message(message);
+            }
+        }
+    }
+
+    pub fn track_message(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::NoteOn(channel, note, velocity) if u8::from(velocity) > 0 => {
+                self.channels[u8::from(channel) as usize] |= 1 << u8::from(note);
+            }
+            MidiMessage::NoteOn(channel, note, _) | MidiMessage::NoteOff(channel, note, _) => {
+                self.channels[u8::from(channel) as usize] &= !(1 << u8::from(note));
+            }
+            MidiMessage::ControlChange(channel, control, value) if u8::from(control) == SUSTAIN => {
+                let bit = 1 << u8::from(channel);
+                match u8::from(value) >= 64 {
+                    true => self.sustained |= bit,
+                    false => self.sustained &= !bit,
+                }
+            }
+            // All Notes Off is also implied by the mode changes that follow it
+            MidiMessage::ControlChange(channel, control, _) if matches!(u8::from(control), ALL_SOUND_OFF | ALL_NOTES_OFF..=POLY_MODE_ON) => {
+                self.channels[u8::from(channel) as usize] = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// The channels with notes sounding or the sustain pedal down, lowest first.
+    pub fn sounding_channels(&self) -> Vec<Channel> {
+        (0..16u8)
+            .filter(|&channel| self.channels[channel as usize] != 0 || self.sustained & (1 << channel) != 0)
+            .map(Channel::from)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.iter().all(|&notes| notes == 0)
+    }
+
+    /// A NoteOff for every sounding note, lowest channel and note first.
+    pub fn note_offs(&self) -> Vec<MidiEvent<'static>> {
+        let notes = self.channels.iter().enumerate().flat_map(|(channel, &notes)| {
+            (0..128u8)
+                .filter(move |&note| notes & (1 << note) != 0)
+                .map(move |note| MidiMessage::NoteOff(Channel::from(channel as u8), Note::from(note), Value7::from(0)))
+        });
+        notes
+            .enumerate()
+            .map(|(i, message)| MidiEvent::new(if i == 0 { None } else { Some(0) }, message.into()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi_types::Control;
+
+    use super::*;
+
+    fn event(message: MidiMessage) -> MidiEvent<'static> {
+        MidiEvent::new(None, message.into())
+    }
+
+    fn note_offs(active_notes: &ActiveNotes) -> Vec<MidiMessage> {
+        let note_offs = active_notes.note_offs();
+        note_offs
+            .iter()
+            .map(|event| match event.command() {
+                RtpMidiMessage::MidiMessage(message) => *message,
+                other => panic!("Expected a MIDI message, got {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_notes_are_tracked_until_they_end() {
+        let mut active_notes = ActiveNotes::default();
+        active_notes.track(&[
+            event(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100))),
+            event(MidiMessage::NoteOn(Channel::C1, Note::from(64), Value7::from(100))),
+            event(MidiMessage::NoteOn(Channel::C10, Note::from(36), Value7::from(100))),
+            event(MidiMessage::NoteOff(Channel::C1, Note::from(64), Value7::from(0))),
+        ]);
+        assert_eq!(
+            note_offs(&active_notes),
+            [
+                MidiMessage::NoteOff(Channel::C1, Note::C4, Value7::from(0)),
+                MidiMessage::NoteOff(Channel::C10, Note::from(36), Value7::from(0)),
+            ]
+        );
+
+        active_notes.track(&[event(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(0)))]);
+        active_notes.track(&[event(MidiMessage::ControlChange(Channel::C10, Control::from(ALL_NOTES_OFF), Value7::from(0)))]);
+        assert!(active_notes.is_empty());
+        assert!(active_notes.note_offs().is_empty());
+    }
+
+    #[test]
+    fn test_sounding_channels_include_sustained_ones() {
+        let mut active_notes = ActiveNotes::default();
+        active_notes.track_message(&MidiMessage::NoteOn(Channel::C3, Note::C4, Value7::from(100)));
+        active_notes.track_message(&MidiMessage::ControlChange(Channel::C2, Control::from(SUSTAIN), Value7::from(127)));
+        assert_eq!(active_notes.sounding_channels(), [Channel::C2, Channel::C3]);
+        active_notes.track_message(&MidiMessage::ControlChange(Channel::C2, Control::from(SUSTAIN), Value7::from(0)));
+        active_notes.track_message(&MidiMessage::NoteOff(Channel::C3, Note::C4, Value7::from(0)));
+        assert!(active_notes.sounding_channels().is_empty());
+    }
+
+    #[test]
+    fn test_highest_note_is_tracked() {
+        let mut active_notes = ActiveNotes::default();
+        active_notes.track(&[event(MidiMessage::NoteOn(Channel::C16, Note::from(127), Value7::from(1)))]);
+        assert_eq!(note_offs(&active_notes), [MidiMessage::NoteOff(Channel::C16, Note::from(127), Value7::from(0))]);
+    }
+}
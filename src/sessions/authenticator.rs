@@ -0,0 +1,62 @@
+use std::fmt;
+use std::time::Duration;
+
+/// How long a peer has to answer our challenge once its handshake is done. One that hasn't by the next clock sync
+/// after this is dropped.
+pub const AUTHENTICATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An extra step in joining a session, for networks that aren't trusted. Once the handshake is done, each side that
+/// has an authenticator sends the other a random nonce in an `AC` packet, an extension of ours rather than part of
+/// AppleMIDI, and the other answers with the proof [`respond`](Authenticator::respond) works out from it, bound to
+/// the two peers and which of them invited the other. Challenges from peers not in a handshake aren't answered.
+/// Until the proof checks out, the peer isn't a participant: its MIDI is ignored and nothing is sent to it. A proof
+/// that doesn't check out, or one that doesn't arrive within [`AUTHENTICATION_TIMEOUT`], ends its session. A
+/// participant that moves to another address has to prove itself again from there.
+///
+/// Both sides need an authenticator with the same secret, so sessions without one can't join. The library doesn't
+/// come with the cryptography; an HMAC of the challenge keyed with a shared secret is the usual choice:
+///
+/// ```
+/// use std::sync::Arc;
+/// use rtpmidi::sessions::authenticator::Authenticator;
+/// use rtpmidi::sessions::session_config::SessionConfig;
+/// # fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> { [key, message].concat() }
+///
+/// struct SharedSecret(Vec<u8>);
+///
+/// impl Authenticator for SharedSecret {
+///     fn respond(&self, challenge: &[u8]) -> Vec<u8> {
+///         hmac_sha256(&self.0, challenge)
+///     }
+/// }
+///
+/// let config = SessionConfig {
+///     authenticator: Some(Arc::new(SharedSecret(b"stage left".to_vec()))),
+///     ..Default::default()
+/// };
+/// ```
+pub trait Authenticator: Send + Sync + 'static {
+    /// The proof of knowing the secret, for a peer that challenged us. `challenge` is the nonce it sent, followed by
+    /// its SSRC and ours, four bytes each and most significant first, then a byte that is 1 if we sent the invitation
+    /// and 0 if it did. A proof is then only good for the handshake it was asked for in, rather than one the peer
+    /// relays it to.
+    fn respond(&self, challenge: &[u8]) -> Vec<u8>;
+
+    /// Whether `proof` is what a peer that knows the secret answers `challenge` with. By default, whether it is what
+    /// [`respond`](Authenticator::respond) works out, compared in constant time.
+    fn verify(&self, challenge: &[u8], proof: &[u8]) -> bool {
+        let expected = self.respond(challenge);
+        expected.len() == proof.len() && expected.iter().zip(proof).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+    }
+}
+
+/// The challenge handed to [`Authenticator::respond`] for `nonce`, sent by `challenger` to `responder`.
+pub(crate) fn challenge(nonce: &[u8], challenger: u32, responder: u32, responder_invited: bool) -> Vec<u8> {
+    [nonce, &challenger.to_be_bytes(), &responder.to_be_bytes(), &[u8::from(responder_invited)]].concat()
+}
+
+impl fmt::Debug for dyn Authenticator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Authenticator")
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::{Mutex, PoisonError};
+
+/// A free list of byte buffers, so that steady-state packet handling reuses allocations instead of making new ones.
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_pooled: usize,
+}
+
+impl BufferPool {
+    pub fn new(max_pooled: usize) -> Self {
+        BufferPool {
+            buffers: Mutex::new(Vec::new()),
+            max_pooled,
+        }
+    }
+
+    /// A zeroed buffer of `len` bytes, allocating only if the pool is empty or its buffer is too small.
+    pub fn take(&self, len: usize) -> Vec<u8> {
+        let mut buffer = self.buffers.lock().unwrap_or_else(PoisonError::into_inner).pop().unwrap_or_default();
+        buffer.resize(len, 0);
+        buffer
+    }
+
+    /// An empty buffer with room for at least `capacity` bytes, for receiving into without zeroing it first.
+    pub fn take_empty(&self, capacity: usize) -> Vec<u8> {
+        let mut buffer = self.buffers.lock().unwrap_or_else(PoisonError::into_inner).pop().unwrap_or_default();
+        buffer.reserve(capacity);
+        buffer
+    }
+
+    /// The bytes of the buffers waiting to be reused.
+    pub fn heap_size(&self) -> usize {
+        self.buffers.lock().unwrap_or_else(PoisonError::into_inner).iter().map(Vec::capacity).sum()
+    }
+
+    /// Returns a buffer to the pool. Buffers beyond the pool's limit are freed.
+    pub fn recycle(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        let mut buffers = self.buffers.lock().unwrap_or_else(PoisonError::into_inner);
+        if buffers.len() < self.max_pooled {
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recycled_buffer_is_reused() {
+        let pool = BufferPool::new(1);
+        let mut buffer = pool.take(3);
+        buffer.copy_from_slice(&[1, 2, 3]);
+        let allocation = buffer.as_ptr();
+        pool.recycle(buffer);
+
+        let buffer = pool.take(2);
+        assert_eq!(buffer, vec![0, 0]);
+        assert_eq!(buffer.as_ptr(), allocation);
+    }
+
+    #[test]
+    fn test_empty_buffers_keep_their_allocation() {
+        let pool = BufferPool::new(1);
+        pool.recycle(Vec::with_capacity(64));
+        let buffer = pool.take_empty(16);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), 64);
+        assert!(pool.take_empty(16).capacity() >= 16);
+    }
+
+    #[test]
+    fn test_pool_is_bounded() {
+        let pool = BufferPool::new(1);
+        pool.recycle(vec![0; 8]);
+        pool.recycle(vec![0; 8]);
+        assert_eq!(pool.buffers.lock().unwrap().len(), 1);
+    }
+}
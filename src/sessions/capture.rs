@@ -0,0 +1,171 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::logging::{Level, event};
+
+/// Raw IP packets, version 4 or 6 going by their first nibble.
+const LINKTYPE_RAW: u32 = 101;
+const UDP: u8 = 17;
+
+/// Writes the datagrams of a session to a pcap file, each wrapped in the IP and UDP headers it would have had on the
+/// wire, so captures can be opened in Wireshark. Written synchronously, as it is for debugging rather than for use in
+/// production.
+pub(crate) struct PacketCapture {
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl PacketCapture {
+    /// Starts a capture in the file at `path`, replacing it if it exists.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::new(Box::new(BufWriter::new(File::create(path)?)))
+    }
+
+    fn new(mut writer: Box<dyn Write + Send>) -> io::Result<Self> {
+        writer.write_all(&0xA1B2_C3D4u32.to_le_bytes())?; // microsecond timestamps, in our byte order
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&4u16.to_le_bytes())?;
+        writer.write_all(&0i32.to_le_bytes())?; // timestamps are UTC
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&(u16::MAX as u32).to_le_bytes())?; // snapshot length
+        writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+        writer.flush()?;
+        Ok(PacketCapture { writer: Mutex::new(writer) })
+    }
+
+    /// Records a datagram sent from `src` to `dst`. Failing to write is logged rather than failing the send or receive.
+    pub fn record(&self, src: SocketAddr, dst: SocketAddr, payload: &[u8]) {
+        let packet = ip_packet(src, dst, payload);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let result = (|| {
+            writer.write_all(&(now.as_secs() as u32).to_le_bytes())?;
+            writer.write_all(&now.subsec_micros().to_le_bytes())?;
+            writer.write_all(&(packet.len() as u32).to_le_bytes())?;
+            writer.write_all(&(packet.len() as u32).to_le_bytes())?;
+            writer.write_all(&packet)?;
+            // So the capture is complete up to the last packet if the process dies
+            writer.flush()
+        })();
+        if let Err(e) = result {
+            event!(Level::WARN, "Failed to write packet capture: {e}");
+        }
+    }
+}
+
+/// The datagram as it would be on the wire. An IPv4 address is mapped to IPv6 if the other one is IPv6.
+fn ip_packet(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let udp_len = (8 + payload.len()) as u16;
+    let mut udp = Vec::with_capacity(udp_len as usize);
+    udp.extend_from_slice(&src.port().to_be_bytes());
+    udp.extend_from_slice(&dst.port().to_be_bytes());
+    udp.extend_from_slice(&udp_len.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]); // checksum, filled in below
+    udp.extend_from_slice(payload);
+
+    let mut packet = Vec::with_capacity(40 + udp.len());
+    let pseudo_header = match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            let mut header = [0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, UDP, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+            header[2..4].copy_from_slice(&(20 + udp_len).to_be_bytes());
+            header[12..16].copy_from_slice(&src.octets());
+            header[16..20].copy_from_slice(&dst.octets());
+            let header_checksum = checksum(&header);
+            header[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+            packet.extend_from_slice(&header);
+            [&src.octets()[..], &dst.octets(), &[0, UDP], &udp_len.to_be_bytes()].concat()
+        }
+        (src, dst) => {
+            let to_v6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            let (src, dst) = (to_v6(src), to_v6(dst));
+            packet.extend_from_slice(&[0x60, 0, 0, 0]);
+            packet.extend_from_slice(&udp_len.to_be_bytes());
+            packet.extend_from_slice(&[UDP, 64]);
+            packet.extend_from_slice(&src.octets());
+            packet.extend_from_slice(&dst.octets());
+            [&src.octets()[..], &dst.octets(), &(udp_len as u32).to_be_bytes(), &[0, 0, 0, UDP]].concat()
+        }
+    };
+    // Zero means there is no checksum, so one that works out to zero is sent as all ones
+    let udp_checksum = match checksum(&[pseudo_header, udp.clone()].concat()) {
+        0 => 0xFFFF,
+        sum => sum,
+    };
+    udp[6..8].copy_from_slice(&udp_checksum.to_be_bytes());
+    packet.extend_from_slice(&udp);
+    packet
+}
+
+/// The Internet checksum (RFC 1071).
+fn checksum(bytes: &[u8]) -> u16 {
+    let mut sum: u32 = bytes
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], pair.get(1).copied().unwrap_or(0)]) as u32)
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// A writer the test can still read from once the capture has it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_records_ipv4_datagrams() {
+        let written = Shared::default();
+        let capture = PacketCapture::new(Box::new(written.clone())).unwrap();
+        capture.record(
+            "10.0.0.1:5004".parse().unwrap(),
+            "10.0.0.2:5005".parse().unwrap(),
+            &[0xFF, 0xFF, b'C', b'K', 0x01],
+        );
+
+        let bytes = written.0.lock().unwrap().clone();
+        assert_eq!(&bytes[..4], &[0xD4, 0xC3, 0xB2, 0xA1]);
+        assert_eq!(u32::from_le_bytes(bytes[20..24].try_into().unwrap()), LINKTYPE_RAW);
+
+        let record = &bytes[24..];
+        assert_eq!(u32::from_le_bytes(record[8..12].try_into().unwrap()), 33);
+        let packet = &record[16..];
+        assert_eq!(packet.len(), 33);
+        assert_eq!(checksum(&packet[..20]), 0, "the IPv4 header checksum should check out");
+        assert_eq!(&packet[12..20], &[10, 0, 0, 1, 10, 0, 0, 2]);
+        assert_eq!(&packet[20..24], &[0x13, 0x8C, 0x13, 0x8D]);
+        assert_eq!(&packet[28..], &[0xFF, 0xFF, b'C', b'K', 0x01]);
+    }
+
+    #[test]
+    fn test_udp_checksum_covers_the_pseudo_header() {
+        let src: SocketAddr = "[::1]:5004".parse().unwrap();
+        let dst: SocketAddr = "127.0.0.1:5005".parse().unwrap();
+        let packet = ip_packet(src, dst, &[1, 2, 3]);
+        assert_eq!(packet[0] >> 4, 6);
+        let udp = &packet[40..];
+        let pseudo_header = [&packet[8..40], &(udp.len() as u32).to_be_bytes(), &[0, 0, 0, UDP]].concat();
+        assert_eq!(checksum(&[&pseudo_header[..], udp].concat()), 0);
+    }
+}
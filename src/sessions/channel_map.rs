@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use midi_types::{Channel, MidiMessage};
+use zerocopy::network_endian::U32;
+
+use crate::packets::midi_packets::midi_event::MidiEvent;
+use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+
+/// Moves channel voice messages from one channel to another, so devices fixed on a channel can share a session.
+/// System messages are left alone.
+///
+/// ```
+/// use midi_types::{Channel, MidiMessage, Note, Value7};
+/// use rtpmidi::sessions::channel_map::ChannelMap;
+///
+/// // A synth that only listens on channel 1 plays what is sent on channel 3
+/// let map = ChannelMap::default().with(Channel::C3, Channel::C1);
+/// let note_on = MidiMessage::NoteOn(Channel::C3, Note::from(60), Value7::from(100));
+/// assert_eq!(map.apply(note_on), MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelMap {
+    channels: [u8; 16], // where each channel goes
+}
+
+impl ChannelMap {
+    /// Leaves every channel where it is.
+    pub const IDENTITY: ChannelMap = ChannelMap {
+        channels: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    };
+
+    /// Moves every channel to `channel`.
+    pub fn all_to(channel: Channel) -> Self {
+        ChannelMap {
+            channels: [u8::from(channel); 16],
+        }
+    }
+
+    /// The map with `from` moved to `to`.
+    pub fn with(mut self, from: Channel, to: Channel) -> Self {
+        self.channels[u8::from(from) as usize] = u8::from(to);
+        self
+    }
+
+    /// Where `channel` goes.
+    pub fn get(&self, channel: Channel) -> Channel {
+        Channel::from(self.channels[u8::from(channel) as usize])
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    pub fn apply(&self, message: MidiMessage) -> MidiMessage {
+        match message {
+            MidiMessage::NoteOff(channel, note, velocity) => MidiMessage::NoteOff(self.get(channel), note, velocity),
+            MidiMessage::NoteOn(channel, note, velocity) => MidiMessage::NoteOn(self.get(channel), note, velocity),
+            MidiMessage::KeyPressure(channel, note, value) => MidiMessage::KeyPressure(self.get(channel), note, value),
+            MidiMessage::ControlChange(channel, control, value) => MidiMessage::ControlChange(self.get(channel), control, value),
+            MidiMessage::ProgramChange(channel, program) => MidiMessage::ProgramChange(self.get(channel), program),
+            MidiMessage::ChannelPressure(channel, value) => MidiMessage::ChannelPressure(self.get(channel), value),
+            MidiMessage::PitchBendChange(channel, value) => MidiMessage::PitchBendChange(self.get(channel), value),
+            other => other,
+        }
+    }
+
+    pub(crate) fn apply_to_event<'a>(&self, event: &MidiEvent<'a>) -> MidiEvent<'a> {
+        match event.command() {
+            RtpMidiMessage::MidiMessage(message) => event.with_command(RtpMidiMessage::MidiMessage(self.apply(*message))),
+            _ => event.clone(),
+        }
+    }
+}
+
+impl Default for ChannelMap {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// The channel maps applied to the MIDI sent to a participant and to the MIDI received from it, before listeners
+/// see it. [`MidiPacketEvent`](crate::sessions::events::event_handling::MidiPacketEvent) listeners still get the
+/// packets as they were received.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelRouting {
+    pub outgoing: ChannelMap,
+    pub incoming: ChannelMap,
+}
+
+/// Shared between the dispatcher, which maps what is received, and the session, which maps what it sends.
+pub(crate) type SharedChannelRoutes = RwLock<ChannelRoutes>;
+
+/// The session's routing, and that of the participants that have their own.
+#[derive(Debug, Default)]
+pub(crate) struct ChannelRoutes {
+    pub default: ChannelRouting,
+    pub participants: HashMap<U32, ChannelRouting>, // keyed by ssrc
+}
+
+impl ChannelRoutes {
+    pub fn new(default: ChannelRouting) -> Self {
+        ChannelRoutes {
+            default,
+            participants: HashMap::new(),
+        }
+    }
+
+    pub fn for_participant(&self, ssrc: U32) -> ChannelRouting {
+        self.participants.get(&ssrc).copied().unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midi_types::{Control, Value7};
+
+    #[test]
+    fn test_only_channel_voice_messages_move() {
+        let map = ChannelMap::all_to(Channel::C10);
+        assert_eq!(
+            map.apply(MidiMessage::ControlChange(Channel::C2, Control::from(7), Value7::from(1))),
+            MidiMessage::ControlChange(Channel::C10, Control::from(7), Value7::from(1))
+        );
+        assert_eq!(map.apply(MidiMessage::TimingClock), MidiMessage::TimingClock);
+        assert!(!map.is_identity());
+        assert!(ChannelMap::default().with(Channel::C4, Channel::C4).is_identity());
+    }
+
+    #[test]
+    fn test_participants_fall_back_to_the_default() {
+        let mut routes = ChannelRoutes::new(ChannelRouting {
+            outgoing: ChannelMap::all_to(Channel::C1),
+            incoming: ChannelMap::IDENTITY,
+        });
+        routes.participants.insert(U32::new(2), ChannelRouting::default());
+        assert_eq!(routes.for_participant(U32::new(1)).outgoing, ChannelMap::all_to(Channel::C1));
+        assert!(routes.for_participant(U32::new(2)).outgoing.is_identity());
+    }
+}
@@ -0,0 +1,240 @@
+//! The quick way to talk to one peer: find it, invite it, wait for it to join, then send and receive, with the
+//! connection kept up in the background.
+//!
+//! ```no_run
+//! use midi_types::{Channel, MidiMessage, Note, Value7};
+//! use rtpmidi::sessions::client::RtpMidiClient;
+//!
+//! # async fn example() -> Result<(), rtpmidi::error::RtpMidiError> {
+//! let mut client = RtpMidiClient::connect("Studio Mac").await?;
+//! client.send(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::new(100))).await?;
+//! while let Some(message) = client.recv().await {
+//!     println!("{message:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use midi_types::MidiMessage;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::error::RtpMidiError;
+use crate::logging::{Level, event};
+use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use crate::sessions::events::event_handling::MidiMessageEvent;
+use crate::sessions::invite_responder::InviteResponder;
+use crate::sessions::random;
+use crate::sessions::rtp_midi_session::{ParticipantMatch, RtpMidiSession};
+use crate::sessions::session_config::SessionConfig;
+use crate::sessions::session_guard::SessionGuard;
+
+/// How an [`RtpMidiClient`] connects. Construct with struct update syntax to override only what you need.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// What the client's session calls itself to the peer.
+    pub name: String,
+    /// The client's control port, with its MIDI port the one above. Any free pair by default.
+    pub port: Option<u16>,
+    /// How long to look for the peer by name, and then how long to wait for it to join. 10 seconds by default.
+    pub connect_timeout: Duration,
+    /// How often to check the peer is still there, inviting it again if it has left. 2 seconds by default. `None`
+    /// leaves a peer that has left gone.
+    pub reconnect_interval: Option<Duration>,
+    /// How many messages from the peer can wait for [`recv`](RtpMidiClient::recv). More are dropped until it catches
+    /// up. 1024 by default.
+    pub queue_capacity: usize,
+    /// The client's session. Initiator-only by default, so nobody else can join it.
+    pub config: SessionConfig,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            name: "RTP-MIDI Client".to_owned(),
+            port: None,
+            connect_timeout: Duration::from_secs(10),
+            reconnect_interval: Some(Duration::from_secs(2)),
+            queue_capacity: 1024,
+            config: SessionConfig {
+                initiator_only: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// A session connected to a single peer. The session is there through [`session`](Self::session) for anything the
+/// client doesn't cover, and is ended, saying goodbye to the peer, when the client is dropped.
+pub struct RtpMidiClient {
+    session: SessionGuard,
+    peer: SocketAddr,
+    messages: mpsc::Receiver<MidiMessage>,
+    reconnect: Option<JoinHandle<()>>,
+}
+
+impl RtpMidiClient {
+    /// Connects to `target` with the default options. The target is the address of the peer's control port, or with
+    /// the `mdns` feature the name it advertises; a string that parses as an address is taken as one.
+    pub async fn connect(target: impl Into<ParticipantMatch>) -> Result<Self, RtpMidiError> {
+        Self::connect_with_options(target, ClientOptions::default()).await
+    }
+
+    /// Finds `target`, starts a session, invites the peer and waits for it to join. Fails with
+    /// [`RtpMidiError::Timeout`] if the peer can't be found or doesn't join in time.
+    pub async fn connect_with_options(target: impl Into<ParticipantMatch>, options: ClientOptions) -> Result<Self, RtpMidiError> {
+        let peer = match target.into() {
+            ParticipantMatch::Addr(addr) => addr,
+            ParticipantMatch::Name(name) => resolve(&name, options.connect_timeout).await?,
+        };
+        let port = options.port.unwrap_or(0);
+        let random = options.config.random.as_deref();
+        random::require(random)?;
+        let ssrc = random::random(random);
+        let session = RtpMidiSession::start_with_config(port, &options.name, ssrc, InviteResponder::Reject, options.config.clone()).await?;
+        let session = SessionGuard::new(session);
+
+        let (sender, messages) = mpsc::channel(options.queue_capacity);
+        session
+            .add_listener(MidiMessageEvent, move |(message, _delta_time)| match sender.try_send(message) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(message)) => event!(Level::WARN, "Client falling behind, dropping {message:?}"),
+                // Once the client is gone, there's nobody to hand it to
+                Err(mpsc::error::TrySendError::Closed(_)) => {}
+            })
+            .await;
+
+        invite(session.session(), peer, options.connect_timeout).await?;
+        event!(Level::INFO, peer = %peer, "Connected");
+
+        let reconnect = options.reconnect_interval.map(|interval| {
+            let session = Arc::clone(session.session());
+            session.spawn(keep_connected(Arc::clone(&session), peer, interval))
+        });
+        Ok(RtpMidiClient {
+            session,
+            peer,
+            messages,
+            reconnect,
+        })
+    }
+
+    /// Sends a message to the peer.
+    pub async fn send<'a>(&self, message: impl Into<RtpMidiMessage<'a>>) -> Result<(), RtpMidiError> {
+        self.session.send_midi(&message.into()).await
+    }
+
+    /// Waits for the next message from the peer. Returns `None` once the session has stopped.
+    pub async fn recv(&mut self) -> Option<MidiMessage> {
+        tokio::select! {
+            message = self.messages.recv() => message,
+            _ = self.session.stopped() => None,
+        }
+    }
+
+    /// Whether the peer is joined right now, rather than between leaving and being invited again.
+    pub async fn is_connected(&self) -> bool {
+        is_joined(&self.session, self.peer).await
+    }
+
+    /// The control port of the peer.
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    pub fn session(&self) -> &Arc<RtpMidiSession> {
+        self.session.session()
+    }
+
+    /// Stops the session gracefully, as [`RtpMidiSession::stop_gracefully`] does.
+    pub async fn close(mut self) {
+        if let Some(reconnect) = self.reconnect.take() {
+            reconnect.abort();
+        }
+        self.session.stop_gracefully().await;
+    }
+}
+
+impl Drop for RtpMidiClient {
+    fn drop(&mut self) {
+        if let Some(reconnect) = &self.reconnect {
+            reconnect.abort();
+        }
+    }
+}
+
+/// Invites the peer and waits for it to finish joining. An invitation that isn't answered in time is withdrawn, so it can be
+/// sent again.
+async fn invite(session: &RtpMidiSession, peer: SocketAddr, timeout: Duration) -> Result<(), RtpMidiError> {
+    session.invite_participant(peer).await?;
+    let result = session.wait_for_participant(peer, timeout).await;
+    if result.is_err() {
+        session.withdraw_invitations(peer).await;
+    }
+    result.map(|_| ())
+}
+
+/// Whether the peer has finished joining, as [`RtpMidiSession::wait_for_participant`] takes it to have.
+async fn is_joined(session: &RtpMidiSession, peer: SocketAddr) -> bool {
+    session
+        .participants()
+        .await
+        .iter()
+        .any(|participant| participant.addr() == peer && participant.is_clock_synced())
+}
+
+async fn keep_connected(session: Arc<RtpMidiSession>, peer: SocketAddr, interval: Duration) {
+    loop {
+        tokio::select! {
+            _ = session.stopped() => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+        if is_joined(&session, peer).await {
+            continue;
+        }
+        event!(Level::INFO, peer = %peer, "Peer has left, inviting it again");
+        if let Err(e) = invite(&session, peer, interval).await {
+            event!(Level::DEBUG, peer = %peer, "Failed to reconnect: {e}");
+        }
+    }
+}
+
+/// Looks for a session advertised as `name` over mDNS, taking the first address it is advertised on.
+#[cfg(feature = "mdns")]
+async fn resolve(name: &str, timeout: Duration) -> Result<SocketAddr, RtpMidiError> {
+    use mdns_sd::{ServiceDaemon, ServiceEvent};
+
+    const SERVICE_TYPE: &str = "_apple-midi._udp.local.";
+    let daemon = ServiceDaemon::new().map_err(std::io::Error::other)?;
+    let events = daemon.browse(SERVICE_TYPE).map_err(std::io::Error::other)?;
+    let found = tokio::time::timeout(timeout, async {
+        while let Ok(event) = events.recv_async().await {
+            if let ServiceEvent::ServiceResolved(info) = event {
+                // Instance names are matched without regard to case, as mDNS does
+                let instance = info.get_fullname().trim_end_matches(SERVICE_TYPE).trim_end_matches('.');
+                let addr = info.get_addresses().iter().next();
+                if let Some(addr) = addr.filter(|_| instance.eq_ignore_ascii_case(name)) {
+                    return Some(SocketAddr::new(*addr, info.get_port()));
+                }
+            }
+        }
+        None
+    })
+    .await;
+    let _ = daemon.shutdown();
+    match found {
+        Ok(Some(addr)) => Ok(addr),
+        _ => Err(RtpMidiError::Timeout("the session to be found over mDNS")),
+    }
+}
+
+#[cfg(not(feature = "mdns"))]
+async fn resolve(name: &str, _timeout: Duration) -> Result<SocketAddr, RtpMidiError> {
+    Err(RtpMidiError::InvalidArgument(format!(
+        "can't look up the session {name:?} without the `mdns` feature, connect to its address instead"
+    )))
+}
@@ -1,26 +1,34 @@
+use super::authenticator;
 use super::invite_responder::InviteResponder;
 use super::rtp_midi_session::RtpMidiSession;
 use super::rtp_port::RtpPort;
+use crate::error::RtpMidiError;
+use crate::logging::{Level, event};
 use crate::packets::control_packets::control_packet::ControlPacket;
+use crate::packets::control_packets::retransmission_request_packet::RetransmissionRequestPacket;
 use crate::packets::control_packets::session_initiation_packet::SessionInitiationPacketBody;
 use crate::participant::Participant;
+use crate::sessions::events::event_dispatcher::QueuedEvent;
+use crate::sessions::events::event_handling::ParticipantRenamed;
+use crate::sessions::flood_guard::Screening;
 use crate::sessions::rtp_midi_session::PendingInvitation;
+use crate::sessions::socket::{Socket, SocketHooks};
+use crate::sessions::stun;
+use bytes::Bytes;
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::UdpSocket;
-use tracing::Level;
-use tracing::event;
-use tracing::instrument;
-use zerocopy::network_endian::U32;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::time::Instant;
+use zerocopy::network_endian::{U16, U32};
 
 pub const MAX_CONTROL_PACKET_SIZE: usize = 1024;
 
 pub(super) struct ControlPort {
-    ssrc: U32,
+    ssrc: Arc<AtomicU32>, // shared by both ports, replaced if a peer turns out to use it
     session_name: CString,
-    socket: Arc<UdpSocket>,
+    socket: Arc<Socket>,
 }
 
 impl RtpPort for ControlPort {
@@ -29,10 +37,10 @@ impl RtpPort for ControlPort {
     }
 
     fn ssrc(&self) -> U32 {
-        self.ssrc
+        U32::new(self.ssrc.load(Ordering::Relaxed))
     }
 
-    fn socket(&self) -> &Arc<UdpSocket> {
+    fn socket(&self) -> &Arc<Socket> {
         &self.socket
     }
 
@@ -42,8 +50,8 @@ impl RtpPort for ControlPort {
 }
 
 impl ControlPort {
-    pub async fn bind(port: u16, name: CString, ssrc: U32) -> std::io::Result<Self> {
-        let socket = Arc::new(UdpSocket::bind((std::net::Ipv4Addr::UNSPECIFIED, port)).await?);
+    pub async fn bind(port: u16, name: CString, ssrc: Arc<AtomicU32>, hooks: SocketHooks) -> Result<Self, RtpMidiError> {
+        let socket = Arc::new(Socket::bind(port, hooks).await?);
 
         Ok(ControlPort {
             session_name: name,
@@ -52,28 +60,45 @@ impl ControlPort {
         })
     }
 
-    #[instrument(skip_all, fields(name = %ctx.name(), addr = %addr))]
-    pub async fn invite_participant(&self, ctx: &RtpMidiSession, addr: SocketAddr) {
-        let initiator_token = U32::new(rand::random::<u32>());
-        let invitation = ControlPacket::new_invitation_as_bytes(initiator_token, self.ssrc, &self.session_name);
-        let result = self.socket.send_to(&invitation, addr).await;
-        if let Err(e) = result {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %ctx.name(), addr = %addr)))]
+    pub async fn invite_participant(&self, ctx: &RtpMidiSession, addr: SocketAddr) -> Result<(), RtpMidiError> {
+        check_invitation_addr(addr)?;
+        let initiator_token = U32::new(ctx.random::<u32>());
+        let invitation = ControlPacket::new_invitation_as_bytes(initiator_token, self.ssrc(), &self.session_name);
+        // Record the invitation before sending it, the acceptance can arrive before send_to returns
+        {
+            let pending_invitations = ctx.pending_invitations.lock().await;
+            let mut sent_invitations = ctx.sent_invitations.lock().await;
+            let midi_addr = SocketAddr::new(addr.ip(), addr.port() + 1);
+            if pending_invitations
+                .values()
+                .chain(sent_invitations.values())
+                .any(|inv| inv.addr == addr || inv.addr == midi_addr)
+            {
+                event!(Level::WARN, "An invitation to this address is already pending");
+                return Err(RtpMidiError::InvalidState("an invitation to this address is already pending"));
+            }
+            sent_invitations.insert(
+                initiator_token,
+                PendingInvitation {
+                    addr,
+                    token: initiator_token,
+                    name: String::new(),
+                    since: Instant::now(),
+                },
+            );
+        }
+        if let Err(e) = self.socket.send_to(&invitation, addr).await {
             event!(Level::ERROR, "Failed to send session invitation: {}", e);
-            return;
+            ctx.sent_invitations.lock().await.remove(&initiator_token);
+            return Err(e.into());
         }
         event!(Level::INFO, "Sent session invitation");
-        ctx.pending_invitations.lock().await.insert(
-            U32::new(0),
-            PendingInvitation {
-                addr,
-                token: initiator_token,
-                name: CString::new("Test Name").unwrap(),
-            },
-        );
+        Ok(())
     }
 
-    #[instrument(skip_all, name = "CTRL", fields(name = %self.session_name.to_string_lossy(), src))]
-    pub async fn start(&self, ctx: &RtpMidiSession, invite_handler: &InviteResponder, buf: &mut [u8; MAX_CONTROL_PACKET_SIZE]) {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, name = "CTRL", fields(name = %self.session_name.to_string_lossy(), src)))]
+    pub async fn start(&self, ctx: &RtpMidiSession, invite_handler: &InviteResponder, buf: &mut [u8]) {
         let recv = self.socket.recv_from(buf).await;
 
         if let Err(e) = recv {
@@ -82,12 +107,26 @@ impl ControlPort {
         }
 
         let (amt, src) = recv.unwrap();
+        #[cfg(feature = "tracing")]
         tracing::Span::current().record("src", src.to_string());
+        if amt == buf.len() {
+            event!(Level::WARN, "Dropping oversized control packet, it exceeds the {} byte limit", buf.len() - 1);
+            return;
+        }
+        if amt == 0 {
+            event!(Level::TRACE, "Received keepalive");
+            return;
+        }
+        if stun::is_stun(&buf[..amt]) {
+            ctx.stun.complete(&buf[..amt], src);
+            return;
+        }
         event!(Level::TRACE, "Received {} bytes", amt);
 
-        let maybe_ctrl_packet = ControlPacket::try_from_bytes(&buf[..amt]);
+        let maybe_ctrl_packet = ControlPacket::try_from_bytes(&buf[..amt], ctx.config.parse_mode);
         if let Err(e) = maybe_ctrl_packet {
             event!(Level::WARN, "Failed to parse control packet: {}", e);
+            ctx.counters.parse_error();
             return;
         }
 
@@ -96,16 +135,38 @@ impl ControlPort {
 
         match packet {
             ControlPacket::Invitation { body, name } => {
-                self.handle_invitation(body, name, invite_handler, ctx, src).await;
+                self.handle_invitation(body, &name, invite_handler, ctx, src).await;
             }
             ControlPacket::Acceptance { body, name } => {
-                self.handle_acceptance(body, name, ctx, src).await;
+                self.handle_acceptance(body, &name, ctx, src).await;
             }
             ControlPacket::Rejection(body) => {
                 self.handle_rejection(body, ctx, src).await;
             }
             ControlPacket::Termination(body) => {
-                self.handle_termination(body.sender_ssrc, src, &ctx.participants).await;
+                let is_sender = ctx
+                    .participants
+                    .read()
+                    .await
+                    .get(&body.sender_ssrc)
+                    .is_some_and(|participant| participant.addr() == src);
+                if is_sender {
+                    ctx.release_active_notes(body.sender_ssrc).await;
+                }
+                self.handle_termination(body.sender_ssrc, src, &ctx.participants, &ctx.events).await;
+                ctx.abandon_authentication(body.sender_ssrc, src).await;
+            }
+            ControlPacket::BitrateLimit(packet) => {
+                self.handle_bitrate_limit(packet, src, &ctx.participants).await;
+            }
+            ControlPacket::AuthenticationChallenge { body, nonce } => {
+                self.answer_authentication_challenge(body.sender_ssrc, nonce, ctx, src).await;
+            }
+            ControlPacket::AuthenticationResponse { body, proof } => {
+                ctx.complete_authentication(body.sender_ssrc, proof, src).await;
+            }
+            ControlPacket::RetransmissionRequest(request) => {
+                self.handle_retransmission_request(request, ctx, src).await;
             }
             _ => {
                 event!(Level::WARN, packet = std::format!("{:?}", packet), "Control: Unhandled control packet");
@@ -113,16 +174,130 @@ impl ControlPort {
         }
     }
 
-    #[instrument(skip_all)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub(super) async fn send_authentication_challenge(&self, nonce: &[u8], addr: SocketAddr) {
+        let packet = ControlPacket::new_authentication_challenge_as_bytes(nonce, self.ssrc());
+        if let Err(e) = self.socket.send_to(&packet, addr).await {
+            event!(Level::WARN, addr = %addr, "Failed to send authentication challenge: {e}");
+        }
+    }
+
+    pub(super) async fn send_retransmission_request(&self, first: u16, count: u16, addr: SocketAddr) {
+        let packet = ControlPacket::new_retransmission_request_as_bytes(U16::new(first), U16::new(count), self.ssrc());
+        if let Err(e) = self.socket.send_to(&packet, addr).await {
+            event!(Level::WARN, addr = %addr, "Failed to send retransmission request: {e}");
+        } else {
+            event!(Level::DEBUG, addr = %addr, first, count, "Asked for MIDI packets to be sent again");
+        }
+    }
+
+    /// Sends a participant the MIDI packets it missed again, as many of them as are still kept.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(src = %src, first = request.sequence_number.get(), count = request.count.get())))]
+    async fn handle_retransmission_request(&self, request: &RetransmissionRequestPacket, ctx: &RtpMidiSession, src: SocketAddr) {
+        if ctx.config.retransmission.is_none() {
+            event!(Level::DEBUG, "Ignoring retransmission request, the session doesn't send packets again");
+            return;
+        }
+        let (datagrams, midi_addr): (Vec<Bytes>, _) = match ctx.participants.read().await.get(&request.sender_ssrc) {
+            Some(participant) if participant.addr() == src => (
+                participant.transmission().retransmission.requested(request).cloned().collect(),
+                participant.midi_port_addr(),
+            ),
+            _ => {
+                event!(Level::WARN, "Received retransmission request but no matching participant found");
+                return;
+            }
+        };
+        if datagrams.is_empty() {
+            event!(Level::DEBUG, "None of the packets asked for are kept any more");
+            return;
+        }
+        ctx.midi_port.resend(ctx, &datagrams, midi_addr).await;
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(ssrc = ssrc.get(), src = %src)))]
+    async fn answer_authentication_challenge(&self, ssrc: U32, nonce: &[u8], ctx: &RtpMidiSession, src: SocketAddr) {
+        let Some(authenticator) = &ctx.config.authenticator else {
+            event!(Level::WARN, "Can't answer authentication challenge, the session has no authenticator");
+            return;
+        };
+        // A peer sending back the nonce we challenged it with would get us to work out its proof for it
+        if ctx.pending_authentications.lock().await.values().any(|pending| pending.nonce == nonce) {
+            event!(Level::WARN, "Not answering an authentication challenge with our own nonce");
+            return;
+        }
+        // Anyone else could have us work out proofs for handshakes of their own
+        let Some(invited_by_us) = ctx.joining_by_invitation(ssrc, src).await else {
+            event!(Level::WARN, "Not answering an authentication challenge from a peer we aren't joining with");
+            return;
+        };
+        let challenge = authenticator::challenge(nonce, ssrc.get(), self.ssrc().get(), invited_by_us);
+        let packet = ControlPacket::new_authentication_response_as_bytes(&authenticator.respond(&challenge), self.ssrc());
+        if let Err(e) = self.socket.send_to(&packet, src).await {
+            event!(Level::WARN, "Failed to send authentication response: {e}");
+        } else {
+            event!(Level::DEBUG, "Answered authentication challenge");
+        }
+    }
+
     async fn handle_invitation(
         &self,
         invitation: &SessionInitiationPacketBody,
-        inviter_name: &CStr,
+        inviter_name: &str,
         invite_handler: &InviteResponder,
         ctx: &RtpMidiSession,
         src: SocketAddr,
     ) {
         event!(Level::INFO, token = invitation.initiator_token.get(), "Received session invitation");
+        match ctx.screen_invitation(src).await {
+            Screening::Admit => {}
+            Screening::Reject => {
+                self.send_invitation_rejection(invitation.initiator_token, src).await;
+                return;
+            }
+            Screening::Ignore => return,
+        }
+        if ctx.config.initiator_only {
+            event!(Level::INFO, "Rejecting session invitation, the session only invites others");
+            self.send_invitation_rejection(invitation.initiator_token, src).await;
+            return;
+        }
+        if !self.check_protocol_version(invitation, src, &ctx.events).await {
+            self.send_invitation_rejection(invitation.initiator_token, src).await;
+            return;
+        }
+
+        if invitation.sender_ssrc == self.ssrc() {
+            if !ctx.is_collision_credible(src).await {
+                event!(Level::WARN, "Rejecting session invitation, it uses our own SSRC");
+                self.send_invitation_rejection(invitation.initiator_token, src).await;
+                return;
+            }
+            event!(Level::WARN, "Peer invited us using our own SSRC");
+            ctx.resolve_ssrc_collision(invitation.sender_ssrc).await;
+        }
+        if self.follow_address_change(invitation, inviter_name, ctx, src).await {
+            return;
+        }
+        if is_ssrc_taken(invitation.sender_ssrc, src, ctx).await {
+            // The newcomer is the one that has to pick another SSRC, the participant keeps its session
+            event!(Level::WARN, "Rejecting session invitation, its SSRC belongs to another peer");
+            self.send_invitation_rejection(invitation.initiator_token, src).await;
+            return;
+        }
+
+        if self.accept_reinvitation(invitation, inviter_name, ctx, src).await {
+            return;
+        }
+        if ctx.config.rtpmidi_quirks && self.answer_repeated_invitation(invitation, ctx, src).await {
+            return;
+        }
+
+        if !ctx.has_room_for(invitation.sender_ssrc, inviter_name, src).await {
+            self.send_invitation_rejection(invitation.initiator_token, src).await;
+            return;
+        }
+
         let accept = invite_handler.handle(invitation, inviter_name, &src);
         if accept {
             event!(Level::INFO, "Accepted session invitation");
@@ -132,48 +307,124 @@ impl ControlPort {
                     addr: src,
                     token: invitation.initiator_token,
                     name: inviter_name.to_owned(),
+                    since: Instant::now(),
                 },
             );
+            ctx.events
+                .push(QueuedEvent::ParticipantRenamed(ParticipantRenamed::learned(
+                    invitation.sender_ssrc.get(),
+                    inviter_name,
+                )))
+                .await;
             self.send_invitation_acceptance(invitation.initiator_token, src).await;
         } else {
             event!(Level::INFO, "Rejected session initiation");
-            let rejection_packet = ControlPacket::new_rejection_as_bytes(invitation.initiator_token, self.ssrc);
-            let result = self.socket.send_to(&rejection_packet, src).await;
-            if let Err(e) = result {
-                event!(Level::ERROR, "Failed to send session rejection: {}", e);
-            } else {
-                event!(Level::DEBUG, "Sent session rejection");
+            self.send_invitation_rejection(invitation.initiator_token, src).await;
+        }
+    }
+
+    /// A participant that invites us again has restarted or renamed its session. Its entry is brought up to date under
+    /// the new SSRC, token and name instead of being left behind as a stale duplicate, and the invite handler isn't
+    /// asked again. The MIDI port invitation that follows is expected as usual.
+    async fn accept_reinvitation(&self, invitation: &SessionInitiationPacketBody, inviter_name: &str, ctx: &RtpMidiSession, src: SocketAddr) -> bool {
+        let mut participants = ctx.participants.write().await;
+        let Some(old_ssrc) = participants.values().find(|participant| participant.addr() == src).map(Participant::ssrc) else {
+            return false;
+        };
+        let mut rename = None;
+        if let Some(mut participant) = participants.remove(&old_ssrc) {
+            event!(
+                Level::INFO,
+                old_name = participant.name(),
+                old_ssrc = old_ssrc.get(),
+                "Participant invited us again"
+            );
+            rename = ParticipantRenamed::between(&participant, invitation.sender_ssrc.get(), inviter_name);
+            participant.reinvited(invitation.initiator_token, inviter_name, invitation.sender_ssrc);
+            participants.insert(invitation.sender_ssrc, participant);
+        }
+        drop(participants);
+        // A restarted session starts its sequence numbers, notes and SysEx over
+        ctx.events.push(QueuedEvent::SsrcRetired(old_ssrc)).await;
+        if let Some(rename) = rename {
+            ctx.events.push(QueuedEvent::ParticipantRenamed(rename)).await;
+        }
+
+        ctx.pending_invitations.lock().await.insert(
+            invitation.sender_ssrc,
+            PendingInvitation {
+                addr: src,
+                token: invitation.initiator_token,
+                name: inviter_name.to_owned(),
+                since: Instant::now(),
+            },
+        );
+        self.send_invitation_acceptance(invitation.initiator_token, src).await;
+        true
+    }
+
+    /// A participant whose address has changed invites us again from the new one, under its SSRC and the token it
+    /// joined with. It is moved rather than turned away for using another peer's SSRC, and the MIDI port invitation
+    /// that follows is expected as usual.
+    async fn follow_address_change(&self, invitation: &SessionInitiationPacketBody, inviter_name: &str, ctx: &RtpMidiSession, src: SocketAddr) -> bool {
+        if !ctx.move_participant(invitation.sender_ssrc, invitation.initiator_token, src).await {
+            return false;
+        }
+        ctx.pending_invitations.lock().await.insert(
+            invitation.sender_ssrc,
+            PendingInvitation {
+                addr: src,
+                token: invitation.initiator_token,
+                name: inviter_name.to_owned(),
+                since: Instant::now(),
+            },
+        );
+        self.send_invitation_acceptance(invitation.initiator_token, src).await;
+        true
+    }
+
+    /// rtpMIDI repeats an invitation until it sees the answer, with a new token each time. If this one comes from a
+    /// peer that was already accepted, it is answered under the new token without asking the invite handler again.
+    async fn answer_repeated_invitation(&self, invitation: &SessionInitiationPacketBody, ctx: &RtpMidiSession, src: SocketAddr) -> bool {
+        match ctx.pending_invitations.lock().await.get_mut(&invitation.sender_ssrc) {
+            Some(pending) if pending.addr == src => {
+                pending.token = invitation.initiator_token;
+                pending.since = Instant::now();
             }
+            _ => return false,
         }
+
+        event!(Level::DEBUG, "Answering repeated session invitation");
+        self.send_invitation_acceptance(invitation.initiator_token, src).await;
+        true
     }
 
-    #[instrument(skip_all, fields(token = rejection.initiator_token.get()))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(token = rejection.initiator_token.get())))]
     async fn handle_rejection(&self, rejection: &SessionInitiationPacketBody, ctx: &RtpMidiSession, src: SocketAddr) {
         event!(Level::INFO, "Received session rejection");
         let _ = self.remove_invitation(rejection, ctx, src).await;
     }
 
-    #[instrument(skip_all)]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn remove_invitation(&self, invitation_response: &SessionInitiationPacketBody, ctx: &RtpMidiSession, src: SocketAddr) -> Option<PendingInvitation> {
         event!(Level::DEBUG, "Removing invitation for SSRC {} at {}", invitation_response.sender_ssrc, src);
-        let mut locked_pending_invitations = ctx.pending_invitations.lock().await;
-        if locked_pending_invitations.contains_key(&invitation_response.sender_ssrc) {
-            locked_pending_invitations.remove(&invitation_response.sender_ssrc)
-        } else if !locked_pending_invitations.contains_key(&invitation_response.sender_ssrc)
-            && locked_pending_invitations.contains_key(&U32::ZERO)
-            && locked_pending_invitations[&U32::ZERO].token == invitation_response.initiator_token
-            && locked_pending_invitations[&U32::ZERO].addr == src
-        {
-            locked_pending_invitations.remove(&U32::ZERO)
-        } else {
-            None
+        if let Some(invitation) = ctx.pending_invitations.lock().await.remove(&invitation_response.sender_ssrc) {
+            return Some(invitation);
+        }
+        let mut sent_invitations = ctx.sent_invitations.lock().await;
+        match sent_invitations.get(&invitation_response.initiator_token) {
+            Some(sent) if sent.addr == src => sent_invitations.remove(&invitation_response.initiator_token),
+            _ => None,
         }
     }
 
-    #[instrument(skip_all, fields(ssrc = ack_body.sender_ssrc.get(), src = %src))]
-    async fn handle_acceptance(&self, ack_body: &SessionInitiationPacketBody, name: &CStr, ctx: &RtpMidiSession, src: SocketAddr) {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(ssrc = ack_body.sender_ssrc.get(), src = %src)))]
+    async fn handle_acceptance(&self, ack_body: &SessionInitiationPacketBody, name: &str, ctx: &RtpMidiSession, src: SocketAddr) {
         event!(Level::INFO, "Received session acknowledgment");
         let inv = self.remove_invitation(ack_body, ctx, src).await;
+        if !self.check_protocol_version(ack_body, src, &ctx.events).await {
+            return;
+        }
         if inv.is_none() {
             event!(Level::WARN, "Received Acknowledgment but no matching invitation found");
             return;
@@ -191,6 +442,23 @@ impl ControlPort {
             return;
         }
 
+        if ack_body.sender_ssrc == self.ssrc() {
+            event!(Level::WARN, "Invited peer uses our own SSRC");
+            ctx.resolve_ssrc_collision(ack_body.sender_ssrc).await;
+            if let Err(e) = ctx.invite_participant(inv.addr).await {
+                event!(Level::WARN, "Failed to invite peer again after changing SSRC: {e}");
+            }
+            return;
+        }
+        if is_ssrc_taken(ack_body.sender_ssrc, src, ctx).await {
+            event!(Level::WARN, "Abandoning session, the invited peer's SSRC belongs to another peer");
+            let termination = ControlPacket::new_termination_as_bytes(ack_body.initiator_token, self.ssrc());
+            if let Err(e) = self.socket.send_to(&termination, src).await {
+                event!(Level::WARN, "Failed to send termination packet: {e}");
+            }
+            return;
+        }
+
         event!(
             Level::DEBUG,
             "Matched Acknowledgment from {} invitation. Sending MIDI port invitation.",
@@ -200,19 +468,41 @@ impl ControlPort {
         let midi_addr = SocketAddr::new(inv.addr.ip(), inv.addr.port() + 1);
 
         // Generate a new token specifically for the MIDI port invitation
-        let midi_token = U32::new(rand::random::<u32>());
+        let midi_token = U32::new(ctx.random::<u32>());
 
-        let mut lock = ctx.pending_invitations.lock().await;
-        lock.insert(
+        ctx.pending_invitations.lock().await.insert(
             ack_body.sender_ssrc,
             PendingInvitation {
                 addr: midi_addr,
                 token: midi_token,
                 name: name.to_owned(),
+                since: Instant::now(),
             },
         );
+        ctx.events
+            .push(QueuedEvent::ParticipantRenamed(ParticipantRenamed::learned(ack_body.sender_ssrc.get(), name)))
+            .await;
 
-        let response_packet = ControlPacket::new_invitation_as_bytes(midi_token, self.ssrc, self.session_name.as_ref());
+        let response_packet = ControlPacket::new_invitation_as_bytes(midi_token, self.ssrc(), self.session_name.as_ref());
         ctx.midi_port.send_invitation(&response_packet, midi_addr).await;
     }
 }
+
+/// Whether a peer other than the one at `src` (on either of its ports) already uses `ssrc`.
+async fn is_ssrc_taken(ssrc: U32, src: SocketAddr, ctx: &RtpMidiSession) -> bool {
+    let is_same_peer = |addr: SocketAddr| addr.ip() == src.ip() && (addr.port() == src.port() || addr.port() == src.port().wrapping_add(1));
+    let participant_addr = ctx.participants.read().await.get(&ssrc).map(Participant::addr);
+    let pending_addr = ctx.pending_invitations.lock().await.get(&ssrc).map(|pending| pending.addr);
+    participant_addr.into_iter().chain(pending_addr).any(|addr| !is_same_peer(addr))
+}
+
+/// Rejects addresses no peer can be invited at. The MIDI port is the one after the control port, so that has to exist too.
+fn check_invitation_addr(addr: SocketAddr) -> Result<(), RtpMidiError> {
+    if addr.ip().is_unspecified() || addr.ip().is_multicast() {
+        return Err(RtpMidiError::InvalidArgument(format!("{} is not a peer address", addr.ip())));
+    }
+    if addr.port() == 0 || addr.port() == u16::MAX {
+        return Err(RtpMidiError::InvalidArgument(format!("{} can't be used as a control port", addr.port())));
+    }
+    Ok(())
+}
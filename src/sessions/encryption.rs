@@ -0,0 +1,45 @@
+/// Seals and opens every datagram of a session, for tunnelling sessions across networks that aren't trusted. Both
+/// ends need the same cipher, so this only works between sessions of this library; a session with a cipher drops
+/// every datagram that doesn't open, and cleartext AppleMIDI peers can't join it. Capture, hexdumps and the wire tap
+/// see the datagrams as they are before sealing and after opening.
+///
+/// The library doesn't come with the cryptography. Use an AEAD such as ChaCha20-Poly1305 from a crate you trust,
+/// with a fresh nonce for every datagram sent along with it, and authenticate the datagram so tampered ones fail to
+/// open:
+///
+/// ```
+/// use std::sync::Arc;
+/// use rtpmidi::sessions::encryption::Cipher;
+/// use rtpmidi::sessions::session_config::SessionConfig;
+/// # fn seal(key: &[u8; 32], plaintext: &[u8]) -> Vec<u8> { plaintext.to_vec() }
+/// # fn open(key: &[u8; 32], sealed: &[u8]) -> Option<Vec<u8>> { Some(sealed.to_vec()) }
+///
+/// struct Tunnel([u8; 32]);
+///
+/// impl Cipher for Tunnel {
+///     fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+///         seal(&self.0, plaintext)
+///     }
+///
+///     fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+///         open(&self.0, sealed)
+///     }
+/// }
+///
+/// let config = SessionConfig {
+///     encryption: Some(Arc::new(Tunnel([7; 32]))),
+///     ..Default::default()
+/// };
+/// ```
+pub trait Cipher: Send + Sync + 'static {
+    fn seal(&self, plaintext: &[u8]) -> Vec<u8>;
+
+    /// The plaintext, or `None` if the datagram wasn't sealed with the same key or has been tampered with.
+    fn open(&self, sealed: &[u8]) -> Option<Vec<u8>>;
+}
+
+impl std::fmt::Debug for dyn Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cipher")
+    }
+}
@@ -0,0 +1,1200 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::Instant;
+
+use crate::logging::{Level, event};
+use midi_types::MidiMessage;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use zerocopy::FromBytes;
+use zerocopy::network_endian::U32;
+
+use crate::packets::midi_packets::controller_change::ControllerCombiner;
+use crate::packets::midi_packets::midi_packet::{FirstDeltaTime, MidiPacket};
+use crate::packets::midi_packets::rtp_midi_message::{RtpMidiMessage, SysExSegment};
+use crate::packets::midi_packets::sysex::SysExMessage;
+use crate::packets::midi_packets::timecode::{QuarterFrameAssembler, TimecodePosition};
+use crate::packets::midi_packets::transport::TransportFollower;
+use crate::packets::parse_mode::ParseMode;
+use crate::participant::Participant;
+use crate::sessions::active_notes::ActiveNotes;
+use crate::sessions::buffer_pool::BufferPool;
+use crate::sessions::channel_map::SharedChannelRoutes;
+use crate::sessions::events::event_handling::{
+    AddressChanged, AuthenticationFailed, ClockSyncRound, EventListeners, InvitationFlood, ListenerRegistry, PacketLossThresholdCrossed,
+    ParticipantLimitReached, ParticipantRenamed, ProtocolVersionMismatch, SysExTooLarge, TempoChange, TimecodeUpdate, TransportUpdate,
+};
+use crate::sessions::events::reorder_buffer::ReorderBuffer;
+use crate::sessions::events::tempo_estimator::TempoEstimators;
+use crate::sessions::interceptor::{Direction, InterceptorChain, Interceptors};
+use crate::sessions::loss_concealment::{LossConcealment, PacketLoss};
+use crate::sessions::session_config::SessionConfig;
+use crate::sessions::stats::map_heap_size;
+
+/// The default for [`SessionConfig::max_sysex_size`].
+pub const MAX_SYSEX_SIZE: usize = 1024 * 1024;
+
+/// The default number of events that can be waiting for the dispatcher before [`SessionConfig::event_queue_overflow`]
+/// applies.
+pub const DEFAULT_EVENT_QUEUE_CAPACITY: usize = 1024;
+
+/// What the socket loops do with an event when listeners have fallen so far behind that the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueOverflow {
+    /// Drop it and count it in [`SessionStats::events_dropped`](crate::sessions::stats::SessionStats::events_dropped),
+    /// so the sockets keep being read and clock syncs and keepalives keep being answered. Only received MIDI packets
+    /// are dropped: participants joining and leaving and the session's other events are queued regardless, so
+    /// listeners never miss them.
+    #[default]
+    Drop,
+    /// Wait for room, so listeners see every event. Reading the sockets stops meanwhile, clock syncs included, so a
+    /// listener that stays slow for long enough gets the session dropped by its peers.
+    Wait,
+}
+
+/// An event waiting to be handed to listeners. It owns everything the listeners get to borrow.
+#[derive(Debug)]
+pub(crate) enum QueuedEvent {
+    /// A received MIDI packet, handed over together with the buffer it was received into. The dispatcher parses
+    /// its commands in place, so listeners borrow straight from the datagram. The participant that sent it comes
+    /// along if there are interceptors to hand it to.
+    MidiPacket(Vec<u8>, Option<Participant>),
+    /// A MIDI packet that was asked for again, with retransmission on. It is handed on as soon as it arrives, to
+    /// [`RecoveredMidiEvent`](super::event_handling::RecoveredMidiEvent) listeners.
+    RecoveredMidiPacket(Vec<u8>, Option<Participant>),
+    /// MIDI packets from a participant that are lost for good, for the session's loss concealment to make up for.
+    PacketsLost {
+        ssrc: U32,
+        missing: u16,
+        /// That of the packet after them.
+        timestamp: u32,
+    },
+    ParticipantJoined(Participant),
+    ParticipantLeft(Participant),
+    /// An SSRC no participant goes by any more, because it was removed or restarted its session, so what the
+    /// dispatcher keeps for it can go. Listeners aren't told.
+    SsrcRetired(U32),
+    ProtocolVersionMismatch(ProtocolVersionMismatch),
+    ParticipantLimitReached(ParticipantLimitReached),
+    InvitationFlood(InvitationFlood),
+    AuthenticationFailed(AuthenticationFailed),
+    AddressChanged(AddressChanged),
+    ParticipantRenamed(ParticipantRenamed),
+    ClockSyncRound(ClockSyncRound),
+    PacketLossThreshold(PacketLossThresholdCrossed),
+}
+
+/// A datagram held back by the reorder buffer, with the participant that sent it.
+type ReceivedDatagram = (Vec<u8>, Option<Participant>);
+
+/// A queued event, with its place among the queue's capacity unless it was let past a full queue. The place is given
+/// back as the dispatcher takes the event.
+type QueueEntry = (QueuedEvent, Option<OwnedSemaphorePermit>);
+
+/// The sending half of the queue between the socket loops and the dispatcher task. The channel itself is unbounded,
+/// its capacity kept by `room`, so that events other than MIDI packets can always be queued in the order they happen.
+#[derive(Clone)]
+pub(crate) struct EventQueue {
+    sender: mpsc::UnboundedSender<QueueEntry>,
+    room: Arc<Semaphore>,
+    overflow: QueueOverflow,
+    dropped: Arc<AtomicU64>,
+    pool: Arc<BufferPool>,
+    memory: Arc<QueueMemory>,
+}
+
+/// The receiving half of the queue, consumed by [`dispatch_events`].
+pub(crate) struct QueuedEvents {
+    receiver: mpsc::UnboundedReceiver<QueueEntry>,
+    pool: Arc<BufferPool>,
+    memory: Arc<QueueMemory>,
+}
+
+/// The events in the queue, the bytes of their datagrams, of those the dispatcher holds on to between events, and of
+/// what it follows of each sender, kept up to date for [`EventQueue::memory_usage`] as they can't be looked at from
+/// outside the dispatcher task.
+#[derive(Default)]
+struct QueueMemory {
+    events: AtomicUsize,
+    queued: AtomicUsize,
+    held: AtomicUsize,
+    followed: AtomicUsize,
+}
+
+impl EventQueue {
+    pub fn channel(capacity: usize, overflow: QueueOverflow) -> (Self, QueuedEvents) {
+        let capacity = capacity.max(1);
+        let (sender, receiver) = mpsc::unbounded_channel();
+        // Every queued event holds at most one buffer, so the pool never needs more than the queue can hold
+        let pool = Arc::new(BufferPool::new(capacity));
+        let memory = Arc::new(QueueMemory::default());
+        (
+            EventQueue {
+                sender,
+                room: Arc::new(Semaphore::new(capacity)),
+                overflow,
+                dropped: Arc::default(),
+                pool: Arc::clone(&pool),
+                memory: Arc::clone(&memory),
+            },
+            QueuedEvents { receiver, pool, memory },
+        )
+    }
+
+    /// An empty buffer with room for `capacity` bytes to receive into, reusing one the dispatcher has finished with
+    /// if it can.
+    pub fn receive_buffer(&self, capacity: usize) -> Vec<u8> {
+        self.pool.take_empty(capacity)
+    }
+
+    /// Queues an event for the dispatcher. If the queue is full, it waits for room as the session's [`QueueOverflow`]
+    /// says, or a MIDI packet is dropped. Other events are let past a full queue.
+    pub async fn push(&self, queued_event: QueuedEvent) {
+        let permit = match self.overflow {
+            // The semaphore is never closed
+            QueueOverflow::Wait => Arc::clone(&self.room).acquire_owned().await.ok(),
+            QueueOverflow::Drop => Arc::clone(&self.room).try_acquire_owned().ok(),
+        };
+        let is_midi = matches!(queued_event, QueuedEvent::MidiPacket(..) | QueuedEvent::RecoveredMidiPacket(..));
+        let unsent = if permit.is_none() && is_midi {
+            event!(Level::WARN, "Listeners have fallen behind and the event queue is full, dropping MIDI packet");
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            Some(queued_event)
+        } else {
+            let size = queued_event.heap_size();
+            self.memory.events.fetch_add(1, Ordering::Relaxed);
+            self.memory.queued.fetch_add(size, Ordering::Relaxed);
+            self.sender.send((queued_event, permit)).err().map(|error| {
+                event!(Level::DEBUG, "Event dispatcher has stopped, dropping event");
+                self.memory.events.fetch_sub(1, Ordering::Relaxed);
+                self.memory.queued.fetch_sub(size, Ordering::Relaxed);
+                error.0.0
+            })
+        };
+        if let Some(QueuedEvent::MidiPacket(bytes, _) | QueuedEvent::RecoveredMidiPacket(bytes, _)) = unsent {
+            self.pool.recycle(bytes);
+        }
+    }
+
+    /// How many events have been dropped for want of room in the queue.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Roughly the bytes held by the events waiting in the queue and the buffers kept for reuse, by what the
+    /// dispatcher holds on to between events: packets in the reorder window and SysEx being put back together, and by
+    /// what it follows of each sender.
+    pub fn memory_usage(&self) -> (usize, usize, usize) {
+        let queued = self.memory.events.load(Ordering::Relaxed) * size_of::<QueueEntry>();
+        (
+            queued + self.memory.queued.load(Ordering::Relaxed) + self.pool.heap_size(),
+            self.memory.held.load(Ordering::Relaxed),
+            self.memory.followed.load(Ordering::Relaxed),
+        )
+    }
+}
+
+impl QueuedEvent {
+    /// The bytes of the datagram the event carries, if it does.
+    fn heap_size(&self) -> usize {
+        match self {
+            QueuedEvent::MidiPacket(bytes, _) | QueuedEvent::RecoveredMidiPacket(bytes, _) => bytes.capacity(),
+            _ => 0,
+        }
+    }
+}
+
+/// Hands queued events to the listeners in the order they were queued, until every sender is gone. With a reorder
+/// window, MIDI packets that arrive ahead of their turn are held back until the ones before them have been handed on.
+/// The tempo of each participant's MIDI clock is followed in `tempos`, and what it sends has its channels mapped by
+/// `channel_routes` and goes through the `interceptors`.
+pub(crate) async fn dispatch_events(
+    queued_events: QueuedEvents,
+    registry: Arc<ListenerRegistry>,
+    config: Arc<SessionConfig>,
+    tempos: Arc<TempoEstimators>,
+    channel_routes: Arc<SharedChannelRoutes>,
+    interceptors: Arc<InterceptorChain>,
+) {
+    let QueuedEvents { mut receiver, pool, memory } = queued_events;
+    let mut dispatcher = Dispatcher {
+        registry,
+        pool,
+        mode: config.parse_mode,
+        first_delta_time: config.first_delta_time,
+        max_sysex_size: config.max_sysex_size,
+        tempos,
+        channel_routes,
+        interceptors,
+        reorder_buffer: config.reorder_window.map(ReorderBuffer::new),
+        loss_concealment: config.loss_concealment.clone(),
+        sounding_notes: HashMap::new(),
+        sysex_buffers: HashMap::new(),
+        controller_combiners: HashMap::new(),
+        quarter_frames: HashMap::new(),
+        transports: HashMap::new(),
+    };
+    loop {
+        let deadline = dispatcher.reorder_buffer.as_ref().and_then(ReorderBuffer::next_deadline);
+        tokio::select! {
+            queued_event = receiver.recv() => match queued_event {
+                Some((queued_event, permit)) => {
+                    drop(permit);
+                    memory.events.fetch_sub(1, Ordering::Relaxed);
+                    memory.queued.fetch_sub(queued_event.heap_size(), Ordering::Relaxed);
+                    dispatcher.dispatch(queued_event);
+                }
+                None => break,
+            },
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {
+                dispatcher.dispatch_reordered(|reorder_buffer| reorder_buffer.release_overdue(Instant::now()));
+            }
+        }
+        memory.held.store(dispatcher.heap_size(), Ordering::Relaxed);
+        memory.followed.store(dispatcher.followed_size(), Ordering::Relaxed);
+    }
+    dispatcher.dispatch_reordered(ReorderBuffer::flush);
+}
+
+struct Dispatcher {
+    registry: Arc<ListenerRegistry>,
+    pool: Arc<BufferPool>,
+    mode: ParseMode,
+    first_delta_time: FirstDeltaTime,
+    max_sysex_size: usize,
+    tempos: Arc<TempoEstimators>,
+    channel_routes: Arc<SharedChannelRoutes>,
+    interceptors: Arc<InterceptorChain>,
+    reorder_buffer: Option<ReorderBuffer<ReceivedDatagram>>,
+    loss_concealment: Option<Arc<dyn LossConcealment>>,
+    sounding_notes: HashMap<U32, ActiveNotes>,              // received, for loss concealment, keyed by sender ssrc
+    sysex_buffers: HashMap<U32, PartialSysEx>,              // in-progress segmented SysEx, keyed by sender ssrc
+    controller_combiners: HashMap<U32, ControllerCombiner>, // keyed by sender ssrc
+    quarter_frames: HashMap<U32, QuarterFrameAssembler>,    // keyed by sender ssrc
+    transports: HashMap<U32, TransportFollower>,            // keyed by sender ssrc
+}
+
+impl Dispatcher {
+    /// The bytes of the datagrams held back in the reorder window and the SysEx messages being put back together.
+    fn heap_size(&self) -> usize {
+        let reordered: usize = self
+            .reorder_buffer
+            .iter()
+            .flat_map(ReorderBuffer::held)
+            .map(|(bytes, _)| bytes.capacity())
+            .sum();
+        let sysex: usize = self
+            .sysex_buffers
+            .values()
+            .map(|partial| match partial {
+                PartialSysEx::Collecting(data) => data.capacity(),
+                PartialSysEx::Oversized => 0,
+            })
+            .sum();
+        reordered + map_heap_size(&self.sysex_buffers) + sysex
+    }
+
+    /// The bytes of what is followed of each sender: the notes it has sounding, its controllers, timecode and transport.
+    fn followed_size(&self) -> usize {
+        map_heap_size(&self.sounding_notes) + map_heap_size(&self.controller_combiners) + map_heap_size(&self.quarter_frames) + map_heap_size(&self.transports)
+    }
+
+    fn dispatch(&mut self, queued_event: QueuedEvent) {
+        let listeners = self.registry.snapshot();
+        match queued_event {
+            QueuedEvent::MidiPacket(bytes, sender) => {
+                let header = MidiPacket::ref_from_bytes(&bytes).map(|packet| (packet.ssrc(), packet.sequence_number().get()));
+                match (&mut self.reorder_buffer, header) {
+                    (Some(reorder_buffer), Ok((ssrc, sequence_number))) => {
+                        for (bytes, sender) in reorder_buffer.push(ssrc, sequence_number, (bytes, sender), Instant::now()) {
+                            self.dispatch_datagram(&listeners, bytes, sender);
+                        }
+                    }
+                    _ => self.dispatch_datagram(&listeners, bytes, sender),
+                }
+            }
+            QueuedEvent::RecoveredMidiPacket(bytes, sender) => {
+                match MidiPacket::ref_from_bytes(&bytes) {
+                    Ok(packet) => self.dispatch_recovered_packet(&listeners, packet, sender.as_ref()),
+                    Err(_) => event!(Level::ERROR, "Queued MIDI packet could not be read back"),
+                }
+                self.pool.recycle(bytes);
+            }
+            QueuedEvent::PacketsLost { ssrc, missing, timestamp } => self.conceal_loss(&listeners, ssrc, missing, timestamp),
+            QueuedEvent::ParticipantJoined(participant) => listeners.notify_participant_joined(&participant),
+            QueuedEvent::ParticipantLeft(participant) => {
+                self.forget(&listeners, participant.ssrc());
+                listeners.notify_participant_left(&participant);
+            }
+            QueuedEvent::SsrcRetired(ssrc) => self.forget(&listeners, ssrc),
+            QueuedEvent::ProtocolVersionMismatch(mismatch) => listeners.notify_protocol_version_mismatch(&mismatch),
+            QueuedEvent::ParticipantLimitReached(rejection) => listeners.notify_participant_limit_reached(&rejection),
+            QueuedEvent::InvitationFlood(flood) => listeners.notify_invitation_flood(&flood),
+            QueuedEvent::AuthenticationFailed(failure) => listeners.notify_authentication_failed(&failure),
+            QueuedEvent::AddressChanged(change) => listeners.notify_address_changed(&change),
+            QueuedEvent::ParticipantRenamed(rename) => listeners.notify_participant_renamed(&rename),
+            QueuedEvent::ClockSyncRound(round) => listeners.notify_clock_sync_round(&round),
+            QueuedEvent::PacketLossThreshold(crossing) => listeners.notify_packet_loss_threshold(&crossing),
+        }
+    }
+
+    /// Drops what is kept for `ssrc` between packets, after handing on those it still had waiting in the reorder
+    /// window.
+    fn forget(&mut self, listeners: &EventListeners, ssrc: U32) {
+        let held = self
+            .reorder_buffer
+            .as_mut()
+            .map(|reorder_buffer| reorder_buffer.remove(ssrc))
+            .unwrap_or_default();
+        for (bytes, sender) in held {
+            self.dispatch_datagram(listeners, bytes, sender);
+        }
+        self.tempos.lock().unwrap_or_else(PoisonError::into_inner).remove(&ssrc);
+        self.sounding_notes.remove(&ssrc);
+        if let Some(PartialSysEx::Collecting(buffer)) = self.sysex_buffers.remove(&ssrc) {
+            self.pool.recycle(buffer);
+        }
+        self.controller_combiners.remove(&ssrc);
+        self.quarter_frames.remove(&ssrc);
+        self.transports.remove(&ssrc);
+    }
+
+    /// Dispatches the packets the reorder buffer is ready to let go of.
+    fn dispatch_reordered(&mut self, release: impl FnOnce(&mut ReorderBuffer<ReceivedDatagram>) -> Vec<ReceivedDatagram>) {
+        let Some(reorder_buffer) = &mut self.reorder_buffer else {
+            return;
+        };
+        let released = release(reorder_buffer);
+        let listeners = self.registry.snapshot();
+        for (bytes, sender) in released {
+            self.dispatch_datagram(&listeners, bytes, sender);
+        }
+    }
+
+    fn dispatch_datagram(&mut self, listeners: &EventListeners, bytes: Vec<u8>, sender: Option<Participant>) {
+        match MidiPacket::ref_from_bytes(&bytes) {
+            Ok(packet) => self.dispatch_midi_packet(listeners, packet, sender.as_ref()),
+            Err(_) => event!(Level::ERROR, "Queued MIDI packet could not be read back"),
+        }
+        self.pool.recycle(bytes);
+    }
+
+    fn dispatch_midi_packet(&mut self, listeners: &EventListeners, packet: &MidiPacket, sender: Option<&Participant>) {
+        listeners.notify_midi_packet(packet);
+        let channel_map = self
+            .channel_routes
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .for_participant(packet.ssrc())
+            .incoming;
+        let interceptors = self.interceptors.snapshot();
+        let intercept = sender.filter(|_| !interceptors.is_empty()).map(|sender| (&interceptors, sender));
+        // Each delta time counts from the command before
+        let mut timestamp = u32::from(packet.timestamp());
+        for command in packet.commands_with_first_delta_time(self.mode, self.first_delta_time) {
+            timestamp = timestamp.wrapping_add(command.delta_time());
+            let message = match command.command() {
+                RtpMidiMessage::MidiMessage(message) => RtpMidiMessage::MidiMessage(channel_map.apply(*message)),
+                RtpMidiMessage::SysExSegment(segment, data) => {
+                    event!(Level::DEBUG, "Received SysEx segment {segment:?}: {data:?}");
+                    if let Some(sysex) = self.reassemble_sysex(listeners, packet.ssrc(), *segment, data) {
+                        self.deliver(listeners, packet.ssrc(), timestamp, RtpMidiMessage::SysEx(&sysex), intercept);
+                        self.pool.recycle(sysex);
+                    }
+                    continue;
+                }
+                RtpMidiMessage::SysEx(sysex) if sysex.len() > self.max_sysex_size => {
+                    self.sysex_too_large(listeners, packet.ssrc(), sysex.len());
+                    continue;
+                }
+                sysex => sysex.clone(),
+            };
+            self.deliver(listeners, packet.ssrc(), timestamp, message, intercept);
+        }
+    }
+
+    /// Hands listeners what the session's loss concealment makes up for lost packets with, as recovered messages.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(ssrc = ssrc.get(), missing = missing)))]
+    fn conceal_loss(&mut self, listeners: &EventListeners, ssrc: U32, missing: u16, timestamp: u32) {
+        let Some(loss_concealment) = &self.loss_concealment else {
+            return;
+        };
+        let sounding_notes = self.sounding_notes.entry(ssrc).or_default();
+        let loss = PacketLoss {
+            ssrc: ssrc.get(),
+            missing,
+            sounding_channels: sounding_notes.sounding_channels(),
+        };
+        let messages = loss_concealment.conceal(&loss);
+        event!(Level::DEBUG, "Concealing lost MIDI packets with {} message(s)", messages.len());
+        for message in messages {
+            sounding_notes.track_message(&message);
+            listeners.notify_recovered_midi(message, timestamp);
+        }
+    }
+
+    /// Hands on the messages of a packet that was sent again, as recovered ones. They come too late to follow the
+    /// sender's clock or transport with, and SysEx segments can't be put back among the rest of their message, so
+    /// only whole messages are handed on.
+    fn dispatch_recovered_packet(&mut self, listeners: &EventListeners, packet: &MidiPacket, sender: Option<&Participant>) {
+        let channel_map = self
+            .channel_routes
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .for_participant(packet.ssrc())
+            .incoming;
+        let interceptors = self.interceptors.snapshot();
+        let intercept = sender.filter(|_| !interceptors.is_empty()).map(|sender| (&interceptors, sender));
+        let mut timestamp = u32::from(packet.timestamp());
+        for command in packet.commands_with_first_delta_time(self.mode, self.first_delta_time) {
+            timestamp = timestamp.wrapping_add(command.delta_time());
+            let message = match command.command() {
+                RtpMidiMessage::MidiMessage(message) => RtpMidiMessage::MidiMessage(channel_map.apply(*message)),
+                RtpMidiMessage::SysEx(sysex) if sysex.len() <= self.max_sysex_size => RtpMidiMessage::SysEx(sysex),
+                _ => {
+                    event!(Level::DEBUG, "Dropping recovered SysEx that can't be handed on whole");
+                    continue;
+                }
+            };
+            match intercepted(message, intercept) {
+                Some(RtpMidiMessage::MidiMessage(message)) => {
+                    event!(Level::DEBUG, "Recovered MIDI message: {message:?}");
+                    if self.loss_concealment.is_some() {
+                        self.sounding_notes.entry(packet.ssrc()).or_default().track_message(&message);
+                    }
+                    listeners.notify_recovered_midi(message, timestamp);
+                }
+                Some(RtpMidiMessage::SysEx(sysex)) => listeners.notify_recovered_sysex(sysex, timestamp),
+                Some(RtpMidiMessage::SysExSegment(..)) => event!(Level::WARN, "An interceptor replaced a received message with a SysEx segment, dropping it"),
+                None => {}
+            }
+        }
+    }
+
+    /// Hands a received message, after the interceptors have had their say, to the listeners.
+    fn deliver(&mut self, listeners: &EventListeners, ssrc: U32, timestamp: u32, message: RtpMidiMessage, intercept: Option<(&Interceptors, &Participant)>) {
+        let Some(message) = intercepted(message, intercept) else {
+            return;
+        };
+        match message {
+            RtpMidiMessage::MidiMessage(message) => {
+                let message = &message;
+                event!(Level::DEBUG, "Received MIDI message: {message:?}");
+                if self.loss_concealment.is_some() {
+                    self.sounding_notes.entry(ssrc).or_default().track_message(message);
+                }
+                listeners.notify_midi_message(*message, timestamp);
+                let is_transport = matches!(
+                    message,
+                    MidiMessage::Start | MidiMessage::Continue | MidiMessage::Stop | MidiMessage::SongPositionPointer(_) | MidiMessage::TimingClock
+                );
+                if is_transport {
+                    self.follow_transport(listeners, ssrc, message);
+                }
+                match message {
+                    MidiMessage::ControlChange(..) => {
+                        let combiner = self.controller_combiners.entry(ssrc).or_default();
+                        if let Some(change) = combiner.push(message) {
+                            listeners.notify_controller_change(change, timestamp);
+                        }
+                    }
+                    MidiMessage::TimingClock => self.follow_tempo(listeners, ssrc, timestamp),
+                    MidiMessage::QuarterFrame(_) => {
+                        let assembler = self.quarter_frames.entry(ssrc).or_default();
+                        if let Some(position) = assembler.push(message) {
+                            notify_timecode(listeners, ssrc, position, true);
+                        }
+                    }
+                    MidiMessage::Stop => {
+                        if let Some(estimator) = self.tempos.lock().unwrap_or_else(PoisonError::into_inner).get_mut(&ssrc) {
+                            estimator.restart();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            RtpMidiMessage::SysEx(sysex) => {
+                event!(Level::DEBUG, "Received SysEx message: {sysex:?}");
+                notify_sysex(listeners, ssrc, sysex);
+            }
+            RtpMidiMessage::SysExSegment(..) => event!(Level::WARN, "An interceptor replaced a received message with a SysEx segment, dropping it"),
+        }
+    }
+
+    fn follow_tempo(&self, listeners: &EventListeners, ssrc: U32, timestamp: u32) {
+        let bpm = self
+            .tempos
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(ssrc)
+            .or_default()
+            .tick(timestamp);
+        if let Some(bpm) = bpm {
+            event!(Level::DEBUG, ssrc = ssrc.get(), bpm, "Tempo changed");
+            listeners.notify_tempo_changed(&TempoChange { ssrc: ssrc.get(), bpm });
+        }
+    }
+
+    fn follow_transport(&mut self, listeners: &EventListeners, ssrc: U32, message: &MidiMessage) {
+        let transport = self.transports.entry(ssrc).or_default();
+        if transport.push(message) {
+            let update = TransportUpdate {
+                ssrc: ssrc.get(),
+                playing: transport.is_playing(),
+                song_position: transport.song_position(),
+            };
+            event!(Level::DEBUG, "Transport changed: {update:?}");
+            listeners.notify_transport(&update);
+        }
+    }
+
+    /// Collects the segments of a SysEx message, returning the complete payload once the last segment arrives. One
+    /// that grows past the limit is let go of, and the rest of it ignored.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(ssrc = ssrc.get(), segment = ?segment)))]
+    fn reassemble_sysex(&mut self, listeners: &EventListeners, ssrc: U32, segment: SysExSegment, data: &[u8]) -> Option<Vec<u8>> {
+        match segment {
+            SysExSegment::First => {
+                let mut buffer = self.pool.take(0);
+                buffer.extend_from_slice(data);
+                let partial = self.check_sysex_size(listeners, ssrc, buffer);
+                if let Some(PartialSysEx::Collecting(discarded)) = self.sysex_buffers.insert(ssrc, partial) {
+                    event!(Level::WARN, "Discarding incomplete SysEx message, a new one has started");
+                    self.pool.recycle(discarded);
+                }
+                None
+            }
+            SysExSegment::Middle => {
+                match self.sysex_buffers.remove(&ssrc) {
+                    Some(PartialSysEx::Collecting(mut buffer)) => {
+                        buffer.extend_from_slice(data);
+                        let partial = self.check_sysex_size(listeners, ssrc, buffer);
+                        self.sysex_buffers.insert(ssrc, partial);
+                    }
+                    Some(PartialSysEx::Oversized) => {
+                        self.sysex_buffers.insert(ssrc, PartialSysEx::Oversized);
+                    }
+                    None => event!(Level::WARN, "Received SysEx segment without a preceding first segment"),
+                }
+                None
+            }
+            SysExSegment::Last => {
+                let mut buffer = match self.sysex_buffers.remove(&ssrc) {
+                    Some(PartialSysEx::Collecting(buffer)) => buffer,
+                    Some(PartialSysEx::Oversized) => return None,
+                    None => {
+                        event!(Level::WARN, "Received final SysEx segment without a preceding first segment");
+                        return None;
+                    }
+                };
+                buffer.extend_from_slice(data);
+                match self.check_sysex_size(listeners, ssrc, buffer) {
+                    PartialSysEx::Collecting(buffer) => Some(buffer),
+                    PartialSysEx::Oversized => None,
+                }
+            }
+            SysExSegment::Cancel => {
+                event!(Level::DEBUG, "SysEx message cancelled by sender");
+                if let Some(PartialSysEx::Collecting(cancelled)) = self.sysex_buffers.remove(&ssrc) {
+                    self.pool.recycle(cancelled);
+                }
+                None
+            }
+        }
+    }
+
+    /// Keeps collecting into `buffer` if it's still within the limit.
+    fn check_sysex_size(&mut self, listeners: &EventListeners, ssrc: U32, buffer: Vec<u8>) -> PartialSysEx {
+        if buffer.len() <= self.max_sysex_size {
+            return PartialSysEx::Collecting(buffer);
+        }
+        self.sysex_too_large(listeners, ssrc, buffer.len());
+        self.pool.recycle(buffer);
+        PartialSysEx::Oversized
+    }
+
+    fn sysex_too_large(&self, listeners: &EventListeners, ssrc: U32, size: usize) {
+        event!(
+            Level::WARN,
+            ssrc = ssrc.get(),
+            size,
+            "Dropping SysEx message over the {} byte limit",
+            self.max_sysex_size
+        );
+        listeners.notify_sysex_too_large(&SysExTooLarge {
+            ssrc: ssrc.get(),
+            limit: self.max_sysex_size,
+            size,
+        });
+    }
+}
+
+/// A segmented SysEx message on its way in.
+enum PartialSysEx {
+    Collecting(Vec<u8>),
+    /// Went over the limit, so what is left of it is ignored until it ends.
+    Oversized,
+}
+
+/// A received message after the sender's interceptors have had their say, or `None` if one dropped it.
+fn intercepted<'a>(message: RtpMidiMessage<'a>, intercept: Option<(&Interceptors, &Participant)>) -> Option<RtpMidiMessage<'a>> {
+    let Some((interceptors, sender)) = intercept else {
+        return Some(message);
+    };
+    let message = interceptors.apply(message, Direction::Inbound, sender);
+    if message.is_none() {
+        event!(Level::DEBUG, "Received message dropped by an interceptor");
+    }
+    message
+}
+
+/// Hands a complete SysEx message on, as it was received and as read, along with the timecode position in it if it's
+/// an MTC full frame.
+fn notify_sysex(listeners: &EventListeners, ssrc: U32, sysex: &[u8]) {
+    listeners.notify_sysex_packet(sysex);
+    let message = SysExMessage::parse(sysex);
+    listeners.notify_sysex_message(&message);
+    if let SysExMessage::FullFrame { position, .. } = message {
+        notify_timecode(listeners, ssrc, position, false);
+    }
+}
+
+fn notify_timecode(listeners: &EventListeners, ssrc: U32, position: TimecodePosition, running: bool) {
+    event!(Level::DEBUG, ssrc = ssrc.get(), running, "Received timecode: {position:?}");
+    let update = TimecodeUpdate {
+        ssrc: ssrc.get(),
+        position,
+        running,
+    };
+    listeners.notify_timecode(&update);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use midi_types::{Channel, Control, MidiMessage, Note, Value7, Value14};
+    use zerocopy::network_endian::U16;
+
+    use super::*;
+    use crate::packets::midi_packets::controller_change::ControllerChange;
+    use crate::packets::midi_packets::midi_event::MidiEvent;
+    use crate::packets::midi_packets::timecode::FrameRate;
+    use crate::sessions::events::event_handling::{
+        ControllerChangeEvent, EventType, MidiMessageEvent, ParticipantLeftEvent, RecoveredMidiEvent, RecoveredSysExEvent, SysExMessageEvent, SysExPacketEvent,
+        SysExTooLargeEvent, TimecodeEvent,
+    };
+    use crate::sessions::loss_concealment::ReleaseNotes;
+    use crate::sessions::session_config::ReorderWindow;
+
+    #[tokio::test]
+    async fn test_events_are_dispatched_in_order() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let received_messages = Arc::clone(&received);
+        let received_sysex = Arc::clone(&received);
+        registry.update(|listeners| {
+            MidiMessageEvent::add_listener_to_storage(listeners, move |(_message, timestamp)| {
+                received_messages.lock().unwrap().push(format!("message {timestamp}"));
+            });
+            SysExPacketEvent::add_listener_to_storage(listeners, move |bytes| {
+                received_sysex.lock().unwrap().push(format!("sysex {bytes:?}"));
+            });
+        });
+
+        let (queue, queued_events) = EventQueue::channel(1, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let note_on = RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100)));
+        let commands = [
+            MidiEvent::new(None, note_on.clone()),
+            MidiEvent::new(Some(1), RtpMidiMessage::SysEx(&[0x7E])),
+            MidiEvent::new(Some(1), note_on.clone()),
+        ];
+        let packet = MidiPacket::new_as_bytes(U16::new(1), U32::new(10), U32::new(2), &commands, false);
+
+        let segments = [
+            MidiEvent::new(None, RtpMidiMessage::SysExSegment(SysExSegment::First, &[0x01])),
+            MidiEvent::new(Some(0), note_on.clone()),
+        ];
+        let first_segment = MidiPacket::new_as_bytes(U16::new(2), U32::new(20), U32::new(2), &segments, false);
+        let segments = [MidiEvent::new(None, RtpMidiMessage::SysExSegment(SysExSegment::Last, &[0x02]))];
+        let last_segment = MidiPacket::new_as_bytes(U16::new(3), U32::new(30), U32::new(2), &segments, false);
+
+        queue.push(QueuedEvent::MidiPacket(packet.to_vec(), None)).await;
+        queue.push(QueuedEvent::MidiPacket(first_segment.to_vec(), None)).await;
+        queue.push(QueuedEvent::MidiPacket(last_segment.to_vec(), None)).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec!["message 10", "sysex [126]", "message 12", "message 20", "sysex [1, 2]"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sysex_over_the_limit_is_dropped() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_sysex = Arc::clone(&received);
+        let received_oversized = Arc::clone(&received);
+        registry.update(|listeners| {
+            SysExPacketEvent::add_listener_to_storage(listeners, move |bytes| {
+                received_sysex.lock().unwrap().push(format!("sysex {bytes:?}"));
+            });
+            SysExTooLargeEvent::add_listener_to_storage(listeners, move |oversized| {
+                received_oversized
+                    .lock()
+                    .unwrap()
+                    .push(format!("too large {} of {}", oversized.size, oversized.limit));
+            });
+        });
+
+        let (queue, queued_events) = EventQueue::channel(8, QueueOverflow::Wait);
+        let config = SessionConfig {
+            max_sysex_size: 4,
+            ..Default::default()
+        };
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::new(config),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let segments: [(SysExSegment, &[u8]); 6] = [
+            (SysExSegment::First, &[1, 2, 3]),
+            (SysExSegment::Middle, &[4, 5]),
+            (SysExSegment::Middle, &[6]),
+            (SysExSegment::Last, &[7]),
+            (SysExSegment::First, &[1, 2]),
+            (SysExSegment::Last, &[3, 4]),
+        ];
+        for (sequence_number, (segment, data)) in segments.into_iter().enumerate() {
+            let commands = [MidiEvent::new(None, RtpMidiMessage::SysExSegment(segment, data))];
+            let packet = MidiPacket::new_as_bytes(U16::new(sequence_number as u16), U32::new(10), U32::new(2), &commands, false);
+            queue.push(QueuedEvent::MidiPacket(packet.to_vec(), None)).await;
+        }
+        let commands = [MidiEvent::new(None, RtpMidiMessage::SysEx(&[1, 2, 3, 4, 5, 6]))];
+        let packet = MidiPacket::new_as_bytes(U16::new(6), U32::new(10), U32::new(2), &commands, false);
+        queue.push(QueuedEvent::MidiPacket(packet.to_vec(), None)).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec!["too large 5 of 4", "sysex [1, 2, 3, 4]", "too large 6 of 4"]);
+    }
+
+    #[tokio::test]
+    async fn test_retired_ssrc_is_forgotten() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_sysex = Arc::clone(&received);
+        registry.update(|listeners| {
+            SysExPacketEvent::add_listener_to_storage(listeners, move |bytes| {
+                received_sysex.lock().unwrap().push(bytes.to_vec());
+            });
+        });
+
+        let (queue, queued_events) = EventQueue::channel(8, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let segment = |sequence_number: u16, segment, data: &'static [u8]| {
+            let commands = [MidiEvent::new(None, RtpMidiMessage::SysExSegment(segment, data))];
+            MidiPacket::new_as_bytes(U16::new(sequence_number), U32::new(10), U32::new(2), &commands, false).to_vec()
+        };
+        queue.push(QueuedEvent::MidiPacket(segment(0, SysExSegment::First, &[1]), None)).await;
+        queue.push(QueuedEvent::SsrcRetired(U32::new(2))).await;
+        // From the session the SSRC belongs to now, which never started this message
+        queue.push(QueuedEvent::MidiPacket(segment(0, SysExSegment::Last, &[2]), None)).await;
+        queue.push(QueuedEvent::MidiPacket(segment(1, SysExSegment::First, &[3]), None)).await;
+        queue.push(QueuedEvent::MidiPacket(segment(2, SysExSegment::Last, &[4]), None)).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![vec![3, 4]]);
+    }
+
+    #[tokio::test]
+    async fn test_reorder_window_puts_packets_back_in_order() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_messages = Arc::clone(&received);
+        registry.update(|listeners| {
+            MidiMessageEvent::add_listener_to_storage(listeners, move |(_message, timestamp)| {
+                received_messages.lock().unwrap().push(timestamp);
+            });
+        });
+
+        let window = ReorderWindow {
+            depth: 4,
+            latency: std::time::Duration::from_millis(20),
+        };
+        let (queue, queued_events) = EventQueue::channel(4, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::new(SessionConfig {
+                reorder_window: Some(window),
+                ..Default::default()
+            }),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let note_on = RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100)));
+        let packet = |sequence_number: u16| {
+            let commands = [MidiEvent::new(None, note_on.clone())];
+            MidiPacket::new_as_bytes(U16::new(sequence_number), U32::new(sequence_number as u32), U32::new(2), &commands, false).to_vec()
+        };
+        for sequence_number in [1, 3, 2, 5] {
+            queue.push(QueuedEvent::MidiPacket(packet(sequence_number), None)).await;
+        }
+
+        // Packet 4 never arrives, so packet 5 is let go once it has waited long enough
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert_eq!(*received.lock().unwrap(), vec![1, 2, 3, 5]);
+        drop(queue);
+        dispatcher.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_controller_changes_are_combined_per_sender() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_changes = Arc::clone(&received);
+        registry.update(|listeners| {
+            ControllerChangeEvent::add_listener_to_storage(listeners, move |(change, timestamp)| {
+                received_changes.lock().unwrap().push((change, timestamp));
+            });
+        });
+
+        let (queue, queued_events) = EventQueue::channel(4, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        // Each sender's MSB only combines with its own LSB
+        let change = ControllerChange::ControlChange14 {
+            channel: Channel::C1,
+            control: Control::from(1),
+            value: Value14::new(1, 2),
+        };
+        let events = change.events();
+        let msb = MidiPacket::new_as_bytes(U16::new(1), U32::new(10), U32::new(2), &events[..1], false);
+        let other_lsb = MidiPacket::new_as_bytes(U16::new(1), U32::new(20), U32::new(3), &events[1..], false);
+        let lsb = MidiPacket::new_as_bytes(U16::new(2), U32::new(30), U32::new(2), &events[1..], false);
+        for packet in [msb, other_lsb, lsb] {
+            queue.push(QueuedEvent::MidiPacket(packet.to_vec(), None)).await;
+        }
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![(change, 30)]);
+    }
+
+    #[tokio::test]
+    async fn test_timecode_is_read_from_full_and_quarter_frames() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_updates = Arc::clone(&received);
+        registry.update(|listeners| {
+            TimecodeEvent::add_listener_to_storage(listeners, move |update| {
+                received_updates.lock().unwrap().push(update.clone());
+            });
+        });
+
+        let (queue, queued_events) = EventQueue::channel(4, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let located = TimecodePosition {
+            hours: 1,
+            minutes: 0,
+            seconds: 0,
+            frames: 0,
+            rate: FrameRate::Fps25,
+        };
+        let running = located.next_frame().next_frame();
+        let full_frame = located.full_frame();
+        let commands = [MidiEvent::new(None, RtpMidiMessage::SysEx(&full_frame))];
+        let packet = MidiPacket::new_as_bytes(U16::new(1), U32::new(10), U32::new(2), &commands, false);
+        queue.push(QueuedEvent::MidiPacket(packet.to_vec(), None)).await;
+        let commands = running.quarter_frames().map(|message| MidiEvent::new(Some(0), message.into()));
+        let packet = MidiPacket::new_as_bytes(U16::new(2), U32::new(20), U32::new(2), &commands, false);
+        queue.push(QueuedEvent::MidiPacket(packet.to_vec(), None)).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![
+                TimecodeUpdate {
+                    ssrc: 2,
+                    position: located,
+                    running: false,
+                },
+                TimecodeUpdate {
+                    ssrc: 2,
+                    position: running,
+                    running: true,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sysex_is_read_into_messages() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_messages = Arc::clone(&received);
+        registry.update(|listeners| {
+            SysExMessageEvent::add_listener_to_storage(listeners, move |message| {
+                received_messages.lock().unwrap().push(message.to_sysex().unwrap());
+                if let SysExMessage::DeviceInquiry { device_id } = message {
+                    received_messages.lock().unwrap().push(vec![*device_id]);
+                }
+            });
+        });
+
+        let (queue, queued_events) = EventQueue::channel(4, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let commands = [
+            MidiEvent::new(None, RtpMidiMessage::SysEx(&[0x7E, 0x10, 0x06, 0x01])),
+            MidiEvent::new(Some(0), RtpMidiMessage::SysEx(&[0x7D, 0x01])),
+        ];
+        let packet = MidiPacket::new_as_bytes(U16::new(1), U32::new(10), U32::new(2), &commands, false);
+        queue.push(QueuedEvent::MidiPacket(packet.to_vec(), None)).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![vec![0x7E, 0x10, 0x06, 0x01], vec![0x10], vec![0x7D, 0x01]]);
+    }
+
+    #[tokio::test]
+    async fn test_recovered_packets_are_handed_on_apart_from_live_ones() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_live = Arc::clone(&received);
+        let received_recovered = Arc::clone(&received);
+        registry.update(|listeners| {
+            MidiMessageEvent::add_listener_to_storage(listeners, move |(message, _)| {
+                received_live.lock().unwrap().push((message, false));
+            });
+            RecoveredMidiEvent::add_listener_to_storage(listeners, move |(message, _)| {
+                received_recovered.lock().unwrap().push((message, true));
+            });
+        });
+
+        let config = SessionConfig {
+            reorder_window: Some(ReorderWindow::default()),
+            ..Default::default()
+        };
+        let (queue, queued_events) = EventQueue::channel(4, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::new(config),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let note = |key: u8| MidiMessage::NoteOn(Channel::C1, Note::from(key), Value7::from(100));
+        let packet = |sequence_number: u16| {
+            let commands = [MidiEvent::new(None, note(sequence_number as u8).into())];
+            MidiPacket::new_as_bytes(U16::new(sequence_number), U32::new(10), U32::new(2), &commands, false).to_vec()
+        };
+        queue.push(QueuedEvent::MidiPacket(packet(1), None)).await;
+        queue.push(QueuedEvent::MidiPacket(packet(3), None)).await;
+        // Not held back for the one before it, which is what the reorder window waits for
+        queue.push(QueuedEvent::RecoveredMidiPacket(packet(2), None)).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![(note(1), false), (note(2), true), (note(3), false)]);
+    }
+
+    #[tokio::test]
+    async fn test_recovered_sysex_is_handed_on_apart_from_live_sysex() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_live = Arc::clone(&received);
+        let received_message = Arc::clone(&received);
+        let received_recovered = Arc::clone(&received);
+        registry.update(|listeners| {
+            SysExPacketEvent::add_listener_to_storage(listeners, move |sysex| {
+                received_live.lock().unwrap().push((sysex.to_vec(), false));
+            });
+            SysExMessageEvent::add_listener_to_storage(listeners, move |message| {
+                received_message.lock().unwrap().push((message.to_sysex().unwrap(), false));
+            });
+            RecoveredSysExEvent::add_listener_to_storage(listeners, move |(sysex, timestamp)| {
+                assert_eq!(timestamp, 10);
+                received_recovered.lock().unwrap().push((sysex.to_vec(), true));
+            });
+        });
+
+        let (queue, queued_events) = EventQueue::channel(4, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let sysex = [0x7E, 0x10, 0x06, 0x01];
+        let commands = [MidiEvent::new(None, RtpMidiMessage::SysEx(&sysex))];
+        let packet = MidiPacket::new_as_bytes(U16::new(1), U32::new(10), U32::new(2), &commands, false).to_vec();
+        queue.push(QueuedEvent::RecoveredMidiPacket(packet, None)).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec![(sysex.to_vec(), true)]);
+    }
+
+    #[tokio::test]
+    async fn test_lost_packets_are_concealed_on_the_channels_left_sounding() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_recovered = Arc::clone(&received);
+        registry.update(|listeners| {
+            RecoveredMidiEvent::add_listener_to_storage(listeners, move |(message, timestamp)| {
+                received_recovered.lock().unwrap().push((message, timestamp));
+            });
+        });
+
+        let config = SessionConfig {
+            loss_concealment: Some(Arc::new(ReleaseNotes)),
+            ..Default::default()
+        };
+        let (queue, queued_events) = EventQueue::channel(4, QueueOverflow::Wait);
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::new(config),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+
+        let commands = [
+            MidiEvent::new(None, MidiMessage::NoteOn(Channel::C2, Note::C4, Value7::from(100)).into()),
+            MidiEvent::new(Some(0), MidiMessage::NoteOn(Channel::C3, Note::C4, Value7::from(100)).into()),
+            MidiEvent::new(Some(0), MidiMessage::NoteOff(Channel::C3, Note::C4, Value7::from(0)).into()),
+        ];
+        let packet = MidiPacket::new_as_bytes(U16::new(1), U32::new(10), U32::new(2), &commands, false);
+        queue.push(QueuedEvent::MidiPacket(packet.to_vec(), None)).await;
+        let loss = || QueuedEvent::PacketsLost {
+            ssrc: U32::new(2),
+            missing: 1,
+            timestamp: 30,
+        };
+        queue.push(loss()).await;
+        // Nothing is left sounding after the first
+        queue.push(loss()).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        let cc = |control| MidiMessage::ControlChange(Channel::C2, Control::from(control), Value7::from(0));
+        assert_eq!(*received.lock().unwrap(), vec![(cc(64), 30), (cc(123), 30)]);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_events_without_waiting() {
+        let (queue, _queued_events) = EventQueue::channel(1, QueueOverflow::Drop);
+        for _ in 0..3 {
+            queue.push(QueuedEvent::MidiPacket(vec![0; 16], None)).await;
+        }
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(queue.memory.queued.load(Ordering::Relaxed), 16);
+        // The buffers of the events dropped go back to the pool, as far as it has room
+        assert_eq!(queue.pool.heap_size(), 16);
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_still_takes_participants_leaving_and_retired_ssrcs() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let received_left = Arc::clone(&received);
+        let received_sysex = Arc::clone(&received);
+        registry.update(|listeners| {
+            ParticipantLeftEvent::add_listener_to_storage(listeners, move |participant| {
+                received_left.lock().unwrap().push(format!("left {}", participant.name()));
+            });
+            SysExPacketEvent::add_listener_to_storage(listeners, move |bytes| {
+                received_sysex.lock().unwrap().push(format!("sysex {bytes:?}"));
+            });
+        });
+
+        let (queue, queued_events) = EventQueue::channel(1, QueueOverflow::Drop);
+        let segment = |sequence_number: u16, segment, data: &'static [u8]| {
+            let commands = [MidiEvent::new(None, RtpMidiMessage::SysExSegment(segment, data))];
+            MidiPacket::new_as_bytes(U16::new(sequence_number), U32::new(10), U32::new(2), &commands, false).to_vec()
+        };
+        // Fills the queue, so the next packet is dropped
+        queue.push(QueuedEvent::MidiPacket(segment(0, SysExSegment::First, &[1]), None)).await;
+        queue.push(QueuedEvent::MidiPacket(segment(1, SysExSegment::Middle, &[2]), None)).await;
+        assert_eq!(queue.dropped(), 1);
+        let participant = Participant::new("127.0.0.1:5004".parse().unwrap(), false, None, "Peer", U32::new(2));
+        queue.push(QueuedEvent::ParticipantLeft(participant)).await;
+        queue.push(QueuedEvent::SsrcRetired(U32::new(2))).await;
+        assert_eq!(queue.dropped(), 1);
+
+        let dispatcher = tokio::spawn(dispatch_events(
+            queued_events,
+            Arc::clone(&registry),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+            Arc::default(),
+        ));
+        while queue.memory.events.load(Ordering::Relaxed) > 0 {
+            tokio::task::yield_now().await;
+        }
+        // The SysEx message started before the SSRC was retired was forgotten with it
+        queue.push(QueuedEvent::MidiPacket(segment(2, SysExSegment::Last, &[3]), None)).await;
+        drop(queue);
+        dispatcher.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec!["left Peer"]);
+    }
+}
@@ -1,80 +1,730 @@
+use std::any::Any;
+use std::fmt;
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, PoisonError, RwLock, Weak};
+use std::time::Duration;
+
 use midi_types::MidiMessage;
 
+use crate::logging::{Level, event};
+use crate::packets::midi_packets::controller_change::ControllerChange;
+use crate::packets::midi_packets::midi_packet::MidiPacket;
+use crate::packets::midi_packets::sysex::SysExMessage;
+use crate::packets::midi_packets::timecode::TimecodePosition;
 use crate::participant::Participant;
 
-pub(super) type MidiMessageListener = dyn Fn((MidiMessage, u32)) + Send + 'static;
-pub(super) type SysExPacketListener = dyn for<'a> Fn(&'a [u8]) + Send + 'static;
-pub(super) type ParticipantListener = dyn for<'a> Fn(&'a Participant) + Send + 'static;
+pub(super) type MidiMessageListener = dyn Fn((MidiMessage, u32)) + Send + Sync + 'static;
+pub(super) type RecoveredMidiListener = dyn Fn((MidiMessage, u32)) + Send + Sync + 'static;
+pub(super) type RecoveredSysExListener = dyn for<'a> Fn((&'a [u8], u32)) + Send + Sync + 'static;
+pub(super) type ControllerChangeListener = dyn Fn((ControllerChange, u32)) + Send + Sync + 'static;
+pub(super) type MidiPacketListener = dyn for<'a> Fn(&'a MidiPacket) + Send + Sync + 'static;
+pub(super) type SysExPacketListener = dyn for<'a> Fn(&'a [u8]) + Send + Sync + 'static;
+pub(super) type SysExMessageListener = dyn for<'a> Fn(&'a SysExMessage<'a>) + Send + Sync + 'static;
+pub(super) type SysExTooLargeListener = dyn for<'a> Fn(&'a SysExTooLarge) + Send + Sync + 'static;
+pub(super) type ParticipantListener = dyn for<'a> Fn(&'a Participant) + Send + Sync + 'static;
+pub(super) type TempoChangeListener = dyn for<'a> Fn(&'a TempoChange) + Send + Sync + 'static;
+pub(super) type TimecodeListener = dyn for<'a> Fn(&'a TimecodeUpdate) + Send + Sync + 'static;
+pub(super) type TransportListener = dyn for<'a> Fn(&'a TransportUpdate) + Send + Sync + 'static;
+pub(super) type ProtocolVersionMismatchListener = dyn for<'a> Fn(&'a ProtocolVersionMismatch) + Send + Sync + 'static;
+pub(super) type ParticipantLimitListener = dyn for<'a> Fn(&'a ParticipantLimitReached) + Send + Sync + 'static;
+pub(super) type ClockSyncRoundListener = dyn for<'a> Fn(&'a ClockSyncRound) + Send + Sync + 'static;
+pub(super) type InvitationFloodListener = dyn for<'a> Fn(&'a InvitationFlood) + Send + Sync + 'static;
+pub(super) type AuthenticationFailedListener = dyn for<'a> Fn(&'a AuthenticationFailed) + Send + Sync + 'static;
+pub(super) type AddressChangedListener = dyn for<'a> Fn(&'a AddressChanged) + Send + Sync + 'static;
+pub(super) type ParticipantRenamedListener = dyn for<'a> Fn(&'a ParticipantRenamed) + Send + Sync + 'static;
+pub(super) type ListenerPanickedListener = dyn for<'a> Fn(&'a ListenerPanicked) + Send + Sync + 'static;
+pub(super) type PacketLossListener = dyn for<'a> Fn(&'a PacketLossThresholdCrossed) + Send + Sync + 'static;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RtpMidiEventType {
     MidiMessage,
+    RecoveredMidi,
+    RecoveredSysEx,
+    ControllerChange,
+    MidiPacket,
     SysExPacket,
+    SysExMessage,
+    SysExTooLarge,
     ParticipantJoined,
     ParticipantLeft,
+    ProtocolVersionMismatch,
+    ParticipantLimitReached,
+    InvitationFlood,
+    AuthenticationFailed,
+    AddressChanged,
+    ParticipantRenamed,
+    ClockSyncRound,
+    PacketLossThreshold,
+    TempoChanged,
+    Timecode,
+    Transport,
+    ListenerPanicked,
+}
+
+/// A peer sent a session initiation packet with an AppleMIDI protocol version other than the one we speak.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProtocolVersionMismatch {
+    pub addr: SocketAddr,
+    pub ssrc: u32,
+    pub version: u32,
+}
+
+/// An invitation was rejected because the session already has as many participants as
+/// [`SessionConfig::max_participants`](crate::sessions::session_config::SessionConfig::max_participants) allows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParticipantLimitReached {
+    pub addr: SocketAddr,
+    pub ssrc: u32,
+    pub name: String,
+    pub max_participants: usize,
+}
+
+/// An invitation was turned away by
+/// [`SessionConfig::flood_protection`](crate::sessions::session_config::SessionConfig::flood_protection).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InvitationFlood {
+    pub addr: SocketAddr,
+    pub reason: FloodReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FloodReason {
+    /// The address sent too many invitations, and its invitations are ignored for this long. Reported once per ban.
+    Banned(Duration),
+    /// As many handshakes as allowed are already under way.
+    TooManyHandshakes(usize),
+}
+
+/// A peer that finished its handshake didn't prove it knows the secret of
+/// [`SessionConfig::authenticator`](crate::sessions::session_config::SessionConfig::authenticator), and its session
+/// was ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthenticationFailed {
+    pub addr: SocketAddr,
+    pub ssrc: u32,
+    pub name: String,
+    /// Whether it didn't answer at all, rather than answering with the wrong proof or ending its session instead.
+    pub timed_out: bool,
+}
+
+/// A listener panicked. The panic was caught, so the other listeners still got the event and the session carries on,
+/// though a panic hook still runs as usual. Builds with `panic = "abort"` can't be helped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ListenerPanicked {
+    /// What the listener was listening for.
+    pub event: RtpMidiEventType,
+    /// The panic message, if it was a string.
+    pub message: Option<String>,
+}
+
+/// A participant sent a SysEx message bigger than
+/// [`SessionConfig::max_sysex_size`](crate::sessions::session_config::SessionConfig::max_sysex_size), which was
+/// dropped. Reported once per message, as soon as it goes over.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SysExTooLarge {
+    pub ssrc: u32,
+    pub limit: usize,
+    /// How much of the message had arrived when it went over.
+    pub size: usize,
+}
+
+/// A participant invited us again from another address, under its SSRC and the token it joined with, as it does
+/// after a DHCP renewal or a WiFi roam. It is reached at the new address from now on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddressChanged {
+    pub ssrc: u32,
+    pub name: String,
+    /// The control port it was at.
+    pub old_addr: SocketAddr,
+    pub new_addr: SocketAddr,
+}
+
+/// A peer's name was learned, from the `IN` or `OK` of its handshake, before it has joined, with `old_name` empty.
+/// Or a participant invited us again under another name, as it does when its session is renamed, in which case its
+/// name is the new one from now on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParticipantRenamed {
+    pub ssrc: u32,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+impl ParticipantRenamed {
+    /// The name the peer going by `ssrc` gave in its handshake.
+    pub(crate) fn learned(ssrc: u32, name: &str) -> Self {
+        ParticipantRenamed {
+            ssrc,
+            old_name: String::new(),
+            new_name: name.to_owned(),
+        }
+    }
+
+    /// The rename to report if the participant, going by `ssrc` from now on, has invited us again under another name.
+    pub(crate) fn between(participant: &Participant, ssrc: u32, name: &str) -> Option<Self> {
+        (participant.name() != name).then(|| ParticipantRenamed {
+            ssrc,
+            old_name: participant.name().to_owned(),
+            new_name: name.to_owned(),
+        })
+    }
+}
+
+/// A clock sync packet was received from a participant. A participant that has stopped sending them has stopped
+/// answering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ClockSyncRound {
+    pub ssrc: u32,
+    pub name: String,
+    /// How far into the exchange the packet was, from 0, opening it, to 2, closing it.
+    pub count: u8,
+    /// The round trip time, if the packet completed a round trip: count 1 for exchanges we opened, count 2 for ones
+    /// the participant opened.
+    pub rtt_estimate: Option<Duration>,
+}
+
+/// A participant's loss rate has crossed
+/// [`SessionConfig::loss_alert_threshold`](crate::sessions::session_config::SessionConfig::loss_alert_threshold).
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketLossThresholdCrossed {
+    pub ssrc: u32,
+    pub name: String,
+    /// The percentage of the participant's most recent MIDI packets that were lost.
+    pub loss_rate: f64,
+    /// Whether the loss rate went above the threshold, rather than back below it.
+    pub above: bool,
+}
+
+/// The tempo of a participant's MIDI clock, estimated from the TimingClock messages it sends, has changed.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TempoChange {
+    pub ssrc: u32,
+    /// Smoothed over the last few beats.
+    pub bpm: f64,
+}
+
+/// A participant sent a MIDI Time Code position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimecodeUpdate {
+    pub ssrc: u32,
+    pub position: TimecodePosition,
+    /// Whether the position was put together from quarter frames, as sent while running, rather than sent as a
+    /// full frame, as sent after locating somewhere else.
+    pub running: bool,
 }
 
+/// A participant sent a transport message: Start, Continue, Stop or a Song Position Pointer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransportUpdate {
+    pub ssrc: u32,
+    pub playing: bool,
+    /// Where playback is, in sixteenth notes from the top of the song, counting the MIDI clocks since the last
+    /// Song Position Pointer.
+    pub song_position: u16,
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> Option<String> {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => Some((*message).to_owned()),
+        None => panic.downcast_ref::<String>().cloned(),
+    }
+}
+
+/// The listeners for each kind of event. A session has one set, and a set can be built up before the session starts
+/// and handed to it through [`SessionConfig::listeners`](crate::sessions::session_config::SessionConfig::listeners).
+#[derive(Clone)]
 pub struct EventListeners {
-    midi_message: Vec<Box<MidiMessageListener>>,
-    sysex_packet: Vec<Box<SysExPacketListener>>,
-    participant_joined: Vec<Box<ParticipantListener>>,
-    participant_left: Vec<Box<ParticipantListener>>,
+    midi_message: Vec<Arc<MidiMessageListener>>,
+    recovered_midi: Vec<Arc<RecoveredMidiListener>>,
+    recovered_sysex: Vec<Arc<RecoveredSysExListener>>,
+    controller_change: Vec<Arc<ControllerChangeListener>>,
+    midi_packet: Vec<Arc<MidiPacketListener>>,
+    sysex_packet: Vec<Arc<SysExPacketListener>>,
+    sysex_message: Vec<Arc<SysExMessageListener>>,
+    sysex_too_large: Vec<Arc<SysExTooLargeListener>>,
+    participant_joined: Vec<Arc<ParticipantListener>>,
+    participant_left: Vec<Arc<ParticipantListener>>,
+    protocol_version_mismatch: Vec<Arc<ProtocolVersionMismatchListener>>,
+    participant_limit_reached: Vec<Arc<ParticipantLimitListener>>,
+    invitation_flood: Vec<Arc<InvitationFloodListener>>,
+    authentication_failed: Vec<Arc<AuthenticationFailedListener>>,
+    address_changed: Vec<Arc<AddressChangedListener>>,
+    participant_renamed: Vec<Arc<ParticipantRenamedListener>>,
+    clock_sync_round: Vec<Arc<ClockSyncRoundListener>>,
+    packet_loss_threshold: Vec<Arc<PacketLossListener>>,
+    tempo_changed: Vec<Arc<TempoChangeListener>>,
+    timecode: Vec<Arc<TimecodeListener>>,
+    transport: Vec<Arc<TransportListener>>,
+    listener_panicked: Vec<Arc<ListenerPanickedListener>>,
 }
 
 pub struct MidiMessageEvent;
+/// A message that was lost on the way and has been made up for since, such as from a recovery journal, rather than
+/// received as it was sent. It comes late, so listeners may want to apply what it sets, such as a controller value,
+/// without playing what it triggers, such as a note. It isn't handed to [`MidiMessageEvent`] listeners.
+///
+/// The recovery journal isn't implemented, so for now these only come from peers using this library, with
+/// [`SessionConfig::retransmission`](crate::sessions::session_config::SessionConfig::retransmission) on.
+pub struct RecoveredMidiEvent;
+/// A SysEx message that was lost on the way and has been made up for since, whole, with the timestamp it was sent
+/// at. As with [`RecoveredMidiEvent`], it isn't handed to [`SysExPacketEvent`] or [`SysExMessageEvent`] listeners,
+/// nor read for timecode.
+pub struct RecoveredSysExEvent;
+/// A high-resolution controller operation, put back together from the Control Change messages carrying it. Those
+/// messages are still handed to [`MidiMessageEvent`] listeners one by one as well.
+pub struct ControllerChangeEvent;
+pub struct MidiPacketEvent;
 pub struct SysExPacketEvent;
+/// A complete SysEx message, read into a [`SysExMessage`]. The same message is handed to [`SysExPacketEvent`]
+/// listeners as raw bytes as well.
+pub struct SysExMessageEvent;
+pub struct SysExTooLargeEvent;
+pub struct ListenerPanickedEvent;
 pub struct ParticipantJoinedEvent;
 pub struct ParticipantLeftEvent;
+pub struct ProtocolVersionMismatchEvent;
+pub struct ParticipantLimitReachedEvent;
+pub struct InvitationFloodEvent;
+pub struct AuthenticationFailedEvent;
+pub struct AddressChangedEvent;
+pub struct ParticipantRenamedEvent;
+pub struct ClockSyncRoundEvent;
+pub struct PacketLossThresholdEvent;
+pub struct TempoChangedEvent;
+pub struct TimecodeEvent;
+pub struct TransportEvent;
+
+/// Names a listener once it has been added, for taking it away again with
+/// [`RtpMidiSession::remove_listener`](crate::sessions::rtp_midi_session::RtpMidiSession::remove_listener).
+#[derive(Clone)]
+pub struct ListenerId(Weak<dyn Any + Send + Sync>);
+
+impl ListenerId {
+    fn of<F: Send + Sync + 'static>(listener: &Arc<F>) -> Self {
+        // A weak reference keeps the listener's address from being reused, so the ID can't name another
+        ListenerId(Arc::downgrade(listener) as Weak<dyn Any + Send + Sync>)
+    }
+
+    fn names<L: ?Sized>(&self, listener: &Arc<L>) -> bool {
+        std::ptr::addr_eq(Arc::as_ptr(listener), self.0.as_ptr())
+    }
+}
+
+impl fmt::Debug for ListenerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ListenerId").field(&self.0.as_ptr()).finish()
+    }
+}
+
+/// Takes the listener `id` names out of `storage`, returning whether it was there.
+fn remove<L: ?Sized>(storage: &mut Vec<Arc<L>>, id: &ListenerId) -> bool {
+    let len = storage.len();
+    storage.retain(|listener| !id.names(listener));
+    storage.len() != len
+}
 
 pub trait EventType {
     type Data<'a>;
 
-    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F)
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
     where
-        F: for<'a> Fn(Self::Data<'a>) + Send + 'static;
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static;
 }
 
 impl EventType for MidiMessageEvent {
     type Data<'a> = (MidiMessage, u32);
 
-    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F)
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
     where
-        F: for<'a> Fn(Self::Data<'a>) + Send + 'static,
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
     {
-        listeners.midi_message.push(Box::new(callback));
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.midi_message.push(listener);
+        id
+    }
+}
+
+impl EventType for RecoveredMidiEvent {
+    type Data<'a> = (MidiMessage, u32);
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.recovered_midi.push(listener);
+        id
+    }
+}
+
+impl EventType for RecoveredSysExEvent {
+    type Data<'a> = (&'a [u8], u32);
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.recovered_sysex.push(listener);
+        id
+    }
+}
+
+impl EventType for ControllerChangeEvent {
+    type Data<'a> = (ControllerChange, u32);
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.controller_change.push(listener);
+        id
+    }
+}
+
+impl EventType for MidiPacketEvent {
+    type Data<'a> = &'a MidiPacket;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.midi_packet.push(listener);
+        id
     }
 }
 
 impl EventType for SysExPacketEvent {
     type Data<'a> = &'a [u8];
 
-    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F)
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.sysex_packet.push(listener);
+        id
+    }
+}
+
+impl EventType for SysExMessageEvent {
+    type Data<'a> = &'a SysExMessage<'a>;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.sysex_message.push(listener);
+        id
+    }
+}
+
+impl EventType for ListenerPanickedEvent {
+    type Data<'a> = &'a ListenerPanicked;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.listener_panicked.push(listener);
+        id
+    }
+}
+
+impl EventType for SysExTooLargeEvent {
+    type Data<'a> = &'a SysExTooLarge;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
     where
-        F: for<'a> Fn(Self::Data<'a>) + Send + 'static,
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
     {
-        listeners.sysex_packet.push(Box::new(callback));
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.sysex_too_large.push(listener);
+        id
     }
 }
 
 impl EventType for ParticipantJoinedEvent {
     type Data<'a> = &'a Participant;
 
-    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F)
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
     where
-        F: for<'a> Fn(Self::Data<'a>) + Send + 'static,
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
     {
-        listeners.participant_joined.push(Box::new(callback));
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.participant_joined.push(listener);
+        id
     }
 }
 
 impl EventType for ParticipantLeftEvent {
     type Data<'a> = &'a Participant;
 
-    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F)
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.participant_left.push(listener);
+        id
+    }
+}
+
+impl EventType for ProtocolVersionMismatchEvent {
+    type Data<'a> = &'a ProtocolVersionMismatch;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.protocol_version_mismatch.push(listener);
+        id
+    }
+}
+
+impl EventType for ParticipantLimitReachedEvent {
+    type Data<'a> = &'a ParticipantLimitReached;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.participant_limit_reached.push(listener);
+        id
+    }
+}
+
+impl EventType for InvitationFloodEvent {
+    type Data<'a> = &'a InvitationFlood;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.invitation_flood.push(listener);
+        id
+    }
+}
+
+impl EventType for AuthenticationFailedEvent {
+    type Data<'a> = &'a AuthenticationFailed;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.authentication_failed.push(listener);
+        id
+    }
+}
+
+impl EventType for AddressChangedEvent {
+    type Data<'a> = &'a AddressChanged;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.address_changed.push(listener);
+        id
+    }
+}
+
+impl EventType for ParticipantRenamedEvent {
+    type Data<'a> = &'a ParticipantRenamed;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
     where
-        F: for<'a> Fn(Self::Data<'a>) + Send + 'static,
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
     {
-        listeners.participant_left.push(Box::new(callback));
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.participant_renamed.push(listener);
+        id
+    }
+}
+
+impl EventType for ClockSyncRoundEvent {
+    type Data<'a> = &'a ClockSyncRound;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.clock_sync_round.push(listener);
+        id
+    }
+}
+
+impl EventType for PacketLossThresholdEvent {
+    type Data<'a> = &'a PacketLossThresholdCrossed;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.packet_loss_threshold.push(listener);
+        id
+    }
+}
+
+impl EventType for TempoChangedEvent {
+    type Data<'a> = &'a TempoChange;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.tempo_changed.push(listener);
+        id
+    }
+}
+
+impl EventType for TimecodeEvent {
+    type Data<'a> = &'a TimecodeUpdate;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.timecode.push(listener);
+        id
+    }
+}
+
+impl EventType for TransportEvent {
+    type Data<'a> = &'a TransportUpdate;
+
+    fn add_listener_to_storage<F>(listeners: &mut EventListeners, callback: F) -> ListenerId
+    where
+        F: for<'a> Fn(Self::Data<'a>) + Send + Sync + 'static,
+    {
+        let listener = Arc::new(callback);
+        let id = ListenerId::of(&listener);
+        listeners.transport.push(listener);
+        id
+    }
+}
+
+/// An event whose data can be copied out of the dispatcher, so that it can be handed to a listener on a
+/// [`tokio::task::LocalSet`] with [`RtpMidiSession::on_local`](crate::sessions::rtp_midi_session::RtpMidiSession::on_local).
+/// Events borrowing from the packet they arrived in, [`MidiPacketEvent`] and [`SysExMessageEvent`], aren't; the
+/// messages in them are handed on by the events that are.
+pub trait LocalEventType: EventType {
+    type Owned: Send + 'static;
+
+    fn to_owned(data: Self::Data<'_>) -> Self::Owned;
+}
+
+macro_rules! local_event_types {
+    ($($event:ty => $owned:ty, |$data:ident| $to_owned:expr;)*) => {
+        $(
+            impl LocalEventType for $event {
+                type Owned = $owned;
+
+                fn to_owned($data: Self::Data<'_>) -> Self::Owned {
+                    $to_owned
+                }
+            }
+        )*
+    };
+}
+
+local_event_types! {
+    MidiMessageEvent => (MidiMessage, u32), |data| data;
+    RecoveredMidiEvent => (MidiMessage, u32), |data| data;
+    RecoveredSysExEvent => (Vec<u8>, u32), |data| (data.0.to_vec(), data.1);
+    ControllerChangeEvent => (ControllerChange, u32), |data| data;
+    SysExPacketEvent => Vec<u8>, |data| data.to_vec();
+    ListenerPanickedEvent => ListenerPanicked, |data| data.clone();
+    SysExTooLargeEvent => SysExTooLarge, |data| data.clone();
+    ParticipantJoinedEvent => Participant, |data| data.clone();
+    ParticipantLeftEvent => Participant, |data| data.clone();
+    ProtocolVersionMismatchEvent => ProtocolVersionMismatch, |data| data.clone();
+    ParticipantLimitReachedEvent => ParticipantLimitReached, |data| data.clone();
+    InvitationFloodEvent => InvitationFlood, |data| data.clone();
+    AuthenticationFailedEvent => AuthenticationFailed, |data| data.clone();
+    AddressChangedEvent => AddressChanged, |data| data.clone();
+    ParticipantRenamedEvent => ParticipantRenamed, |data| data.clone();
+    ClockSyncRoundEvent => ClockSyncRound, |data| data.clone();
+    PacketLossThresholdEvent => PacketLossThresholdCrossed, |data| data.clone();
+    TempoChangedEvent => TempoChange, |data| data.clone();
+    TimecodeEvent => TimecodeUpdate, |data| data.clone();
+    TransportEvent => TransportUpdate, |data| data.clone();
+}
+
+/// Listener storage updated copy-on-write: dispatch works on a snapshot, so no lock is held while callbacks run
+/// and a callback may register further listeners.
+#[derive(Default)]
+pub(crate) struct ListenerRegistry {
+    current: RwLock<Arc<EventListeners>>,
+}
+
+impl ListenerRegistry {
+    pub fn new(listeners: EventListeners) -> Self {
+        ListenerRegistry {
+            current: RwLock::new(Arc::new(listeners)),
+        }
+    }
+
+    pub fn snapshot(&self) -> Arc<EventListeners> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+
+    pub fn update<T>(&self, update: impl FnOnce(&mut EventListeners) -> T) -> T {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let mut next = EventListeners::clone(&current);
+        let result = update(&mut next);
+        *current = Arc::new(next);
+        result
     }
 }
 
@@ -84,37 +734,311 @@ impl Default for EventListeners {
     }
 }
 
+impl fmt::Debug for EventListeners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventListeners").finish_non_exhaustive()
+    }
+}
+
 impl EventListeners {
+    /// Adds a listener for events of the given type, as
+    /// [`RtpMidiSession::add_listener`](crate::sessions::rtp_midi_session::RtpMidiSession::add_listener) does.
+    pub fn add_listener<E, F>(&mut self, _event_type: E, callback: F) -> ListenerId
+    where
+        E: EventType,
+        F: for<'a> Fn(E::Data<'a>) + Send + Sync + 'static,
+    {
+        E::add_listener_to_storage(self, callback)
+    }
+
+    /// Takes away the listener `id` names, returning whether it was there.
+    pub fn remove_listener(&mut self, id: &ListenerId) -> bool {
+        remove(&mut self.midi_message, id)
+            | remove(&mut self.recovered_midi, id)
+            | remove(&mut self.recovered_sysex, id)
+            | remove(&mut self.controller_change, id)
+            | remove(&mut self.midi_packet, id)
+            | remove(&mut self.sysex_packet, id)
+            | remove(&mut self.sysex_message, id)
+            | remove(&mut self.sysex_too_large, id)
+            | remove(&mut self.participant_joined, id)
+            | remove(&mut self.participant_left, id)
+            | remove(&mut self.protocol_version_mismatch, id)
+            | remove(&mut self.participant_limit_reached, id)
+            | remove(&mut self.invitation_flood, id)
+            | remove(&mut self.authentication_failed, id)
+            | remove(&mut self.address_changed, id)
+            | remove(&mut self.participant_renamed, id)
+            | remove(&mut self.clock_sync_round, id)
+            | remove(&mut self.packet_loss_threshold, id)
+            | remove(&mut self.tempo_changed, id)
+            | remove(&mut self.timecode, id)
+            | remove(&mut self.transport, id)
+            | remove(&mut self.listener_panicked, id)
+    }
+
     pub fn new() -> Self {
         Self {
             midi_message: Vec::new(),
+            recovered_midi: Vec::new(),
+            recovered_sysex: Vec::new(),
+            controller_change: Vec::new(),
+            midi_packet: Vec::new(),
             sysex_packet: Vec::new(),
+            sysex_message: Vec::new(),
+            sysex_too_large: Vec::new(),
             participant_joined: Vec::new(),
             participant_left: Vec::new(),
+            protocol_version_mismatch: Vec::new(),
+            participant_limit_reached: Vec::new(),
+            invitation_flood: Vec::new(),
+            authentication_failed: Vec::new(),
+            address_changed: Vec::new(),
+            participant_renamed: Vec::new(),
+            clock_sync_round: Vec::new(),
+            packet_loss_threshold: Vec::new(),
+            tempo_changed: Vec::new(),
+            timecode: Vec::new(),
+            transport: Vec::new(),
+            listener_panicked: Vec::new(),
+        }
+    }
+
+    /// Calls a listener, catching a panic so it doesn't take the dispatcher, and every listener after it, down.
+    fn guarded(&self, event: RtpMidiEventType, call: impl FnOnce()) {
+        let Err(panic) = panic::catch_unwind(AssertUnwindSafe(call)) else {
+            return;
+        };
+        let message = panic_message(panic.as_ref());
+        event!(Level::ERROR, "A {event:?} listener panicked: {}", message.as_deref().unwrap_or("(no message)"));
+        let panicked = ListenerPanicked { event, message };
+        for listener in &self.listener_panicked {
+            // Not reported again, so a listener that always panics can't keep it going
+            if panic::catch_unwind(AssertUnwindSafe(|| listener(&panicked))).is_err() {
+                event!(Level::ERROR, "A ListenerPanicked listener panicked");
+            }
         }
     }
 
     pub fn notify_midi_message(&self, message: MidiMessage, delta_time: u32) {
         for listener in &self.midi_message {
-            listener((message, delta_time));
+            self.guarded(RtpMidiEventType::MidiMessage, || listener((message, delta_time)));
+        }
+    }
+
+    pub fn notify_recovered_midi(&self, message: MidiMessage, timestamp: u32) {
+        for listener in &self.recovered_midi {
+            self.guarded(RtpMidiEventType::RecoveredMidi, || listener((message, timestamp)));
+        }
+    }
+
+    pub fn notify_recovered_sysex(&self, sysex: &[u8], timestamp: u32) {
+        for listener in &self.recovered_sysex {
+            self.guarded(RtpMidiEventType::RecoveredSysEx, || listener((sysex, timestamp)));
+        }
+    }
+
+    pub fn notify_controller_change(&self, change: ControllerChange, timestamp: u32) {
+        for listener in &self.controller_change {
+            self.guarded(RtpMidiEventType::ControllerChange, || listener((change, timestamp)));
+        }
+    }
+
+    pub fn notify_midi_packet(&self, packet: &MidiPacket) {
+        for listener in &self.midi_packet {
+            self.guarded(RtpMidiEventType::MidiPacket, || listener(packet));
         }
     }
 
     pub fn notify_sysex_packet(&self, bytes: &[u8]) {
         for listener in &self.sysex_packet {
-            listener(bytes);
+            self.guarded(RtpMidiEventType::SysExPacket, || listener(bytes));
+        }
+    }
+
+    pub fn notify_sysex_message(&self, message: &SysExMessage) {
+        for listener in &self.sysex_message {
+            self.guarded(RtpMidiEventType::SysExMessage, || listener(message));
+        }
+    }
+
+    pub fn notify_sysex_too_large(&self, oversized: &SysExTooLarge) {
+        for listener in &self.sysex_too_large {
+            self.guarded(RtpMidiEventType::SysExTooLarge, || listener(oversized));
         }
     }
 
     pub fn notify_participant_joined(&self, participant: &Participant) {
         for listener in &self.participant_joined {
-            listener(participant);
+            self.guarded(RtpMidiEventType::ParticipantJoined, || listener(participant));
         }
     }
 
     pub fn notify_participant_left(&self, participant: &Participant) {
         for listener in &self.participant_left {
-            listener(participant);
+            self.guarded(RtpMidiEventType::ParticipantLeft, || listener(participant));
+        }
+    }
+
+    pub fn notify_protocol_version_mismatch(&self, mismatch: &ProtocolVersionMismatch) {
+        for listener in &self.protocol_version_mismatch {
+            self.guarded(RtpMidiEventType::ProtocolVersionMismatch, || listener(mismatch));
+        }
+    }
+
+    pub fn notify_participant_limit_reached(&self, rejection: &ParticipantLimitReached) {
+        for listener in &self.participant_limit_reached {
+            self.guarded(RtpMidiEventType::ParticipantLimitReached, || listener(rejection));
+        }
+    }
+
+    pub fn notify_invitation_flood(&self, flood: &InvitationFlood) {
+        for listener in &self.invitation_flood {
+            self.guarded(RtpMidiEventType::InvitationFlood, || listener(flood));
+        }
+    }
+
+    pub fn notify_authentication_failed(&self, failure: &AuthenticationFailed) {
+        for listener in &self.authentication_failed {
+            self.guarded(RtpMidiEventType::AuthenticationFailed, || listener(failure));
+        }
+    }
+
+    pub fn notify_address_changed(&self, change: &AddressChanged) {
+        for listener in &self.address_changed {
+            self.guarded(RtpMidiEventType::AddressChanged, || listener(change));
+        }
+    }
+
+    pub fn notify_participant_renamed(&self, rename: &ParticipantRenamed) {
+        for listener in &self.participant_renamed {
+            self.guarded(RtpMidiEventType::ParticipantRenamed, || listener(rename));
+        }
+    }
+
+    pub fn notify_clock_sync_round(&self, round: &ClockSyncRound) {
+        for listener in &self.clock_sync_round {
+            self.guarded(RtpMidiEventType::ClockSyncRound, || listener(round));
+        }
+    }
+
+    pub fn notify_packet_loss_threshold(&self, crossing: &PacketLossThresholdCrossed) {
+        for listener in &self.packet_loss_threshold {
+            self.guarded(RtpMidiEventType::PacketLossThreshold, || listener(crossing));
+        }
+    }
+
+    pub fn notify_tempo_changed(&self, change: &TempoChange) {
+        for listener in &self.tempo_changed {
+            self.guarded(RtpMidiEventType::TempoChanged, || listener(change));
         }
     }
+
+    pub fn notify_timecode(&self, update: &TimecodeUpdate) {
+        for listener in &self.timecode {
+            self.guarded(RtpMidiEventType::Timecode, || listener(update));
+        }
+    }
+
+    pub fn notify_transport(&self, update: &TransportUpdate) {
+        for listener in &self.transport {
+            self.guarded(RtpMidiEventType::Transport, || listener(update));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_listener_can_register_listener_during_dispatch() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let registry_clone = Arc::clone(&registry);
+        let calls_clone = Arc::clone(&calls);
+        registry.update(|listeners| {
+            SysExPacketEvent::add_listener_to_storage(listeners, move |_data| {
+                let calls = Arc::clone(&calls_clone);
+                registry_clone.update(|listeners| {
+                    SysExPacketEvent::add_listener_to_storage(listeners, move |_data| {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                    })
+                });
+            })
+        });
+
+        // The snapshot taken for dispatch is unaffected by registrations made while it runs
+        registry.snapshot().notify_sysex_packet(&[]);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        registry.snapshot().notify_sysex_packet(&[]);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_removed_listener_is_no_longer_called() {
+        let mut listeners = EventListeners::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let ids = [1, 10].map(|step| {
+            let calls = Arc::clone(&calls);
+            listeners.add_listener(SysExPacketEvent, move |_data| {
+                calls.fetch_add(step, Ordering::SeqCst);
+            })
+        });
+
+        assert!(listeners.remove_listener(&ids[0]));
+        assert!(!listeners.remove_listener(&ids[0]));
+        listeners.notify_sysex_packet(&[]);
+        assert_eq!(calls.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn test_panicking_listener_is_reported_and_the_rest_still_run() {
+        let registry = Arc::new(ListenerRegistry::default());
+        let calls = Arc::new(AtomicUsize::new(0));
+        let panics = Arc::new(RwLock::new(Vec::new()));
+
+        let calls_clone = Arc::clone(&calls);
+        let panics_clone = Arc::clone(&panics);
+        registry.update(|listeners| {
+            SysExPacketEvent::add_listener_to_storage(listeners, |data| panic!("bad SysEx {data:?}"));
+            SysExPacketEvent::add_listener_to_storage(listeners, move |_data| {
+                calls_clone.fetch_add(1, Ordering::SeqCst);
+            });
+            ListenerPanickedEvent::add_listener_to_storage(listeners, move |panicked| panics_clone.write().unwrap().push(panicked.clone()));
+            ListenerPanickedEvent::add_listener_to_storage(listeners, |_panicked| panic!("and again"));
+        });
+
+        registry.snapshot().notify_sysex_packet(&[1]);
+        registry.snapshot().notify_sysex_packet(&[2]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *panics.read().unwrap(),
+            [1, 2].map(|byte| ListenerPanicked {
+                event: RtpMidiEventType::SysExPacket,
+                message: Some(format!("bad SysEx [{byte}]")),
+            })
+        );
+    }
+
+    #[test]
+    fn test_recovered_messages_are_kept_apart_from_live_ones() {
+        let live = Arc::new(AtomicUsize::new(0));
+        let recovered = Arc::new(RwLock::new(Vec::new()));
+        let mut listeners = EventListeners::new();
+        let live_clone = Arc::clone(&live);
+        listeners.add_listener(MidiMessageEvent, move |_message| {
+            live_clone.fetch_add(1, Ordering::SeqCst);
+        });
+        let recovered_clone = Arc::clone(&recovered);
+        listeners.add_listener(RecoveredMidiEvent, move |message| recovered_clone.write().unwrap().push(message));
+
+        listeners.notify_recovered_midi(MidiMessage::TimingClock, 10);
+        assert_eq!(live.load(Ordering::SeqCst), 0);
+        assert_eq!(*recovered.read().unwrap(), [(MidiMessage::TimingClock, 10)]);
+    }
 }
@@ -1 +1,4 @@
+pub(crate) mod event_dispatcher;
 pub mod event_handling;
+pub(crate) mod reorder_buffer;
+pub(crate) mod tempo_estimator;
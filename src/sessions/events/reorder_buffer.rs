@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use zerocopy::network_endian::U32;
+
+use crate::sessions::session_config::ReorderWindow;
+
+/// Holds back MIDI packets that arrive ahead of the ones before them, so that each sender's packets are handed on in
+/// sequence number order. A packet waits until the gap in front of it fills, until more than the window's depth are
+/// waiting, or until it has waited the window's latency; after that the missing packets are given up on.
+///
+/// Each packet is held as a `T`, the datagram along with whatever else the dispatcher needs to keep with it.
+pub(crate) struct ReorderBuffer<T = Vec<u8>> {
+    window: ReorderWindow,
+    streams: HashMap<U32, Stream<T>>, // keyed by sender ssrc
+}
+
+struct Stream<T> {
+    next: Option<u16>,
+    held: Vec<HeldPacket<T>>, // ordered by how far ahead of `next` they are
+}
+
+impl<T> Default for Stream<T> {
+    fn default() -> Self {
+        Stream { next: None, held: Vec::new() }
+    }
+}
+
+struct HeldPacket<T> {
+    sequence_number: u16,
+    arrived: Instant,
+    datagram: T,
+}
+
+impl<T> ReorderBuffer<T> {
+    pub fn new(window: ReorderWindow) -> Self {
+        ReorderBuffer {
+            window,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// The datagrams being held back, in no particular order.
+    pub fn held(&self) -> impl Iterator<Item = &T> {
+        self.streams.values().flat_map(|stream| stream.held.iter().map(|held| &held.datagram))
+    }
+
+    /// Takes a received packet, returning the datagrams that are now ready, in the order to dispatch them.
+    pub fn push(&mut self, ssrc: U32, sequence_number: u16, datagram: T, now: Instant) -> Vec<T> {
+        let stream = self.streams.entry(ssrc).or_default();
+        let next = *stream.next.get_or_insert(sequence_number);
+        let mut ready = Vec::new();
+
+        match sequence_number.wrapping_sub(next) {
+            0 => {
+                ready.push(datagram);
+                stream.next = Some(sequence_number.wrapping_add(1));
+            }
+            ahead @ 1..0x8000 => {
+                let position = stream.held.partition_point(|held| held.sequence_number.wrapping_sub(next) < ahead);
+                stream.held.insert(
+                    position,
+                    HeldPacket {
+                        sequence_number,
+                        arrived: now,
+                        datagram,
+                    },
+                );
+                if stream.held.len() > self.window.depth {
+                    stream.release_through(0, &mut ready);
+                }
+            }
+            // Older than packets that have already been handed on, too late to put back in place
+            _ => ready.push(datagram),
+        }
+        stream.release_in_sequence(&mut ready);
+        ready
+    }
+
+    /// Gives up on the gaps in front of packets that have waited the window's latency, returning the datagrams that
+    /// are now ready.
+    pub fn release_overdue(&mut self, now: Instant) -> Vec<T> {
+        let mut ready = Vec::new();
+        for stream in self.streams.values_mut() {
+            if let Some(last_overdue) = stream.held.iter().rposition(|held| held.arrived + self.window.latency <= now) {
+                stream.release_through(last_overdue, &mut ready);
+                stream.release_in_sequence(&mut ready);
+            }
+        }
+        ready
+    }
+
+    /// When the next held packet becomes overdue, if any are held.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let held = self.streams.values().flat_map(|stream| &stream.held);
+        held.map(|held| held.arrived + self.window.latency).min()
+    }
+
+    /// Hands on the packets held for `ssrc`, in order, and forgets where its sequence numbers were up to.
+    pub fn remove(&mut self, ssrc: U32) -> Vec<T> {
+        let held = self.streams.remove(&ssrc).map(|stream| stream.held).unwrap_or_default();
+        held.into_iter().map(|held| held.datagram).collect()
+    }
+
+    /// Hands on every held packet, in order.
+    pub fn flush(&mut self) -> Vec<T> {
+        let streams = self.streams.values_mut();
+        streams.flat_map(|stream| stream.held.drain(..).map(|held| held.datagram)).collect()
+    }
+}
+
+impl<T> Stream<T> {
+    /// Hands on the held packets up to and including the one at `index`, skipping whatever is missing before them.
+    fn release_through(&mut self, index: usize, ready: &mut Vec<T>) {
+        for held in self.held.drain(..=index) {
+            self.next = Some(held.sequence_number.wrapping_add(1));
+            ready.push(held.datagram);
+        }
+    }
+
+    fn release_in_sequence(&mut self, ready: &mut Vec<T>) {
+        while self.held.first().is_some_and(|held| Some(held.sequence_number) == self.next) {
+            self.release_through(0, ready);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const SSRC: U32 = U32::new(1);
+
+    fn buffer(depth: usize) -> ReorderBuffer {
+        ReorderBuffer::new(ReorderWindow {
+            depth,
+            latency: Duration::from_millis(10),
+        })
+    }
+
+    fn push(buffer: &mut ReorderBuffer, sequence_number: u16, now: Instant) -> Vec<u8> {
+        let ready = buffer.push(SSRC, sequence_number, vec![sequence_number as u8], now);
+        ready.into_iter().flatten().collect()
+    }
+
+    #[test]
+    fn test_packets_are_put_back_in_order() {
+        let mut buffer = buffer(4);
+        let now = Instant::now();
+        assert_eq!(push(&mut buffer, 1, now), [1]);
+        assert!(push(&mut buffer, 3, now).is_empty());
+        assert!(push(&mut buffer, 4, now).is_empty());
+        assert_eq!(push(&mut buffer, 2, now), [2, 3, 4]);
+        assert_eq!(buffer.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_gap_is_skipped_when_the_window_is_full() {
+        let mut buffer = buffer(2);
+        let now = Instant::now();
+        push(&mut buffer, 1, now);
+        assert!(push(&mut buffer, 4, now).is_empty());
+        assert!(push(&mut buffer, 3, now).is_empty());
+        assert_eq!(push(&mut buffer, 6, now), [3, 4]);
+        assert_eq!(push(&mut buffer, 5, now), [5, 6]);
+    }
+
+    #[test]
+    fn test_gap_is_skipped_after_the_latency() {
+        let mut buffer = buffer(4);
+        let now = Instant::now();
+        push(&mut buffer, 1, now);
+        push(&mut buffer, 3, now);
+        push(&mut buffer, 5, now + Duration::from_millis(5));
+        assert_eq!(buffer.next_deadline(), Some(now + Duration::from_millis(10)));
+
+        let ready = buffer.release_overdue(now + Duration::from_millis(10));
+        assert_eq!(ready, [vec![3]]);
+        assert_eq!(push(&mut buffer, 4, now + Duration::from_millis(11)), [4, 5]);
+    }
+
+    #[test]
+    fn test_late_packets_are_passed_straight_on() {
+        let mut buffer = buffer(4);
+        let now = Instant::now();
+        push(&mut buffer, 10, now);
+        assert_eq!(push(&mut buffer, 8, now), [8]);
+    }
+
+    #[test]
+    fn test_sequence_numbers_wrap() {
+        let mut buffer = buffer(4);
+        let now = Instant::now();
+        push(&mut buffer, u16::MAX - 1, now);
+        assert!(push(&mut buffer, 0, now).is_empty());
+        assert_eq!(push(&mut buffer, u16::MAX, now), [u16::MAX as u8, 0]);
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use zerocopy::network_endian::U32;
+
+/// Timestamps count in units of 100 microseconds.
+const TIMESTAMP_RATE: f64 = 10_000.0;
+const TICKS_PER_BEAT: f64 = 24.0;
+/// How much each new tick interval moves the estimate.
+const SMOOTHING: f64 = 0.1;
+/// Ticks needed before the first estimate is reported, a quarter of a beat.
+const WARM_UP_TICKS: u32 = 6;
+/// The smallest change in the estimate worth reporting, in beats per minute.
+const REPORT_THRESHOLD: f64 = 0.1;
+/// A gap longer than a tick at 10 beats per minute means the clock was stopped, so the estimate starts over.
+const MAX_TICK_INTERVAL: u32 = (60.0 * TIMESTAMP_RATE / (10.0 * TICKS_PER_BEAT)) as u32;
+
+/// Shared between the dispatcher, which feeds the estimators, and the session, which reads them.
+pub(crate) type TempoEstimators = Mutex<HashMap<U32, TempoEstimator>>; // keyed by sender ssrc
+
+/// Follows the tempo of one sender's MIDI clock (24 TimingClock messages per beat), from the timestamps the sender
+/// gave the ticks rather than when they arrived, so network jitter doesn't show up in the estimate.
+#[derive(Debug, Default)]
+pub(crate) struct TempoEstimator {
+    last_tick: Option<u32>,
+    interval: Option<f64>, // smoothed, in timestamp units
+    ticks: u32,
+    reported: Option<f64>,
+}
+
+impl TempoEstimator {
+    /// Takes the timestamp of a received tick, returning the estimate in beats per minute if it has changed enough
+    /// to report.
+    pub fn tick(&mut self, timestamp: u32) -> Option<f64> {
+        let last_tick = self.last_tick.replace(timestamp);
+        let interval = timestamp.wrapping_sub(last_tick?);
+        if interval == 0 || interval > MAX_TICK_INTERVAL {
+            self.restart();
+            self.last_tick = Some(timestamp);
+            return None;
+        }
+
+        let interval = interval as f64;
+        let smoothed = self.interval.map_or(interval, |smoothed| smoothed + SMOOTHING * (interval - smoothed));
+        self.interval = Some(smoothed);
+        self.ticks += 1;
+        if self.ticks < WARM_UP_TICKS {
+            return None;
+        }
+
+        let bpm = 60.0 * TIMESTAMP_RATE / (TICKS_PER_BEAT * smoothed);
+        if self.reported.is_some_and(|reported| (bpm - reported).abs() < REPORT_THRESHOLD) {
+            return None;
+        }
+        self.reported = Some(bpm);
+        Some(bpm)
+    }
+
+    /// The latest estimate reported.
+    pub fn bpm(&self) -> Option<f64> {
+        self.reported
+    }
+
+    /// Forgets the ticks so far, for when the clock has stopped.
+    pub fn restart(&mut self) {
+        *self = TempoEstimator::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ticks at `bpm` from `start`, returning the last estimate reported.
+    fn ticks(estimator: &mut TempoEstimator, start: u32, bpm: f64, count: u32) -> Option<f64> {
+        let interval = 60.0 * TIMESTAMP_RATE / (TICKS_PER_BEAT * bpm);
+        (0..count)
+            .filter_map(|i| estimator.tick(start.wrapping_add((i as f64 * interval) as u32)))
+            .last()
+    }
+
+    #[test]
+    fn test_steady_clock_is_estimated() {
+        let mut estimator = TempoEstimator::default();
+        assert_eq!(ticks(&mut estimator, 0, 120.0, WARM_UP_TICKS), None);
+        let bpm = ticks(&mut estimator, 0, 120.0, 2 * 24).unwrap();
+        assert!((bpm - 120.0).abs() < 1.0, "{bpm}");
+    }
+
+    #[test]
+    fn test_estimate_follows_a_tempo_change() {
+        let mut estimator = TempoEstimator::default();
+        ticks(&mut estimator, 0, 120.0, 2 * 24);
+        ticks(&mut estimator, 10_000, 90.0, 4 * 24);
+        let bpm = estimator.bpm().unwrap();
+        assert!((bpm - 90.0).abs() < 1.0, "{bpm}");
+    }
+
+    #[test]
+    fn test_long_gap_starts_over() {
+        let mut estimator = TempoEstimator::default();
+        ticks(&mut estimator, u32::MAX - 1000, 120.0, 2 * 24);
+        assert!(estimator.bpm().is_some());
+        assert_eq!(estimator.tick(u32::MAX / 2), None);
+        assert_eq!(estimator.bpm(), None);
+    }
+}
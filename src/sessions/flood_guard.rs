@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, PoisonError};
+use std::time::Instant;
+
+use crate::sessions::session_config::FloodProtection;
+
+/// What to do with an invitation, going by how many the address has sent lately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    Allow,
+    /// The address has just gone over the limit, and is banned from now on.
+    Ban,
+    /// The address is still serving a ban.
+    Banned,
+}
+
+/// What becomes of an invitation that has been through the flood protection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Screening {
+    Admit,
+    /// Turned down with a rejection, as the peer might be honest.
+    Reject,
+    /// Dropped without an answer, so a flood gets nothing back.
+    Ignore,
+}
+
+#[derive(Debug)]
+struct Sender {
+    window_start: Instant,
+    invitations: u32,
+    banned_until: Option<Instant>,
+}
+
+/// Counts the invitations each address sends, and bans the ones that send too many.
+#[derive(Debug)]
+pub(crate) struct FloodGuard {
+    protection: FloodProtection,
+    senders: Mutex<HashMap<IpAddr, Sender>>,
+}
+
+impl FloodGuard {
+    pub fn new(protection: FloodProtection) -> Self {
+        FloodGuard {
+            protection,
+            senders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn protection(&self) -> &FloodProtection {
+        &self.protection
+    }
+
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Verdict {
+        let mut senders = self.senders.lock().unwrap_or_else(PoisonError::into_inner);
+        // Forget the addresses that have gone quiet, so spoofed ones can't fill the map for good
+        senders.retain(|_, sender| sender.banned_until.is_some_and(|until| until > now) || now.duration_since(sender.window_start) < self.protection.window);
+
+        let sender = senders.entry(ip).or_insert(Sender {
+            window_start: now,
+            invitations: 0,
+            banned_until: None,
+        });
+        if sender.banned_until.is_some_and(|until| until > now) {
+            return Verdict::Banned;
+        }
+        if now.duration_since(sender.window_start) >= self.protection.window {
+            sender.window_start = now;
+            sender.invitations = 0;
+        }
+        sender.invitations += 1;
+        if sender.invitations > self.protection.max_invitations {
+            sender.banned_until = Some(now + self.protection.ban);
+            return Verdict::Ban;
+        }
+        Verdict::Allow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn guard() -> FloodGuard {
+        FloodGuard::new(FloodProtection {
+            max_invitations: 2,
+            window: Duration::from_secs(1),
+            max_handshakes: 4,
+            ban: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn test_too_many_invitations_get_the_address_banned() {
+        let guard = guard();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+        assert_eq!(guard.check(ip, now), Verdict::Allow);
+        assert_eq!(guard.check(ip, now), Verdict::Allow);
+        assert_eq!(guard.check(ip, now), Verdict::Ban);
+        assert_eq!(guard.check(ip, now + Duration::from_secs(5)), Verdict::Banned);
+        assert_eq!(guard.check("10.0.0.2".parse().unwrap(), now), Verdict::Allow);
+        assert_eq!(guard.check(ip, now + Duration::from_secs(10)), Verdict::Allow);
+    }
+
+    #[test]
+    fn test_the_count_starts_over_each_window() {
+        let guard = guard();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+        for second in 0..5 {
+            let now = now + Duration::from_secs(second);
+            assert_eq!(guard.check(ip, now), Verdict::Allow);
+            assert_eq!(guard.check(ip, now), Verdict::Allow);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt::Write;
+use std::net::SocketAddr;
+
+use crate::logging::{Level, event};
+use crate::packets::packet::RtpMidiPacket;
+use crate::packets::parse_mode::ParseMode;
+use crate::sessions::interceptor::Direction;
+
+/// Logs a datagram at TRACE. Packets that parse get the annotated dump of [`RtpMidiPacket::describe`]; anything
+/// else is headed by what it looks like it is, then dumped as hex and ASCII, sixteen bytes a line.
+pub(crate) fn log_datagram(direction: Direction, addr: SocketAddr, bytes: &[u8]) {
+    let arrow = match direction {
+        Direction::Inbound => "from",
+        Direction::Outbound => "to",
+    };
+    event!(Level::TRACE, "{} bytes {arrow} {addr}, {}", bytes.len(), describe(bytes));
+}
+
+fn describe(bytes: &[u8]) -> String {
+    match RtpMidiPacket::parse(bytes, ParseMode::Lenient) {
+        Ok(packet) => packet.describe().trim_end().to_owned(),
+        Err(_) if crate::sessions::stun::is_stun(bytes) => format!("STUN\n{}", hexdump(bytes)),
+        Err(e) => format!("not recognised ({e})\n{}", hexdump(bytes)),
+    }
+}
+
+fn hexdump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:04X} ", line * 16);
+        for i in 0..16 {
+            match chunk.get(i) {
+                Some(byte) => {
+                    let _ = write!(out, " {byte:02X}");
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  ");
+        out.extend(
+            chunk
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }),
+        );
+        out.push('\n');
+    }
+    out.pop();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let rtp = [0x80, 0x61, 0x00, 0x07, 0, 0, 0, 0, 0x12, 0x34, 0x56, 0x78, 0x03, 0x90, 0x3C, 0x64];
+        assert_eq!(
+            describe(&rtp),
+            "RTP-MIDI packet, 16 bytes\n\
+             0000  80 61                    Version 2, P=0, X=0, CC=0, M=0, payload type 97\n\
+             0002  00 07                    Sequence number: 7\n\
+             0004  00 00 00 00              Timestamp: 0\n\
+             0008  12 34 56 78              SSRC: 0x12345678\n\
+             000C  03                       Command list: B=0, J=0, Z=0, P=0, length 3\n\
+             000D  90 3C 64                 Command at delta 0: NoteOn(Channel(0), Note(60), Value7(100))"
+        );
+        assert!(describe(&[0xFF, 0xFF, b'C', b'K', 0x11]).starts_with("not recognised ("));
+        assert!(describe(&[1, 2, 3]).ends_with("\n0000  01 02 03                                         ..."));
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let bytes: Vec<u8> = (0x30..0x42).collect();
+        assert_eq!(
+            hexdump(&bytes),
+            "0000  30 31 32 33 34 35 36 37 38 39 3A 3B 3C 3D 3E 3F  0123456789:;<=>?\n\
+             0010  40 41                                            @A"
+        );
+    }
+}
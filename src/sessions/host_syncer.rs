@@ -1,6 +1,6 @@
 use super::rtp_midi_session::RtpMidiSession;
+use crate::logging::{Level, event};
 use std::time::{Duration, Instant};
-use tracing::{Level, event, instrument};
 use zerocopy::U64;
 
 pub(super) struct HostSyncer {}
@@ -10,7 +10,7 @@ impl HostSyncer {
     }
 
     async fn cleanup_stale_participants(&self, ctx: &RtpMidiSession) {
-        let lock = ctx.participants.lock().await;
+        let lock = ctx.participants.read().await;
 
         if lock.is_empty() {
             event!(Level::DEBUG, "No participants to clean up");
@@ -36,21 +36,21 @@ impl HostSyncer {
 
     async fn send_clock_syncs(&self, ctx: &RtpMidiSession) {
         let timestamps = [U64::new(0); 3];
-        let lock = ctx.participants.lock().await;
-        let participants: Vec<_> = lock.values().cloned().collect();
-        drop(lock);
+        let participants = ctx.participants.read().await;
 
         if !participants.is_empty() {
             event!(Level::DEBUG, "Sending clock sync to {} participants", participants.len());
-            ctx.midi_port.send_clock_sync(&participants, timestamps, 0).await;
+            ctx.midi_port.send_clock_sync(participants.values(), timestamps, 0).await;
         } else {
             event!(Level::DEBUG, "No participants to send clock sync to");
         }
     }
 
-    #[instrument(skip_all, fields(name = %ctx.name()))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %ctx.name())))]
     pub async fn cleanup(&self, ctx: &RtpMidiSession) {
         self.cleanup_stale_participants(ctx).await;
+        ctx.expire_invitations().await;
+        ctx.expire_authentications().await;
         self.send_clock_syncs(ctx).await;
     }
 }
@@ -0,0 +1,177 @@
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::packets::midi_packets::midi_event::MidiEvent;
+use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use crate::participant::Participant;
+
+/// Which way a message intercepted by an [`Interceptor`] is going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Direction {
+    /// Received from the participant, on its way to the listeners.
+    Inbound,
+    /// On its way to the participant.
+    Outbound,
+}
+
+/// What an [`Interceptor`] wants done with a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Hand the message, with any changes made to it, on to the next interceptor.
+    Pass,
+    /// Drop the message. The interceptors after this one don't see it.
+    Drop,
+    /// Hand this message on instead.
+    Replace(RtpMidiMessage<'static>),
+}
+
+/// A hook that sees each message sent to or received from a participant, and can change it, drop it or replace it.
+/// Interceptors are called in the order they were added, on the sending or receiving task, so they should be quick
+/// and must not block.
+///
+/// Messages received go through the interceptors after the participant's incoming channel map, and messages sent go
+/// through them before its outgoing channel map. Received SysEx is put back together before the interceptors see it,
+/// but SysEx too large for one packet is seen in its segments on the way out. Received messages are only
+/// intercepted if they come from a participant, and
+/// [`MidiPacketEvent`](crate::sessions::events::event_handling::MidiPacketEvent) listeners still get the packets as
+/// they were received.
+///
+/// ```
+/// use midi_types::{MidiMessage, Value7};
+/// use rtpmidi::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+/// use rtpmidi::sessions::interceptor::{Action, Direction};
+/// use rtpmidi::sessions::rtp_midi_session::RtpMidiSession;
+///
+/// // Halves the velocity of the notes sent, and keeps SysEx to ourselves
+/// fn soften(session: &RtpMidiSession) {
+///     session.add_interceptor(|message, direction, _participant| match message {
+///         RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(_, _, velocity)) if direction == Direction::Outbound => {
+///             *velocity = Value7::from(u8::from(*velocity) / 2);
+///             Action::Pass
+///         }
+///         RtpMidiMessage::SysEx(_) if direction == Direction::Outbound => Action::Drop,
+///         _ => Action::Pass,
+///     });
+/// }
+/// ```
+pub type Interceptor = dyn for<'a, 'b> Fn(&'b mut RtpMidiMessage<'a>, Direction, &Participant) -> Action + Send + Sync + 'static;
+
+/// The interceptors of a session, shared by the sending path and the dispatcher. Kept as a snapshot that is replaced
+/// as a whole, so running the chain never waits for one being added.
+#[derive(Default)]
+pub(crate) struct InterceptorChain {
+    current: RwLock<Arc<Vec<Arc<Interceptor>>>>,
+}
+
+impl InterceptorChain {
+    pub fn push(&self, interceptor: Arc<Interceptor>) {
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let mut next = Vec::clone(&current);
+        next.push(interceptor);
+        *current = Arc::new(next);
+    }
+
+    pub fn clear(&self) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = Arc::default();
+    }
+
+    pub fn snapshot(&self) -> Interceptors {
+        Interceptors(Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner)))
+    }
+}
+
+/// The interceptors of a session at one point in time.
+pub(crate) struct Interceptors(Arc<Vec<Arc<Interceptor>>>);
+
+impl Interceptors {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Runs the message through every interceptor, returning what comes out the end, if anything.
+    pub fn apply<'a>(&self, mut message: RtpMidiMessage<'a>, direction: Direction, participant: &Participant) -> Option<RtpMidiMessage<'a>> {
+        for interceptor in self.0.iter() {
+            match interceptor(&mut message, direction, participant) {
+                Action::Pass => {}
+                Action::Drop => return None,
+                Action::Replace(replacement) => message = replacement,
+            }
+        }
+        Some(message)
+    }
+
+    /// Runs each event of an outgoing batch through the interceptors. The delta times of dropped events are added to
+    /// the event after them, so the ones that are left keep their timing.
+    pub fn apply_to_events<'a>(&self, events: &[MidiEvent<'a>], participant: &Participant) -> Vec<MidiEvent<'a>> {
+        let mut carried = 0u32;
+        let mut intercepted = Vec::with_capacity(events.len());
+        for event in events {
+            match self.apply(event.command().clone(), Direction::Outbound, participant) {
+                Some(message) => {
+                    let delta_time = event.delta_time().saturating_add(carried);
+                    intercepted.push(MidiEvent::new(Some(delta_time), message));
+                    carried = 0;
+                }
+                None => carried = carried.saturating_add(event.delta_time()),
+            }
+        }
+        intercepted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use midi_types::{Channel, MidiMessage, Note, Value7};
+    use zerocopy::network_endian::U32;
+
+    fn participant() -> Participant {
+        Participant::new("127.0.0.1:5004".parse().unwrap(), false, None, "Peer", U32::new(1))
+    }
+
+    #[test]
+    fn test_interceptors_run_in_order() {
+        let chain = InterceptorChain::default();
+        chain.push(Arc::new(|message: &mut RtpMidiMessage, _: Direction, _: &Participant| {
+            if let RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(_, note, _)) = message {
+                *note = Note::from(u8::from(*note) + 12);
+            }
+            Action::Pass
+        }));
+        chain.push(Arc::new(|message: &mut RtpMidiMessage, _: Direction, _: &Participant| match message {
+            RtpMidiMessage::SysEx(_) => Action::Drop,
+            RtpMidiMessage::MidiMessage(MidiMessage::TimingClock) => Action::Replace(MidiMessage::Start.into()),
+            _ => Action::Pass,
+        }));
+
+        let interceptors = chain.snapshot();
+        let note_on = |note| RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::from(note), Value7::from(100)));
+        assert_eq!(interceptors.apply(note_on(60), Direction::Inbound, &participant()), Some(note_on(72)));
+        assert_eq!(interceptors.apply(RtpMidiMessage::SysEx(&[0x7E]), Direction::Inbound, &participant()), None);
+        assert_eq!(
+            interceptors.apply(MidiMessage::TimingClock.into(), Direction::Outbound, &participant()),
+            Some(MidiMessage::Start.into())
+        );
+
+        chain.clear();
+        assert!(chain.snapshot().is_empty());
+        // Snapshots taken before keep running the chain they were taken from
+        assert!(!interceptors.is_empty());
+    }
+
+    #[test]
+    fn test_dropped_events_keep_the_timing_of_the_rest() {
+        let chain = InterceptorChain::default();
+        chain.push(Arc::new(|message: &mut RtpMidiMessage, _: Direction, _: &Participant| match message {
+            RtpMidiMessage::SysEx(_) => Action::Drop,
+            _ => Action::Pass,
+        }));
+        let events = [
+            MidiEvent::new(None, MidiMessage::Start.into()),
+            MidiEvent::new(Some(5), RtpMidiMessage::SysEx(&[0x7E])),
+            MidiEvent::new(Some(3), MidiMessage::Stop.into()),
+        ];
+        let intercepted = chain.snapshot().apply_to_events(&events, &participant());
+        assert_eq!(intercepted.iter().map(MidiEvent::delta_time).collect::<Vec<_>>(), [0, 8], "{intercepted:?}");
+    }
+}
@@ -1,8 +1,8 @@
-use std::{ffi::CStr, net::SocketAddr};
+use std::net::SocketAddr;
 
 use crate::packets::control_packets::session_initiation_packet::SessionInitiationPacketBody;
 
-pub type InviteHandler = dyn Fn(&SessionInitiationPacketBody, &CStr, &SocketAddr) -> bool + Send + Sync + 'static;
+pub type InviteHandler = dyn Fn(&SessionInitiationPacketBody, &str, &SocketAddr) -> bool + Send + Sync + 'static;
 
 pub enum InviteResponder {
     Accept,
@@ -11,7 +11,7 @@ pub enum InviteResponder {
 }
 
 impl InviteResponder {
-    pub fn handle(&self, packet: &SessionInitiationPacketBody, name: &CStr, addr: &SocketAddr) -> bool {
+    pub fn handle(&self, packet: &SessionInitiationPacketBody, name: &str, addr: &SocketAddr) -> bool {
         match self {
             InviteResponder::Accept => true,
             InviteResponder::Reject => false,
@@ -21,7 +21,7 @@ impl InviteResponder {
 
     pub fn new<F>(handler: F) -> InviteResponder
     where
-        F: Fn(&SessionInitiationPacketBody, &CStr, &SocketAddr) -> bool + Send + Sync + 'static,
+        F: Fn(&SessionInitiationPacketBody, &str, &SocketAddr) -> bool + Send + Sync + 'static,
     {
         InviteResponder::Custom(Box::new(handler))
     }
@@ -0,0 +1,37 @@
+use std::net::SocketAddr;
+
+use crate::participant::Participant;
+
+/// A peer the session was connected to, saved so it can be invited again later, e.g. after a restart. With the
+/// `serde` feature it can be stored in whatever format suits.
+///
+/// ```no_run
+/// use rtpmidi::sessions::invite_responder::InviteResponder;
+/// use rtpmidi::sessions::rtp_midi_session::RtpMidiSession;
+/// use rtpmidi::sessions::session_config::SessionConfig;
+///
+/// # async fn example(session: &RtpMidiSession) -> Result<(), rtpmidi::error::RtpMidiError> {
+/// let peers = session.export_peers().await;
+/// // ...saved before a power cycle, and loaded again once back up...
+/// let config = SessionConfig { peers, ..Default::default() };
+/// let session = RtpMidiSession::start_with_config(5004, "My Session", rand::random(), InviteResponder::Accept, config).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KnownPeer {
+    /// The peer's control port.
+    pub addr: SocketAddr,
+    /// What the peer called itself, for display only. It is asked again when invited.
+    pub name: String,
+}
+
+impl From<&Participant> for KnownPeer {
+    fn from(participant: &Participant) -> Self {
+        KnownPeer {
+            addr: participant.addr(),
+            name: participant.name().to_owned(),
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::fmt;
+
+use midi_types::{Channel, Control, MidiMessage, Value7};
+
+/// MIDI packets from a participant that went missing and won't be sent again, handed to a [`LossConcealment`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketLoss {
+    pub ssrc: u32,
+    /// How many packets are missing.
+    pub missing: u16,
+    /// The channels the participant has notes sounding on, or the sustain pedal down, as far as what has been
+    /// received shows. Anything the missing packets did on them is unknown.
+    pub sounding_channels: Vec<Channel>,
+}
+
+/// What a session does about MIDI packets that are lost for good, set with
+/// [`SessionConfig::loss_concealment`](super::session_config::SessionConfig::loss_concealment). Without a recovery
+/// journal, a lost NoteOff leaves a note hanging, so the conservative choice is to end whatever was sounding.
+///
+/// The messages [`conceal`](LossConcealment::conceal) returns are handed to
+/// [`RecoveredMidiEvent`](super::events::event_handling::RecoveredMidiEvent) listeners, as if from the participant,
+/// before the packet after the loss. A loss is only concealed once it's known to be for good: right away without
+/// [`SessionConfig::retransmission`](super::session_config::SessionConfig::retransmission), and otherwise only for
+/// the packets too far back to be asked for. A reorder window doesn't hold it up, so a packet that was only
+/// reordered may turn up after its loss has been concealed.
+///
+/// Closures taking a [`PacketLoss`] are policies too:
+///
+/// ```
+/// use std::sync::Arc;
+/// use midi_types::MidiMessage;
+/// use rtpmidi::sessions::loss_concealment::PacketLoss;
+/// use rtpmidi::sessions::session_config::SessionConfig;
+///
+/// let config = SessionConfig {
+///     loss_concealment: Some(Arc::new(|loss: &PacketLoss| {
+///         loss.sounding_channels.iter().map(|&channel| MidiMessage::ChannelPressure(channel, 0.into())).collect()
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+pub trait LossConcealment: Send + Sync + 'static {
+    /// The messages that make up for the loss, in the order they're handed on.
+    fn conceal(&self, loss: &PacketLoss) -> Vec<MidiMessage>;
+}
+
+impl fmt::Debug for dyn LossConcealment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LossConcealment")
+    }
+}
+
+impl<F> LossConcealment for F
+where
+    F: Fn(&PacketLoss) -> Vec<MidiMessage> + Send + Sync + 'static,
+{
+    fn conceal(&self, loss: &PacketLoss) -> Vec<MidiMessage> {
+        self(loss)
+    }
+}
+
+/// Lifts the sustain pedal and ends every note on the channels that had something sounding.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReleaseNotes;
+
+const SUSTAIN: u8 = 64;
+const ALL_NOTES_OFF: u8 = 123;
+
+impl LossConcealment for ReleaseNotes {
+    fn conceal(&self, loss: &PacketLoss) -> Vec<MidiMessage> {
+        loss.sounding_channels
+            .iter()
+            .flat_map(|&channel| [SUSTAIN, ALL_NOTES_OFF].map(|control| MidiMessage::ControlChange(channel, Control::from(control), Value7::from(0))))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_release_notes_ends_what_was_sounding() {
+        let loss = PacketLoss {
+            ssrc: 1,
+            missing: 2,
+            sounding_channels: vec![Channel::C1, Channel::C10],
+        };
+        let cc = |channel, control| MidiMessage::ControlChange(channel, Control::from(control), Value7::from(0));
+        assert_eq!(
+            ReleaseNotes.conceal(&loss),
+            [cc(Channel::C1, 64), cc(Channel::C1, 123), cc(Channel::C10, 64), cc(Channel::C10, 123)]
+        );
+    }
+}
@@ -1,15 +1,43 @@
 #[cfg(feature = "mdns")]
-pub fn advertise_mdns(instance_name: &str, port: u16) -> Result<mdns_sd::ServiceDaemon, mdns_sd::Error> {
-    use mdns_sd::{ServiceDaemon, ServiceInfo};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
 
-    let mdns = ServiceDaemon::new()?;
-    let service_type = "_apple-midi._udp.local.";
-    let ip = local_ip_address::local_ip().expect("Failed to get local IP address").to_string();
+/// A session's service record, registered on either its own daemon or one shared by a
+/// [`SessionManager`](super::session_manager::SessionManager).
+#[cfg(feature = "mdns")]
+#[derive(Clone)]
+pub(super) struct MdnsAdvertisement {
+    daemon: ServiceDaemon,
+    fullname: String,
+    owns_daemon: bool,
+}
+
+#[cfg(feature = "mdns")]
+impl MdnsAdvertisement {
+    /// Advertises the session on `daemon`, or on a daemon of its own if there isn't one to share.
+    pub fn start(daemon: Option<&ServiceDaemon>, instance_name: &str, port: u16) -> Result<Self, mdns_sd::Error> {
+        let service_type = "_apple-midi._udp.local.";
+        let ip = local_ip_address::local_ip().expect("Failed to get local IP address").to_string();
+
+        let raw_hostname = hostname::get().expect("Failed to get hostname").to_string_lossy().to_string();
+        let hostname = format!("{raw_hostname}.local.");
+        let service = ServiceInfo::new(service_type, instance_name, &hostname, ip, port, None)?;
+        let fullname = service.get_fullname().to_owned();
+
+        let (daemon, owns_daemon) = match daemon {
+            Some(daemon) => (daemon.clone(), false),
+            None => (ServiceDaemon::new()?, true),
+        };
+        daemon.register(service)?;
 
-    let raw_hostname = hostname::get().expect("Failed to get hostname").to_string_lossy().to_string();
-    let hostname = format!("{raw_hostname}.local.");
-    let service = ServiceInfo::new(service_type, instance_name, &hostname, ip, port, None)?;
-    mdns.register(service)?;
+        Ok(MdnsAdvertisement { daemon, fullname, owns_daemon })
+    }
 
-    Ok(mdns)
+    /// Withdraws the advertisement, shutting the daemon down too unless it is shared.
+    pub fn stop(&self) {
+        if self.owns_daemon {
+            let _ = self.daemon.shutdown();
+        } else {
+            let _ = self.daemon.unregister(&self.fullname);
+        }
+    }
 }
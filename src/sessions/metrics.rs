@@ -0,0 +1,168 @@
+//! Session health in the Prometheus text exposition format, for fleets scraped into dashboards such as Grafana.
+//! Serve what [`render`] returns from whatever HTTP server the application already runs, under `/metrics`.
+//!
+//! ```no_run
+//! use rtpmidi::sessions::metrics;
+//! use rtpmidi::sessions::session_manager::SessionManager;
+//!
+//! # async fn example(manager: &SessionManager) {
+//! let mut stats = Vec::new();
+//! for session in manager.sessions().await {
+//!     stats.push((session.name().to_owned(), session.stats().await));
+//! }
+//! let body = metrics::render(stats.iter().map(|(name, stats)| (name.as_str(), stats)));
+//! # }
+//! ```
+
+use std::fmt::Write;
+
+use crate::sessions::stats::{ParticipantStats, SessionStats};
+
+/// The content type to serve [`render`]'s output with.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+type SessionCounter = (&'static str, &'static str, fn(&SessionStats) -> u64);
+type ParticipantCounter = (&'static str, &'static str, fn(&ParticipantStats) -> u64);
+
+const SESSION_COUNTERS: [SessionCounter; 8] = [
+    ("rtpmidi_packets_sent_total", "MIDI packets sent.", |stats| stats.packets_sent),
+    ("rtpmidi_bytes_sent_total", "Bytes of MIDI packets sent.", |stats| stats.bytes_sent),
+    ("rtpmidi_packets_received_total", "MIDI packets received.", |stats| stats.packets_received),
+    ("rtpmidi_bytes_received_total", "Bytes of MIDI packets received.", |stats| stats.bytes_received),
+    ("rtpmidi_parse_errors_total", "Datagrams that couldn't be parsed.", |stats| stats.parse_errors),
+    ("rtpmidi_duplicates_dropped_total", "MIDI packets received more than once.", |stats| {
+        stats.duplicates_dropped
+    }),
+    ("rtpmidi_sequence_gaps_total", "MIDI packets skipped over in sequence numbers.", |stats| {
+        stats.sequence_gaps
+    }),
+    ("rtpmidi_events_dropped_total", "Events dropped because listeners fell behind.", |stats| {
+        stats.events_dropped
+    }),
+];
+
+const PARTICIPANT_COUNTERS: [ParticipantCounter; 7] = [
+    ("rtpmidi_participant_packets_sent_total", "MIDI packets sent to the participant.", |stats| {
+        stats.packets_sent
+    }),
+    (
+        "rtpmidi_participant_bytes_sent_total",
+        "Bytes of MIDI packets sent to the participant.",
+        |stats| stats.bytes_sent,
+    ),
+    (
+        "rtpmidi_participant_packets_received_total",
+        "MIDI packets received from the participant.",
+        |stats| stats.packets_received,
+    ),
+    (
+        "rtpmidi_participant_bytes_received_total",
+        "Bytes of MIDI packets received from the participant.",
+        |stats| stats.bytes_received,
+    ),
+    (
+        "rtpmidi_participant_lost_packets_total",
+        "MIDI packets from the participant that were skipped over.",
+        |stats| stats.lost_packets,
+    ),
+    (
+        "rtpmidi_participant_late_packets_total",
+        "MIDI packets from the participant that arrived after a newer one.",
+        |stats| stats.late_packets,
+    ),
+    (
+        "rtpmidi_participant_duplicate_packets_total",
+        "MIDI packets from the participant received more than once.",
+        |stats| stats.duplicate_packets,
+    ),
+];
+
+/// Renders the stats of each named session. Participants are labelled with their session, name and SSRC.
+pub fn render<'a>(sessions: impl IntoIterator<Item = (&'a str, &'a SessionStats)>) -> String {
+    let sessions: Vec<_> = sessions.into_iter().collect();
+    let mut out = String::new();
+
+    for (metric, help, value) in SESSION_COUNTERS {
+        family(&mut out, metric, help, "counter");
+        for (name, stats) in &sessions {
+            let _ = writeln!(out, "{metric}{{session=\"{}\"}} {}", escape(name), value(stats));
+        }
+    }
+    for (metric, help, value) in PARTICIPANT_COUNTERS {
+        family(&mut out, metric, help, "counter");
+        for (name, participant) in participants(&sessions) {
+            let _ = writeln!(out, "{metric}{} {}", participant_labels(name, participant), value(participant));
+        }
+    }
+
+    let metric = "rtpmidi_participant_latency_seconds";
+    family(&mut out, metric, "Round trip time measured by the last clock sync.", "gauge");
+    for (name, participant) in participants(&sessions) {
+        if let Some(latency) = participant.latency {
+            let _ = writeln!(out, "{metric}{} {}", participant_labels(name, participant), latency.as_secs_f64());
+        }
+    }
+    out
+}
+
+fn family(out: &mut String, metric: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {metric} {help}");
+    let _ = writeln!(out, "# TYPE {metric} {kind}");
+}
+
+fn participants<'a>(sessions: &'a [(&'a str, &'a SessionStats)]) -> impl Iterator<Item = (&'a str, &'a ParticipantStats)> {
+    sessions
+        .iter()
+        .flat_map(|(name, stats)| stats.participants.iter().map(move |participant| (*name, participant)))
+}
+
+fn participant_labels(session: &str, participant: &ParticipantStats) -> String {
+    format!(
+        "{{session=\"{}\",participant=\"{}\",ssrc=\"{}\"}}",
+        escape(session),
+        escape(&participant.name),
+        participant.ssrc
+    )
+}
+
+/// Label values escape backslashes, double quotes and line feeds.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let stats = SessionStats {
+            packets_sent: 3,
+            parse_errors: 1,
+            participants: vec![ParticipantStats {
+                ssrc: 7,
+                name: "Studio \"A\"".to_owned(),
+                packets_sent: 3,
+                bytes_sent: 60,
+                packets_received: 0,
+                bytes_received: 0,
+                lost_packets: 0,
+                late_packets: 0,
+                duplicate_packets: 0,
+                latency: Some(Duration::from_micros(1500)),
+                latency_summary: None,
+                loss_rate: 0.0,
+            }],
+            ..Default::default()
+        };
+        let rendered = render([("Rig", &stats)]);
+
+        assert!(rendered.contains("# TYPE rtpmidi_packets_sent_total counter\nrtpmidi_packets_sent_total{session=\"Rig\"} 3\n"));
+        assert!(rendered.contains("rtpmidi_parse_errors_total{session=\"Rig\"} 1\n"));
+        assert!(rendered.contains("rtpmidi_participant_bytes_sent_total{session=\"Rig\",participant=\"Studio \\\"A\\\"\",ssrc=\"7\"} 60\n"));
+        assert!(rendered.contains("rtpmidi_participant_latency_seconds{session=\"Rig\",participant=\"Studio \\\"A\\\"\",ssrc=\"7\"} 0.0015\n"));
+        assert_eq!(rendered.lines().filter(|line| line.starts_with("# TYPE")).count(), 16);
+    }
+}
@@ -1,26 +1,42 @@
+use super::invite_responder::InviteResponder;
 use super::rtp_midi_session::{RtpMidiSession, current_timestamp};
 use super::rtp_port::RtpPort;
+use crate::error::RtpMidiError;
+use crate::logging::{Level, event};
 use crate::packets::control_packets::clock_sync_packet::ClockSyncPacket;
 use crate::packets::control_packets::control_packet::ControlPacket;
 use crate::packets::control_packets::session_initiation_packet::SessionInitiationPacketBody;
+use crate::packets::midi_packets::midi_command_list_body::{MAX_COMMAND_LIST_LENGTH, split_into_command_lists};
 use crate::packets::midi_packets::midi_event::MidiEvent;
 use crate::packets::midi_packets::midi_packet::MidiPacket;
-use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use crate::packets::midi_packets::rtp_midi_message::{MAX_SYSEX_SEGMENT_SIZE, RtpMidiMessage};
 use crate::packets::packet::RtpMidiPacket;
-use crate::participant::Participant;
-use crate::sessions::events::event_handling::EventListeners;
+use crate::participant::{Participant, SequenceStatus};
+use crate::sessions::channel_map::ChannelMap;
+use crate::sessions::events::event_dispatcher::QueuedEvent;
+use crate::sessions::events::event_handling::{ClockSyncRound, PacketLossThresholdCrossed, ParticipantRenamed};
+use crate::sessions::flood_guard::Screening;
 use crate::sessions::rtp_midi_session::current_timestamp_u32;
+use crate::sessions::socket::{Socket, SocketHooks};
+use crate::sessions::stun;
+use bytes::{Bytes, BytesMut};
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
 use std::iter;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use std::time::Instant;
-use tokio::net::UdpSocket;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
-use tracing::{Level, event, instrument};
 use zerocopy::network_endian::{U16, U32, U64};
 
 pub const MAX_MIDI_PACKET_SIZE: usize = 32768;
+pub const MAX_SENT_MIDI_PACKET_SIZE: usize = 1400;
+/// What a MIDI packet takes besides its commands: the RTP header, and a command list header with a 12-bit length.
+const PACKET_OVERHEAD: usize = 12 + 2;
+/// Where the sequence number sits in the RTP header of a MIDI packet.
+const SEQUENCE_NUMBER: Range<usize> = 2..4;
 
 impl RtpPort for MidiPort {
     fn session_name(&self) -> &CStr {
@@ -28,10 +44,10 @@ impl RtpPort for MidiPort {
     }
 
     fn ssrc(&self) -> U32 {
-        self.ssrc
+        U32::new(self.ssrc.load(Ordering::Relaxed))
     }
 
-    fn socket(&self) -> &Arc<UdpSocket> {
+    fn socket(&self) -> &Arc<Socket> {
         &self.socket
     }
 
@@ -42,119 +58,297 @@ impl RtpPort for MidiPort {
 
 pub(super) struct MidiPort {
     name: CString,
-    ssrc: U32,
+    ssrc: Arc<AtomicU32>, // shared by both ports, replaced if a peer turns out to use it
     start_time: Instant,
-    sequence_number: Arc<Mutex<u16>>,
-    socket: Arc<UdpSocket>,
+    send_buffer: Mutex<BytesMut>, // reused for every outgoing MIDI packet
+    socket: Arc<Socket>,
 }
 
 impl MidiPort {
-    pub async fn bind(port: u16, name: CString, ssrc: U32) -> std::io::Result<Self> {
-        let socket = Arc::new(UdpSocket::bind((std::net::Ipv4Addr::UNSPECIFIED, port)).await?);
+    pub async fn bind(port: u16, name: CString, ssrc: Arc<AtomicU32>, hooks: SocketHooks) -> Result<Self, RtpMidiError> {
+        let socket = Arc::new(Socket::bind(port, hooks).await?);
 
         Ok(MidiPort {
             ssrc,
             start_time: Instant::now(),
             name,
-            sequence_number: Arc::new(Mutex::new(0)),
+            send_buffer: Mutex::new(BytesMut::new()),
             socket,
         })
     }
 
-    #[instrument(name = "MIDI", skip_all, fields(name = %ctx.name(), src, src_name))]
-    pub async fn start(&self, ctx: &RtpMidiSession, listeners: Arc<Mutex<EventListeners>>, buf: &mut [u8; MAX_MIDI_PACKET_SIZE]) {
-        let recv = self.socket.recv_from(buf).await;
+    #[cfg(not(all(feature = "recvmmsg", target_os = "linux")))]
+    pub async fn start(&self, ctx: &RtpMidiSession, invite_handler: &InviteResponder, buf: &mut Vec<u8>) {
+        let recv = self.socket.recv_buf_from(buf, ctx.config.max_midi_packet_size + 1).await;
         if recv.is_err() {
             event!(Level::ERROR, "Failed to receive data on MIDI port: {recv:?}");
             return;
         }
 
         let (amt, src) = recv.unwrap();
-        tracing::Span::current().record("src", src.to_string());
+        self.handle_datagram(ctx, invite_handler, buf, amt, src).await;
+    }
+
+    /// Like [`start`](Self::start), but handles as many datagrams as one `recvmmsg` call brings, one per buffer in
+    /// `bufs` at most. `received` is where their lengths and senders go, kept to save allocating it each time.
+    #[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+    pub async fn start_batch(&self, ctx: &RtpMidiSession, invite_handler: &InviteResponder, bufs: &mut [Vec<u8>], received: &mut Vec<(usize, SocketAddr)>) {
+        if let Err(error) = self.socket.recv_batch(bufs, ctx.config.max_midi_packet_size + 1, received).await {
+            event!(Level::ERROR, "Failed to receive data on MIDI port: {error:?}");
+            return;
+        }
+        for (buf, &(amt, src)) in bufs.iter_mut().zip(received.iter()) {
+            self.handle_datagram(ctx, invite_handler, buf, amt, src).await;
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "MIDI", skip_all, fields(name = %ctx.name(), src = %src, src_name)))]
+    async fn handle_datagram(&self, ctx: &RtpMidiSession, invite_handler: &InviteResponder, buf: &mut Vec<u8>, amt: usize, src: SocketAddr) {
+        if amt > ctx.config.max_midi_packet_size {
+            event!(
+                Level::WARN,
+                "Dropping oversized MIDI packet, it exceeds the {} byte limit",
+                ctx.config.max_midi_packet_size
+            );
+            return;
+        }
+        if amt == 0 {
+            event!(Level::TRACE, "Received keepalive");
+            return;
+        }
+        if stun::is_stun(&buf[..amt]) {
+            ctx.stun.complete(&buf[..amt], src);
+            return;
+        }
         event!(Level::TRACE, "Received {amt} bytes");
 
-        let packet = RtpMidiPacket::parse(&buf[..amt]);
+        let packet = RtpMidiPacket::parse_with_first_delta_time(&buf[..amt], ctx.config.parse_mode, ctx.config.first_delta_time);
         if packet.is_err() {
             event!(Level::ERROR, "Failed to parse RTP MIDI packet: {packet:?}");
+            ctx.counters.parse_error();
             return;
         }
 
         let packet = packet.unwrap();
         event!(Level::TRACE, "Parsed RTP MIDI packet: {:?}", &packet);
+        let mut delivery = match packet {
+            RtpMidiPacket::Midi(_) => Delivery::Live,
+            RtpMidiPacket::Control(_) => Delivery::Drop,
+        };
+        let mut sender = None;
         match packet {
             RtpMidiPacket::Control(control_packet) => match control_packet {
                 ControlPacket::Invitation { body, name } => {
-                    event!(Level::INFO, name = name.to_str().unwrap_or("Unknown"), "Received session invitation");
-                    self.handle_invitation(body, name, src, ctx).await;
+                    event!(Level::INFO, name = %name, "Received session invitation");
+                    self.handle_invitation(body, &name, invite_handler, src, ctx).await;
                 }
                 ControlPacket::Acceptance { body, name } => {
-                    event!(Level::INFO, name = name.to_str().unwrap_or("Unknown"), "Received session acceptance");
-                    if let Ok(participant) = self.handle_acceptance(body, ctx).await {
+                    event!(Level::INFO, name = %name, "Received session acceptance");
+                    if let Ok(Some(participant)) = self.handle_acceptance(body, ctx).await {
                         event!(Level::INFO, "Accepted MIDI port invitation from {participant}");
-                        listeners.lock().await.notify_participant_joined(&participant);
+                        ctx.events.push(QueuedEvent::ParticipantJoined(participant)).await;
                     }
                 }
                 ControlPacket::ClockSync(clock_sync_packet) => {
                     event!(Level::DEBUG, "Received clock sync from {}", src);
-                    self.handle_clock_sync(clock_sync_packet, ctx).await;
+                    self.handle_clock_sync(clock_sync_packet, src, ctx).await;
                 }
                 ControlPacket::Termination(body) => {
                     event!(Level::INFO, "Received session termination from {}", src);
-                    let mut part_lock = ctx.participants.lock().await;
-                    if let Some(participant) = part_lock.remove(&body.sender_ssrc) {
-                        listeners.lock().await.notify_participant_left(&participant);
+                    let is_sender = ctx
+                        .participants
+                        .read()
+                        .await
+                        .get(&body.sender_ssrc)
+                        .is_some_and(|participant| participant.midi_port_addr() == src);
+                    if is_sender {
+                        ctx.release_active_notes(body.sender_ssrc).await;
+                    }
+                    let mut part_lock = ctx.participants.write().await;
+                    if let Some(participant) = is_sender.then(|| part_lock.remove(&body.sender_ssrc)).flatten() {
+                        drop(part_lock);
                         event!(Level::INFO, "Removed participant: {participant}");
+                        ctx.events.push(QueuedEvent::ParticipantLeft(participant)).await;
                     } else {
                         event!(Level::WARN, "No participant found for SSRC {}", body.sender_ssrc.get());
                     }
                 }
+                ControlPacket::BitrateLimit(packet) => {
+                    self.handle_bitrate_limit(packet, src, &ctx.participants).await;
+                }
                 _ => {
                     event!(Level::WARN, "Unhandled control packet {:?}", control_packet);
                 }
             },
             RtpMidiPacket::Midi(midi_packet) => {
                 event!(Level::DEBUG, "Parsed MIDI packet: {:#?}", midi_packet);
-                let mut seq = self.sequence_number.lock().await;
-                *seq = midi_packet.sequence_number().get().wrapping_add(1);
-                for command in midi_packet.commands() {
-                    match command.command() {
-                        RtpMidiMessage::MidiMessage(message) => {
-                            event!(Level::DEBUG, "Received MIDI message: {message:?}");
-                            let timestamp = u32::from(midi_packet.timestamp()) + command.delta_time();
-                            listeners.lock().await.notify_midi_message(*message, timestamp);
-                        }
-                        RtpMidiMessage::SysEx(sysex) => {
-                            event!(Level::DEBUG, "Received SysEx message: {sysex:?}");
-                            listeners.lock().await.notify_sysex_packet(sysex);
-                        }
-                    }
+                if midi_packet.ssrc() == self.ssrc() && ctx.is_collision_credible(src).await {
+                    event!(Level::WARN, "Received MIDI packet sent under our own SSRC");
+                    ctx.resolve_ssrc_collision(midi_packet.ssrc()).await;
+                }
+                ctx.counters.received(amt);
+                delivery = self.check_sequence_number(midi_packet, amt, src, ctx).await;
+                // Only looked up if there are interceptors to hand it to
+                if delivery != Delivery::Drop && !ctx.interceptors.snapshot().is_empty() {
+                    let participants = ctx.participants.read().await;
+                    sender = participants
+                        .get(&midi_packet.ssrc())
+                        .filter(|participant| participant.midi_port_addr() == src)
+                        .cloned();
                 }
             }
         }
+
+        if delivery != Delivery::Drop {
+            // The commands are read by the dispatcher, straight from the buffer the datagram arrived in
+            let datagram = std::mem::replace(buf, ctx.events.receive_buffer(ctx.config.max_midi_packet_size + 1));
+            let queued_event = match delivery {
+                Delivery::Recovered => QueuedEvent::RecoveredMidiPacket(datagram, sender),
+                _ => QueuedEvent::MidiPacket(datagram, sender),
+            };
+            ctx.events.push(queued_event).await;
+        }
     }
 
-    #[instrument(skip_all, fields(sender = %sender_name.to_str().unwrap_or("Unknown"), token = %body.initiator_token, src = %src))]
-    async fn handle_invitation(&self, body: &SessionInitiationPacketBody, sender_name: &CStr, src: SocketAddr, ctx: &RtpMidiSession) {
-        let invitation = ctx.pending_invitations.lock().await.remove(&body.sender_ssrc);
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(sender = %sender_name, token = %body.initiator_token, src = %src)))]
+    async fn handle_invitation(
+        &self,
+        body: &SessionInitiationPacketBody,
+        sender_name: &str,
+        invite_handler: &InviteResponder,
+        src: SocketAddr,
+        ctx: &RtpMidiSession,
+    ) {
+        // The control port is the one before, which a datagram from port 0 has none of
+        let Some(ctrl_port) = src.port().checked_sub(1) else {
+            event!(Level::WARN, "Dropping MIDI port invitation from port 0");
+            return;
+        };
+        let ctrl_addr = SocketAddr::new(src.ip(), ctrl_port);
+        if ctx.config.initiator_only {
+            event!(Level::INFO, "Rejecting MIDI port invitation, the session only invites others");
+            self.send_invitation_rejection(body.initiator_token, src).await;
+            return;
+        }
+        if !self.check_protocol_version(body, src, &ctx.events).await {
+            ctx.pending_invitations.lock().await.remove(&body.sender_ssrc);
+            self.send_invitation_rejection(body.initiator_token, src).await;
+            return;
+        }
+
+        if body.sender_ssrc == self.ssrc() {
+            if !ctx.is_collision_credible(src).await {
+                event!(Level::WARN, "Rejecting MIDI port invitation, it uses our own SSRC");
+                ctx.pending_invitations.lock().await.remove(&body.sender_ssrc);
+                self.send_invitation_rejection(body.initiator_token, src).await;
+                return;
+            }
+            event!(Level::WARN, "Peer invited us using our own SSRC");
+            ctx.resolve_ssrc_collision(body.sender_ssrc).await;
+        }
+
+        let invitation = {
+            let mut pending_invitations = ctx.pending_invitations.lock().await;
+            match pending_invitations.get(&body.sender_ssrc) {
+                Some(pending) if pending.addr != ctrl_addr => None,
+                _ => pending_invitations.remove(&body.sender_ssrc),
+            }
+        };
         match invitation {
+            None if ctx.config.rtpmidi_quirks && self.update_repeated_invitation(body, src, ctx).await => {
+                event!(Level::DEBUG, "Answering repeated MIDI port invitation");
+                self.send_invitation_acceptance(body.initiator_token, src).await;
+            }
+            None if ctx.config.accept_midi_port_invitations => {
+                event!(Level::INFO, "Received MIDI port invitation without a control port handshake");
+                let screening = ctx.screen_invitation(src).await;
+                if screening == Screening::Ignore {
+                    return;
+                }
+                if screening == Screening::Reject || !ctx.has_room_for(body.sender_ssrc, sender_name, src).await {
+                    self.send_invitation_rejection(body.initiator_token, src).await;
+                } else if invite_handler.handle(body, sender_name, &src) {
+                    event!(Level::INFO, "Accepted session invitation");
+                    self.add_invited_participant(body, sender_name, src, ctrl_addr, ctx).await;
+                } else {
+                    event!(Level::INFO, "Rejected session invitation");
+                    self.send_invitation_rejection(body.initiator_token, src).await;
+                }
+            }
             None => {
                 event!(Level::WARN, "Received unexpected MIDI port invitation for SSRC {}", body.sender_ssrc.get());
             }
             Some(_inv) => {
                 event!(Level::DEBUG, "Found pending invitation for SSRC {}", body.sender_ssrc.get());
+                self.add_invited_participant(body, sender_name, src, ctrl_addr, ctx).await;
+            }
+        }
+    }
 
-                let ctrl_addr = SocketAddr::new(src.ip(), src.port() - 1);
-                ctx.participants.lock().await.insert(
-                    body.sender_ssrc,
-                    Participant::new(ctrl_addr, false, Some(body.initiator_token), sender_name, body.sender_ssrc),
-                );
+    async fn add_invited_participant(
+        &self,
+        body: &SessionInitiationPacketBody,
+        sender_name: &str,
+        src: SocketAddr,
+        ctrl_addr: SocketAddr,
+        ctx: &RtpMidiSession,
+    ) {
+        let mut participants = ctx.participants.write().await;
+        let mut rename = None;
+        match participants.get_mut(&body.sender_ssrc) {
+            // Completing a re-invitation, which already brought the entry up to date unless the name differs here
+            Some(participant) if participant.addr() == ctrl_addr => {
+                rename = ParticipantRenamed::between(participant, body.sender_ssrc.get(), sender_name);
+                participant.reinvited(body.initiator_token, sender_name, body.sender_ssrc);
+            }
+            // Inviting us from where it has moved to, under the token it joined with
+            Some(participant) if participant.initiator_token() == Some(body.initiator_token) => {
+                drop(participants);
+                ctx.move_participant(body.sender_ssrc, body.initiator_token, ctrl_addr).await;
+                self.send_invitation_acceptance(body.initiator_token, src).await;
+                ctx.send_authentication_challenge(body.sender_ssrc).await;
+                return;
+            }
+            Some(_) => {
+                drop(participants);
+                event!(Level::WARN, "Rejecting MIDI port invitation, its SSRC belongs to another peer");
+                self.send_invitation_rejection(body.initiator_token, src).await;
+                return;
+            }
+            // A participant that moved, finishing the handshake it has to authenticate again after
+            None if ctx.is_authenticating(body.sender_ssrc, ctrl_addr).await => {
+                drop(participants);
                 self.send_invitation_acceptance(body.initiator_token, src).await;
+                ctx.send_authentication_challenge(body.sender_ssrc).await;
+                return;
+            }
+            None => {
+                let participant = Participant::new(ctrl_addr, false, Some(body.initiator_token), sender_name, body.sender_ssrc);
+                ctx.establish(&mut participants, participant).await;
             }
         }
+        drop(participants);
+        self.send_invitation_acceptance(body.initiator_token, src).await;
+        if let Some(rename) = rename {
+            ctx.events.push(QueuedEvent::ParticipantRenamed(rename)).await;
+        }
+    }
+
+    /// rtpMIDI repeats an invitation until it sees the answer, with a new token each time. If this one comes from a
+    /// participant that has already joined, the participant takes the new token so its termination still matches.
+    async fn update_repeated_invitation(&self, body: &SessionInitiationPacketBody, src: SocketAddr, ctx: &RtpMidiSession) -> bool {
+        match ctx.participants.write().await.get_mut(&body.sender_ssrc) {
+            Some(participant) if participant.midi_port_addr() == src => {
+                participant.set_initiator_token(body.initiator_token);
+                true
+            }
+            _ => false,
+        }
     }
 
-    #[instrument(skip_all, fields(token = %ack_body.initiator_token))]
-    async fn handle_acceptance(&self, ack_body: &SessionInitiationPacketBody, ctx: &RtpMidiSession) -> Result<Participant, &str> {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(token = %ack_body.initiator_token)))]
+    /// Returns the participant that joined, or `None` if it has to authenticate first.
+    async fn handle_acceptance(&self, ack_body: &SessionInitiationPacketBody, ctx: &RtpMidiSession) -> Result<Option<Participant>, RtpMidiError> {
         let mut locked_pending_invitations = ctx.pending_invitations.lock().await;
 
         let inv = locked_pending_invitations.get(&ack_body.sender_ssrc).cloned();
@@ -164,13 +358,13 @@ impl MidiPort {
                 ssrc = ack_body.sender_ssrc.get(),
                 "Received Acceptance but no pending invitation found for this SSRC."
             );
-            return Err("No pending invitation found");
+            return Err(RtpMidiError::Handshake("no pending invitation for this SSRC"));
         }
 
         let inv = inv.unwrap();
         if inv.token != ack_body.initiator_token {
             event!(Level::WARN, expected = inv.token.get(), "Received Acceptance with mismatched token",);
-            return Err("Token mismatch in acceptance");
+            return Err(RtpMidiError::Handshake("acceptance token doesn't match the invitation"));
         }
 
         locked_pending_invitations.remove(&ack_body.sender_ssrc);
@@ -178,13 +372,111 @@ impl MidiPort {
         event!(Level::DEBUG, "Matched Acceptance for MIDI port invitation. Sending Clock Sync.");
         let ctrl_addr = SocketAddr::new(inv.addr.ip(), inv.addr.port() - 1);
         let participant = Participant::new(ctrl_addr, true, Some(inv.token), &inv.name, ack_body.sender_ssrc);
-        ctx.participants.lock().await.insert(ack_body.sender_ssrc, participant.clone());
+        if !ctx.establish(&mut *ctx.participants.write().await, participant.clone()).await {
+            event!(Level::DEBUG, "Waiting for the participant to authenticate");
+            return Ok(None);
+        }
         let timestamps = [U64::new(0); 3];
         self.send_clock_sync(std::iter::once(&participant), timestamps, 1).await;
-        Ok(participant)
+        Ok(Some(participant))
     }
 
-    #[instrument(skip_all, fields(count = count))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(ssrc = packet.ssrc().get(), sequence_number = packet.sequence_number().get())))]
+    /// Tracks the packet's sequence number, working out whether it is handed on, and asking for any packets missing
+    /// before it to be sent again if the session does that.
+    async fn check_sequence_number(&self, packet: &MidiPacket, len: usize, src: SocketAddr, ctx: &RtpMidiSession) -> Delivery {
+        let sequence_number = packet.sequence_number().get();
+        let mut crossing = None;
+        let mut recovered = false;
+        let mut request = None;
+        let mut lost_for_good = 0;
+        // Only read, as what each participant is sent and has sent us is kept behind locks of its own
+        let status = ctx
+            .participants
+            .read()
+            .await
+            .get(&packet.ssrc())
+            .filter(|participant| participant.midi_port_addr() == src)
+            .map(|participant| {
+                participant.received_packet(len);
+                let status = participant.received_sequence_number(sequence_number);
+                if let SequenceStatus::Gap(missing) = status {
+                    lost_for_good = missing;
+                }
+                if let Some(retransmission) = ctx.config.retransmission {
+                    match status {
+                        SequenceStatus::Gap(missing) => {
+                            let count = missing.min(u16::try_from(retransmission.history).unwrap_or(u16::MAX));
+                            let first = sequence_number.wrapping_sub(count);
+                            let displaced = participant.transmission().retransmission.missing(first, count, retransmission.history);
+                            request = Some((participant.addr(), first, count)).filter(|_| count > 0);
+                            lost_for_good = missing - count + displaced;
+                        }
+                        SequenceStatus::Late => recovered = participant.transmission().retransmission.take_missing(sequence_number),
+                        _ => {}
+                    }
+                    // What was asked for and hasn't come back in time is lost for good after all
+                    lost_for_good += participant.transmission().retransmission.expire_missing(retransmission.timeout);
+                }
+                if let Some(threshold) = ctx.config.loss_alert_threshold
+                    && let Some(above) = participant.check_loss_threshold(threshold)
+                {
+                    crossing = Some(PacketLossThresholdCrossed {
+                        ssrc: participant.ssrc().get(),
+                        name: participant.name().to_owned(),
+                        loss_rate: participant.loss_rate(),
+                        above,
+                    });
+                }
+                status
+            });
+        if let Some(crossing) = crossing {
+            event!(
+                Level::WARN,
+                loss_rate = crossing.loss_rate,
+                above = crossing.above,
+                "Packet loss crossed the alert threshold"
+            );
+            ctx.events.push(QueuedEvent::PacketLossThreshold(crossing)).await;
+        }
+        if let Some((addr, first, count)) = request {
+            ctx.request_retransmission(addr, first, count).await;
+        }
+        // Queued ahead of the packet, so what makes up for the loss is handed on before it
+        if lost_for_good > 0 && ctx.config.loss_concealment.is_some() {
+            let loss = QueuedEvent::PacketsLost {
+                ssrc: packet.ssrc(),
+                missing: lost_for_good,
+                timestamp: packet.timestamp().get(),
+            };
+            ctx.events.push(loss).await;
+        }
+
+        match status {
+            Some(SequenceStatus::InOrder) => {}
+            Some(SequenceStatus::Gap(missing)) => {
+                event!(Level::WARN, "{missing} MIDI packet(s) lost");
+                ctx.counters.sequence_gap(missing);
+            }
+            Some(SequenceStatus::Late) if recovered => {
+                event!(Level::DEBUG, "Received MIDI packet that was sent again");
+                return Delivery::Recovered;
+            }
+            Some(SequenceStatus::Late) => event!(Level::WARN, "Received MIDI packet out of order"),
+            Some(SequenceStatus::Duplicate) => {
+                event!(Level::DEBUG, "Dropping duplicate MIDI packet");
+                ctx.counters.duplicate_dropped();
+                return Delivery::Drop;
+            }
+            None => {
+                event!(Level::DEBUG, "Dropping MIDI packet from an unknown participant");
+                return Delivery::Drop;
+            }
+        }
+        Delivery::Live
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = count)))]
     pub(super) async fn send_clock_sync<'a, I>(&self, participants: I, mut timestamps: [U64; 3], count: u8)
     where
         I: IntoIterator<Item = &'a Participant>,
@@ -195,72 +487,277 @@ impl MidiPort {
         }
         timestamps[count as usize] = current_timestamp(self.start_time);
 
-        let packet = ControlPacket::new_clock_sync_as_bytes(count, timestamps, self.ssrc);
+        let packet = ControlPacket::new_clock_sync_as_bytes(count, timestamps, self.ssrc());
         for participant in participants {
             if let Err(e) = self.socket.send_to(&packet, participant.midi_port_addr()).await {
                 event!(
                     Level::WARN,
-                    name = participant.name().to_str().unwrap_or("Unknown"),
+                    name = participant.name(),
                     addr = %participant.midi_port_addr(),
                     "Failed to send clock sync: {e}"
                 );
             } else {
-                event!(Level::DEBUG, name = participant.name().to_str().unwrap_or("Unknown"), "Sent clock sync");
+                event!(Level::DEBUG, name = participant.name(), "Sent clock sync");
             }
         }
     }
 
-    #[instrument(skip_all, fields(count = packet.count, ssrc = packet.sender_ssrc.get(), src_name))]
-    async fn handle_clock_sync(&self, packet: &ClockSyncPacket, ctx: &RtpMidiSession) {
-        let mut part_lock = ctx.participants.lock().await;
-        let maybe_participant = part_lock.get_mut(&packet.sender_ssrc);
-
-        if maybe_participant.is_none() {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = packet.count, ssrc = packet.sender_ssrc.get(), src_name)))]
+    async fn handle_clock_sync(&self, packet: &ClockSyncPacket, src: SocketAddr, ctx: &RtpMidiSession) {
+        let mut part_lock = ctx.participants.write().await;
+        let Some(participant) = part_lock.get_mut(&packet.sender_ssrc).filter(|participant| participant.midi_port_addr() == src) else {
+            drop(part_lock);
+            // rtpMIDI starts syncing as soon as its invitation is accepted, before it has finished joining
+            if ctx.config.rtpmidi_quirks && packet.count < 2 && ctx.pending_invitations.lock().await.contains_key(&packet.sender_ssrc) {
+                event!(Level::DEBUG, "Answering clock sync from a peer that hasn't finished joining");
+                self.send_clock_sync_to(src, packet.timestamps, packet.count + 1).await;
+                return;
+            }
             event!(Level::WARN, "Received clock sync but no matching participant found");
             return;
-        }
-        let participant = maybe_participant.unwrap();
-        tracing::Span::current().record("src_name", participant.name().to_str().unwrap_or("Unknown"));
+        };
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("src_name", participant.name());
         participant.received_clock_sync();
+        // Only one that opens an exchange doesn't get an answer from us
+        let completed = packet.count > 0;
+        if completed {
+            participant.completed_clock_sync();
+        }
         event!(Level::DEBUG, "Updated clock sync for existing participant");
+        // The first timestamp is the initiator's, and so is the one it comes back with. The sync sent on joining has
+        // no first timestamp, so nothing can be measured from it.
+        let round_trip = match packet.count {
+            _ if packet.timestamps[0].get() == 0 => None,
+            1 => current_timestamp(self.start_time).get().checked_sub(packet.timestamps[0].get()),
+            2 => packet.timestamps[2].get().checked_sub(packet.timestamps[0].get()),
+            _ => None,
+        };
+        let rtt_estimate = round_trip
+            .filter(|&elapsed| elapsed > 0 || !ctx.config.rtpmidi_quirks)
+            .map(|elapsed| Duration::from_micros(elapsed * 100));
+        if let Some(latency) = rtt_estimate {
+            participant.set_latency(latency);
+        }
         let participant = participant.clone();
         drop(part_lock);
+        if completed {
+            ctx.participants_synced.send_replace(());
+        }
+
+        let round = ClockSyncRound {
+            ssrc: participant.ssrc().get(),
+            name: participant.name().to_owned(),
+            count: packet.count,
+            rtt_estimate,
+        };
+        ctx.events.push(QueuedEvent::ClockSyncRound(round)).await;
 
         match packet.count {
             0 | 1 => {
                 self.send_clock_sync(iter::once(&participant), packet.timestamps, packet.count + 1).await;
             }
-            2 => {
-                let latency_estimate = (packet.timestamps[2].get() - packet.timestamps[0].get()) as f32 / 10.0;
-                event!(Level::INFO, latency_estimate = std::format!("{latency_estimate}ms"), "Clock sync finalized");
-            }
+            2 => match packet.timestamps[2].get().checked_sub(packet.timestamps[0].get()) {
+                Some(elapsed) if elapsed > 0 || !ctx.config.rtpmidi_quirks => {
+                    let latency_estimate = elapsed as f32 / 10.0;
+                    event!(Level::INFO, latency_estimate = std::format!("{latency_estimate}ms"), "Clock sync finalized");
+                }
+                _ if ctx.config.rtpmidi_quirks => event!(Level::DEBUG, "Clock sync finalized without a usable latency estimate"),
+                _ => event!(Level::WARN, "Clock sync finalized with timestamps that go backwards"),
+            },
             _ => {
                 event!(Level::ERROR, "Unexpected clock sync count");
             }
         }
     }
 
-    #[instrument(skip_all, fields(name = %ctx.name(), participants))]
-    pub async fn send_midi_batch<'a>(&self, ctx: &RtpMidiSession, commands: &'a [MidiEvent<'a>]) -> std::io::Result<()> {
-        let lock = ctx.participants.lock().await;
-        let participants: Vec<Participant> = lock.values().cloned().collect();
-        let mut seq = self.sequence_number.lock().await;
-        let packet = MidiPacket::new_as_bytes(U16::new(*seq), current_timestamp_u32(self.start_time), self.ssrc, commands, false);
-        *seq = seq.wrapping_add(1);
+    async fn send_clock_sync_to(&self, addr: SocketAddr, mut timestamps: [U64; 3], count: u8) {
+        timestamps[count as usize] = current_timestamp(self.start_time);
+        let packet = ControlPacket::new_clock_sync_as_bytes(count, timestamps, self.ssrc());
+        if let Err(e) = self.socket.send_to(&packet, addr).await {
+            event!(Level::WARN, addr = %addr, "Failed to send clock sync: {e}");
+        }
+    }
+
+    /// Sends a batch of commands in packets of at most [`SessionConfig::max_sent_midi_packet_size`], splitting any
+    /// SysEx too long for one into segments that are each carried in their own packet, and the rest over as many
+    /// packets as their command lists need. Everything the session sends comes through here.
+    ///
+    /// [`SessionConfig::max_sent_midi_packet_size`]: super::session_config::SessionConfig::max_sent_midi_packet_size
+    pub async fn send_midi_batch<'a>(&self, ctx: &RtpMidiSession, commands: &'a [MidiEvent<'a>], recipients: Recipients<'_>) -> Result<(), RtpMidiError> {
+        if ctx.config.receive_only {
+            return Err(RtpMidiError::InvalidState("the session is receive-only"));
+        }
+        let max_list_length = ctx
+            .config
+            .max_sent_midi_packet_size
+            .saturating_sub(PACKET_OVERHEAD)
+            .min(MAX_COMMAND_LIST_LENGTH);
+        // A segment's status and end bytes, and the delta time written before it with the Z flag
+        let max_segment_size = max_list_length.saturating_sub(3).min(MAX_SYSEX_SEGMENT_SIZE);
+        let is_oversized_sysex = |event: &MidiEvent| matches!(event.command(), RtpMidiMessage::SysEx(data) if data.len() > max_segment_size);
+        if commands
+            .iter()
+            .any(|event| !is_oversized_sysex(event) && event.command().len() > MAX_COMMAND_LIST_LENGTH)
+        {
+            return Err(RtpMidiError::InvalidArgument(std::format!(
+                "a command can't be longer than the {MAX_COMMAND_LIST_LENGTH} bytes a command list can carry"
+            )));
+        }
+        if !commands.iter().any(is_oversized_sysex) {
+            return self.send_command_lists(ctx, commands, max_list_length, recipients).await;
+        }
+
+        let mut pending: Vec<MidiEvent<'a>> = Vec::new();
+        for command in commands {
+            let RtpMidiMessage::SysEx(data) = command.command() else {
+                pending.push(command.clone());
+                continue;
+            };
+            if data.len() <= max_segment_size {
+                pending.push(command.clone());
+                continue;
+            }
+
+            if !pending.is_empty() {
+                self.send_command_lists(ctx, &pending, max_list_length, recipients).await?;
+                pending.clear();
+            }
+            for segment in RtpMidiMessage::sysex_segments(data, max_segment_size) {
+                let timestamp = current_timestamp_u32(self.start_time);
+                self.send_midi_packet(ctx, &[MidiEvent::new(None, segment)], timestamp, recipients).await?;
+            }
+        }
+
+        if !pending.is_empty() {
+            self.send_command_lists(ctx, &pending, max_list_length, recipients).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends commands in as many packets as it takes to keep each command list within `max_length`, keeping them all
+    /// where they were in the batch. Each packet after the first is timestamped with the time of the command before
+    /// it if the first command's delta time is sent, and of its first command if not.
+    async fn send_command_lists<'a>(
+        &self,
+        ctx: &RtpMidiSession,
+        commands: &'a [MidiEvent<'a>],
+        max_length: usize,
+        recipients: Recipients<'_>,
+    ) -> Result<(), RtpMidiError> {
+        let z_flag = ctx.config.send_first_delta_time;
+        let mut timestamp = current_timestamp_u32(self.start_time).get();
+        for (i, list) in split_into_command_lists(commands, max_length, z_flag).enumerate() {
+            if i > 0 && !z_flag {
+                timestamp = timestamp.wrapping_add(list[0].delta_time());
+            }
+            self.send_midi_packet(ctx, list, U32::new(timestamp), recipients).await?;
+            let timed = list.iter().skip(if z_flag { 0 } else { 1 });
+            timestamp = timed.fold(timestamp, |timestamp, command| timestamp.wrapping_add(command.delta_time()));
+        }
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %ctx.name(), participants)))]
+    async fn send_midi_packet<'a>(
+        &self,
+        ctx: &RtpMidiSession,
+        commands: &'a [MidiEvent<'a>],
+        timestamp: U32,
+        recipients: Recipients<'_>,
+    ) -> Result<(), RtpMidiError> {
+        // Held for the whole send, so the participants are borrowed rather than cloned. Only read, as what each one is
+        // sent is kept behind a lock of its own.
+        let participants = ctx.participants.read().await;
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("participants", participants.len());
+        let mut packet = self.send_buffer.lock().await;
+        packet.clear();
+        // Written with each participant's own sequence number before it is sent
+        MidiPacket::write_to(&mut packet, U16::new(0), timestamp, self.ssrc(), commands, ctx.config.send_first_delta_time);
         event!(Level::DEBUG, "Sending MIDI packet batch");
-        for participant in participants {
-            self.socket.send_to(&packet, participant.midi_port_addr()).await?;
+        let mut solo = ctx.solo.read().unwrap_or_else(PoisonError::into_inner).clone();
+        // Soloed participants that have since left don't count
+        solo.retain(|ssrc| participants.contains_key(ssrc));
+        let channel_maps: HashMap<U32, ChannelMap> = {
+            let routes = ctx.channel_routes.read().unwrap_or_else(PoisonError::into_inner);
+            participants
+                .keys()
+                .map(|&ssrc| (ssrc, routes.for_participant(ssrc).outgoing))
+                .filter(|(_, map)| !map.is_identity())
+                .collect()
+        };
+        let interceptors = ctx.interceptors.snapshot();
+        let history = ctx.config.retransmission.map(|retransmission| retransmission.history);
+        let is_recipient = |participant: &&Participant| recipients.includes(participant, &solo);
+        for participant in participants.values().filter(is_recipient) {
+            let channel_map = channel_maps.get(&participant.ssrc());
+            if interceptors.is_empty() && channel_map.is_none() {
+                let sequence_number = participant.transmission().next_sequence_number();
+                packet[SEQUENCE_NUMBER].copy_from_slice(&sequence_number.to_be_bytes());
+                self.socket.send_to(&packet, participant.midi_port_addr()).await?;
+                ctx.counters.sent(packet.len());
+                let mut transmission = participant.transmission();
+                transmission.sent_packet(packet.len());
+                if let Some(history) = history {
+                    transmission.retransmission.sent(sequence_number, Bytes::copy_from_slice(&packet), history);
+                }
+            } else {
+                let mut own_commands = if interceptors.is_empty() {
+                    commands.to_vec()
+                } else {
+                    interceptors.apply_to_events(commands, participant)
+                };
+                if let Some(map) = channel_map {
+                    own_commands = own_commands.iter().map(|command| map.apply_to_event(command)).collect();
+                }
+                // Nothing is sent if the interceptors dropped everything, and no sequence number used up
+                if !own_commands.is_empty() {
+                    let sequence_number = participant.transmission().next_sequence_number();
+                    let mut own_packet = BytesMut::new();
+                    MidiPacket::write_to(
+                        &mut own_packet,
+                        U16::new(sequence_number),
+                        timestamp,
+                        self.ssrc(),
+                        &own_commands,
+                        ctx.config.send_first_delta_time,
+                    );
+                    self.socket.send_to(&own_packet, participant.midi_port_addr()).await?;
+                    ctx.counters.sent(own_packet.len());
+                    let mut transmission = participant.transmission();
+                    transmission.sent_packet(own_packet.len());
+                    if let Some(history) = history {
+                        transmission.retransmission.sent(sequence_number, own_packet.freeze(), history);
+                    }
+                }
+            }
+            // As they were before mapping and interception, so the NoteOffs that release them go the same way
+            participant.transmission().active_notes.track(commands);
         }
         Ok(())
     }
 
-    #[instrument(skip_all, fields(name = %ctx.name()))]
-    pub async fn send_midi<'a>(&self, ctx: &RtpMidiSession, command: &'a RtpMidiMessage<'a>) -> std::io::Result<()> {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %ctx.name())))]
+    pub async fn send_midi<'a>(&self, ctx: &RtpMidiSession, command: &'a RtpMidiMessage<'a>) -> Result<(), RtpMidiError> {
         let batch: [MidiEvent; 1] = [MidiEvent::new(None, command.to_owned())];
-        self.send_midi_batch(ctx, &batch).await
+        self.send_midi_batch(ctx, &batch, Recipients::All).await
+    }
+
+    /// Sends packets a participant asked for again, just as they went out the first time.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(addr = %addr, count = datagrams.len())))]
+    pub(super) async fn resend(&self, ctx: &RtpMidiSession, datagrams: &[Bytes], addr: SocketAddr) {
+        for datagram in datagrams {
+            if let Err(e) = self.socket.send_to(datagram, addr).await {
+                event!(Level::WARN, "Failed to send MIDI packet again: {e}");
+                return;
+            }
+            ctx.counters.sent(datagram.len());
+        }
+        event!(Level::DEBUG, "Sent MIDI packets again");
     }
 
-    #[instrument(skip_all, fields(addr = %addr))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(addr = %addr)))]
     pub(super) async fn send_invitation(&self, invitation: &[u8], addr: SocketAddr) {
         event!(Level::DEBUG, "Sending session invitation");
         let result = self.socket.send_to(invitation, addr).await;
@@ -271,3 +768,40 @@ impl MidiPort {
         }
     }
 }
+
+/// What becomes of a received MIDI packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivery {
+    /// It has been received before.
+    Drop,
+    Live,
+    /// It is one that was asked for again, see [`SessionConfig::retransmission`](super::session_config::SessionConfig::retransmission).
+    Recovered,
+}
+
+/// Who a MIDI packet is sent to.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Recipients<'a> {
+    /// Every participant that isn't muted, or left out by a solo.
+    All,
+    /// The participants with these SSRCs, unless they are muted or left out by a solo.
+    Among(&'a [U32]),
+    /// Just the participant with this SSRC, even if it is muted or left out by a solo.
+    Only(U32),
+}
+
+impl Recipients<'_> {
+    fn includes(&self, participant: &Participant, solo: &HashSet<U32>) -> bool {
+        let audible = || is_audible(participant, solo);
+        match self {
+            Recipients::All => audible(),
+            Recipients::Among(ssrcs) => ssrcs.contains(&participant.ssrc()) && audible(),
+            Recipients::Only(ssrc) => participant.ssrc() == *ssrc,
+        }
+    }
+}
+
+/// Whether MIDI sent to the session reaches the participant: it isn't muted, and either nobody is soloed or it is.
+pub(super) fn is_audible(participant: &Participant, solo: &HashSet<U32>) -> bool {
+    !participant.is_muted() && (solo.is_empty() || solo.contains(&participant.ssrc()))
+}
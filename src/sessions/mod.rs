@@ -1,8 +1,37 @@
+pub(crate) mod active_notes;
+pub mod authenticator;
+mod buffer_pool;
+mod capture;
+pub mod channel_map;
+pub mod client;
 pub mod control_port;
+pub mod encryption;
 pub mod events;
+mod flood_guard;
+#[cfg(feature = "hexdump")]
+mod hexdump;
 mod host_syncer;
+pub mod interceptor;
 pub mod invite_responder;
+pub mod known_peer;
+pub mod loss_concealment;
 mod mdns;
+#[cfg(feature = "metrics")]
+pub mod metrics;
 pub mod midi_port;
+#[cfg(feature = "osc")]
+pub mod osc;
+mod participant_groups;
+pub mod random;
+pub(crate) mod retransmission;
 pub mod rtp_midi_session;
 mod rtp_port;
+mod scheduler;
+pub mod session_config;
+pub mod session_guard;
+pub mod session_manager;
+mod socket;
+pub mod stats;
+pub mod stun;
+pub mod transport;
+pub mod wire_tap;
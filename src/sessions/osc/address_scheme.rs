@@ -0,0 +1,293 @@
+use midi_types::{Channel, Control, MidiMessage, Note, Program, Value7, Value14};
+
+use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use crate::sessions::osc::osc_message::{OscArg, OscMessage};
+
+/// The numbers a MIDI message carries, named as they are in address templates, in the order they are kept in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// Counted from 1 to 16, as OSC controllers label them.
+    Channel,
+    /// The key, controller or program number.
+    Number,
+    /// The velocity, pressure, controller value or pitch bend.
+    Value,
+}
+
+impl Field {
+    fn placeholder(self) -> &'static str {
+        match self {
+            Field::Channel => "{channel}",
+            Field::Number => "{number}",
+            Field::Value => "{value}",
+        }
+    }
+}
+
+const CHANNEL_NUMBER_VALUE: &[Field] = &[Field::Channel, Field::Number, Field::Value];
+const CHANNEL_NUMBER: &[Field] = &[Field::Channel, Field::Number];
+const CHANNEL_VALUE: &[Field] = &[Field::Channel, Field::Value];
+
+/// Where each kind of MIDI message goes in the OSC address space, and where OSC messages are taken from.
+///
+/// Each address is a template whose segments may be `{channel}`, `{number}` or `{value}`. Those a message carries
+/// but its template leaves out are sent as int arguments instead, in that order; so the default
+/// `/midi/{channel}/cc` carries the controller number and value as arguments, while `/midi/{channel}/cc/{number}`
+/// would carry just the value. Received float arguments are rounded. Kinds left as `None` aren't bridged.
+///
+/// ```
+/// use midi_types::{Channel, Control, MidiMessage, Value7};
+/// use rtpmidi::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+/// use rtpmidi::sessions::osc::address_scheme::OscAddressScheme;
+/// use rtpmidi::sessions::osc::osc_message::{OscArg, OscMessage};
+///
+/// let scheme = OscAddressScheme {
+///     control_change: Some("/mixer/{channel}/fader/{number}".into()),
+///     ..Default::default()
+/// };
+/// let fader = OscMessage::new("/mixer/2/fader/7", vec![OscArg::Float(100.0)]);
+/// let expected = MidiMessage::ControlChange(Channel::C2, Control::from(7), Value7::from(100));
+/// assert_eq!(scheme.to_midi(&fader), Some(RtpMidiMessage::MidiMessage(expected)));
+/// assert_eq!(scheme.to_osc(&RtpMidiMessage::MidiMessage(expected)), Some(OscMessage::new("/mixer/2/fader/7", vec![OscArg::Int(100)])));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OscAddressScheme {
+    pub note_on: Option<String>,
+    pub note_off: Option<String>,
+    pub key_pressure: Option<String>,
+    pub control_change: Option<String>,
+    pub program_change: Option<String>,
+    pub channel_pressure: Option<String>,
+    /// Values from 0 to 16383, centred on 8192.
+    pub pitch_bend: Option<String>,
+    pub start: Option<String>,
+    pub resume: Option<String>,
+    pub stop: Option<String>,
+    /// Carried as a single blob argument holding the bytes between F0 and F7.
+    pub sysex: Option<String>,
+}
+
+impl Default for OscAddressScheme {
+    fn default() -> Self {
+        let address = |path: &str| Some(String::from(path));
+        OscAddressScheme {
+            note_on: address("/midi/{channel}/note_on"),
+            note_off: address("/midi/{channel}/note_off"),
+            key_pressure: address("/midi/{channel}/key_pressure"),
+            control_change: address("/midi/{channel}/cc"),
+            program_change: address("/midi/{channel}/program"),
+            channel_pressure: address("/midi/{channel}/pressure"),
+            pitch_bend: address("/midi/{channel}/pitch_bend"),
+            start: address("/midi/start"),
+            resume: address("/midi/continue"),
+            stop: address("/midi/stop"),
+            sysex: address("/midi/sysex"),
+        }
+    }
+}
+
+impl OscAddressScheme {
+    /// The OSC message carrying `message`, if its kind is bridged.
+    pub fn to_osc(&self, message: &RtpMidiMessage) -> Option<OscMessage> {
+        let message = match *message {
+            RtpMidiMessage::MidiMessage(message) => message,
+            RtpMidiMessage::SysEx(sysex) => return Some(OscMessage::new(self.sysex.as_deref()?, vec![OscArg::Blob(sysex.to_vec())])),
+            // Segments are put back together before listeners see them
+            RtpMidiMessage::SysExSegment(..) => return None,
+        };
+        let (template, fields, values): (_, _, [i32; 3]) = match message {
+            MidiMessage::NoteOn(channel, key, velocity) => (&self.note_on, CHANNEL_NUMBER_VALUE, [num(channel), num(key), num(velocity)]),
+            MidiMessage::NoteOff(channel, key, velocity) => (&self.note_off, CHANNEL_NUMBER_VALUE, [num(channel), num(key), num(velocity)]),
+            MidiMessage::KeyPressure(channel, key, pressure) => (&self.key_pressure, CHANNEL_NUMBER_VALUE, [num(channel), num(key), num(pressure)]),
+            MidiMessage::ControlChange(channel, control, value) => (&self.control_change, CHANNEL_NUMBER_VALUE, [num(channel), num(control), num(value)]),
+            MidiMessage::ProgramChange(channel, program) => (&self.program_change, CHANNEL_NUMBER, [num(channel), num(program), 0]),
+            MidiMessage::ChannelPressure(channel, pressure) => (&self.channel_pressure, CHANNEL_VALUE, [num(channel), 0, num(pressure)]),
+            MidiMessage::PitchBendChange(channel, value) => (&self.pitch_bend, CHANNEL_VALUE, [num(channel), 0, i32::from(u16::from(value))]),
+            MidiMessage::Start => (&self.start, &[][..], [0; 3]),
+            MidiMessage::Continue => (&self.resume, &[][..], [0; 3]),
+            MidiMessage::Stop => (&self.stop, &[][..], [0; 3]),
+            _ => return None,
+        };
+        // Channels are counted from 1 in addresses
+        let value_of = |field| values[field as usize] + i32::from(field == Field::Channel);
+
+        let template = template.as_deref()?;
+        let mut address = String::from(template);
+        let mut args = Vec::new();
+        for &field in fields {
+            if template.split('/').any(|segment| segment == field.placeholder()) {
+                address = address.replace(field.placeholder(), &value_of(field).to_string());
+            } else {
+                args.push(OscArg::Int(value_of(field)));
+            }
+        }
+        Some(OscMessage::new(address, args))
+    }
+
+    /// The MIDI message `message` carries, if its address matches one of the templates and its numbers are in range.
+    pub fn to_midi<'a>(&self, message: &'a OscMessage) -> Option<RtpMidiMessage<'a>> {
+        if matches_template(self.sysex.as_deref(), &message.address, &[]).is_some() {
+            let [OscArg::Blob(sysex)] = &message.args[..] else {
+                return None;
+            };
+            let sysex = sysex.strip_prefix(&[0xF0]).unwrap_or(sysex);
+            let sysex = sysex.strip_suffix(&[0xF7]).unwrap_or(sysex);
+            return sysex.iter().all(|&byte| byte < 0x80).then_some(RtpMidiMessage::SysEx(sysex));
+        }
+
+        let kinds: [(&Option<String>, &[Field], BuildMessage); 10] = [
+            (&self.note_on, CHANNEL_NUMBER_VALUE, |[channel, key, velocity]| {
+                Some(MidiMessage::NoteOn(
+                    to_channel(channel)?,
+                    Note::from(to_u7(key)?),
+                    Value7::from(to_u7(velocity)?),
+                ))
+            }),
+            (&self.note_off, CHANNEL_NUMBER_VALUE, |[channel, key, velocity]| {
+                Some(MidiMessage::NoteOff(
+                    to_channel(channel)?,
+                    Note::from(to_u7(key)?),
+                    Value7::from(to_u7(velocity)?),
+                ))
+            }),
+            (&self.key_pressure, CHANNEL_NUMBER_VALUE, |[channel, key, pressure]| {
+                Some(MidiMessage::KeyPressure(
+                    to_channel(channel)?,
+                    Note::from(to_u7(key)?),
+                    Value7::from(to_u7(pressure)?),
+                ))
+            }),
+            (&self.control_change, CHANNEL_NUMBER_VALUE, |[channel, control, value]| {
+                Some(MidiMessage::ControlChange(
+                    to_channel(channel)?,
+                    Control::from(to_number(control)?),
+                    Value7::from(to_u7(value)?),
+                ))
+            }),
+            (&self.program_change, CHANNEL_NUMBER, |[channel, program, _]| {
+                Some(MidiMessage::ProgramChange(to_channel(channel)?, Program::from(to_number(program)?)))
+            }),
+            (&self.channel_pressure, CHANNEL_VALUE, |[channel, _, pressure]| {
+                Some(MidiMessage::ChannelPressure(to_channel(channel)?, Value7::from(to_u7(pressure)?)))
+            }),
+            (&self.pitch_bend, CHANNEL_VALUE, |[channel, _, value]| {
+                let value = u16::try_from(value).ok().filter(|value| *value <= 0x3FFF)?;
+                Some(MidiMessage::PitchBendChange(to_channel(channel)?, Value14::from(value)))
+            }),
+            (&self.start, &[], |_| Some(MidiMessage::Start)),
+            (&self.resume, &[], |_| Some(MidiMessage::Continue)),
+            (&self.stop, &[], |_| Some(MidiMessage::Stop)),
+        ];
+        let (fields, build, found) = kinds
+            .into_iter()
+            .find_map(|(template, fields, build)| Some((fields, build, matches_template(template.as_deref(), &message.address, fields)?)))?;
+
+        // Fields the address leaves out come from the arguments, in order; any beyond those are ignored
+        let mut args = message.args.iter();
+        let mut values = [0; 3];
+        for (&field, found) in fields.iter().zip(found) {
+            values[field as usize] = match found {
+                Some(value) => value,
+                None => args.next()?.as_int()?,
+            };
+        }
+        build(values).map(RtpMidiMessage::MidiMessage)
+    }
+}
+
+fn num(number: impl Into<u8>) -> i32 {
+    i32::from(number.into())
+}
+
+/// Builds a message from its channel, number and value, or `None` if one is out of range.
+type BuildMessage = fn([i32; 3]) -> Option<MidiMessage>;
+
+fn to_channel(channel: i32) -> Option<Channel> {
+    (1..=16).contains(&channel).then(|| Channel::from(channel as u8 - 1))
+}
+
+fn to_u7(value: i32) -> Option<u8> {
+    u8::try_from(value).ok().filter(|value| *value < 0x80)
+}
+
+/// Controller and program numbers, which midi-types only takes below 127.
+fn to_number(number: i32) -> Option<u8> {
+    to_u7(number).filter(|number| *number < 127)
+}
+
+/// Matches `address` against `template`, returning the numbers in the placeholder segments, in the order of
+/// `fields`; `None` in a slot means the template leaves that field out.
+fn matches_template(template: Option<&str>, address: &str, fields: &[Field]) -> Option<Vec<Option<i32>>> {
+    let template = template?;
+    let mut found = vec![None; fields.len()];
+    let mut segments = address.split('/');
+    for template_segment in template.split('/') {
+        let segment = segments.next()?;
+        match fields.iter().position(|field| field.placeholder() == template_segment) {
+            Some(index) => found[index] = Some(segment.parse().ok()?),
+            None if segment == template_segment => {}
+            None => return None,
+        }
+    }
+    segments.next().is_none().then_some(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn midi(message: MidiMessage) -> RtpMidiMessage<'static> {
+        RtpMidiMessage::MidiMessage(message)
+    }
+
+    #[test]
+    fn test_default_scheme_round_trip() {
+        let scheme = OscAddressScheme::default();
+        let messages = [
+            MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100)),
+            MidiMessage::ProgramChange(Channel::C10, Program::from(5)),
+            MidiMessage::PitchBendChange(Channel::C16, Value14::from(0x3FFFu16)),
+            MidiMessage::Continue,
+        ];
+        for message in messages {
+            let osc = scheme.to_osc(&midi(message)).unwrap();
+            assert_eq!(scheme.to_midi(&osc), Some(midi(message)), "{osc:?}");
+        }
+
+        let note = scheme.to_osc(&midi(messages[0])).unwrap();
+        assert_eq!(note, OscMessage::new("/midi/1/note_on", vec![OscArg::Int(60), OscArg::Int(100)]));
+        let sysex = scheme.to_osc(&RtpMidiMessage::SysEx(&[0x7E, 0x7F])).unwrap();
+        assert_eq!(scheme.to_midi(&sysex), Some(RtpMidiMessage::SysEx(&[0x7E, 0x7F])));
+    }
+
+    #[test]
+    fn test_out_of_range_numbers_are_dropped() {
+        let scheme = OscAddressScheme::default();
+        let messages = [
+            OscMessage::new("/midi/17/note_on", vec![OscArg::Int(60), OscArg::Int(100)]),
+            OscMessage::new("/midi/1/note_on", vec![OscArg::Int(60), OscArg::Int(128)]),
+            OscMessage::new("/midi/1/cc", vec![OscArg::Int(7)]),
+            OscMessage::new("/midi/one/cc", vec![OscArg::Int(7), OscArg::Int(1)]),
+            OscMessage::new("/midi/1/pitch_bend", vec![OscArg::Int(-1)]),
+            OscMessage::new("/midi/sysex", vec![OscArg::Blob(vec![0x80])]),
+            OscMessage::new("/somewhere/else", vec![]),
+        ];
+        for message in &messages {
+            assert_eq!(scheme.to_midi(message), None, "{message:?}");
+        }
+    }
+
+    #[test]
+    fn test_kinds_left_out_are_not_bridged() {
+        let scheme = OscAddressScheme {
+            note_off: None,
+            ..Default::default()
+        };
+        let note_off = MidiMessage::NoteOff(Channel::C1, Note::from(60), Value7::from(0));
+        assert_eq!(scheme.to_osc(&midi(note_off)), None);
+        assert_eq!(
+            scheme.to_midi(&OscMessage::new("/midi/1/note_off", vec![OscArg::Int(60), OscArg::Int(0)])),
+            None
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub mod address_scheme;
+pub mod osc_bridge;
+pub mod osc_message;
@@ -0,0 +1,145 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::logging::{Level, event};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::RtpMidiError;
+use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use crate::sessions::events::event_handling::{ListenerId, MidiMessageEvent, SysExPacketEvent};
+use crate::sessions::osc::address_scheme::OscAddressScheme;
+use crate::sessions::osc::osc_message::OscMessage;
+use crate::sessions::rtp_midi_session::RtpMidiSession;
+
+/// Largest OSC datagram accepted. Anything bigger is dropped.
+const MAX_OSC_PACKET_SIZE: usize = 65507;
+
+/// How many OSC messages can wait to be sent. More are dropped until the bridge catches up.
+const OSC_QUEUE_CAPACITY: usize = 1024;
+
+/// Passes MIDI between a session and an OSC peer, such as a lighting desk or show controller: what participants
+/// send the session goes out as OSC, and OSC that arrives is sent on to the participants. Where each kind of message
+/// goes in the OSC address space is set by an [`OscAddressScheme`].
+///
+/// The bridge runs on the session's runtime, see
+/// [`SessionConfig::runtime`](crate::sessions::session_config::SessionConfig::runtime), until it is stopped or
+/// dropped.
+pub struct OscBridge {
+    local_addr: SocketAddr,
+    session: Arc<RtpMidiSession>,
+    listeners: [ListenerId; 2],
+    cancel_token: CancellationToken,
+}
+
+impl OscBridge {
+    /// Listens for OSC on `bind_addr` from any sender, and sends the session's MIDI as OSC to `osc_target`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(session, scheme)))]
+    pub async fn start(session: Arc<RtpMidiSession>, bind_addr: SocketAddr, osc_target: SocketAddr, scheme: OscAddressScheme) -> Result<Self, RtpMidiError> {
+        // Bound from a task on the session's runtime, as sockets are driven by the runtime they're bound from
+        let socket = match session.spawn(UdpSocket::bind(bind_addr)).await {
+            Ok(socket) => socket?,
+            Err(_) => return Err(RtpMidiError::InvalidState("the session's runtime has shut down")),
+        };
+        let local_addr = socket.local_addr()?;
+        let scheme = Arc::new(scheme);
+
+        let (outgoing, mut to_send) = mpsc::channel(OSC_QUEUE_CAPACITY);
+        let midi_outgoing = outgoing.clone();
+        let midi_scheme = Arc::clone(&scheme);
+        let midi_listener = session
+            .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+                if let Some(osc) = midi_scheme.to_osc(&RtpMidiMessage::MidiMessage(message)) {
+                    queue(&midi_outgoing, osc);
+                }
+            })
+            .await;
+        let sysex_scheme = Arc::clone(&scheme);
+        let sysex_listener = session
+            .add_listener(SysExPacketEvent, move |sysex| {
+                if let Some(osc) = sysex_scheme.to_osc(&RtpMidiMessage::SysEx(sysex)) {
+                    queue(&outgoing, osc);
+                }
+            })
+            .await;
+
+        let cancel_token = CancellationToken::new();
+        let task_cancel_token = cancel_token.clone();
+        let task_session = Arc::clone(&session);
+        session.spawn(async move {
+            let mut buf = vec![0; MAX_OSC_PACKET_SIZE + 1];
+            loop {
+                tokio::select! {
+                    _ = task_cancel_token.cancelled() => {
+                        event!(Level::DEBUG, "OSC bridge: cancellation requested");
+                        break;
+                    },
+                    Some(osc) = to_send.recv() => {
+                        if let Err(e) = socket.send_to(&osc.to_bytes(), osc_target).await {
+                            event!(Level::WARN, "Failed to send OSC message to {osc_target}: {e}");
+                        }
+                    },
+                    received = socket.recv_from(&mut buf) => match received {
+                        Ok((amt, src)) if amt <= MAX_OSC_PACKET_SIZE => forward_to_midi(&task_session, &scheme, &buf[..amt], src).await,
+                        Ok((_, src)) => event!(Level::WARN, "Dropping oversized OSC packet from {src}"),
+                        Err(e) => event!(Level::ERROR, "Failed to receive OSC packet: {e}"),
+                    },
+                }
+            }
+        });
+
+        event!(Level::INFO, "Started OSC bridge on {local_addr}");
+        Ok(OscBridge {
+            local_addr,
+            session,
+            listeners: [midi_listener, sysex_listener],
+            cancel_token,
+        })
+    }
+
+    /// The address OSC is received on, which tells the port picked if `bind_addr` gave port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops the bridge and takes its listeners off the session.
+    pub fn stop(&self) {
+        self.cancel_token.cancel();
+        for listener in &self.listeners {
+            self.session.remove_listener(listener);
+        }
+    }
+}
+
+impl Drop for OscBridge {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Hands `osc` to the bridge's task to send, dropping it if the task has fallen that far behind.
+fn queue(outgoing: &mpsc::Sender<OscMessage>, osc: OscMessage) {
+    match outgoing.try_send(osc) {
+        Ok(()) => {}
+        Err(mpsc::error::TrySendError::Full(osc)) => event!(Level::WARN, "OSC bridge falling behind, dropping OSC message {}", osc.address),
+        // Once the bridge has stopped, there's nothing left to do
+        Err(mpsc::error::TrySendError::Closed(_)) => {}
+    }
+}
+
+async fn forward_to_midi(session: &RtpMidiSession, scheme: &OscAddressScheme, datagram: &[u8], src: SocketAddr) {
+    let Some(messages) = OscMessage::from_bytes(datagram) else {
+        event!(Level::WARN, "Dropping OSC packet from {src} that couldn't be parsed");
+        return;
+    };
+    for osc in &messages {
+        let Some(midi) = scheme.to_midi(osc) else {
+            event!(Level::DEBUG, "No MIDI message for OSC message {}", osc.address);
+            continue;
+        };
+        if let Err(e) = session.send_midi(&midi).await {
+            event!(Level::WARN, "Failed to send MIDI from OSC message {}: {e}", osc.address);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! The parts of Open Sound Control (OSC) 1.0 the bridge needs: messages with int, float, string and blob arguments,
+//! and bundles, whose time tags are ignored.
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Blob(Vec<u8>),
+}
+
+impl OscArg {
+    /// The argument as a whole number, rounding floats, as senders like TouchOSC send faders as floats.
+    pub fn as_int(&self) -> Option<i32> {
+        match *self {
+            OscArg::Int(value) => Some(value),
+            OscArg::Float(value) if value.is_finite() => Some(value.round() as i32),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: impl Into<String>, args: Vec<OscArg>) -> Self {
+        OscMessage { address: address.into(), args }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_string(&mut bytes, self.address.as_bytes());
+        let tags: String = core::iter::once(',')
+            .chain(self.args.iter().map(|arg| match arg {
+                OscArg::Int(_) => 'i',
+                OscArg::Float(_) => 'f',
+                OscArg::String(_) => 's',
+                OscArg::Blob(_) => 'b',
+            }))
+            .collect();
+        write_string(&mut bytes, tags.as_bytes());
+        for arg in &self.args {
+            match arg {
+                OscArg::Int(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+                OscArg::Float(value) => bytes.extend_from_slice(&value.to_be_bytes()),
+                OscArg::String(value) => write_string(&mut bytes, value.as_bytes()),
+                OscArg::Blob(value) => {
+                    bytes.extend_from_slice(&(value.len() as i32).to_be_bytes());
+                    bytes.extend_from_slice(value);
+                    bytes.resize(bytes.len().next_multiple_of(4), 0);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Reads a datagram, which holds either a single message or a bundle of them. Returns `None` if it isn't valid
+    /// OSC, or uses argument types other than int, float, string and blob.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Vec<OscMessage>> {
+        let mut messages = Vec::new();
+        read_packet(bytes, &mut messages)?;
+        Some(messages)
+    }
+}
+
+/// Writes a string with its NUL terminator, padded to a multiple of four bytes.
+fn write_string(bytes: &mut Vec<u8>, string: &[u8]) {
+    bytes.extend_from_slice(string);
+    bytes.push(0);
+    bytes.resize(bytes.len().next_multiple_of(4), 0);
+}
+
+fn read_packet(bytes: &[u8], messages: &mut Vec<OscMessage>) -> Option<()> {
+    let Some(mut rest) = bytes.strip_prefix(BUNDLE_TAG) else {
+        messages.push(read_message(bytes)?);
+        return Some(());
+    };
+    let (_time_tag, elements) = rest.split_at_checked(8)?;
+    rest = elements;
+    while !rest.is_empty() {
+        let size = read_size(&mut rest)?;
+        let (element, remaining) = rest.split_at_checked(size)?;
+        read_packet(element, messages)?;
+        rest = remaining;
+    }
+    Some(())
+}
+
+fn read_message(bytes: &[u8]) -> Option<OscMessage> {
+    let mut rest = bytes;
+    let address = read_string(&mut rest)?;
+    if !address.starts_with('/') {
+        return None;
+    }
+    // Very old senders leave the type tags out altogether; they can't be told apart from no arguments
+    let tags = if rest.is_empty() { String::from(",") } else { read_string(&mut rest)? };
+    let mut args = Vec::new();
+    for tag in tags.strip_prefix(',')?.chars() {
+        let arg = match tag {
+            'i' => OscArg::Int(i32::from_be_bytes(read_array(&mut rest)?)),
+            'f' => OscArg::Float(f32::from_be_bytes(read_array(&mut rest)?)),
+            's' => OscArg::String(read_string(&mut rest)?),
+            'b' => {
+                let size = read_size(&mut rest)?;
+                let (blob, remaining) = rest.split_at_checked(size)?;
+                rest = remaining.get(size.next_multiple_of(4) - size..)?;
+                OscArg::Blob(blob.to_vec())
+            }
+            _ => return None,
+        };
+        args.push(arg);
+    }
+    Some(OscMessage { address, args })
+}
+
+fn read_array<const N: usize>(rest: &mut &[u8]) -> Option<[u8; N]> {
+    let (bytes, remaining) = rest.split_first_chunk::<N>()?;
+    *rest = remaining;
+    Some(*bytes)
+}
+
+fn read_size(rest: &mut &[u8]) -> Option<usize> {
+    usize::try_from(i32::from_be_bytes(read_array(rest)?)).ok()
+}
+
+fn read_string(rest: &mut &[u8]) -> Option<String> {
+    let end = rest.iter().position(|&byte| byte == 0)?;
+    let string = core::str::from_utf8(&rest[..end]).ok()?.to_owned();
+    *rest = rest.get((end + 1).next_multiple_of(4)..)?;
+    Some(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_layout() {
+        let message = OscMessage::new("/a", vec![OscArg::Int(1), OscArg::Blob(vec![0xF0])]);
+        assert_eq!(
+            message.to_bytes(),
+            [b'/', b'a', 0, 0, b',', b'i', b'b', 0, 0, 0, 0, 1, 0, 0, 0, 1, 0xF0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_messages_round_trip() {
+        let message = OscMessage::new(
+            "/midi/1/note_on",
+            vec![
+                OscArg::Int(60),
+                OscArg::Float(0.5),
+                OscArg::String("abcd".into()),
+                OscArg::Blob(vec![1, 2, 3, 4, 5]),
+            ],
+        );
+        assert_eq!(OscMessage::from_bytes(&message.to_bytes()), Some(vec![message]));
+    }
+
+    #[test]
+    fn test_bundles_are_unpacked() {
+        let first = OscMessage::new("/first", vec![OscArg::Int(1)]).to_bytes();
+        let second = OscMessage::new("/second", vec![]).to_bytes();
+        let mut bundle = [BUNDLE_TAG, &[0, 0, 0, 0, 0, 0, 0, 1]].concat();
+        for element in [&first, &second] {
+            bundle.extend_from_slice(&(element.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(element);
+        }
+        let messages = OscMessage::from_bytes(&bundle).unwrap();
+        assert_eq!(
+            messages.iter().map(|message| message.address.as_str()).collect::<Vec<_>>(),
+            ["/first", "/second"]
+        );
+
+        // An element running past the end of the bundle
+        bundle.truncate(bundle.len() - 1);
+        assert_eq!(OscMessage::from_bytes(&bundle), None);
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use zerocopy::network_endian::U32;
+
+/// Named groups of participants, such as "FOH" or "monitors", that MIDI can be sent to as a whole. Members are kept
+/// by SSRC, so a participant that leaves and later rejoins is still in its groups. Groups without members don't
+/// exist.
+#[derive(Debug, Default)]
+pub(crate) struct ParticipantGroups {
+    groups: BTreeMap<String, BTreeSet<u32>>,
+}
+
+impl ParticipantGroups {
+    /// Returns whether the participant wasn't in the group already.
+    pub fn add(&mut self, group: &str, ssrc: U32) -> bool {
+        self.groups.entry(group.to_owned()).or_default().insert(ssrc.get())
+    }
+
+    /// Returns whether the participant was in the group.
+    pub fn remove(&mut self, group: &str, ssrc: U32) -> bool {
+        let Some(members) = self.groups.get_mut(group) else {
+            return false;
+        };
+        let removed = members.remove(&ssrc.get());
+        if members.is_empty() {
+            self.groups.remove(group);
+        }
+        removed
+    }
+
+    pub fn members(&self, group: &str) -> Vec<U32> {
+        self.groups.get(group).into_iter().flatten().map(|&ssrc| U32::new(ssrc)).collect()
+    }
+
+    /// The groups the participant is in, in name order.
+    pub fn groups_of(&self, ssrc: U32) -> Vec<String> {
+        self.groups
+            .iter()
+            .filter(|(_, members)| members.contains(&ssrc.get()))
+            .map(|(group, _)| group.clone())
+            .collect()
+    }
+
+    /// All the groups, in name order.
+    pub fn names(&self) -> Vec<String> {
+        self.groups.keys().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership() {
+        let mut groups = ParticipantGroups::default();
+        assert!(groups.add("monitors", U32::new(1)));
+        assert!(groups.add("FOH", U32::new(1)));
+        assert!(groups.add("FOH", U32::new(2)));
+        assert!(!groups.add("FOH", U32::new(2)));
+
+        assert_eq!(groups.members("FOH"), [U32::new(1), U32::new(2)]);
+        assert_eq!(groups.groups_of(U32::new(1)), ["FOH", "monitors"]);
+        assert!(groups.members("stage").is_empty());
+
+        assert!(groups.remove("monitors", U32::new(1)));
+        assert!(!groups.remove("monitors", U32::new(1)));
+        assert_eq!(groups.names(), ["FOH"]);
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+
+use zerocopy::{FromBytes, IntoBytes};
+
+use crate::error::RtpMidiError;
+
+/// Where a session gets the random numbers it makes up: invitation tokens, STUN transaction IDs, the SSRC it moves to
+/// after a collision, and authentication nonces, which need to be unpredictable. The `rand` crate's thread-local
+/// generator is used without one, with the `rand` feature; builds without it, for targets where every dependency
+/// counts, have to give sessions one through [`SessionConfig::random`](super::session_config::SessionConfig::random).
+///
+/// Closures filling a buffer are sources too, such as one reading the system's generator:
+///
+/// ```
+/// use std::io::Read;
+/// use std::sync::Arc;
+/// use rtpmidi::sessions::session_config::SessionConfig;
+///
+/// let config = SessionConfig {
+///     random: Some(Arc::new(|bytes: &mut [u8]| {
+///         std::fs::File::open("/dev/urandom").and_then(|mut urandom| urandom.read_exact(bytes)).expect("no /dev/urandom");
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+pub trait RandomSource: Send + Sync + 'static {
+    /// Fills `bytes` with random ones.
+    fn fill_bytes(&self, bytes: &mut [u8]);
+}
+
+impl fmt::Debug for dyn RandomSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RandomSource")
+    }
+}
+
+impl<F> RandomSource for F
+where
+    F: Fn(&mut [u8]) + Send + Sync + 'static,
+{
+    fn fill_bytes(&self, bytes: &mut [u8]) {
+        self(bytes)
+    }
+}
+
+/// Fails unless there is somewhere to get random numbers from, so sessions can't start without.
+pub(crate) fn require(source: Option<&dyn RandomSource>) -> Result<(), RtpMidiError> {
+    match source {
+        None if !cfg!(feature = "rand") => Err(RtpMidiError::InvalidArgument(
+            "SessionConfig::random has to be given without the `rand` feature".to_owned(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// A random value from `source`, or from the `rand` crate without one. Only called once [`require`] has passed.
+pub(crate) fn random<T: FromBytes + IntoBytes>(source: Option<&dyn RandomSource>) -> T {
+    let mut value = T::new_zeroed();
+    match source {
+        Some(source) => source.fill_bytes(value.as_mut_bytes()),
+        #[cfg(feature = "rand")]
+        None => rand::fill(value.as_mut_bytes()),
+        #[cfg(not(feature = "rand"))]
+        None => unreachable!("sessions don't start without a random source"),
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_values_come_from_the_source() {
+        let source = |bytes: &mut [u8]| bytes.iter_mut().enumerate().for_each(|(i, byte)| *byte = i as u8 + 1);
+        assert!(require(Some(&source)).is_ok());
+        assert_eq!(random::<u32>(Some(&source)), u32::from_ne_bytes([1, 2, 3, 4]));
+        assert_eq!(random::<[u8; 3]>(Some(&source)), [1, 2, 3]);
+        assert_eq!(require(None).is_ok(), cfg!(feature = "rand"));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use crate::packets::control_packets::retransmission_request_packet::RetransmissionRequestPacket;
+
+/// What [`SessionConfig::retransmission`](super::session_config::SessionConfig::retransmission) keeps for one
+/// participant: the latest packets sent to it, as they went out, and the sequence numbers asked of it that haven't
+/// arrived yet, with when they were asked for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct RetransmissionHistory {
+    sent: VecDeque<(u16, Bytes)>,
+    missing: VecDeque<(u16, Instant)>,
+}
+
+impl RetransmissionHistory {
+    /// The bytes of the packets kept and of the sequence numbers waited for.
+    pub fn heap_size(&self) -> usize {
+        let sent: usize = self.sent.iter().map(|(_, datagram)| datagram.len()).sum();
+        sent + self.sent.capacity() * size_of::<(u16, Bytes)>() + self.missing.capacity() * size_of::<(u16, Instant)>()
+    }
+
+    /// Keeps a packet sent to the participant, letting go of the oldest once there are more than `history`.
+    pub fn sent(&mut self, sequence_number: u16, datagram: Bytes, history: usize) {
+        push_bounded(&mut self.sent, (sequence_number, datagram), history);
+    }
+
+    /// The packets kept that the participant has asked for, oldest first.
+    pub fn requested<'a>(&'a self, request: &'a RetransmissionRequestPacket) -> impl Iterator<Item = &'a Bytes> + 'a {
+        self.sent
+            .iter()
+            .filter(|(sequence_number, _)| request.includes(*sequence_number))
+            .map(|(_, datagram)| datagram)
+    }
+
+    /// Remembers that the `count` packets from `first` on have been asked for, letting go of the oldest asked for
+    /// once more than `history` are waited for. Returns how many were let go of, as they are lost for good.
+    pub fn missing(&mut self, first: u16, count: u16, history: usize) -> u16 {
+        let now = Instant::now();
+        (0..count)
+            .filter(|&offset| push_bounded(&mut self.missing, (first.wrapping_add(offset), now), history))
+            .count() as u16
+    }
+
+    /// Gives up on the packets asked for longer than `timeout` ago, returning how many there were.
+    pub fn expire_missing(&mut self, timeout: Duration) -> u16 {
+        let Some(deadline) = Instant::now().checked_sub(timeout) else {
+            return 0;
+        };
+        let expired = self.missing.iter().take_while(|(_, asked)| *asked <= deadline).count();
+        self.missing.drain(..expired);
+        expired as u16
+    }
+
+    /// Whether the packet was asked for, forgetting that it was so a second copy counts as a duplicate.
+    pub fn take_missing(&mut self, sequence_number: u16) -> bool {
+        match self.missing.iter().position(|&(missing, _)| missing == sequence_number) {
+            Some(index) => {
+                self.missing.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops waiting for anything asked for, as when the participant's sequence numbers start over.
+    pub fn forget_missing(&mut self) {
+        self.missing.clear();
+    }
+}
+
+/// Adds `item`, first letting go of the oldest if there are `bound` already. Returns whether one was let go of, or
+/// `item` was, if the bound is 0.
+fn push_bounded<T>(queue: &mut VecDeque<T>, item: T, bound: usize) -> bool {
+    if bound == 0 {
+        return true;
+    }
+    let evicted = queue.len() >= bound && queue.pop_front().is_some();
+    queue.push_back(item);
+    evicted
+}
+
+#[cfg(test)]
+mod tests {
+    use zerocopy::network_endian::{U16, U32};
+
+    use super::*;
+
+    #[test]
+    fn test_only_the_latest_packets_are_kept() {
+        let mut history = RetransmissionHistory::default();
+        for sequence_number in 0..5u16 {
+            history.sent(sequence_number, Bytes::from(vec![sequence_number as u8]), 3);
+        }
+        let request = RetransmissionRequestPacket::new(U16::new(0), U16::new(4), U32::new(1));
+        let resent: Vec<_> = history.requested(&request).cloned().collect();
+        assert_eq!(resent, [Bytes::from_static(&[2]), Bytes::from_static(&[3])]);
+    }
+
+    #[test]
+    fn test_missing_packets_are_only_taken_once() {
+        let mut history = RetransmissionHistory::default();
+        assert_eq!(history.missing(0xFFFF, 2, 8), 0);
+        assert!(history.take_missing(0));
+        assert!(!history.take_missing(0));
+        assert!(history.take_missing(0xFFFF));
+        assert!(!history.take_missing(1));
+    }
+
+    #[test]
+    fn test_missing_packets_that_dont_fit_are_given_up_on() {
+        let mut history = RetransmissionHistory::default();
+        assert_eq!(history.missing(0, 3, 4), 0);
+        assert_eq!(history.missing(3, 3, 4), 2);
+        assert!(!history.take_missing(1));
+        assert!(history.take_missing(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_missing_packets_are_given_up_on_after_the_timeout() {
+        let mut history = RetransmissionHistory::default();
+        history.missing(0, 2, 8);
+        tokio::time::advance(Duration::from_millis(60)).await;
+        history.missing(2, 1, 8);
+        tokio::time::advance(Duration::from_millis(60)).await;
+        assert_eq!(history.expire_missing(Duration::from_millis(100)), 2);
+        assert_eq!(history.expire_missing(Duration::from_millis(100)), 0);
+        assert!(history.take_missing(2));
+    }
+}
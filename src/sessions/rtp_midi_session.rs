@@ -1,95 +1,329 @@
-use std::collections::HashMap;
+use crate::logging::{Level, event};
+use midi_types::{Channel, Control, MidiMessage, Note, Program, Value7, Value14};
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, PoisonError};
 use std::time::{Duration, Instant};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock, mpsc, watch};
 use tokio::task::JoinHandle;
-use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
-use tracing::{Level, event, instrument};
 use zerocopy::network_endian::{U32, U64};
+use zerocopy::{FromBytes, IntoBytes};
 
 use super::host_syncer::HostSyncer;
 use super::invite_responder::InviteResponder;
 #[cfg(feature = "mdns")]
-use super::mdns::advertise_mdns;
+use super::mdns::MdnsAdvertisement;
 use super::rtp_port::RtpPort;
+use crate::error::RtpMidiError;
 use crate::packets::midi_packets::midi_event::MidiEvent;
 use crate::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
 use crate::participant::Participant;
-use crate::sessions::control_port::{ControlPort, MAX_CONTROL_PACKET_SIZE};
-use crate::sessions::events::event_handling::{EventListeners, EventType};
-use crate::sessions::midi_port::{MAX_MIDI_PACKET_SIZE, MidiPort};
+use crate::sessions::authenticator::{self, AUTHENTICATION_TIMEOUT};
+use crate::sessions::capture::PacketCapture;
+use crate::sessions::channel_map::{ChannelRoutes, ChannelRouting, SharedChannelRoutes};
+use crate::sessions::control_port::ControlPort;
+use crate::sessions::events::event_dispatcher::{EventQueue, QueuedEvent, QueuedEvents, dispatch_events};
+use crate::sessions::events::event_handling::{
+    AddressChanged, AuthenticationFailed, EventType, FloodReason, InvitationFlood, ListenerId, ListenerRegistry, LocalEventType, ParticipantLimitReached,
+};
+use crate::sessions::events::tempo_estimator::{TempoEstimator, TempoEstimators};
+use crate::sessions::flood_guard::{FloodGuard, Screening, Verdict};
+use crate::sessions::interceptor::{Action, Direction, InterceptorChain};
+use crate::sessions::known_peer::KnownPeer;
+use crate::sessions::midi_port::{MidiPort, Recipients, is_audible};
+use crate::sessions::participant_groups::ParticipantGroups;
+use crate::sessions::random;
+use crate::sessions::scheduler::{Scheduler, Timer};
+use crate::sessions::session_config::SessionConfig;
+#[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+use crate::sessions::socket::MAX_RECEIVE_BATCH;
+use crate::sessions::socket::SocketHooks;
+use crate::sessions::stats::{MemoryUsage, ParticipantStats, SessionCounters, SessionStats, map_heap_size};
+use crate::sessions::stun::{self, ExternalAddresses, StunTransactions};
+use crate::sessions::wire_tap::WireTapSlot;
 
 #[derive(Clone)]
 pub struct RtpMidiSession {
-    pub(super) participants: Arc<Mutex<HashMap<U32, Participant>>>,              // key by ssrc
+    pub(super) participants: Arc<RwLock<HashMap<U32, Participant>>>,             // key by ssrc
     pub(super) pending_invitations: Arc<Mutex<HashMap<U32, PendingInvitation>>>, // key by ssrc
+    pub(super) counters: Arc<SessionCounters>,
+    pub(super) sent_invitations: Arc<Mutex<HashMap<U32, PendingInvitation>>>, // not answered yet, key by token
+    pub(super) pending_authentications: Arc<Mutex<HashMap<U32, PendingAuthentication>>>, // key by ssrc
     pub(super) midi_port: Arc<MidiPort>,
+    pub(super) listeners: Arc<ListenerRegistry>,
+    pub(super) events: EventQueue,
+    pub(super) config: Arc<SessionConfig>,
+    pub(super) solo: Arc<std::sync::RwLock<HashSet<U32>>>,
+    pub(super) channel_routes: Arc<SharedChannelRoutes>,
+    pub(super) interceptors: Arc<InterceptorChain>,
+    pub(super) participants_synced: Arc<watch::Sender<()>>, // sent whenever a participant completes a clock sync
 
-    listeners: Arc<Mutex<EventListeners>>,
+    tempos: Arc<TempoEstimators>,
+    groups: Arc<std::sync::RwLock<ParticipantGroups>>,
+    wire_tap: Arc<WireTapSlot>,
+    pub(super) stun: Arc<StunTransactions>,
+    external_addresses: Arc<std::sync::RwLock<Option<ExternalAddresses>>>,
+    flood_guard: Option<Arc<FloodGuard>>,
     control_port: Arc<ControlPort>,
+    ssrc: Arc<AtomicU32>,
     host_syncer: Arc<HostSyncer>,
     cancel_token: Arc<CancellationToken>,
     task_handles: Arc<Mutex<Vec<JoinHandle<()>>>>,
     name: CString,
     #[cfg(feature = "mdns")]
-    mdns: mdns_sd::ServiceDaemon,
+    mdns: Option<MdnsAdvertisement>, // not advertised if it can't be joined
+}
+
+/// What a session gets from the [`SessionManager`](super::session_manager::SessionManager) running it, rather than
+/// setting up for itself.
+#[derive(Clone, Default)]
+pub(super) struct SharedResources {
+    #[cfg(feature = "mdns")]
+    pub mdns: Option<mdns_sd::ServiceDaemon>,
+    /// Whether the manager sends the session's clock syncs, instead of the session running a task of its own for them.
+    pub managed_clock_sync: bool,
+}
+
+/// A peer that has finished its handshake, but not yet answered our authentication challenge.
+#[derive(Debug, Clone)]
+pub(super) struct PendingAuthentication {
+    pub participant: Participant,
+    pub nonce: [u8; 16],
+    pub since: Instant,
+    /// For a participant that has moved, the change to report once it has proven itself at its new address.
+    pub moved: Option<AddressChanged>,
+}
+
+/// Which participant [`RtpMidiSession::wait_for_participant`] waits for, or which peer
+/// [`RtpMidiClient`](crate::sessions::client::RtpMidiClient) connects to: the one at a control port address, or the
+/// one going by a name. A string that parses as an address is taken as one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParticipantMatch {
+    Addr(SocketAddr),
+    Name(String),
+}
+
+impl ParticipantMatch {
+    fn matches(&self, participant: &Participant) -> bool {
+        match self {
+            ParticipantMatch::Addr(addr) => participant.addr() == *addr,
+            ParticipantMatch::Name(name) => participant.name() == name,
+        }
+    }
+}
+
+/// Written as the address or the name, so it converts back from the string it is written as.
+impl std::fmt::Display for ParticipantMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParticipantMatch::Addr(addr) => write!(f, "{addr}"),
+            ParticipantMatch::Name(name) => f.write_str(name),
+        }
+    }
+}
+
+impl From<SocketAddr> for ParticipantMatch {
+    fn from(addr: SocketAddr) -> Self {
+        ParticipantMatch::Addr(addr)
+    }
+}
+
+impl From<&str> for ParticipantMatch {
+    fn from(participant: &str) -> Self {
+        match participant.parse() {
+            Ok(addr) => ParticipantMatch::Addr(addr),
+            Err(_) => ParticipantMatch::Name(participant.to_owned()),
+        }
+    }
+}
+
+impl From<String> for ParticipantMatch {
+    fn from(participant: String) -> Self {
+        ParticipantMatch::from(participant.as_str())
+    }
 }
 
 #[derive(Debug, Clone)]
 pub(super) struct PendingInvitation {
     pub addr: SocketAddr,
     pub token: U32,
-    pub name: CString,
+    pub name: String,
+    /// When the invitation was sent or accepted, for giving up on it after [`INVITATION_TIMEOUT`].
+    pub since: tokio::time::Instant,
 }
 
 impl RtpMidiSession {
-    async fn bind(port: u16, name: &str, ssrc: u32) -> std::io::Result<Self> {
-        let cstr_name = CString::new(name).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    #[cfg_attr(not(feature = "mdns"), allow(unused_variables))]
+    async fn bind(port: u16, name: &str, ssrc: u32, mut config: SessionConfig, events: EventQueue, shared: &SharedResources) -> Result<Self, RtpMidiError> {
+        let cstr_name = CString::new(name).map_err(|e| RtpMidiError::InvalidArgument(format!("session name: {e}")))?;
+        random::require(config.random.as_deref())?;
+        let ssrc = Arc::new(AtomicU32::new(ssrc));
+        let hooks = SocketHooks {
+            capture: match &config.capture {
+                Some(path) => Some(Arc::new(PacketCapture::create(path)?)),
+                None => None,
+            },
+            wire_tap: Arc::default(),
+            encryption: config.encryption.clone(),
+            #[cfg(feature = "hexdump")]
+            hexdump: config.hexdump_packets,
+        };
+        let (control_port, midi_port) = Self::bind_ports(port, &cstr_name, &ssrc, &hooks).await?;
+        #[cfg(feature = "mdns")]
+        let mdns = if config.initiator_only {
+            None
+        } else {
+            let port = control_port.socket().local_addr().port();
+            Some(MdnsAdvertisement::start(shared.mdns.as_ref(), name, port).map_err(std::io::Error::other)?)
+        };
 
         let context = RtpMidiSession {
-            participants: Arc::new(Mutex::new(HashMap::new())),
+            participants: Arc::new(RwLock::new(HashMap::new())),
             pending_invitations: Arc::new(Mutex::new(HashMap::new())),
-            control_port: Arc::new(ControlPort::bind(port, cstr_name.to_owned(), U32::new(ssrc)).await?),
-            midi_port: Arc::new(MidiPort::bind(port + 1, cstr_name.to_owned(), U32::new(ssrc)).await?),
+            sent_invitations: Arc::default(),
+            pending_authentications: Arc::default(),
+            counters: Arc::default(),
+            tempos: Arc::default(),
+            groups: Arc::default(),
+            flood_guard: config.flood_protection.map(|protection| Arc::new(FloodGuard::new(protection))),
+            control_port: Arc::new(control_port),
+            midi_port: Arc::new(midi_port),
+            wire_tap: hooks.wire_tap,
+            stun: Arc::default(),
+            external_addresses: Arc::default(),
+            ssrc,
             host_syncer: Arc::new(HostSyncer::new()),
-            listeners: Arc::new(Mutex::new(EventListeners::new())),
+            listeners: Arc::new(ListenerRegistry::new(std::mem::take(&mut config.listeners))),
+            events,
+            solo: Arc::default(),
+            channel_routes: Arc::new(SharedChannelRoutes::new(ChannelRoutes::new(config.channel_routing))),
+            interceptors: Arc::default(),
+            participants_synced: Arc::new(watch::Sender::new(())),
+            config: Arc::new(config),
             cancel_token: Arc::new(CancellationToken::new()),
             task_handles: Arc::new(Mutex::new(Vec::new())),
             name: cstr_name,
             #[cfg(feature = "mdns")]
-            mdns: advertise_mdns(name, port).map_err(|e| std::io::Error::other(e.to_string()))?,
+            mdns,
         };
         Ok(context)
     }
 
-    #[instrument(skip(port),fields(control_port = %port, midi_port = %port + 1))]
-    pub async fn start(port: u16, name: &str, ssrc: u32, invite_handler: InviteResponder) -> std::io::Result<Arc<Self>> {
-        event!(tracing::Level::INFO, "Starting RTP-MIDI session");
-        let ctx = Arc::new(Self::bind(port, name, ssrc).await?);
-        ctx.start_threads(invite_handler);
+    /// Binds the control port and the MIDI port above it. With port 0 the system picks the control port, and picks
+    /// again if the one above it is taken.
+    async fn bind_ports(port: u16, name: &CString, ssrc: &Arc<AtomicU32>, hooks: &SocketHooks) -> Result<(ControlPort, MidiPort), RtpMidiError> {
+        let mut attempts = 0;
+        loop {
+            let control_port = ControlPort::bind(port, name.to_owned(), Arc::clone(ssrc), hooks.clone()).await?;
+            let midi_result = match control_port.socket().local_addr().port().checked_add(1) {
+                Some(midi_port) => MidiPort::bind(midi_port, name.to_owned(), Arc::clone(ssrc), hooks.clone()).await,
+                None => Err(RtpMidiError::InvalidArgument("the control port has no port above it for MIDI".to_owned())),
+            };
+            match midi_result {
+                Ok(midi_port) => return Ok((control_port, midi_port)),
+                Err(_) if port == 0 && attempts < EPHEMERAL_PORT_ATTEMPTS => attempts += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Starts a session with its control port on `port` and its MIDI port on the one above. With port 0 the system
+    /// picks a free pair, which [`local_control_addr`](Self::local_control_addr) tells.
+    pub async fn start(port: u16, name: &str, ssrc: u32, invite_handler: InviteResponder) -> Result<Arc<Self>, RtpMidiError> {
+        Self::start_with_config(port, name, ssrc, invite_handler, SessionConfig::default()).await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(port, config),fields(control_port = %port, midi_port = %port + 1)))]
+    pub async fn start_with_config(
+        port: u16,
+        name: &str,
+        ssrc: u32,
+        invite_handler: InviteResponder,
+        config: SessionConfig,
+    ) -> Result<Arc<Self>, RtpMidiError> {
+        Self::start_with_resources(port, name, ssrc, invite_handler, config, SharedResources::default()).await
+    }
+
+    pub(super) async fn start_with_resources(
+        port: u16,
+        name: &str,
+        ssrc: u32,
+        invite_handler: InviteResponder,
+        config: SessionConfig,
+        shared: SharedResources,
+    ) -> Result<Arc<Self>, RtpMidiError> {
+        let Some(runtime) = config.runtime.clone() else {
+            return Self::start_here(port, name, ssrc, invite_handler, config, shared).await;
+        };
+        // Bound from a task on the session's runtime, as sockets are driven by the runtime they're bound from
+        let name = name.to_owned();
+        let started = runtime.spawn(async move { Self::start_here(port, &name, ssrc, invite_handler, config, shared).await });
+        match started.await {
+            Ok(started) => started,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => Err(RtpMidiError::InvalidState("the session's runtime has shut down")),
+        }
+    }
+
+    async fn start_here(
+        port: u16,
+        name: &str,
+        ssrc: u32,
+        invite_handler: InviteResponder,
+        config: SessionConfig,
+        shared: SharedResources,
+    ) -> Result<Arc<Self>, RtpMidiError> {
+        event!(Level::INFO, "Starting RTP-MIDI session");
+        let (events, queued_events) = EventQueue::channel(config.event_queue_capacity, config.event_queue_overflow);
+        let ctx = Arc::new(Self::bind(port, name, ssrc, config, events, &shared).await?);
+        ctx.start_threads(invite_handler, queued_events, shared.managed_clock_sync);
+        if let Err(e) = ctx.restore_peers(&ctx.config.peers).await {
+            event!(Level::WARN, "Failed to invite a configured peer: {e}");
+        }
         Ok(ctx)
     }
 
-    fn start_threads(&self, invite_handler: InviteResponder) {
+    fn start_threads(&self, invite_handler: InviteResponder, queued_events: QueuedEvents, managed_clock_sync: bool) {
         let mut handles = Vec::new();
+        // Invitations can arrive on either port
+        let invite_handler = Arc::new(invite_handler);
+
+        // Event dispatcher, so slow listeners don't hold up the sockets
+        let listeners = Arc::clone(&self.listeners);
+        let config = Arc::clone(&self.config);
+        let tempos = Arc::clone(&self.tempos);
+        let channel_routes = Arc::clone(&self.channel_routes);
+        let interceptors = Arc::clone(&self.interceptors);
+        let dispatcher_cancel_token = Arc::clone(&self.cancel_token);
+        let handle = self.spawn(async move {
+            tokio::select! {
+                _ = dispatcher_cancel_token.cancelled() => {
+                    event!(Level::DEBUG, "dispatch_events: cancellation requested");
+                },
+                _ = dispatch_events(queued_events, listeners, config, tempos, channel_routes, interceptors) => {}
+            }
+        });
+        handles.push(handle);
 
         // Control port listener
         let control_port = Arc::clone(&self.control_port);
         let ctx_control = self.clone();
         let control_cancel_token = Arc::clone(&self.cancel_token);
+        let max_control_packet_size = self.config.max_control_packet_size;
+        let control_invite_handler = Arc::clone(&invite_handler);
 
-        let handle = tokio::spawn(async move {
-            let mut buf = [0u8; MAX_CONTROL_PACKET_SIZE];
+        let handle = self.spawn(async move {
+            let mut buf = receive_buffer(max_control_packet_size);
             loop {
                 tokio::select! {
                     _ = control_cancel_token.cancelled() => {
                         event!(Level::DEBUG, "listen_for_control: cancellation requested");
                         break;
                     },
-                    _ = control_port.start(&ctx_control, &invite_handler, &mut buf) => {}
+                    _ = control_port.start(&ctx_control, &control_invite_handler, &mut buf) => {}
                 }
             }
         });
@@ -98,56 +332,126 @@ impl RtpMidiSession {
         // MIDI port listener
         let ctx_midi = self.clone();
         let midi_port_listener = Arc::clone(&self.midi_port);
-        let listeners_midi = Arc::clone(&self.listeners);
         let midi_cancel_token = Arc::clone(&self.cancel_token);
+        let max_midi_packet_size = self.config.max_midi_packet_size;
 
-        let handle = tokio::spawn(async move {
-            let mut buf = [0u8; MAX_MIDI_PACKET_SIZE];
+        let handle = self.spawn(async move {
+            // Drawn from the pool because it gets handed to the dispatcher, and left unzeroed as it's received into
+            #[cfg(not(all(feature = "recvmmsg", target_os = "linux")))]
+            let mut buf = ctx_midi.events.receive_buffer(max_midi_packet_size + 1);
+            #[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+            let (mut bufs, mut received) = {
+                let batch = ctx_midi.config.receive_batch.clamp(1, MAX_RECEIVE_BATCH);
+                let bufs: Vec<_> = (0..batch).map(|_| ctx_midi.events.receive_buffer(max_midi_packet_size + 1)).collect();
+                (bufs, Vec::with_capacity(batch))
+            };
             loop {
+                #[cfg(not(all(feature = "recvmmsg", target_os = "linux")))]
+                let receive = midi_port_listener.start(&ctx_midi, &invite_handler, &mut buf);
+                #[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+                let receive = midi_port_listener.start_batch(&ctx_midi, &invite_handler, &mut bufs, &mut received);
                 tokio::select! {
                     _ = midi_cancel_token.cancelled() => {
                         event!(Level::DEBUG, "listen_for_midi: cancellation requested");
                         break;
                     },
-                    _ = midi_port_listener.start(&ctx_midi, listeners_midi.clone(), &mut buf) => {}
+                    _ = receive => {}
                 }
             }
         });
         handles.push(handle);
 
-        // Host clock sync
-        let ctx_clock = self.clone();
-        let syncer_clock = Arc::clone(&self.host_syncer);
-        let syncer_cancel_token = Arc::clone(&self.cancel_token);
-        let handle = tokio::spawn(async move {
-            loop {
-                tokio::select! {
-                    _ = syncer_cancel_token.cancelled() => {
-                        event!(Level::DEBUG, "listen_for_clock_sync: cancellation requested");
-                        break;
-                    },
-                    _ = sleep(Duration::from_secs(10)) => syncer_clock.cleanup(&ctx_clock).await
+        // Host clock sync, unless a session manager does it for all of its sessions, and NAT keepalives, separate from
+        // clock sync as they need to be more frequent, all run from one task. Each timer's work is awaited there
+        // rather than spawned, so runs never overlap, and a keepalive due while `sync_clocks` is busy waits for it
+        let mut scheduler = Scheduler::default();
+        if !managed_clock_sync {
+            scheduler.every(Timer::ClockSync, CLOCK_SYNC_INTERVAL);
+        }
+        if let Some(interval) = self.config.nat_keepalive {
+            scheduler.every(Timer::NatKeepalive, interval);
+        }
+        if !scheduler.is_empty() {
+            let ctx_timers = self.clone();
+            let timers_cancel_token = Arc::clone(&self.cancel_token);
+            let handle = self.spawn(async move {
+                loop {
+                    tokio::select! {
+                        _ = timers_cancel_token.cancelled() => {
+                            event!(Level::DEBUG, "timers: cancellation requested");
+                            break;
+                        },
+                        timer = scheduler.next() => match timer {
+                            Timer::ClockSync => ctx_timers.sync_clocks().await,
+                            Timer::NatKeepalive => ctx_timers.send_keepalives().await,
+                        }
+                    }
                 }
-            }
-        });
-        handles.push(handle);
+            });
+            handles.push(handle);
+        }
 
         // Store all handles
         let task_handles = self.task_handles.clone();
-        tokio::spawn(async move {
+        self.spawn(async move {
             let mut guard = task_handles.lock().await;
             guard.extend(handles);
         });
     }
 
-    #[instrument(skip_all, fields(name = %self.name()))]
+    /// Runs `future` on the session's runtime, see [`SessionConfig::runtime`].
+    pub(super) fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.config.runtime {
+            Some(runtime) => runtime.spawn(future),
+            None => tokio::spawn(future),
+        }
+    }
+
+    /// Waits until the session has been stopped.
+    pub(super) async fn stopped(&self) {
+        self.cancel_token.cancelled().await;
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name())))]
     pub fn stop_immediately(&self) {
         event!(Level::INFO, name = self.name(), "Stopping RTP-MIDI session");
         self.cancel_token.cancel();
         #[cfg(feature = "mdns")]
-        let _ = self.mdns.shutdown();
+        if let Some(mdns) = &self.mdns {
+            mdns.stop();
+        }
+    }
+    /// Says goodbye to every participant without waiting, then stops, for where nothing can be awaited such as in a
+    /// [`SessionGuard`](super::session_guard::SessionGuard) being dropped. Unlike
+    /// [`stop_gracefully`](Self::stop_gracefully), notes left sounding aren't let go of, and a goodbye that can't go
+    /// out straight away is skipped.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name())))]
+    pub(super) fn terminate_now(&self) {
+        if self.cancel_token.is_cancelled() {
+            return;
+        }
+        match self.participants.try_read() {
+            Ok(participants) => {
+                for participant in participants.values() {
+                    let result = self
+                        .control_port
+                        .try_send_termination_packet(participant)
+                        .and(self.midi_port.try_send_termination_packet(participant));
+                    if let Err(e) = result {
+                        event!(Level::WARN, participant = participant.name(), "Failed to say goodbye to participant: {e}");
+                    }
+                }
+            }
+            Err(_) => event!(Level::WARN, "Participants are being changed, stopping without saying goodbye"),
+        }
+        self.stop_immediately();
     }
-    #[instrument(skip_all, fields(name = %self.name()))]
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name())))]
     pub async fn stop_gracefully(&self) {
         self.remove_all_participants().await;
         self.stop_immediately();
@@ -166,51 +470,871 @@ impl RtpMidiSession {
         event!(Level::INFO, "Graceful shutdown complete");
     }
 
-    #[instrument(skip_all, fields(name = %self.name()))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name())))]
     pub async fn remove_all_participants(&self) {
         let participants = self.participants().await;
         for participant in participants {
-            self.remove_participant(&participant).await;
+            if let Err(e) = self.remove_participant(&participant).await {
+                event!(Level::WARN, participant = participant.name(), "Failed to say goodbye to participant: {e}");
+            }
         }
     }
 
-    pub async fn invite_participant(&self, addr: SocketAddr) {
-        self.control_port.invite_participant(self, addr).await;
+    /// Invites the session listening on the control port at `addr`. Fails if the address can't be a peer, an invitation
+    /// to it is still awaiting a reply, or the invitation can't be sent.
+    pub async fn invite_participant(&self, addr: SocketAddr) -> Result<(), RtpMidiError> {
+        self.control_port.invite_participant(self, addr).await
+    }
+
+    /// Forgets the invitations to `addr` that haven't been answered, so it can be invited again. An answer that still
+    /// arrives is dropped.
+    pub(super) async fn withdraw_invitations(&self, addr: SocketAddr) {
+        let midi_addr = SocketAddr::new(addr.ip(), addr.port() + 1);
+        let is_to_addr = |invitation: &PendingInvitation| invitation.addr == addr || invitation.addr == midi_addr;
+        self.pending_invitations.lock().await.retain(|_, invitation| !is_to_addr(invitation));
+        self.sent_invitations.lock().await.retain(|_, invitation| !is_to_addr(invitation));
+    }
+
+    /// The participants, as peers that can be invited again with [`restore_peers`](Self::restore_peers).
+    pub async fn export_peers(&self) -> Vec<KnownPeer> {
+        self.participants.read().await.values().map(KnownPeer::from).collect()
+    }
+
+    /// Invites each of the peers that isn't a participant already. Every peer is tried, and the first invitation
+    /// that couldn't be sent is returned as the error. Peers that don't answer simply don't join.
+    pub async fn restore_peers(&self, peers: &[KnownPeer]) -> Result<(), RtpMidiError> {
+        let joined: HashSet<SocketAddr> = self.participants.read().await.values().map(Participant::addr).collect();
+        let mut result = Ok(());
+        for peer in peers.iter().filter(|peer| !joined.contains(&peer.addr)) {
+            if let Err(e) = self.invite_participant(peer.addr).await {
+                event!(Level::WARN, addr = %peer.addr, name = peer.name, "Failed to invite known peer: {e}");
+                result = result.and(Err(e));
+            }
+        }
+        result
     }
 
     pub async fn participants(&self) -> Vec<Participant> {
-        let participants = self.participants.lock().await;
+        let participants = self.participants.read().await;
         participants.values().cloned().collect()
     }
 
-    #[instrument(skip_all, fields(participant = %participant.name().to_str().unwrap_or("Unknown")))]
-    pub async fn remove_participant(&self, participant: &Participant) {
+    /// The participant going by `name`. If more than one does, the one with the lowest SSRC, so the same one is
+    /// returned each time.
+    pub async fn participant_by_name(&self, name: &str) -> Option<Participant> {
+        let participants = self.participants.read().await;
+        participants
+            .values()
+            .filter(|participant| participant.name() == name)
+            .min_by_key(|participant| participant.ssrc().get())
+            .cloned()
+    }
+
+    pub async fn participant_by_ssrc(&self, ssrc: u32) -> Option<Participant> {
+        self.participants.read().await.get(&U32::new(ssrc)).cloned()
+    }
+
+    /// Waits for the participant to finish joining, which is once a clock sync with it has gone all the way round, and
+    /// returns it. Returns straight away if it already has. Fails with [`RtpMidiError::Timeout`] if it doesn't in
+    /// time.
+    pub async fn wait_for_participant(&self, participant: impl Into<ParticipantMatch>, timeout: Duration) -> Result<Participant, RtpMidiError> {
+        let wanted = participant.into();
+        // Subscribed before looking, so a sync completed in between isn't missed
+        let mut synced = self.participants_synced.subscribe();
+        let joined = async {
+            loop {
+                let participants = self.participants.read().await;
+                if let Some(participant) = participants
+                    .values()
+                    .find(|participant| participant.is_clock_synced() && wanted.matches(participant))
+                {
+                    return Ok(participant.clone());
+                }
+                drop(participants);
+                tokio::select! {
+                    _ = synced.changed() => {}
+                    _ = self.stopped() => return Err(RtpMidiError::InvalidState("session stopped")),
+                }
+            }
+        };
+        tokio::time::timeout(timeout, joined)
+            .await
+            .unwrap_or(Err(RtpMidiError::Timeout("the participant to join")))
+    }
+
+    /// Ends the notes we left sounding on the participant, then sends it a termination on both ports and forgets
+    /// about it. The participant is removed even if the terminations can't be sent, in which case the first failure
+    /// is returned.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(participant = %participant.name())))]
+    pub async fn remove_participant(&self, participant: &Participant) -> Result<(), RtpMidiError> {
         event!(Level::INFO, "Removing participant");
-        self.control_port.send_termination_packet(participant).await;
-        self.midi_port.send_termination_packet(participant).await;
-        self.participants.lock().await.remove(&participant.ssrc());
+        self.release_active_notes(participant.ssrc()).await;
+        let control_result = self.control_port.send_termination_packet(participant).await;
+        let midi_result = self.midi_port.send_termination_packet(participant).await;
+        if self.participants.write().await.remove(&participant.ssrc()).is_some() {
+            self.events.push(QueuedEvent::SsrcRetired(participant.ssrc())).await;
+        }
+        control_result.and(midi_result)
     }
 
-    pub async fn add_listener<E, F>(&self, _event_type: E, callback: F)
+    /// The traffic counters of the session and each of its participants, for monitoring.
+    pub async fn stats(&self) -> SessionStats {
+        let participants = self.participants.read().await.values().map(ParticipantStats::from).collect();
+        SessionStats {
+            events_dropped: self.events.dropped(),
+            ..self.counters.snapshot(participants)
+        }
+    }
+
+    /// Roughly how much memory the session holds, for long-running installations to watch for it growing without
+    /// bound.
+    pub async fn memory_usage(&self) -> MemoryUsage {
+        let (participants, retransmission_history) = {
+            let participants = self.participants.read().await;
+            (
+                map_heap_size(&participants) + participants.values().map(Participant::heap_size).sum::<usize>(),
+                participants
+                    .values()
+                    .map(|participant| participant.transmission().retransmission.heap_size())
+                    .sum(),
+            )
+        };
+        let invitations = |invitations: &HashMap<U32, PendingInvitation>| {
+            map_heap_size(invitations) + invitations.values().map(|invitation| invitation.name.capacity()).sum::<usize>()
+        };
+        let pending_invitations = invitations(&*self.pending_invitations.lock().await) + invitations(&*self.sent_invitations.lock().await) + {
+            let authentications = self.pending_authentications.lock().await;
+            map_heap_size(&authentications) + authentications.values().map(|pending| pending.participant.heap_size()).sum::<usize>()
+        };
+        let (event_queue, reassembly, followed) = self.events.memory_usage();
+        let tempos = map_heap_size(&self.tempos.lock().unwrap_or_else(PoisonError::into_inner));
+        let peers = &self.config.peers;
+        MemoryUsage {
+            participants,
+            pending_invitations,
+            retransmission_history,
+            event_queue,
+            reassembly,
+            followed: followed + tempos,
+            known_peers: peers.capacity() * size_of::<KnownPeer>() + peers.iter().map(|peer| peer.name.capacity()).sum::<usize>(),
+        }
+    }
+
+    /// The tempo of the participant's MIDI clock in beats per minute, estimated from the TimingClock messages it
+    /// sends. [`TempoChangedEvent`](crate::sessions::events::event_handling::TempoChangedEvent) listeners hear
+    /// about changes as they happen.
+    pub fn tempo(&self, participant: &Participant) -> Option<f64> {
+        let tempos = self.tempos.lock().unwrap_or_else(PoisonError::into_inner);
+        tempos.get(&participant.ssrc()).and_then(TempoEstimator::bpm)
+    }
+
+    /// Asks every participant to send us no more than `limit` bits per second. Peers are free to ignore this.
+    /// Fails with the first error if the request can't be sent to a participant, after trying all of them.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(limit = limit)))]
+    pub async fn send_bitrate_limit(&self, limit: u32) -> Result<(), RtpMidiError> {
+        let participants = self.participants().await;
+        let mut result = Ok(());
+        for participant in &participants {
+            let sent = self.control_port.send_bitrate_limit(participant, limit).await;
+            result = result.and(sent);
+        }
+        result
+    }
+
+    pub async fn add_listener<E, F>(&self, event_type: E, callback: F) -> ListenerId
     where
         E: EventType,
-        F: for<'a> Fn(E::Data<'a>) + Send + 'static,
+        F: for<'a> Fn(E::Data<'a>) + Send + Sync + 'static,
     {
-        let mut listeners = self.listeners.lock().await;
-        E::add_listener_to_storage(&mut listeners, callback);
+        self.on(event_type, callback)
     }
 
-    pub async fn send_midi_batch<'a>(&self, commands: &[MidiEvent<'a>]) -> std::io::Result<()> {
-        self.midi_port.send_midi_batch(self, commands).await
+    /// Adds a listener, as [`add_listener`](Self::add_listener) does, from code that can't await. Events that arrived
+    /// before it was added aren't handed to it; for those, add it to [`SessionConfig::listeners`] before starting.
+    pub fn on<E, F>(&self, _event_type: E, callback: F) -> ListenerId
+    where
+        E: EventType,
+        F: for<'a> Fn(E::Data<'a>) + Send + Sync + 'static,
+    {
+        self.listeners.update(|listeners| E::add_listener_to_storage(listeners, callback))
     }
 
-    pub async fn send_midi<'a>(&self, command: &RtpMidiMessage<'a>) -> std::io::Result<()> {
+    /// Takes away a listener added with [`add_listener`](Self::add_listener), [`on`](Self::on) or through
+    /// [`SessionConfig::listeners`], returning whether it was there. Events already on their way to it may still be
+    /// handed to it.
+    pub fn remove_listener(&self, id: &ListenerId) -> bool {
+        self.listeners.update(|listeners| listeners.remove_listener(id))
+    }
+
+    /// Adds a listener that doesn't have to be `Send`, such as one holding `Rc`-based application state or GUI
+    /// objects. It runs on a task of the [`tokio::task::LocalSet`] this is called from, which it panics without, and
+    /// gets a copy of each event from there; the task ends when the session is dropped. Events queue up without bound
+    /// while the local set doesn't run, and a panic ends the task instead of being reported as a [`ListenerPanicked`].
+    ///
+    /// [`ListenerPanicked`]: crate::sessions::events::event_handling::ListenerPanicked
+    pub fn on_local<E, F>(&self, event_type: E, callback: F) -> JoinHandle<()>
+    where
+        E: LocalEventType,
+        F: Fn(E::Owned) + 'static,
+    {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        self.on(event_type, move |data| {
+            // Only fails once the local task is gone, and then there is no one to tell
+            let _ = sender.send(E::to_owned(data));
+        });
+        tokio::task::spawn_local(async move {
+            while let Some(data) = receiver.recv().await {
+                callback(data);
+            }
+        })
+    }
+
+    pub async fn send_midi_batch<'a>(&self, commands: &[MidiEvent<'a>]) -> Result<(), RtpMidiError> {
+        self.midi_port.send_midi_batch(self, commands, Recipients::All).await
+    }
+
+    /// Silences every participant: on all 16 channels, releases the sustain pedal and sends All Sound Off and
+    /// All Notes Off, for when notes are left hanging.
+    pub async fn panic(&self) -> Result<(), RtpMidiError> {
+        self.midi_port.send_midi_batch(self, &panic_commands(), Recipients::All).await
+    }
+
+    /// Like [`panic`](Self::panic), but only for `participant`.
+    pub async fn panic_to(&self, participant: &Participant) -> Result<(), RtpMidiError> {
+        if !self.participants.read().await.contains_key(&participant.ssrc()) {
+            return Err(RtpMidiError::InvalidArgument(format!("{participant} is not in this session")));
+        }
+        self.midi_port
+            .send_midi_batch(self, &panic_commands(), Recipients::Only(participant.ssrc()))
+            .await
+    }
+
+    pub async fn send_midi<'a>(&self, command: &RtpMidiMessage<'a>) -> Result<(), RtpMidiError> {
         self.midi_port.send_midi(self, command).await
     }
 
+    /// Sends a Note On to the session. The helpers from here to [`pitch_bend`](Self::pitch_bend) take the same
+    /// midi-types values as [`MidiBatchBuilder`](crate::packets::builder::MidiBatchBuilder) does, channels counted from
+    /// [`Channel::C1`] and all.
+    pub async fn note_on(&self, channel: Channel, note: Note, velocity: Value7) -> Result<(), RtpMidiError> {
+        self.send_midi(&MidiMessage::NoteOn(channel, note, velocity).into()).await
+    }
+
+    pub async fn note_off(&self, channel: Channel, note: Note, velocity: Value7) -> Result<(), RtpMidiError> {
+        self.send_midi(&MidiMessage::NoteOff(channel, note, velocity).into()).await
+    }
+
+    pub async fn control_change(&self, channel: Channel, control: Control, value: Value7) -> Result<(), RtpMidiError> {
+        self.send_midi(&MidiMessage::ControlChange(channel, control, value).into()).await
+    }
+
+    pub async fn program_change(&self, channel: Channel, program: Program) -> Result<(), RtpMidiError> {
+        self.send_midi(&MidiMessage::ProgramChange(channel, program).into()).await
+    }
+
+    /// Sends a Pitch Bend, centred on 8192.
+    pub async fn pitch_bend(&self, channel: Channel, value: Value14) -> Result<(), RtpMidiError> {
+        self.send_midi(&MidiMessage::PitchBendChange(channel, value).into()).await
+    }
+
+    /// Mutes or unmutes the participant: while muted, MIDI sent to the whole session or a group skips it, though it
+    /// stays connected. The notes we left sounding on it are ended when it is muted.
+    pub async fn set_muted(&self, participant: &Participant, muted: bool) -> Result<(), RtpMidiError> {
+        match self.participants.write().await.get_mut(&participant.ssrc()) {
+            Some(participant) => participant.set_muted(muted),
+            None => return Err(RtpMidiError::InvalidArgument(format!("{participant} is not in this session"))),
+        }
+        self.release_silenced_notes().await;
+        Ok(())
+    }
+
+    /// Adds the participant to the solo set, or takes it out. While anyone in the session is soloed, MIDI sent to the
+    /// whole session or a group only goes to the soloed participants that aren't muted. The notes we left sounding on
+    /// participants that can no longer be heard are ended.
+    pub async fn set_soloed(&self, participant: &Participant, soloed: bool) -> Result<(), RtpMidiError> {
+        if !self.participants.read().await.contains_key(&participant.ssrc()) {
+            return Err(RtpMidiError::InvalidArgument(format!("{participant} is not in this session")));
+        }
+        {
+            let mut solo = self.solo.write().unwrap_or_else(PoisonError::into_inner);
+            if soloed {
+                solo.insert(participant.ssrc());
+            } else {
+                solo.remove(&participant.ssrc());
+            }
+        }
+        self.release_silenced_notes().await;
+        Ok(())
+    }
+
+    pub fn is_soloed(&self, participant: &Participant) -> bool {
+        self.solo.read().unwrap_or_else(PoisonError::into_inner).contains(&participant.ssrc())
+    }
+
+    /// Takes everyone out of the solo set, so MIDI goes to every participant that isn't muted again.
+    pub fn clear_solo(&self) {
+        self.solo.write().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// Adds an [`Interceptor`](crate::sessions::interceptor::Interceptor) to the end of the chain that every message
+    /// sent to or received from a participant goes through.
+    pub fn add_interceptor<F>(&self, interceptor: F)
+    where
+        F: for<'a, 'b> Fn(&'b mut RtpMidiMessage<'a>, Direction, &Participant) -> Action + Send + Sync + 'static,
+    {
+        self.interceptors.push(Arc::new(interceptor));
+    }
+
+    /// Sets the [`WireTap`](crate::sessions::wire_tap::WireTap) that sees every datagram the session sends and
+    /// receives, replacing any set before.
+    pub fn set_wire_tap<F>(&self, tap: F)
+    where
+        F: Fn(Direction, &SocketAddr, &[u8]) + Send + Sync + 'static,
+    {
+        self.wire_tap.set(Some(Arc::new(tap)));
+    }
+
+    pub fn clear_wire_tap(&self) {
+        self.wire_tap.set(None);
+    }
+
+    /// Asks the STUN server at `server` where it sees the control and MIDI ports, which is where peers on the other
+    /// side of a NAT can reach the session, and keeps the answer for [`external_addresses`](Self::external_addresses).
+    /// Fails with [`RtpMidiError::Timeout`] if the server doesn't answer for either port.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name(), server = %server)))]
+    pub async fn discover_external_addresses(&self, server: SocketAddr) -> Result<ExternalAddresses, RtpMidiError> {
+        let (control, midi) = tokio::try_join!(
+            stun::query(self.control_port.socket(), &self.stun, server, self.random()),
+            stun::query(self.midi_port.socket(), &self.stun, server, self.random()),
+        )?;
+        let addresses = ExternalAddresses { control, midi };
+        if !addresses.is_consecutive() {
+            event!(Level::WARN, control = %control, midi = %midi, "The NAT doesn't keep the MIDI port after the control port");
+        }
+        *self.external_addresses.write().unwrap_or_else(PoisonError::into_inner) = Some(addresses);
+        Ok(addresses)
+    }
+
+    /// Where the last [`discover_external_addresses`](Self::discover_external_addresses) found the session can be
+    /// reached from outside its NAT.
+    pub fn external_addresses(&self) -> Option<ExternalAddresses> {
+        *self.external_addresses.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn clear_interceptors(&self) {
+        self.interceptors.clear();
+    }
+
+    /// Sets the channel maps for participants that haven't been given their own, replacing
+    /// [`SessionConfig::channel_routing`].
+    pub fn set_channel_routing(&self, routing: ChannelRouting) {
+        self.channel_routes.write().unwrap_or_else(PoisonError::into_inner).default = routing;
+    }
+
+    /// Gives the participant channel maps of its own, or with `None` has it use the session's again. They are kept by
+    /// SSRC, so a participant that leaves and rejoins keeps them.
+    pub async fn set_participant_channel_routing(&self, participant: &Participant, routing: Option<ChannelRouting>) -> Result<(), RtpMidiError> {
+        if !self.participants.read().await.contains_key(&participant.ssrc()) {
+            return Err(RtpMidiError::InvalidArgument(format!("{participant} is not in this session")));
+        }
+        let mut routes = self.channel_routes.write().unwrap_or_else(PoisonError::into_inner);
+        match routing {
+            Some(routing) => routes.participants.insert(participant.ssrc(), routing),
+            None => routes.participants.remove(&participant.ssrc()),
+        };
+        Ok(())
+    }
+
+    /// The channel maps applied to the participant's MIDI, its own or the session's.
+    pub fn channel_routing(&self, participant: &Participant) -> ChannelRouting {
+        self.channel_routes
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .for_participant(participant.ssrc())
+    }
+
+    /// Puts the participant in `group`, creating the group if need be. Participants can be in any number of groups,
+    /// and stay in them when they leave and rejoin with the same SSRC.
+    pub async fn add_to_group(&self, group: &str, participant: &Participant) -> Result<(), RtpMidiError> {
+        if !self.participants.read().await.contains_key(&participant.ssrc()) {
+            return Err(RtpMidiError::InvalidArgument(format!("{participant} is not in this session")));
+        }
+        self.groups.write().unwrap_or_else(PoisonError::into_inner).add(group, participant.ssrc());
+        Ok(())
+    }
+
+    /// Takes the participant out of `group`, which goes away once it has no members left. Returns whether the
+    /// participant was in it.
+    pub fn remove_from_group(&self, group: &str, participant: &Participant) -> bool {
+        self.groups.write().unwrap_or_else(PoisonError::into_inner).remove(group, participant.ssrc())
+    }
+
+    /// The groups with members, in name order.
+    pub fn groups(&self) -> Vec<String> {
+        self.groups.read().unwrap_or_else(PoisonError::into_inner).names()
+    }
+
+    /// The groups the participant is in, in name order.
+    pub fn groups_of(&self, participant: &Participant) -> Vec<String> {
+        self.groups.read().unwrap_or_else(PoisonError::into_inner).groups_of(participant.ssrc())
+    }
+
+    /// The members of `group` that are currently in the session.
+    pub async fn group_members(&self, group: &str) -> Vec<Participant> {
+        let members = self.groups.read().unwrap_or_else(PoisonError::into_inner).members(group);
+        let participants = self.participants.read().await;
+        members.iter().filter_map(|ssrc| participants.get(ssrc).cloned()).collect()
+    }
+
+    /// Like [`send_midi`](Self::send_midi), but only to the members of `group`. If none of them are in the session,
+    /// nothing is sent.
+    pub async fn send_midi_to_group<'a>(&self, group: &str, command: &RtpMidiMessage<'a>) -> Result<(), RtpMidiError> {
+        self.send_midi_batch_to_group(group, &[MidiEvent::new(None, command.to_owned())]).await
+    }
+
+    /// Like [`send_midi_batch`](Self::send_midi_batch), but only to the members of `group`.
+    pub async fn send_midi_batch_to_group<'a>(&self, group: &str, commands: &[MidiEvent<'a>]) -> Result<(), RtpMidiError> {
+        let members = self.groups.read().unwrap_or_else(PoisonError::into_inner).members(group);
+        if members.is_empty() {
+            return Ok(());
+        }
+        self.midi_port.send_midi_batch(self, commands, Recipients::Among(&members)).await
+    }
+
     pub fn name(&self) -> &str {
         self.name.to_str().unwrap_or("Unnamed Session")
     }
+
+    /// Where the control port is listening. The IP address is the unspecified one, as the session listens on all
+    /// interfaces.
+    pub fn local_control_addr(&self) -> SocketAddr {
+        self.control_port.socket().local_addr()
+    }
+
+    /// Where the MIDI port is listening, the port above the control port.
+    pub fn local_midi_addr(&self) -> SocketAddr {
+        self.midi_port.socket().local_addr()
+    }
+
+    /// The SSRC we currently send under. It starts out as the one the session was started with, but is replaced if
+    /// a peer turns out to be using the same one.
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc.load(Ordering::Relaxed)
+    }
+
+    /// Sends each participant the empty datagram of [`SessionConfig::nat_keepalive`] on both ports, so the mappings
+    /// NATs between us keep for them don't expire.
+    async fn send_keepalives(&self) {
+        let participants = self.participants.read().await;
+        event!(Level::TRACE, "Sending keepalives to {} participants", participants.len());
+        self.control_port.send_keepalive(participants.values()).await;
+        self.midi_port.send_keepalive(participants.values()).await;
+    }
+
+    /// Drops participants we invited that have stopped answering, and sends clock syncs to the rest.
+    pub(super) async fn sync_clocks(&self) {
+        self.host_syncer.cleanup(self).await;
+    }
+
+    /// Ends the notes still sounding on participants that MIDI sent to the session no longer reaches, as the
+    /// NoteOffs would skip them.
+    async fn release_silenced_notes(&self) {
+        let mut solo = self.solo.read().unwrap_or_else(PoisonError::into_inner).clone();
+        let participants = self.participants.read().await;
+        solo.retain(|ssrc| participants.contains_key(ssrc));
+        let silenced: Vec<U32> = participants
+            .values()
+            .filter(|participant| !is_audible(participant, &solo))
+            .map(Participant::ssrc)
+            .collect();
+        drop(participants);
+        for ssrc in silenced {
+            self.release_active_notes(ssrc).await;
+        }
+    }
+
+    /// Sends NoteOffs for the notes still sounding on the participant, so its synths aren't left hanging when the
+    /// session ends.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(ssrc = ssrc.get())))]
+    pub(super) async fn release_active_notes(&self, ssrc: U32) {
+        let note_offs = match self.participants.read().await.get(&ssrc).map(Participant::transmission) {
+            Some(transmission) if !transmission.active_notes.is_empty() => transmission.active_notes.note_offs(),
+            _ => return,
+        };
+        event!(Level::DEBUG, notes = note_offs.len(), "Releasing active notes");
+        if let Err(e) = self.midi_port.send_midi_batch(self, &note_offs, Recipients::Only(ssrc)).await {
+            event!(Level::WARN, "Failed to release active notes: {e}");
+        }
+    }
+
+    /// Puts an invitation from `src` through [`SessionConfig::flood_protection`], notifying listeners of what it turns
+    /// away. Only invitations that start a handshake are screened, not the MIDI port invitation finishing one.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name(), src = %src)))]
+    pub(super) async fn screen_invitation(&self, src: SocketAddr) -> Screening {
+        let Some(flood_guard) = &self.flood_guard else {
+            return Screening::Admit;
+        };
+        let reason = match flood_guard.check(src.ip(), Instant::now()) {
+            Verdict::Banned => return Screening::Ignore,
+            Verdict::Ban => FloodReason::Banned(flood_guard.protection().ban),
+            Verdict::Allow => {
+                let max_handshakes = flood_guard.protection().max_handshakes;
+                if self.pending_invitations.lock().await.len() < max_handshakes {
+                    return Screening::Admit;
+                }
+                FloodReason::TooManyHandshakes(max_handshakes)
+            }
+        };
+
+        event!(Level::WARN, reason = ?reason, "Turning away session invitation, flood protection");
+        self.events.push(QueuedEvent::InvitationFlood(InvitationFlood { addr: src, reason })).await;
+        match reason {
+            FloodReason::Banned(_) => Screening::Ignore,
+            FloodReason::TooManyHandshakes(_) => Screening::Reject,
+        }
+    }
+
+    /// A random value from [`SessionConfig::random`], or the `rand` crate without one.
+    pub(super) fn random<T: FromBytes + IntoBytes>(&self) -> T {
+        random::random(self.config.random.as_deref())
+    }
+
+    /// Asks the participant at `addr` to send the `count` MIDI packets from `first` on again, see
+    /// [`SessionConfig::retransmission`].
+    pub(super) async fn request_retransmission(&self, addr: SocketAddr, first: u16, count: u16) {
+        self.control_port.send_retransmission_request(first, count, addr).await;
+    }
+
+    /// Makes a peer that has finished its handshake a participant, or, with
+    /// [`SessionConfig::authenticator`], challenges it to prove itself first. Returns whether it joined straight away.
+    pub(super) async fn establish(&self, participants: &mut HashMap<U32, Participant>, participant: Participant) -> bool {
+        if self.config.authenticator.is_none() {
+            participants.insert(participant.ssrc(), participant);
+            return true;
+        }
+
+        let ssrc = participant.ssrc();
+        self.await_authentication(participant, None).await;
+        self.send_authentication_challenge(ssrc).await;
+        false
+    }
+
+    /// Holds the participant back until it has answered a challenge, which it has [`AUTHENTICATION_TIMEOUT`] to do.
+    async fn await_authentication(&self, participant: Participant, moved: Option<AddressChanged>) {
+        let pending = PendingAuthentication {
+            participant,
+            nonce: self.random::<[u8; 16]>(),
+            since: Instant::now(),
+            moved,
+        };
+        self.pending_authentications.lock().await.insert(pending.participant.ssrc(), pending);
+    }
+
+    /// Challenges the peer going by `ssrc`, held back with [`await_authentication`](Self::await_authentication), to
+    /// prove itself.
+    pub(super) async fn send_authentication_challenge(&self, ssrc: U32) {
+        let Some((nonce, addr)) = self
+            .pending_authentications
+            .lock()
+            .await
+            .get(&ssrc)
+            .map(|pending| (pending.nonce, pending.participant.addr()))
+        else {
+            return;
+        };
+        self.control_port.send_authentication_challenge(&nonce, addr).await;
+    }
+
+    /// Checks the proof a peer answered our challenge with, making it a participant if it checks out and ending its
+    /// session if it doesn't.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name(), ssrc = ssrc.get(), src = %src)))]
+    pub(super) async fn complete_authentication(&self, ssrc: U32, proof: &[u8], src: SocketAddr) {
+        let Some(authenticator) = &self.config.authenticator else {
+            event!(Level::WARN, "Received an authentication response, but the session doesn't authenticate peers");
+            return;
+        };
+        let pending = {
+            let mut pending_authentications = self.pending_authentications.lock().await;
+            match pending_authentications.get(&ssrc) {
+                Some(pending) if pending.participant.addr() == src => pending_authentications.remove(&ssrc),
+                _ => None,
+            }
+        };
+        let Some(pending) = pending else {
+            event!(Level::WARN, "Received an authentication response nobody was challenged for");
+            return;
+        };
+
+        let challenge = authenticator::challenge(&pending.nonce, self.ssrc(), ssrc.get(), !pending.participant.is_invited_by_us());
+        if !authenticator.verify(&challenge, proof) {
+            event!(Level::WARN, "Peer failed to authenticate");
+            self.fail_authentication(pending, false).await;
+            return;
+        }
+        event!(Level::INFO, "Peer authenticated");
+        let participant = pending.participant;
+        self.participants.write().await.insert(participant.ssrc(), participant.clone());
+        if let Some(change) = pending.moved {
+            event!(Level::INFO, old_addr = %change.old_addr, "Participant moved to another address");
+            self.events.push(QueuedEvent::AddressChanged(change)).await;
+        } else if participant.is_invited_by_us() {
+            self.midi_port.send_clock_sync(std::iter::once(&participant), [U64::new(0); 3], 1).await;
+            self.events.push(QueuedEvent::ParticipantJoined(participant)).await;
+        }
+    }
+
+    /// Forgets the invitations that haven't been answered within [`INVITATION_TIMEOUT`], so peers that never finish
+    /// their handshake don't keep a place in [`FloodProtection::max_handshakes`](super::session_config::FloodProtection::max_handshakes)
+    /// or stop their address being invited again.
+    pub(super) async fn expire_invitations(&self) {
+        let unanswered = |invitation: &PendingInvitation| {
+            let expired = invitation.since.elapsed() >= INVITATION_TIMEOUT;
+            if expired {
+                event!(Level::INFO, addr = %invitation.addr, "Giving up on an invitation that wasn't answered in time");
+            }
+            expired
+        };
+        let mut pending_invitations = self.pending_invitations.lock().await;
+        let mut sent_invitations = self.sent_invitations.lock().await;
+        pending_invitations.retain(|_, invitation| !unanswered(invitation));
+        sent_invitations.retain(|_, invitation| !unanswered(invitation));
+    }
+
+    /// Whether we sent the invitation, for the peer at `src` going by `ssrc` if it is joining the session or has
+    /// joined it. `None` for any other peer.
+    pub(super) async fn joining_by_invitation(&self, ssrc: U32, src: SocketAddr) -> Option<bool> {
+        if let Some(pending) = self.pending_authentications.lock().await.get(&ssrc)
+            && pending.participant.addr() == src
+        {
+            return Some(pending.participant.is_invited_by_us());
+        }
+        if let Some(participant) = self.participants.read().await.get(&ssrc)
+            && participant.addr() == src
+        {
+            return Some(participant.is_invited_by_us());
+        }
+        // Our MIDI port invitation, which the peer can have accepted before we see its answer
+        let midi_addr = SocketAddr::new(src.ip(), src.port().checked_add(1)?);
+        self.pending_invitations
+            .lock()
+            .await
+            .get(&ssrc)
+            .filter(|pending| pending.addr == midi_addr)
+            .map(|_| true)
+    }
+
+    /// Whether the peer at the control port `addr` going by `ssrc` has been challenged and hasn't answered yet.
+    pub(super) async fn is_authenticating(&self, ssrc: U32, addr: SocketAddr) -> bool {
+        self.pending_authentications
+            .lock()
+            .await
+            .get(&ssrc)
+            .is_some_and(|pending| pending.participant.addr() == addr)
+    }
+
+    /// Ends the sessions of the peers that haven't answered our challenge in time.
+    pub(super) async fn expire_authentications(&self) {
+        let expired: Vec<PendingAuthentication> = {
+            let mut pending_authentications = self.pending_authentications.lock().await;
+            let expired: Vec<U32> = pending_authentications
+                .iter()
+                .filter(|(_, pending)| pending.since.elapsed() >= AUTHENTICATION_TIMEOUT)
+                .map(|(ssrc, _)| *ssrc)
+                .collect();
+            expired.iter().filter_map(|ssrc| pending_authentications.remove(ssrc)).collect()
+        };
+        for pending in expired {
+            event!(
+                Level::WARN,
+                name = pending.participant.name(),
+                "Peer didn't answer our authentication challenge"
+            );
+            self.fail_authentication(pending, true).await;
+        }
+    }
+
+    /// Gives up on the peer at `src` going by `ssrc` if it ends its session while we wait for its proof, as it does
+    /// once we have failed its own challenge.
+    pub(super) async fn abandon_authentication(&self, ssrc: U32, src: SocketAddr) {
+        let pending = {
+            let mut pending_authentications = self.pending_authentications.lock().await;
+            match pending_authentications.get(&ssrc) {
+                Some(pending) if pending.participant.addr() == src => pending_authentications.remove(&ssrc),
+                _ => None,
+            }
+        };
+        if let Some(pending) = pending {
+            event!(Level::WARN, "Peer ended its session instead of authenticating");
+            self.report_authentication_failure(pending, false).await;
+        }
+    }
+
+    async fn fail_authentication(&self, pending: PendingAuthentication, timed_out: bool) {
+        if let Err(e) = self.control_port.send_termination_packet(&pending.participant).await {
+            event!(Level::WARN, "Failed to send termination packet: {e}");
+        }
+        self.report_authentication_failure(pending, timed_out).await;
+    }
+
+    async fn report_authentication_failure(&self, pending: PendingAuthentication, timed_out: bool) {
+        let participant = pending.participant;
+        let failure = AuthenticationFailed {
+            addr: participant.addr(),
+            ssrc: participant.ssrc().get(),
+            name: participant.name().to_owned(),
+            timed_out,
+        };
+        self.events.push(QueuedEvent::AuthenticationFailed(failure)).await;
+        // A participant that moved was one until then, and isn't any more
+        if pending.moved.is_some() {
+            self.events.push(QueuedEvent::SsrcRetired(participant.ssrc())).await;
+        }
+    }
+
+    /// Moves the participant with `ssrc` to the control port `ctrl_addr`, if `initiator_token` is the one it joined with
+    /// and it isn't there already, notifying listeners. Returns whether it was moved. With
+    /// [`SessionConfig::authenticator`], it stops being a participant until it has proven itself again from there, as
+    /// the token alone doesn't show the peer at the new address is the same one; the challenge is up to the caller
+    /// to send, with [`send_authentication_challenge`](Self::send_authentication_challenge), once the handshake from
+    /// there is done.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name(), ssrc = ssrc.get(), new_addr = %ctrl_addr)))]
+    pub(super) async fn move_participant(&self, ssrc: U32, initiator_token: U32, ctrl_addr: SocketAddr) -> bool {
+        let change = {
+            let mut participants = self.participants.write().await;
+            let Some(participant) = participants
+                .get_mut(&ssrc)
+                .filter(|participant| participant.addr() != ctrl_addr && participant.initiator_token() == Some(initiator_token))
+            else {
+                return false;
+            };
+            let old_addr = participant.addr();
+            participant.moved_to(ctrl_addr);
+            let change = AddressChanged {
+                ssrc: ssrc.get(),
+                name: participant.name().to_owned(),
+                old_addr,
+                new_addr: ctrl_addr,
+            };
+            if self.config.authenticator.is_some()
+                && let Some(participant) = participants.remove(&ssrc)
+            {
+                drop(participants);
+                event!(Level::INFO, old_addr = %change.old_addr, "Participant moved to another address, it has to authenticate again");
+                self.await_authentication(participant, Some(change)).await;
+                return true;
+            }
+            change
+        };
+        event!(Level::INFO, old_addr = %change.old_addr, "Participant moved to another address");
+        self.events.push(QueuedEvent::AddressChanged(change)).await;
+        true
+    }
+
+    /// Returns `false` (after notifying listeners) if a newcomer with `ssrc` would take the session past
+    /// [`SessionConfig::max_participants`]. Participants and the invitations still under way both count.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name(), ssrc = ssrc.get(), src = %src)))]
+    pub(super) async fn has_room_for(&self, ssrc: U32, name: &str, src: SocketAddr) -> bool {
+        let Some(max_participants) = self.config.max_participants else {
+            return true;
+        };
+        let joined: HashSet<U32> = self.participants.read().await.keys().copied().collect();
+        let joining = self.pending_invitations.lock().await.keys().filter(|pending| !joined.contains(pending)).count();
+        if joined.len() + joining < max_participants {
+            return true;
+        }
+
+        event!(Level::WARN, max_participants, "Rejecting session invitation, the session is full");
+        let rejection = ParticipantLimitReached {
+            addr: src,
+            ssrc: ssrc.get(),
+            name: name.to_owned(),
+            max_participants,
+        };
+        self.events.push(QueuedEvent::ParticipantLimitReached(rejection)).await;
+        false
+    }
+
+    /// Whether a datagram from `src` carrying our SSRC is a collision to act on: it has to come from a participant, or
+    /// a peer we have invited, as anyone else could send our SSRC to make us drop every session.
+    pub(super) async fn is_collision_credible(&self, src: SocketAddr) -> bool {
+        let from_participant = self
+            .participants
+            .read()
+            .await
+            .values()
+            .any(|participant| participant.addr() == src || participant.midi_port_addr() == src);
+        from_participant || self.sent_invitations.lock().await.values().any(|invitation| invitation.addr == src)
+    }
+
+    /// Recovers from a peer using our SSRC: we pick a new one, and every participant is told the old session ended and
+    /// is invited again under the new SSRC. Does nothing if `colliding_ssrc` has already been replaced.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(name = %self.name(), ssrc = colliding_ssrc.get())))]
+    pub(super) async fn resolve_ssrc_collision(&self, colliding_ssrc: U32) {
+        // Held until the new SSRC is in place, so a second report of the same collision finds it already resolved
+        let mut participants_guard = self.participants.write().await;
+        if self.ssrc() != colliding_ssrc.get() {
+            return;
+        }
+        let participants: Vec<Participant> = participants_guard.drain().map(|(_, participant)| participant).collect();
+
+        for participant in &participants {
+            // Sent while our SSRC is still the old one, so the peer knows which session ended
+            let control_result = self.control_port.send_termination_packet(participant).await;
+            let midi_result = self.midi_port.send_termination_packet(participant).await;
+            if let Err(e) = control_result.and(midi_result) {
+                event!(Level::WARN, participant = participant.name(), "Failed to end session before changing SSRC: {e}");
+            }
+        }
+
+        let new_ssrc = loop {
+            let candidate = self.random::<u32>();
+            if candidate != colliding_ssrc.get() && participants.iter().all(|participant| participant.ssrc() != candidate) {
+                break candidate;
+            }
+        };
+        self.ssrc.store(new_ssrc, Ordering::Relaxed);
+        drop(participants_guard);
+        event!(Level::WARN, new_ssrc, "A peer is using our SSRC, changed to a new one");
+
+        for participant in participants {
+            self.events.push(QueuedEvent::ParticipantLeft(participant.clone())).await;
+            if let Err(e) = self.invite_participant(participant.addr()).await {
+                event!(
+                    Level::WARN,
+                    participant = participant.name(),
+                    "Failed to invite participant again after changing SSRC: {e}"
+                );
+            }
+        }
+    }
+}
+
+/// How often clock syncs are sent to participants.
+pub(super) const CLOCK_SYNC_INTERVAL: Duration = Duration::from_secs(10);
+/// How long an invitation, sent or accepted, is waited on before the handshake is given up on. It is checked with
+/// each round of clock syncs, so it can be up to the 10 seconds between those longer.
+pub const INVITATION_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many control ports the system is asked for before giving up on finding a free MIDI port above one.
+const EPHEMERAL_PORT_ATTEMPTS: u32 = 16;
+
+fn panic_commands() -> Vec<MidiEvent<'static>> {
+    const SUSTAIN: u8 = 64;
+    const ALL_SOUND_OFF: u8 = 120;
+    const ALL_NOTES_OFF: u8 = 123;
+
+    let messages = (0..16).flat_map(|channel| {
+        [SUSTAIN, ALL_SOUND_OFF, ALL_NOTES_OFF].map(|control| MidiMessage::ControlChange(Channel::from(channel), Control::from(control), Value7::from(0)))
+    });
+    messages
+        .enumerate()
+        .map(|(i, message)| MidiEvent::new(if i == 0 { None } else { Some(0) }, message.into()))
+        .collect()
+}
+
+/// Allocates a buffer one byte larger than the largest accepted packet, so that a datagram filling it can be
+/// recognised as truncated rather than parsed.
+fn receive_buffer(max_packet_size: usize) -> Vec<u8> {
+    vec![0u8; max_packet_size + 1]
 }
 
 pub fn current_timestamp(start_time: Instant) -> U64 {
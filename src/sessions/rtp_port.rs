@@ -1,19 +1,26 @@
 use std::{collections::HashMap, ffi::CStr, net::SocketAddr, sync::Arc};
 
-use tokio::{net::UdpSocket, sync::Mutex};
-use tracing::{Level, event, instrument};
+use crate::logging::{Level, event};
+use tokio::sync::RwLock;
 use zerocopy::network_endian::U32;
 
-use crate::{packets::control_packets::control_packet::ControlPacket, participant::Participant};
+use crate::error::RtpMidiError;
+use crate::packets::control_packets::{
+    bitrate_limit_packet::BitrateLimitPacket, control_packet::ControlPacket, session_initiation_packet::SessionInitiationPacketBody,
+};
+use crate::participant::Participant;
+use crate::sessions::events::event_dispatcher::{EventQueue, QueuedEvent};
+use crate::sessions::events::event_handling::ProtocolVersionMismatch;
+use crate::sessions::socket::Socket;
 
 pub(super) trait RtpPort {
     fn session_name(&self) -> &CStr;
     fn ssrc(&self) -> U32;
-    fn socket(&self) -> &Arc<UdpSocket>;
+    fn socket(&self) -> &Arc<Socket>;
     fn participant_addr(participant: &Participant) -> SocketAddr;
 
-    #[instrument(skip_all, fields(destination = %destination))]
-    async fn send_invitation_acceptance<'a>(&self, initiator_token: U32, destination: SocketAddr) {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(destination = %destination)))]
+    async fn send_invitation_acceptance(&self, initiator_token: U32, destination: SocketAddr) {
         let response_packet = ControlPacket::new_acceptance_as_bytes(initiator_token, self.ssrc(), self.session_name());
 
         if let Err(e) = self.socket().send_to(&response_packet, destination).await {
@@ -23,21 +30,94 @@ pub(super) trait RtpPort {
         }
     }
 
-    #[instrument(skip_all, fields(ssrc = ssrc.get(), src = %src))]
-    async fn handle_termination(&self, ssrc: U32, src: SocketAddr, participants: &Arc<Mutex<HashMap<U32, Participant>>>) {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(destination = %destination)))]
+    async fn send_invitation_rejection(&self, initiator_token: U32, destination: SocketAddr) {
+        let rejection_packet = ControlPacket::new_rejection_as_bytes(initiator_token, self.ssrc());
+
+        if let Err(e) = self.socket().send_to(&rejection_packet, destination).await {
+            event!(Level::ERROR, "Failed to send session rejection: {}", e);
+        } else {
+            event!(Level::DEBUG, "Sent session rejection");
+        }
+    }
+
+    /// Sends each participant an empty datagram, which only holds the NAT mapping on the way to it open.
+    async fn send_keepalive<'a>(&self, participants: impl Iterator<Item = &'a Participant>) {
+        for participant in participants {
+            let destination = Self::participant_addr(participant);
+            if let Err(e) = self.socket().send_to(&[], destination).await {
+                event!(Level::WARN, destination = %destination, "Failed to send keepalive: {}", e);
+            }
+        }
+    }
+
+    /// Returns `false` (after notifying listeners) if the peer speaks a protocol version we don't support.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(version = body.protocol_version.get(), src = %src)))]
+    async fn check_protocol_version(&self, body: &SessionInitiationPacketBody, src: SocketAddr, events: &EventQueue) -> bool {
+        if body.has_supported_version() {
+            return true;
+        }
+
+        event!(Level::WARN, "Peer uses unsupported protocol version");
+        let mismatch = ProtocolVersionMismatch {
+            addr: src,
+            ssrc: body.sender_ssrc.get(),
+            version: body.protocol_version.get(),
+        };
+        events.push(QueuedEvent::ProtocolVersionMismatch(mismatch)).await;
+        false
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(ssrc = ssrc.get(), src = %src)))]
+    async fn handle_termination(&self, ssrc: U32, src: SocketAddr, participants: &RwLock<HashMap<U32, Participant>>, events: &EventQueue) {
         event!(Level::INFO, "Received termination packet");
-        let mut lock = participants.lock().await;
-        lock.remove(&ssrc);
+        let mut lock = participants.write().await;
+        // Only the participant itself can end its session, not another peer that happens to use the same SSRC
+        if lock.get(&ssrc).is_some_and(|participant| Self::participant_addr(participant) == src) {
+            lock.remove(&ssrc);
+            drop(lock);
+            events.push(QueuedEvent::SsrcRetired(ssrc)).await;
+        }
     }
 
-    #[instrument(skip_all, fields(destination = %participant.addr(), participant = participant.name().to_str().unwrap_or("Unknown")))]
-    async fn send_termination_packet(&self, participant: &Participant) {
-        let termination_packet = ControlPacket::new_termination_as_bytes(participant.initiator_token().unwrap(), self.ssrc());
-        let addr = Self::participant_addr(participant);
-        if let Err(e) = self.socket().send_to(&termination_packet, addr).await {
-            event!(Level::WARN, "Failed to send termination packet: {}", e);
-        } else {
-            event!(Level::INFO, "Sent termination packet");
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(ssrc = packet.sender_ssrc.get(), limit = packet.limit.get())))]
+    async fn handle_bitrate_limit(&self, packet: &BitrateLimitPacket, src: SocketAddr, participants: &RwLock<HashMap<U32, Participant>>) {
+        match participants.write().await.get_mut(&packet.sender_ssrc) {
+            Some(participant) if Self::participant_addr(participant) == src => {
+                participant.set_bitrate_limit(packet.limit.get());
+                event!(Level::INFO, "Participant set a bitrate limit");
+            }
+            _ => event!(Level::WARN, "Received bitrate limit but no matching participant found"),
         }
     }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(destination = %Self::participant_addr(participant), participant = participant.name())))]
+    async fn send_bitrate_limit(&self, participant: &Participant, limit: u32) -> Result<(), RtpMidiError> {
+        let packet = ControlPacket::new_bitrate_limit_as_bytes(U32::new(limit), self.ssrc());
+        self.socket().send_to(&packet, Self::participant_addr(participant)).await?;
+        event!(Level::DEBUG, "Sent bitrate limit");
+        Ok(())
+    }
+
+    /// Like [`send_termination_packet`](Self::send_termination_packet), without waiting to send it.
+    fn try_send_termination_packet(&self, participant: &Participant) -> Result<(), RtpMidiError> {
+        let initiator_token = participant
+            .initiator_token()
+            .ok_or(RtpMidiError::InvalidState("participant has no initiator token"))?;
+        let termination_packet = ControlPacket::new_termination_as_bytes(initiator_token, self.ssrc());
+        self.socket().try_send_to(&termination_packet, Self::participant_addr(participant))?;
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(destination = %participant.addr(), participant = participant.name())))]
+    async fn send_termination_packet(&self, participant: &Participant) -> Result<(), RtpMidiError> {
+        let initiator_token = participant
+            .initiator_token()
+            .ok_or(RtpMidiError::InvalidState("participant has no initiator token"))?;
+        let termination_packet = ControlPacket::new_termination_as_bytes(initiator_token, self.ssrc());
+        let addr = Self::participant_addr(participant);
+        self.socket().send_to(&termination_packet, addr).await?;
+        event!(Level::INFO, "Sent termination packet");
+        Ok(())
+    }
 }
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use tokio::time::{Instant, sleep_until};
+
+/// Work a session does at regular intervals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Timer {
+    ClockSync,
+    NatKeepalive,
+}
+
+/// The periodic work of a session, all waited on by one task: it sleeps until the next timer is due, so an idle
+/// session has a single timer pending however many kinds of periodic work it has. Timers due at the same time go
+/// off in the order they were added.
+#[derive(Debug, Default)]
+pub(crate) struct Scheduler {
+    timers: Vec<Scheduled>,
+}
+
+#[derive(Debug)]
+struct Scheduled {
+    timer: Timer,
+    interval: Duration,
+    due: Instant,
+}
+
+impl Scheduler {
+    /// Has `timer` go off every `interval`, starting one interval from now.
+    pub fn every(&mut self, timer: Timer, interval: Duration) {
+        self.timers.push(Scheduled {
+            timer,
+            interval,
+            due: Instant::now() + interval,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timers.is_empty()
+    }
+
+    /// Waits for the next timer to go off and returns it, never if there are none. Safe to cancel: a timer only
+    /// moves on to its next time once it has been returned. One left behind, by work that took longer than its
+    /// interval, goes off once and then keeps to its interval from then, rather than catching up.
+    pub async fn next(&mut self) -> Timer {
+        let Some(index) = (0..self.timers.len()).min_by_key(|&i| self.timers[i].due) else {
+            return std::future::pending().await;
+        };
+        sleep_until(self.timers[index].due).await;
+        let scheduled = &mut self.timers[index];
+        let now = Instant::now();
+        scheduled.due += scheduled.interval;
+        if scheduled.due < now {
+            scheduled.due = now + scheduled.interval;
+        }
+        scheduled.timer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timers_go_off_in_order() {
+        let start = Instant::now();
+        let mut scheduler = Scheduler::default();
+        scheduler.every(Timer::ClockSync, Duration::from_millis(10));
+        scheduler.every(Timer::NatKeepalive, Duration::from_millis(25));
+
+        let mut fired = Vec::new();
+        for _ in 0..7 {
+            let timer = scheduler.next().await;
+            fired.push((timer, (Instant::now() - start).as_millis()));
+        }
+        use Timer::*;
+        assert_eq!(
+            fired,
+            [
+                (ClockSync, 10),
+                (ClockSync, 20),
+                (NatKeepalive, 25),
+                (ClockSync, 30),
+                (ClockSync, 40),
+                (ClockSync, 50),
+                (NatKeepalive, 50)
+            ]
+        );
+
+        // Work that overruns doesn't leave the timer trying to catch up
+        tokio::time::advance(Duration::from_millis(100)).await;
+        assert_eq!(scheduler.next().await, ClockSync);
+        assert_eq!(scheduler.next().await, NatKeepalive);
+        assert_eq!(scheduler.next().await, ClockSync);
+        assert_eq!((Instant::now() - start).as_millis(), 160);
+    }
+}
@@ -0,0 +1,250 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+
+use crate::packets::midi_packets::midi_packet::FirstDeltaTime;
+use crate::packets::parse_mode::ParseMode;
+use crate::sessions::authenticator::Authenticator;
+use crate::sessions::channel_map::ChannelRouting;
+use crate::sessions::control_port::MAX_CONTROL_PACKET_SIZE;
+use crate::sessions::encryption::Cipher;
+use crate::sessions::events::event_dispatcher::{DEFAULT_EVENT_QUEUE_CAPACITY, MAX_SYSEX_SIZE, QueueOverflow};
+use crate::sessions::events::event_handling::EventListeners;
+use crate::sessions::known_peer::KnownPeer;
+use crate::sessions::loss_concealment::LossConcealment;
+use crate::sessions::midi_port::{MAX_MIDI_PACKET_SIZE, MAX_SENT_MIDI_PACKET_SIZE};
+use crate::sessions::random::RandomSource;
+
+/// Tunables for an [`RtpMidiSession`](super::rtp_midi_session::RtpMidiSession).
+///
+/// Construct with struct update syntax to override only what you need:
+///
+/// ```
+/// use rtpmidi::sessions::session_config::SessionConfig;
+///
+/// let config = SessionConfig {
+///     max_midi_packet_size: 2048,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Largest datagram accepted on the control port. Anything bigger is dropped. The control port receives into a
+    /// buffer of this size, allocated when the session starts. 1 KiB by default.
+    pub max_control_packet_size: usize,
+    /// Largest datagram accepted on the MIDI port. Anything bigger is dropped. The MIDI port receives into a buffer
+    /// of this size, allocated when the session starts, and so is each received packet waiting in the event queue,
+    /// so memory-constrained targets can get by with 2 KiB or so. The peers then need to keep their packets that
+    /// small, as this library does with `max_sent_midi_packet_size`. 32 KiB by default.
+    pub max_midi_packet_size: usize,
+    /// Largest MIDI packet sent, before any sealing by `encryption`. Batches go out over as many packets as it takes
+    /// to keep each within it, and SysEx in as many segments; only a single command too long to fit gets a bigger
+    /// packet to itself. 1400 bytes by default, so packets cross Ethernet without being fragmented.
+    pub max_sent_midi_packet_size: usize,
+    /// Largest SysEx message accepted, whether it arrives in one packet or in segments. A transfer that runs over is
+    /// dropped, and reported to [`SysExTooLargeEvent`](super::events::event_handling::SysExTooLargeEvent)
+    /// listeners, rather than buffered without limit. 1 MiB by default.
+    pub max_sysex_size: usize,
+    /// How tolerant the session is of packets that don't quite follow the specifications.
+    pub parse_mode: ParseMode,
+    /// Whether the first command of each MIDI packet received has a delta time before it, for peers that write one
+    /// without setting the Z flag. As the Z flag says by default.
+    pub first_delta_time: FirstDeltaTime,
+    /// Writes the delta time of the first command of each MIDI packet sent, setting the Z flag, for peers that expect
+    /// one. Otherwise it's left out, and the command falls on the packet's timestamp. Off by default.
+    pub send_first_delta_time: bool,
+    /// How many events can wait for listeners before `event_queue_overflow` applies.
+    pub event_queue_capacity: usize,
+    /// What becomes of events that arrive while `event_queue_capacity` of them are already waiting for listeners.
+    /// Received MIDI packets are dropped and counted by default, so a slow listener can't stop the session reading its
+    /// sockets, and other events are queued regardless.
+    pub event_queue_overflow: QueueOverflow,
+    /// Works around the quirks of the rtpMIDI driver for Windows (by Tobias Erichsen) instead of dropping its packets:
+    /// invitations it repeats with a new token are answered again rather than treated as a new session, clock syncs
+    /// it sends before it has fully joined are answered, and the zero latency its clock syncs often work out to is
+    /// expected rather than reported.
+    pub rtpmidi_quirks: bool,
+    /// Accepts invitations that arrive on the MIDI port without a handshake on the control port first, as some
+    /// embedded stacks send them. The invite handler decides as usual, and the peer's control port is taken to be
+    /// the one just below its MIDI port.
+    pub accept_midi_port_invitations: bool,
+    /// Puts each participant's MIDI packets back in sequence number order before listeners see them, for networks
+    /// (WiFi in particular) that reorder them. Off by default, as it delays every packet that arrives after a gap.
+    pub reorder_window: Option<ReorderWindow>,
+    /// Asks participants for the MIDI packets that went missing on the way, and sends them again when asked, over an
+    /// extension of ours on the control port. Only peers using this library take part; others log the requests and
+    /// ignore them. What comes back late is handed to
+    /// [`RecoveredMidiEvent`](super::events::event_handling::RecoveredMidiEvent) and
+    /// [`RecoveredSysExEvent`](super::events::event_handling::RecoveredSysExEvent) listeners rather than the ones for
+    /// live MIDI, and bypasses the reorder window. Off by default.
+    pub retransmission: Option<Retransmission>,
+    /// What to hand listeners in place of MIDI packets that are lost for good, such as a release of the notes left
+    /// sounding, see [`LossConcealment`]. Nothing by default.
+    pub loss_concealment: Option<Arc<dyn LossConcealment>>,
+    /// The channel maps for participants that haven't been given their own. Leaves every channel alone by default.
+    pub channel_routing: ChannelRouting,
+    /// The most participants the session lets join. Invitations beyond that are rejected without asking the invite
+    /// handler, and reported to [`ParticipantLimitReachedEvent`](super::events::event_handling::ParticipantLimitReachedEvent)
+    /// listeners. Invitations the session sends itself aren't limited. No limit by default.
+    pub max_participants: Option<usize>,
+    /// Makes the session a listener only, for loggers and monitors: it joins sessions and keeps its clock in sync as
+    /// usual, but never sends MIDI, and sending fails with [`RtpMidiError::InvalidState`](crate::error::RtpMidiError::InvalidState).
+    pub receive_only: bool,
+    /// Makes the session one that can't be joined, for kiosk devices: every invitation is rejected without asking the
+    /// invite handler, and the session isn't advertised over mDNS. It only has the participants it invites itself
+    /// with [`invite_participant`](super::rtp_midi_session::RtpMidiSession::invite_participant).
+    pub initiator_only: bool,
+    /// Peers invited as soon as the session starts, such as those saved with
+    /// [`export_peers`](super::rtp_midi_session::RtpMidiSession::export_peers) before a restart. An invitation that
+    /// can't be sent is logged rather than failing the start. None by default.
+    pub peers: Vec<KnownPeer>,
+    /// Writes every datagram the session sends and receives, on both ports, to a pcap file at this path, for opening
+    /// in Wireshark when reporting interoperability problems. The file is replaced if it exists. Meant for
+    /// debugging, as the file is written to as each datagram goes by. Off by default.
+    pub capture: Option<PathBuf>,
+    /// The percentage of lost MIDI packets, over a participant's most recent ones, above which
+    /// [`PacketLossThresholdEvent`](super::events::event_handling::PacketLossThresholdEvent) listeners are told, and
+    /// told again once it drops back below. Off by default.
+    pub loss_alert_threshold: Option<f64>,
+    /// Limits how many invitations each address can send and how many handshakes can be under way at once, so a
+    /// hostile or broken peer can't tie the session up. Invitations over the limits are rejected and reported to
+    /// [`InvitationFloodEvent`](super::events::event_handling::InvitationFloodEvent) listeners. Off by default.
+    pub flood_protection: Option<FloodProtection>,
+    /// Makes peers prove they know a shared secret before they become participants, see [`Authenticator`]. Peers
+    /// that fail are reported to
+    /// [`AuthenticationFailedEvent`](super::events::event_handling::AuthenticationFailedEvent) listeners. Off by
+    /// default.
+    pub authenticator: Option<Arc<dyn Authenticator>>,
+    /// Seals every datagram the session sends and opens every one it receives with a [`Cipher`], for sessions
+    /// between instances of this library across networks that aren't trusted. Cleartext AppleMIDI by default, for
+    /// talking to everything else.
+    pub encryption: Option<Arc<dyn Cipher>>,
+    /// Where the session gets its tokens, nonces and new SSRCs from, see [`RandomSource`]. The `rand` crate by
+    /// default; without the `rand` feature, sessions fail to start unless this is given.
+    pub random: Option<Arc<dyn RandomSource>>,
+    /// The tokio runtime the session's sockets and tasks live on, to keep it to a runtime of its own or a particular
+    /// group of worker threads. Its listeners are called from there too. The runtime the session is started from by
+    /// default.
+    pub runtime: Option<Handle>,
+    /// How often to send each participant an empty datagram on both ports, for sessions crossing NAT. Clock syncs
+    /// are further apart than many routers keep a mapping open for, and the peer drops the datagram without a reply.
+    /// Keepalives take turns with clock syncs, so one falling due while stale participants are being dropped goes
+    /// out once that's done; keep the interval well inside the routers' timeout. Off by default.
+    pub nat_keepalive: Option<Duration>,
+    /// Listeners in place from the moment the session starts, so none of its events can be missed, see
+    /// [`EventListeners::add_listener`]. More can be added once it is running. None by default.
+    pub listeners: EventListeners,
+    /// Logs every datagram the session sends and receives at TRACE, as an annotated hexdump. Only there with the
+    /// `hexdump` feature, so builds without it don't carry the code. Off by default.
+    #[cfg(feature = "hexdump")]
+    pub hexdump_packets: bool,
+    /// How many datagrams the MIDI port takes from its socket with each `recvmmsg` call, so a busy session makes a
+    /// system call per wakeup rather than one per packet. It keeps a receive buffer of `max_midi_packet_size` for
+    /// each, up to 64. Only there with the `recvmmsg` feature on Linux. 8 by default.
+    #[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+    pub receive_batch: usize,
+}
+
+/// How long MIDI packets that arrive ahead of a missing one are held back, waiting for it to turn up.
+#[derive(Debug, Clone, Copy)]
+pub struct ReorderWindow {
+    /// The most packets held back per participant. Once more have arrived, the missing one is given up on.
+    pub depth: usize,
+    /// The longest a packet is held back for.
+    pub latency: Duration,
+}
+
+impl Default for ReorderWindow {
+    fn default() -> Self {
+        Self {
+            depth: 8,
+            latency: Duration::from_millis(10),
+        }
+    }
+}
+
+/// How much of [`SessionConfig::retransmission`] each participant is kept for.
+#[derive(Debug, Clone, Copy)]
+pub struct Retransmission {
+    /// How many of the latest packets sent to a participant are kept to send again, and how many missing ones are
+    /// asked for at a time. A missing one that more recently missing ones take the place of is given up on.
+    pub history: usize,
+    /// How long a missing packet is waited for once asked for. Checked as the participant's packets arrive, so peers
+    /// that don't send them again, which is any not using this library, only delay
+    /// [`SessionConfig::loss_concealment`] by this much.
+    pub timeout: Duration,
+}
+
+impl Default for Retransmission {
+    fn default() -> Self {
+        Self {
+            history: 64,
+            timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+/// The limits of [`SessionConfig::flood_protection`].
+#[derive(Debug, Clone, Copy)]
+pub struct FloodProtection {
+    /// The most invitations an address can send per `window`. One more gets its invitations ignored for `ban`.
+    pub max_invitations: u32,
+    pub window: Duration,
+    /// The most invitations that can be accepted without the peer having finished joining. Invitations beyond that
+    /// are rejected until some have, or have been given up on after
+    /// [`INVITATION_TIMEOUT`](super::rtp_midi_session::INVITATION_TIMEOUT).
+    pub max_handshakes: usize,
+    pub ban: Duration,
+}
+
+impl Default for FloodProtection {
+    fn default() -> Self {
+        Self {
+            max_invitations: 5,
+            window: Duration::from_secs(1),
+            max_handshakes: 16,
+            ban: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            max_control_packet_size: MAX_CONTROL_PACKET_SIZE,
+            max_midi_packet_size: MAX_MIDI_PACKET_SIZE,
+            max_sent_midi_packet_size: MAX_SENT_MIDI_PACKET_SIZE,
+            max_sysex_size: MAX_SYSEX_SIZE,
+            parse_mode: ParseMode::default(),
+            first_delta_time: FirstDeltaTime::default(),
+            send_first_delta_time: false,
+            event_queue_capacity: DEFAULT_EVENT_QUEUE_CAPACITY,
+            event_queue_overflow: QueueOverflow::default(),
+            rtpmidi_quirks: false,
+            accept_midi_port_invitations: false,
+            reorder_window: None,
+            retransmission: None,
+            loss_concealment: None,
+            channel_routing: ChannelRouting::default(),
+            max_participants: None,
+            receive_only: false,
+            initiator_only: false,
+            peers: Vec::new(),
+            capture: None,
+            loss_alert_threshold: None,
+            flood_protection: None,
+            authenticator: None,
+            encryption: None,
+            random: None,
+            runtime: None,
+            nat_keepalive: None,
+            listeners: EventListeners::new(),
+            #[cfg(feature = "hexdump")]
+            hexdump_packets: false,
+            #[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+            receive_batch: 8,
+        }
+    }
+}
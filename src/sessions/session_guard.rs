@@ -0,0 +1,59 @@
+//! Stopping a session on the way out of a scope, however it is left.
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use super::rtp_midi_session::RtpMidiSession;
+
+/// Owns a session and ends it when dropped: an early return, a `?`, a panic unwinding or the application exiting
+/// main. Its participants are sent their goodbyes first, so they see it leave rather than waiting for it to time
+/// out. Dropping `RtpMidiSession` itself only stops its tasks.
+///
+/// A drop can't wait, so goodbyes that can't go out straight away are skipped. Call [`stop`](Self::stop) where
+/// waiting is possible, to stop gracefully instead.
+///
+/// ```no_run
+/// use rtpmidi::sessions::invite_responder::InviteResponder;
+/// use rtpmidi::sessions::rtp_midi_session::RtpMidiSession;
+/// use rtpmidi::sessions::session_guard::SessionGuard;
+///
+/// # async fn example() -> Result<(), rtpmidi::error::RtpMidiError> {
+/// let session = SessionGuard::new(RtpMidiSession::start(5004, "My Session", 0x12345678, InviteResponder::Accept).await?);
+/// session.invite_participant("192.168.1.20:5004".parse().unwrap()).await?;
+/// // Participants are told the session has ended however this returns
+/// # Ok(())
+/// # }
+/// ```
+pub struct SessionGuard {
+    session: Arc<RtpMidiSession>,
+}
+
+impl SessionGuard {
+    pub fn new(session: Arc<RtpMidiSession>) -> Self {
+        SessionGuard { session }
+    }
+
+    /// The session, for handing to tasks. They don't keep it alive past the guard.
+    pub fn session(&self) -> &Arc<RtpMidiSession> {
+        &self.session
+    }
+
+    /// Stops the session gracefully, as [`RtpMidiSession::stop_gracefully`] does.
+    pub async fn stop(self) {
+        self.session.stop_gracefully().await;
+    }
+}
+
+impl Deref for SessionGuard {
+    type Target = RtpMidiSession;
+
+    fn deref(&self) -> &RtpMidiSession {
+        &self.session
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.session.terminate_now();
+    }
+}
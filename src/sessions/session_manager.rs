@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::logging::{Level, event};
+use tokio::runtime::Handle;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::RtpMidiError;
+use crate::sessions::events::event_handling::EventType;
+use crate::sessions::invite_responder::InviteResponder;
+use crate::sessions::rtp_midi_session::{CLOCK_SYNC_INTERVAL, RtpMidiSession, SharedResources};
+use crate::sessions::session_config::SessionConfig;
+
+type Sessions = Arc<RwLock<HashMap<String, Arc<RtpMidiSession>>>>;
+/// Adds one of the manager's listeners to a session.
+type ListenerAttacher = dyn Fn(&RtpMidiSession) + Send + Sync + 'static;
+
+/// Runs many sessions side by side, for hosts like patchbays that offer a session per port. The sessions share one
+/// mDNS daemon (with the `mdns` feature) and one clock sync task, can be looked up by name, and listeners added to
+/// the manager hear the events of all of them.
+///
+/// ```no_run
+/// use rtpmidi::sessions::events::event_handling::MidiMessageEvent;
+/// use rtpmidi::sessions::invite_responder::InviteResponder;
+/// use rtpmidi::sessions::session_config::SessionConfig;
+/// use rtpmidi::sessions::session_manager::SessionManager;
+///
+/// # async fn example() -> Result<(), rtpmidi::error::RtpMidiError> {
+/// let manager = SessionManager::new().await?;
+/// for (port, name) in [(5004, "Port A"), (5006, "Port B")] {
+///     manager.start_session(port, name, rand::random(), InviteResponder::Accept, SessionConfig::default()).await?;
+/// }
+/// manager
+///     .add_listener(MidiMessageEvent, |session, (message, _delta_time)| println!("{session}: {message:?}"))
+///     .await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SessionManager {
+    sessions: Sessions,
+    listeners: Mutex<Vec<Arc<ListenerAttacher>>>,
+    shared: SharedResources,
+    cancel_token: CancellationToken,
+    clock_sync: Mutex<Option<JoinHandle<()>>>,
+    runtime: Option<Handle>,
+}
+
+impl SessionManager {
+    pub async fn new() -> Result<Self, RtpMidiError> {
+        Self::create(None).await
+    }
+
+    /// A manager whose clock sync task lives on `runtime`, as do the sessions it starts unless their
+    /// [`SessionConfig::runtime`] says otherwise.
+    pub async fn with_runtime(runtime: Handle) -> Result<Self, RtpMidiError> {
+        Self::create(Some(runtime)).await
+    }
+
+    async fn create(runtime: Option<Handle>) -> Result<Self, RtpMidiError> {
+        let sessions = Sessions::default();
+        let shared = SharedResources {
+            #[cfg(feature = "mdns")]
+            mdns: Some(mdns_sd::ServiceDaemon::new().map_err(std::io::Error::other)?),
+            managed_clock_sync: true,
+        };
+
+        let cancel_token = CancellationToken::new();
+        let clock_cancel_token = cancel_token.clone();
+        let clock_sessions = Arc::clone(&sessions);
+        let clock_sync = async move {
+            loop {
+                tokio::select! {
+                    _ = clock_cancel_token.cancelled() => {
+                        event!(Level::DEBUG, "session manager clock sync: cancellation requested");
+                        break;
+                    },
+                    _ = sleep(CLOCK_SYNC_INTERVAL) => {
+                        let sessions: Vec<_> = clock_sessions.read().await.values().cloned().collect();
+                        for session in sessions {
+                            session.sync_clocks().await;
+                        }
+                    }
+                }
+            }
+        };
+        let clock_sync = match &runtime {
+            Some(runtime) => runtime.spawn(clock_sync),
+            None => tokio::spawn(clock_sync),
+        };
+
+        Ok(SessionManager {
+            sessions,
+            listeners: Mutex::default(),
+            shared,
+            cancel_token,
+            clock_sync: Mutex::new(Some(clock_sync)),
+            runtime,
+        })
+    }
+
+    /// Starts a session like [`RtpMidiSession::start_with_config`], but run by the manager and found under `name`,
+    /// on the manager's runtime if it was given one and `config` doesn't name another. Fails if the manager already
+    /// has a session called `name`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, invite_handler, config)))]
+    pub async fn start_session(
+        &self,
+        port: u16,
+        name: &str,
+        ssrc: u32,
+        invite_handler: InviteResponder,
+        mut config: SessionConfig,
+    ) -> Result<Arc<RtpMidiSession>, RtpMidiError> {
+        // Both held until the session is in the map, so two sessions can't be started under the same name and a
+        // listener being added reaches this one exactly once. Always locked in this order.
+        let listeners = self.listeners.lock().await;
+        let mut sessions = self.sessions.write().await;
+        if sessions.contains_key(name) {
+            return Err(RtpMidiError::InvalidArgument(format!("there is already a session called {name}")));
+        }
+        config.runtime = config.runtime.or_else(|| self.runtime.clone());
+        let session = RtpMidiSession::start_with_resources(port, name, ssrc, invite_handler, config, self.shared.clone()).await?;
+        for attach in listeners.iter() {
+            attach(&session);
+        }
+        sessions.insert(name.to_owned(), Arc::clone(&session));
+        Ok(session)
+    }
+
+    /// The session called `name`, if the manager has one.
+    pub async fn session(&self, name: &str) -> Option<Arc<RtpMidiSession>> {
+        self.sessions.read().await.get(name).cloned()
+    }
+
+    pub async fn sessions(&self) -> Vec<Arc<RtpMidiSession>> {
+        self.sessions.read().await.values().cloned().collect()
+    }
+
+    /// Stops the session called `name` gracefully and forgets about it. Returns whether there was one.
+    pub async fn stop_session(&self, name: &str) -> bool {
+        let Some(session) = self.sessions.write().await.remove(name) else {
+            return false;
+        };
+        session.stop_gracefully().await;
+        true
+    }
+
+    /// Adds a listener to every session, including those started later. It is given the name of the session the
+    /// event happened in along with the event.
+    pub async fn add_listener<E, F>(&self, _event_type: E, callback: F)
+    where
+        E: EventType + 'static,
+        F: for<'a> Fn(&str, E::Data<'a>) + Send + Sync + 'static,
+    {
+        let callback = Arc::new(callback);
+        let attach: Arc<ListenerAttacher> = Arc::new(move |session: &RtpMidiSession| {
+            let name = session.name().to_owned();
+            let callback = Arc::clone(&callback);
+            session
+                .listeners
+                .update(|listeners| E::add_listener_to_storage(listeners, move |data| callback(&name, data)));
+        });
+
+        let mut listeners = self.listeners.lock().await;
+        for session in self.sessions.read().await.values() {
+            attach(session);
+        }
+        listeners.push(attach);
+    }
+
+    /// Stops every session gracefully, then the tasks the manager runs for them.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn stop_gracefully(&self) {
+        let sessions: Vec<_> = self.sessions.write().await.drain().map(|(_, session)| session).collect();
+        for session in sessions {
+            session.stop_gracefully().await;
+        }
+        self.cancel_token.cancel();
+        if let Some(clock_sync) = self.clock_sync.lock().await.take()
+            && let Err(e) = clock_sync.await
+        {
+            event!(Level::WARN, "Clock sync task failed to complete cleanly: {}", e);
+        }
+        #[cfg(feature = "mdns")]
+        if let Some(mdns) = &self.shared.mdns {
+            let _ = mdns.shutdown();
+        }
+        event!(Level::INFO, "Session manager stopped");
+    }
+}
+
+impl Drop for SessionManager {
+    fn drop(&mut self) {
+        self.cancel_token.cancel();
+        #[cfg(feature = "mdns")]
+        if let Some(mdns) = &self.shared.mdns {
+            let _ = mdns.shutdown();
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::net::UdpSocket;
+
+use crate::logging::{Level, event};
+use crate::sessions::capture::PacketCapture;
+use crate::sessions::encryption::Cipher;
+use crate::sessions::interceptor::Direction;
+use crate::sessions::stun;
+use crate::sessions::wire_tap::WireTapSlot;
+
+/// What a session's sockets hand each datagram to as it goes through them.
+#[derive(Clone, Default)]
+pub(crate) struct SocketHooks {
+    pub capture: Option<Arc<PacketCapture>>,
+    pub wire_tap: Arc<WireTapSlot>,
+    pub encryption: Option<Arc<dyn Cipher>>,
+    #[cfg(feature = "hexdump")]
+    pub hexdump: bool,
+}
+
+/// One of a session's UDP sockets, recording what goes through it if the session is capturing packets, and handing
+/// it to the session's wire tap. With encryption, datagrams are sealed last thing before they are sent and opened
+/// first thing once received.
+pub(super) struct Socket {
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+    hooks: SocketHooks,
+}
+
+impl Socket {
+    pub async fn bind(port: u16, hooks: SocketHooks) -> io::Result<Self> {
+        let socket = UdpSocket::bind((std::net::Ipv4Addr::UNSPECIFIED, port)).await?;
+        let local_addr = socket.local_addr()?;
+        Ok(Socket { socket, local_addr, hooks })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        let sent = match &self.hooks.encryption {
+            Some(cipher) => {
+                self.socket.send_to(&cipher.seal(buf), target).await?;
+                buf.len()
+            }
+            None => self.socket.send_to(buf, target).await?,
+        };
+        self.sent(&buf[..sent], target);
+        Ok(sent)
+    }
+
+    /// Like [`send_to`](Self::send_to), but fails rather than waits if the datagram can't go out straight away, for
+    /// where nothing can be awaited.
+    pub fn try_send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        let sent = match &self.hooks.encryption {
+            Some(cipher) => {
+                self.socket.try_send_to(&cipher.seal(buf), target)?;
+                buf.len()
+            }
+            None => self.socket.try_send_to(buf, target)?,
+        };
+        self.sent(&buf[..sent], target);
+        Ok(sent)
+    }
+
+    /// Sends a datagram meant for something other than a peer, such as a STUN server, without sealing it.
+    pub async fn send_in_clear_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        let sent = self.socket.send_to(buf, target).await?;
+        self.sent(&buf[..sent], target);
+        Ok(sent)
+    }
+
+    fn sent(&self, datagram: &[u8], target: SocketAddr) {
+        if let Some(capture) = &self.hooks.capture {
+            capture.record(self.local_addr, target, datagram);
+        }
+        #[cfg(feature = "hexdump")]
+        if self.hooks.hexdump {
+            crate::sessions::hexdump::log_datagram(Direction::Outbound, target, datagram);
+        }
+        self.hooks.wire_tap.tap(Direction::Outbound, target, datagram);
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            let (amt, src) = self.socket.recv_from(buf).await?;
+            if let Some(amt) = self.received(buf, amt, src) {
+                return Ok((amt, src));
+            }
+        }
+    }
+
+    /// Like [`recv_from`](Self::recv_from), but receives into the spare capacity of `buf`, up to `limit` bytes, so
+    /// it needn't be zeroed first. What was in `buf` is replaced by the datagram.
+    #[cfg(not(all(feature = "recvmmsg", target_os = "linux")))]
+    pub async fn recv_buf_from(&self, buf: &mut Vec<u8>, limit: usize) -> io::Result<(usize, SocketAddr)> {
+        loop {
+            buf.clear();
+            buf.reserve(limit);
+            let (amt, src) = self.socket.recv_buf_from(&mut bytes::BufMut::limit(&mut *buf, limit)).await?;
+            if let Some(amt) = self.received(buf, amt, src) {
+                buf.truncate(amt);
+                return Ok((amt, src));
+            }
+        }
+    }
+
+    /// Like [`recv_buf_from`](Self::recv_buf_from), but takes as many datagrams as are waiting, up to one per buffer
+    /// in `bufs`, with a single `recvmmsg` call. Their lengths and senders replace what's in `received`, in order.
+    #[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+    pub async fn recv_batch(&self, bufs: &mut [Vec<u8>], limit: usize, received: &mut Vec<(usize, SocketAddr)>) -> io::Result<()> {
+        received.clear();
+        while received.is_empty() {
+            self.socket.readable().await?;
+            let datagrams = match self
+                .socket
+                .try_io(tokio::io::Interest::READABLE, || recv_mmsg(&self.socket, bufs, limit, received))
+            {
+                Ok(()) => received.len(),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(error) => return Err(error),
+            };
+            let mut kept = 0;
+            for i in 0..datagrams {
+                let (amt, src) = received[i];
+                if let Some(amt) = self.received(&mut bufs[i], amt, src) {
+                    bufs[i].truncate(amt);
+                    // Dropped datagrams leave their buffer behind, so the ones after it move up to stay in line
+                    bufs.swap(kept, i);
+                    received[kept] = (amt, src);
+                    kept += 1;
+                }
+            }
+            received.truncate(kept);
+        }
+        Ok(())
+    }
+
+    /// Opens a datagram that has just arrived in `buf`, if the session is encrypted, and hands it to the hooks.
+    /// Returns its length, or nothing if it is to be dropped.
+    fn received(&self, buf: &mut [u8], amt: usize, src: SocketAddr) -> Option<usize> {
+        let amt = match &self.hooks.encryption {
+            // Answers to what went out in the clear
+            Some(_) if stun::is_stun(&buf[..amt]) => amt,
+            Some(cipher) => match cipher.open(&buf[..amt]) {
+                Some(plaintext) if plaintext.len() <= buf.len() => {
+                    buf[..plaintext.len()].copy_from_slice(&plaintext);
+                    plaintext.len()
+                }
+                _ => {
+                    event!(Level::WARN, src = %src, "Dropping datagram that doesn't open with the session's cipher");
+                    return None;
+                }
+            },
+            None => amt,
+        };
+        if let Some(capture) = &self.hooks.capture {
+            capture.record(src, self.local_addr, &buf[..amt]);
+        }
+        #[cfg(feature = "hexdump")]
+        if self.hooks.hexdump {
+            crate::sessions::hexdump::log_datagram(Direction::Inbound, src, &buf[..amt]);
+        }
+        self.hooks.wire_tap.tap(Direction::Inbound, src, &buf[..amt]);
+        Some(amt)
+    }
+}
+
+/// The most datagrams [`Socket::recv_batch`] takes with one call, whatever the number of buffers.
+#[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+pub(crate) const MAX_RECEIVE_BATCH: usize = 64;
+
+/// One non-blocking `recvmmsg` into the spare capacity of `bufs`, up to `limit` bytes each, recording each
+/// datagram's length and sender in `received`. The buffers filled are left holding their datagram.
+#[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+fn recv_mmsg(socket: &UdpSocket, bufs: &mut [Vec<u8>], limit: usize, received: &mut Vec<(usize, SocketAddr)>) -> io::Result<()> {
+    use std::mem;
+    use std::os::fd::AsRawFd;
+
+    let count = bufs.len().min(MAX_RECEIVE_BATCH);
+    // SAFETY: these are plain C structs, for which all zeroes is a valid value (null pointers and zero lengths)
+    let mut addresses: [libc::sockaddr_storage; MAX_RECEIVE_BATCH] = unsafe { mem::zeroed() };
+    let mut iovecs: [libc::iovec; MAX_RECEIVE_BATCH] = unsafe { mem::zeroed() };
+    let mut headers: [libc::mmsghdr; MAX_RECEIVE_BATCH] = unsafe { mem::zeroed() };
+    for i in 0..count {
+        bufs[i].clear();
+        bufs[i].reserve(limit);
+        iovecs[i] = libc::iovec {
+            iov_base: bufs[i].as_mut_ptr().cast(),
+            iov_len: limit,
+        };
+        headers[i].msg_hdr.msg_name = (&raw mut addresses[i]).cast();
+        headers[i].msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        headers[i].msg_hdr.msg_iov = &raw mut iovecs[i];
+        headers[i].msg_hdr.msg_iovlen = 1;
+    }
+    // SAFETY: the first `count` headers point at an address and a buffer each that outlive the call, with their sizes
+    let datagrams = unsafe {
+        libc::recvmmsg(
+            socket.as_raw_fd(),
+            headers.as_mut_ptr(),
+            count as libc::c_uint,
+            libc::MSG_DONTWAIT,
+            std::ptr::null_mut(),
+        )
+    };
+    if datagrams < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    for ((header, address), buf) in headers.iter().zip(&addresses).zip(bufs.iter_mut()).take(datagrams as usize) {
+        // SAFETY: the kernel wrote this many bytes into the buffer's capacity, which is at least `limit`
+        unsafe { buf.set_len(header.msg_len as usize) };
+        received.push((header.msg_len as usize, socket_addr(address)?));
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+fn socket_addr(address: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV6};
+
+    match address.ss_family as libc::c_int {
+        libc::AF_INET => {
+            // SAFETY: the family says the storage holds an IPv4 address, and it's big and aligned enough for any
+            let address = unsafe { &*(address as *const libc::sockaddr_storage).cast::<libc::sockaddr_in>() };
+            Ok(SocketAddr::new(
+                Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)).into(),
+                u16::from_be(address.sin_port),
+            ))
+        }
+        libc::AF_INET6 => {
+            // SAFETY: as above, for an IPv6 address
+            let address = unsafe { &*(address as *const libc::sockaddr_storage).cast::<libc::sockaddr_in6>() };
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                Ipv6Addr::from(address.sin6_addr.s6_addr),
+                u16::from_be(address.sin6_port),
+                address.sin6_flowinfo,
+                address.sin6_scope_id,
+            )))
+        }
+        family => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("datagram from an address of family {family}"),
+        )),
+    }
+}
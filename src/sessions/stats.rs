@@ -0,0 +1,322 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::participant::Participant;
+
+/// What a session has sent and received since it started, from
+/// [`RtpMidiSession::stats`](super::rtp_midi_session::RtpMidiSession::stats). The traffic counted is the MIDI packets;
+/// session handshakes and clock syncs aren't.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionStats {
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    /// Including the packets from peers that aren't participants.
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    /// Datagrams on either port that couldn't be parsed.
+    pub parse_errors: u64,
+    /// Packets received more than once, including from participants that have since left.
+    pub duplicates_dropped: u64,
+    /// Packets skipped over in a participant's sequence numbers, including from participants that have since left.
+    pub sequence_gaps: u64,
+    /// Events dropped because listeners had fallen behind, see
+    /// [`SessionConfig::event_queue_overflow`](super::session_config::SessionConfig::event_queue_overflow).
+    pub events_dropped: u64,
+    /// The participants currently in the session.
+    pub participants: Vec<ParticipantStats>,
+}
+
+/// What has been sent to and received from one participant since it joined.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParticipantStats {
+    pub ssrc: u32,
+    pub name: String,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub lost_packets: u64,
+    pub late_packets: u64,
+    pub duplicate_packets: u64,
+    /// The round trip time measured by the last clock sync, if one has completed.
+    pub latency: Option<Duration>,
+    /// The spread of the round trip times measured by the most recent clock syncs.
+    pub latency_summary: Option<LatencySummary>,
+    /// The percentage of the most recent MIDI packets from the participant that were lost, see
+    /// [`LOSS_WINDOW`].
+    pub loss_rate: f64,
+}
+
+/// The round trip times measured by a participant's most recent clock syncs, up to [`LATENCY_SAMPLES`] of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// The average difference between one round trip time and the next, zero with a single sample.
+    pub jitter: Duration,
+}
+
+/// Roughly how many bytes of memory a session holds, from
+/// [`RtpMidiSession::memory_usage`](super::rtp_midi_session::RtpMidiSession::memory_usage). It is counted from the
+/// lengths and capacities of what the session keeps, leaving out allocator overhead and the fixed cost of its tasks,
+/// so it is only approximate, but it follows what can grow: a total that keeps going up while the participants stay
+/// the same is a sign of a leak.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryUsage {
+    /// The participants, with their names and the statistics kept on them.
+    pub participants: usize,
+    /// Invitations sent and received that haven't been answered, and peers yet to pass authentication.
+    pub pending_invitations: usize,
+    /// The packets kept to be sent again, with
+    /// [`SessionConfig::retransmission`](super::session_config::SessionConfig::retransmission). One sent to every
+    /// participant is counted for each of them.
+    pub retransmission_history: usize,
+    /// Events waiting to be handed to listeners, with the packets they carry, and receive buffers kept for reuse.
+    pub event_queue: usize,
+    /// Packets held back in the reorder window, and segmented SysEx messages being put back together.
+    pub reassembly: usize,
+    /// What is followed of each participant's MIDI as it arrives: the notes it has sounding, its high-resolution
+    /// controllers, timecode, transport and tempo.
+    pub followed: usize,
+    /// The peers of [`SessionConfig::peers`](super::session_config::SessionConfig::peers), invited when the session
+    /// starts.
+    pub known_peers: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.participants + self.pending_invitations + self.retransmission_history + self.event_queue + self.reassembly + self.followed + self.known_peers
+    }
+}
+
+/// The bytes a hash map's table takes up, leaving out what its keys and values point to.
+pub(crate) fn map_heap_size<K, V>(map: &HashMap<K, V>) -> usize {
+    map.capacity() * size_of::<(K, V)>()
+}
+
+/// How many round trip times are kept per participant. Clock syncs run every ten seconds, so this covers the last
+/// few minutes.
+pub const LATENCY_SAMPLES: usize = 16;
+
+/// The round trip times a participant's summary is worked out from, oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LatencyHistory {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyHistory {
+    pub fn heap_size(&self) -> usize {
+        self.samples.capacity() * size_of::<Duration>()
+    }
+
+    pub fn push(&mut self, latency: Duration) {
+        if self.samples.len() == LATENCY_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    pub fn summary(&self) -> Option<LatencySummary> {
+        let count = self.samples.len();
+        let min = *self.samples.iter().min()?;
+        let max = *self.samples.iter().max()?;
+        let avg = self.samples.iter().sum::<Duration>() / count as u32;
+        let differences = self.samples.iter().zip(self.samples.iter().skip(1)).map(|(a, b)| a.abs_diff(*b));
+        let jitter = if count > 1 {
+            differences.sum::<Duration>() / (count - 1) as u32
+        } else {
+            Duration::ZERO
+        };
+        Some(LatencySummary {
+            samples: count,
+            min,
+            avg,
+            max,
+            jitter,
+        })
+    }
+}
+
+impl From<&Participant> for ParticipantStats {
+    fn from(participant: &Participant) -> Self {
+        ParticipantStats {
+            ssrc: participant.ssrc().get(),
+            name: participant.name().to_owned(),
+            packets_sent: participant.packets_sent(),
+            bytes_sent: participant.bytes_sent(),
+            packets_received: participant.packets_received(),
+            bytes_received: participant.bytes_received(),
+            lost_packets: participant.lost_packets(),
+            late_packets: participant.late_packets(),
+            duplicate_packets: participant.duplicate_packets(),
+            latency: participant.latency(),
+            latency_summary: participant.latency_summary(),
+            loss_rate: participant.loss_rate(),
+        }
+    }
+}
+
+/// How many of the most recent MIDI packets from a participant its loss rate is worked out over.
+pub const LOSS_WINDOW: usize = 100;
+
+/// The fewest packets received before a participant's loss rate is compared with
+/// [`SessionConfig::loss_alert_threshold`](super::session_config::SessionConfig::loss_alert_threshold), as a single
+/// gap in the first few packets would look like a lot.
+pub(crate) const MIN_LOSS_SAMPLES: usize = 20;
+
+/// The MIDI packets recently received from a participant, each with how many were missing just before it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct LossWindow {
+    missing_before: VecDeque<u16>,
+    missing: u32,
+}
+
+impl LossWindow {
+    pub fn heap_size(&self) -> usize {
+        self.missing_before.capacity() * size_of::<u16>()
+    }
+
+    pub fn received(&mut self, missing_before: u16) {
+        if self.missing_before.len() == LOSS_WINDOW
+            && let Some(dropped) = self.missing_before.pop_front()
+        {
+            self.missing -= dropped as u32;
+        }
+        self.missing_before.push_back(missing_before);
+        self.missing += missing_before as u32;
+    }
+
+    /// A packet counted as missing has turned up after all.
+    pub fn received_late(&mut self) {
+        if let Some(missing_before) = self.missing_before.iter_mut().rev().find(|missing_before| **missing_before > 0) {
+            *missing_before -= 1;
+            self.missing -= 1;
+        }
+        self.received(0);
+    }
+
+    pub fn samples(&self) -> usize {
+        self.missing_before.len()
+    }
+
+    /// The percentage of the packets expected over the window that never arrived.
+    pub fn rate(&self) -> f64 {
+        let expected = self.missing_before.len() as u32 + self.missing;
+        if expected == 0 {
+            return 0.0;
+        }
+        self.missing as f64 * 100.0 / expected as f64
+    }
+}
+
+/// The session-wide counters, bumped by the socket loops as they go.
+#[derive(Debug, Default)]
+pub(crate) struct SessionCounters {
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_received: AtomicU64,
+    parse_errors: AtomicU64,
+    duplicates_dropped: AtomicU64,
+    sequence_gaps: AtomicU64,
+}
+
+impl SessionCounters {
+    pub fn sent(&self, bytes: usize) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn received(&self, bytes: usize) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn parse_error(&self) {
+        self.parse_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn duplicate_dropped(&self) {
+        self.duplicates_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn sequence_gap(&self, missing: u16) {
+        self.sequence_gaps.fetch_add(missing as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, participants: Vec<ParticipantStats>) -> SessionStats {
+        SessionStats {
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            parse_errors: self.parse_errors.load(Ordering::Relaxed),
+            duplicates_dropped: self.duplicates_dropped.load(Ordering::Relaxed),
+            sequence_gaps: self.sequence_gaps.load(Ordering::Relaxed),
+            events_dropped: 0,
+            participants,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loss_rate() {
+        let mut window = LossWindow::default();
+        assert_eq!(window.rate(), 0.0);
+        window.received(0);
+        window.received(2);
+        window.received(0);
+        assert_eq!(window.rate(), 40.0);
+        window.received_late();
+        assert_eq!(window.rate(), 20.0);
+    }
+
+    #[test]
+    fn test_loss_rate_only_covers_the_window() {
+        let mut window = LossWindow::default();
+        window.received(10);
+        for _ in 0..LOSS_WINDOW {
+            window.received(0);
+        }
+        assert_eq!(window.samples(), LOSS_WINDOW);
+        assert_eq!(window.rate(), 0.0);
+    }
+
+    #[test]
+    fn test_latency_summary() {
+        let mut history = LatencyHistory::default();
+        assert_eq!(history.summary(), None);
+        for millis in [4, 2, 6] {
+            history.push(Duration::from_millis(millis));
+        }
+        let summary = history.summary().unwrap();
+        assert_eq!(summary.samples, 3);
+        assert_eq!(summary.min, Duration::from_millis(2));
+        assert_eq!(summary.avg, Duration::from_millis(4));
+        assert_eq!(summary.max, Duration::from_millis(6));
+        assert_eq!(summary.jitter, Duration::from_millis(3));
+    }
+
+    #[test]
+    fn test_latency_history_keeps_the_most_recent_samples() {
+        let mut history = LatencyHistory::default();
+        for millis in 0..LATENCY_SAMPLES as u64 + 4 {
+            history.push(Duration::from_millis(millis));
+        }
+        let summary = history.summary().unwrap();
+        assert_eq!(summary.samples, LATENCY_SAMPLES);
+        assert_eq!(summary.min, Duration::from_millis(4));
+        assert_eq!(summary.jitter, Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,226 @@
+//! Discovering the public address and port a NAT maps a session's sockets to, by asking a STUN server (RFC 5389), so
+//! remote jam setups can tell each other where they can be reached. See
+//! [`RtpMidiSession::discover_external_addresses`](super::rtp_midi_session::RtpMidiSession::discover_external_addresses).
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+
+use crate::error::RtpMidiError;
+use crate::logging::{Level, event};
+use crate::sessions::socket::Socket;
+
+const MAGIC_COOKIE: [u8; 4] = [0x21, 0x12, 0xA4, 0x42];
+const BINDING_REQUEST: [u8; 2] = [0x00, 0x01];
+const BINDING_SUCCESS: [u8; 2] = [0x01, 0x01];
+const MAPPED_ADDRESS: u16 = 0x0001;
+const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+/// How long to wait for the server before asking again. The wait doubles each time.
+pub const STUN_RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How many times the server is asked before giving up.
+pub const STUN_ATTEMPTS: u32 = 3;
+
+/// Where the other side of a session's NAT sees its sockets, according to a STUN server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExternalAddresses {
+    pub control: SocketAddr,
+    pub midi: SocketAddr,
+}
+
+impl ExternalAddresses {
+    /// Whether the NAT kept the MIDI port right after the control port. AppleMIDI peers only know the control port
+    /// and assume this, so if it doesn't hold they can't reach the MIDI port from outside.
+    pub fn is_consecutive(&self) -> bool {
+        self.midi.ip() == self.control.ip() && self.control.port().checked_add(1) == Some(self.midi.port())
+    }
+}
+
+pub(crate) type TransactionId = [u8; 12];
+
+/// A binding request with no attributes.
+pub(crate) fn binding_request(transaction_id: &TransactionId) -> [u8; 20] {
+    let mut request = [0u8; 20];
+    request[..2].copy_from_slice(&BINDING_REQUEST);
+    request[4..8].copy_from_slice(&MAGIC_COOKIE);
+    request[8..].copy_from_slice(transaction_id);
+    request
+}
+
+/// Whether the datagram is a STUN message rather than AppleMIDI or RTP, which start with their top bits set.
+pub(crate) fn is_stun(bytes: &[u8]) -> bool {
+    bytes.len() >= 20 && bytes[0] & 0xC0 == 0 && bytes[4..8] == MAGIC_COOKIE
+}
+
+/// The transaction and mapped address of a binding success response. The XOR-MAPPED-ADDRESS is preferred over the
+/// MAPPED-ADDRESS old servers send instead, as some NATs rewrite addresses they find in payloads.
+pub(crate) fn parse_binding_response(bytes: &[u8]) -> Option<(TransactionId, SocketAddr)> {
+    if !is_stun(bytes) || bytes[..2] != BINDING_SUCCESS {
+        return None;
+    }
+    let transaction_id: TransactionId = bytes[8..20].try_into().ok()?;
+    let length = u16::from_be_bytes([bytes[2], bytes[3]]) as usize;
+    let mut attributes = bytes.get(20..20 + length)?;
+
+    let mut mapped = None;
+    while attributes.len() >= 4 {
+        let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let value_length = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let value = attributes.get(4..4 + value_length)?;
+        match kind {
+            XOR_MAPPED_ADDRESS => return Some((transaction_id, xor_address(address(value)?, &transaction_id))),
+            MAPPED_ADDRESS => mapped = address(value),
+            _ => {}
+        }
+        // Values are padded to a multiple of four bytes
+        let padded = (4 + value_length).next_multiple_of(4);
+        attributes = attributes.get(padded..).unwrap_or_default();
+    }
+    Some((transaction_id, mapped?))
+}
+
+fn address(value: &[u8]) -> Option<SocketAddr> {
+    let port = u16::from_be_bytes([*value.get(2)?, *value.get(3)?]);
+    let ip = match value.get(1)? {
+        0x01 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(value.get(4..8)?).ok()?)),
+        0x02 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(value.get(4..20)?).ok()?)),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Undoes the XOR with the magic cookie, followed by the transaction ID for IPv6.
+fn xor_address(addr: SocketAddr, transaction_id: &TransactionId) -> SocketAddr {
+    let mut key = [0u8; 16];
+    key[..4].copy_from_slice(&MAGIC_COOKIE);
+    key[4..].copy_from_slice(transaction_id);
+    let port = addr.port() ^ u16::from_be_bytes([key[0], key[1]]);
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => IpAddr::V4(Ipv4Addr::from(u32::from(ip) ^ u32::from_be_bytes(MAGIC_COOKIE))),
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) ^ u128::from_be_bytes(key))),
+    };
+    SocketAddr::new(ip, port)
+}
+
+/// The binding requests waiting for an answer, which arrives on the socket the request went out on, among the
+/// session's other datagrams.
+#[derive(Default)]
+pub(crate) struct StunTransactions {
+    pending: Mutex<HashMap<TransactionId, oneshot::Sender<SocketAddr>>>,
+}
+
+impl StunTransactions {
+    fn start(&self, transaction_id: TransactionId) -> oneshot::Receiver<SocketAddr> {
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner).insert(transaction_id, sender);
+        receiver
+    }
+
+    fn finish(&self, transaction_id: &TransactionId) {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner).remove(transaction_id);
+    }
+
+    /// Hands a STUN message to the request waiting for it. Ones nothing is waiting for are dropped.
+    pub fn complete(&self, bytes: &[u8], src: SocketAddr) {
+        let Some((transaction_id, mapped)) = parse_binding_response(bytes) else {
+            event!(Level::WARN, src = %src, "Dropping STUN message that isn't a binding success response");
+            return;
+        };
+        match self.pending.lock().unwrap_or_else(PoisonError::into_inner).remove(&transaction_id) {
+            Some(sender) => {
+                let _ = sender.send(mapped);
+            }
+            None => event!(Level::DEBUG, src = %src, "Dropping STUN response nothing is waiting for"),
+        }
+    }
+}
+
+/// Asks `server` where it sees `socket`, retransmitting as the server may be far away. `transaction_id` is a random
+/// one, to tell the answer apart.
+pub(super) async fn query(
+    socket: &Socket,
+    transactions: &StunTransactions,
+    server: SocketAddr,
+    transaction_id: TransactionId,
+) -> Result<SocketAddr, RtpMidiError> {
+    let mut response = transactions.start(transaction_id);
+    let request = binding_request(&transaction_id);
+    let result = async {
+        let mut wait = STUN_RETRANSMIT_TIMEOUT;
+        for _ in 0..STUN_ATTEMPTS {
+            socket.send_in_clear_to(&request, server).await?;
+            if let Ok(mapped) = tokio::time::timeout(wait, &mut response).await {
+                return mapped.map_err(|_| RtpMidiError::InvalidState("session stopped"));
+            }
+            wait *= 2;
+        }
+        Err(RtpMidiError::Timeout("a STUN response"))
+    }
+    .await;
+    transactions.finish(&transaction_id);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The IPv4 response from RFC 5769.
+    const RESPONSE: [u8; 80] = [
+        0x01, 0x01, 0x00, 0x3C, 0x21, 0x12, 0xA4, 0x42, 0xB7, 0xE7, 0xA7, 0x01, 0xBC, 0x34, 0xD6, 0x86, 0xFA, 0x87, 0xDF, 0xAE, // header
+        0x80, 0x22, 0x00, 0x0B, b't', b'e', b's', b't', b' ', b'v', b'e', b'c', b't', b'o', b'r', b' ', // SOFTWARE
+        0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xA1, 0x47, 0xE1, 0x12, 0xA6, 0x43, // XOR-MAPPED-ADDRESS
+        0x00, 0x08, 0x00, 0x14, 0x2B, 0x91, 0xF5, 0x99, 0xFD, 0x9E, 0x90, 0xC3, 0x8C, 0x74, 0x89, 0xF9, 0x2A, 0xF9, 0xBA, 0x53, 0xF0, 0x6B, 0xE7,
+        0xD7, // MESSAGE-INTEGRITY
+        0x80, 0x28, 0x00, 0x04, 0xC0, 0x7D, 0x4C, 0x96, // FINGERPRINT
+    ];
+    const TRANSACTION_ID: TransactionId = [0xB7, 0xE7, 0xA7, 0x01, 0xBC, 0x34, 0xD6, 0x86, 0xFA, 0x87, 0xDF, 0xAE];
+
+    #[test]
+    fn test_parses_the_xor_mapped_address() {
+        assert_eq!(parse_binding_response(&RESPONSE), Some((TRANSACTION_ID, "192.0.2.1:32853".parse().unwrap())));
+    }
+
+    #[test]
+    fn test_parses_ipv6_and_old_mapped_addresses() {
+        let mut response = vec![0x01, 0x01, 0x00, 0x18, 0x21, 0x12, 0xA4, 0x42];
+        response.extend_from_slice(&TRANSACTION_ID);
+        response.extend_from_slice(&[0x00, 0x20, 0x00, 0x14, 0x00, 0x02, 0xA1, 0x47]);
+        response.extend_from_slice(&[0x01, 0x13, 0xA9, 0xFA, 0xA5, 0xD3, 0xF1, 0x79, 0xBC, 0x25, 0xF4, 0xB5, 0xBE, 0xD2, 0xB9, 0xD9]);
+        let (_, mapped) = parse_binding_response(&response).unwrap();
+        assert_eq!(mapped, "[2001:db8:1234:5678:11:2233:4455:6677]:32853".parse().unwrap());
+
+        let mut response = vec![0x01, 0x01, 0x00, 0x0C, 0x21, 0x12, 0xA4, 0x42];
+        response.extend_from_slice(&TRANSACTION_ID);
+        response.extend_from_slice(&[0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x13, 0x8C, 203, 0, 113, 7]);
+        let (_, mapped) = parse_binding_response(&response).unwrap();
+        assert_eq!(mapped, "203.0.113.7:5004".parse().unwrap());
+    }
+
+    #[test]
+    fn test_rejects_truncated_and_other_messages() {
+        assert_eq!(parse_binding_response(&RESPONSE[..40]), None);
+        assert_eq!(parse_binding_response(&binding_request(&TRANSACTION_ID)), None);
+        assert!(is_stun(&binding_request(&TRANSACTION_ID)));
+        assert!(!is_stun(&[0xFF, 0xFF, b'C', b'K', 0x21, 0x12, 0xA4, 0x42, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_is_consecutive() {
+        let control: SocketAddr = "203.0.113.7:5004".parse().unwrap();
+        let consecutive = ExternalAddresses {
+            control,
+            midi: "203.0.113.7:5005".parse().unwrap(),
+        };
+        assert!(consecutive.is_consecutive());
+        let remapped = ExternalAddresses {
+            control,
+            midi: "203.0.113.7:61234".parse().unwrap(),
+        };
+        assert!(!remapped.is_consecutive());
+    }
+}
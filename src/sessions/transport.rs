@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use crate::logging::{Level, event};
+use midi_types::MidiMessage;
+use tokio::sync::Mutex;
+
+use crate::error::RtpMidiError;
+use crate::packets::midi_packets::midi_event::MidiEvent;
+use crate::packets::midi_packets::transport::{MAX_SONG_POSITION, locate_messages};
+use crate::sessions::rtp_midi_session::RtpMidiSession;
+
+/// Runs the transport of every participant sequencing along with the session: Start, Continue and Stop, and the
+/// Song Position Pointer to move between them. The MIDI clock itself is up to the application to send.
+///
+/// Participants' own transport messages are followed by
+/// [`TransportEvent`](crate::sessions::events::event_handling::TransportEvent) listeners.
+pub struct Transport {
+    session: Arc<RtpMidiSession>,
+    playing: Mutex<bool>,
+}
+
+impl Transport {
+    /// A transport starting out stopped.
+    pub fn new(session: Arc<RtpMidiSession>) -> Self {
+        Transport {
+            session,
+            playing: Mutex::new(false),
+        }
+    }
+
+    pub async fn is_playing(&self) -> bool {
+        *self.playing.lock().await
+    }
+
+    /// Starts playback from the top of the song.
+    pub async fn play(&self) -> Result<(), RtpMidiError> {
+        self.send(|_| (true, vec![MidiMessage::Start])).await
+    }
+
+    /// Carries on playback from the current song position.
+    pub async fn resume(&self) -> Result<(), RtpMidiError> {
+        self.send(|_| (true, vec![MidiMessage::Continue])).await
+    }
+
+    pub async fn stop(&self) -> Result<(), RtpMidiError> {
+        self.send(|_| (false, vec![MidiMessage::Stop])).await
+    }
+
+    /// Moves playback to `song_position`, in sixteenth notes from the top of the song. While playing, participants
+    /// are stopped, moved and continued in one go.
+    pub async fn locate(&self, song_position: u16) -> Result<(), RtpMidiError> {
+        if song_position > MAX_SONG_POSITION {
+            return Err(RtpMidiError::InvalidArgument(format!(
+                "song position {song_position} is beyond the last one, {MAX_SONG_POSITION}"
+            )));
+        }
+        self.send(|playing| (playing, locate_messages(song_position, playing))).await
+    }
+
+    /// Sends the messages `transition` gives for the current state, and moves on to the state it gives.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn send(&self, transition: impl FnOnce(bool) -> (bool, Vec<MidiMessage>)) -> Result<(), RtpMidiError> {
+        // Held while sending, so concurrent calls reach participants in the order they change the state
+        let mut playing = self.playing.lock().await;
+        let (next, messages) = transition(*playing);
+        let events: Vec<MidiEvent> = messages
+            .into_iter()
+            .enumerate()
+            .map(|(i, message)| MidiEvent::new(if i == 0 { None } else { Some(0) }, message.into()))
+            .collect();
+        self.session.send_midi_batch(&events).await?;
+        *playing = next;
+        event!(Level::DEBUG, playing = next, "Sent transport messages");
+        Ok(())
+    }
+}
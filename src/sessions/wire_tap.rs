@@ -0,0 +1,40 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use crate::sessions::interceptor::Direction;
+
+/// A hook that sees every datagram the session receives, before it is parsed, and every one it sends, once it has
+/// been serialized, on both ports, along with the address of the peer at the other end. Datagrams that fail to
+/// parse are seen too. It is called on the receiving and sending tasks, so it should be quick and must not block.
+///
+/// ```
+/// use rtpmidi::sessions::interceptor::Direction;
+/// use rtpmidi::sessions::rtp_midi_session::RtpMidiSession;
+///
+/// fn dump(session: &RtpMidiSession) {
+///     session.set_wire_tap(|direction, addr, bytes| {
+///         let arrow = if direction == Direction::Inbound { "<-" } else { "->" };
+///         println!("{arrow} {addr} {bytes:02X?}");
+///     });
+/// }
+/// ```
+pub type WireTap = dyn Fn(Direction, &SocketAddr, &[u8]) + Send + Sync + 'static;
+
+/// Where a session's wire tap is kept, shared by its sockets.
+#[derive(Default)]
+pub(crate) struct WireTapSlot {
+    tap: RwLock<Option<Arc<WireTap>>>,
+}
+
+impl WireTapSlot {
+    pub fn set(&self, tap: Option<Arc<WireTap>>) {
+        *self.tap.write().unwrap_or_else(PoisonError::into_inner) = tap;
+    }
+
+    pub fn tap(&self, direction: Direction, addr: SocketAddr, bytes: &[u8]) {
+        let tap = self.tap.read().unwrap_or_else(PoisonError::into_inner).clone();
+        if let Some(tap) = tap {
+            tap(direction, &addr, bytes);
+        }
+    }
+}
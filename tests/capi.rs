@@ -0,0 +1,115 @@
+mod common;
+
+use common::find_consecutive_ports;
+use rtpmidi::capi::*;
+use std::ffi::{CString, c_void};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+unsafe extern "C" fn collect(user_data: *mut c_void, data: *const u8, len: usize) {
+    let received = unsafe { &*(user_data as *const Mutex<Vec<Vec<u8>>>) };
+    received.lock().unwrap().push(unsafe { std::slice::from_raw_parts(data, len) }.to_vec());
+}
+
+#[test]
+fn test_c_api_round_trip() {
+    let (control_port_1, _) = find_consecutive_ports();
+    let (control_port_2, _) = find_consecutive_ports();
+    let name = CString::new("C session").unwrap();
+    let received = Mutex::new(Vec::<Vec<u8>>::new());
+
+    unsafe {
+        let session1 = rtpmidi_session_create(control_port_1, name.as_ptr(), 0x11111111);
+        let session2 = rtpmidi_session_create(control_port_2, name.as_ptr(), 0x22222222);
+        assert!(!session1.is_null() && !session2.is_null());
+        let user_data = &received as *const _ as *mut c_void;
+        assert_eq!(rtpmidi_session_set_callback(session2, Some(collect), user_data), RTPMIDI_OK);
+
+        let addr = CString::new(format!("127.0.0.1:{control_port_2}")).unwrap();
+        assert_eq!(rtpmidi_session_invite(session1, addr.as_ptr()), RTPMIDI_OK);
+
+        // A note on and off in running status, with a real-time message between them that leaves it in effect, then a
+        // SysEx message. Keep sending until the invitation has been accepted and the messages get through.
+        let bytes = [0x90, 60, 100, 0xF8, 60, 0, 0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while received.lock().unwrap().is_empty() && Instant::now() < deadline {
+            assert_eq!(rtpmidi_session_send(session1, bytes.as_ptr(), bytes.len()), RTPMIDI_OK);
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        assert_eq!(rtpmidi_session_set_callback(session2, None, std::ptr::null_mut()), RTPMIDI_OK);
+        rtpmidi_session_destroy(session1);
+        rtpmidi_session_destroy(session2);
+    }
+
+    let received = received.into_inner().unwrap();
+    assert_eq!(
+        received[..4],
+        [vec![0x90, 60, 100], vec![0xF8], vec![0x90, 60, 0], vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]]
+    );
+}
+
+unsafe extern "C" fn collect_slowly(user_data: *mut c_void, _data: *const u8, _len: usize) {
+    let in_call = unsafe { &*(user_data as *const AtomicBool) };
+    in_call.store(true, Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(200));
+    in_call.store(false, Ordering::SeqCst);
+}
+
+#[test]
+fn test_clearing_the_callback_waits_for_calls_under_way() {
+    let (control_port_1, _) = find_consecutive_ports();
+    let (control_port_2, _) = find_consecutive_ports();
+    let name = CString::new("C session").unwrap();
+    let in_call = AtomicBool::new(false);
+
+    unsafe {
+        let session1 = rtpmidi_session_create(control_port_1, name.as_ptr(), 0x11111111);
+        let session2 = rtpmidi_session_create(control_port_2, name.as_ptr(), 0x22222222);
+        assert!(!session1.is_null() && !session2.is_null());
+        let user_data = &in_call as *const _ as *mut c_void;
+        assert_eq!(rtpmidi_session_set_callback(session2, Some(collect_slowly), user_data), RTPMIDI_OK);
+
+        let addr = CString::new(format!("127.0.0.1:{control_port_2}")).unwrap();
+        assert_eq!(rtpmidi_session_invite(session1, addr.as_ptr()), RTPMIDI_OK);
+        let bytes = [0x90, 60, 100];
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !in_call.load(Ordering::SeqCst) && Instant::now() < deadline {
+            assert_eq!(rtpmidi_session_send(session1, bytes.as_ptr(), bytes.len()), RTPMIDI_OK);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(in_call.load(Ordering::SeqCst));
+
+        assert_eq!(rtpmidi_session_set_callback(session2, None, std::ptr::null_mut()), RTPMIDI_OK);
+        assert!(!in_call.load(Ordering::SeqCst));
+        rtpmidi_session_destroy(session1);
+        rtpmidi_session_destroy(session2);
+    }
+}
+
+#[test]
+fn test_c_api_rejects_bad_arguments() {
+    let (control_port, _) = find_consecutive_ports();
+    let name = CString::new("C session").unwrap();
+
+    unsafe {
+        assert!(rtpmidi_session_create(control_port, std::ptr::null(), 1).is_null());
+        let session = rtpmidi_session_create(control_port, name.as_ptr(), 1);
+        assert!(!session.is_null());
+
+        let truncated = [0x90, 60];
+        assert_eq!(rtpmidi_session_send(session, truncated.as_ptr(), truncated.len()), RTPMIDI_ERR_INVALID_ARGUMENT);
+        let without_status = [60, 100];
+        assert_eq!(
+            rtpmidi_session_send(session, without_status.as_ptr(), without_status.len()),
+            RTPMIDI_ERR_INVALID_ARGUMENT
+        );
+        let not_an_addr = CString::new("somewhere").unwrap();
+        assert_eq!(rtpmidi_session_invite(session, not_an_addr.as_ptr()), RTPMIDI_ERR_INVALID_ARGUMENT);
+        assert_eq!(rtpmidi_session_send(std::ptr::null_mut(), truncated.as_ptr(), 0), RTPMIDI_ERR_INVALID_ARGUMENT);
+
+        rtpmidi_session_destroy(session);
+        rtpmidi_session_destroy(std::ptr::null_mut());
+    }
+}
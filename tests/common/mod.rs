@@ -1,5 +1,19 @@
-use std::net::UdpSocket;
+// Each test crate uses only some of these
+#![allow(dead_code)]
 
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rtpmidi::sessions::invite_responder::InviteResponder;
+use rtpmidi::sessions::rtp_midi_session::RtpMidiSession;
+use rtpmidi::sessions::session_config::SessionConfig;
+
+/// How long [`connect`] waits for the invited session to finish joining.
+pub const JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Two consecutive ports that were free a moment ago, for tests that need to know them before the session binds them.
+/// Anything else binding port 0 meanwhile can take them, so peers played by a test use [`peer_sockets`].
 pub fn find_consecutive_ports() -> (u16, u16) {
     loop {
         let socket = UdpSocket::bind(("0.0.0.0", 0)).unwrap();
@@ -12,3 +26,54 @@ pub fn find_consecutive_ports() -> (u16, u16) {
         }
     }
 }
+
+/// A control and a MIDI socket on consecutive loopback ports, for a test to play a peer with. They are bound straight
+/// away, so a session started meanwhile can't take the ports.
+pub async fn peer_sockets() -> (tokio::net::UdpSocket, tokio::net::UdpSocket) {
+    loop {
+        let control = tokio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let port = control.local_addr().unwrap().port();
+        let Some(next_port) = port.checked_add(1) else {
+            continue;
+        };
+        if let Ok(midi) = tokio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, next_port)).await {
+            return (control, midi);
+        }
+    }
+}
+
+/// Where `session`'s control port is reached over loopback.
+pub fn control_addr(session: &RtpMidiSession) -> SocketAddr {
+    SocketAddr::new(Ipv4Addr::LOCALHOST.into(), session.local_control_addr().port())
+}
+
+/// Where `session`'s MIDI port is reached over loopback.
+pub fn midi_addr(session: &RtpMidiSession) -> SocketAddr {
+    SocketAddr::new(Ipv4Addr::LOCALHOST.into(), session.local_midi_addr().port())
+}
+
+/// Starts a session that accepts invitations, on ports the system picks.
+pub async fn start_session(name: &str, ssrc: u32, config: SessionConfig) -> Arc<RtpMidiSession> {
+    RtpMidiSession::start_with_config(0, name, ssrc, InviteResponder::Accept, config)
+        .await
+        .expect("Failed to start RTP MIDI session")
+}
+
+/// Has `inviter` invite `invitee`, and waits for it to finish joining.
+pub async fn connect(inviter: &RtpMidiSession, invitee: &RtpMidiSession) {
+    let addr = control_addr(invitee);
+    inviter.invite_participant(addr).await.unwrap();
+    inviter
+        .wait_for_participant(addr, JOIN_TIMEOUT)
+        .await
+        .expect("The invited session never joined");
+}
+
+/// "Session1" (SSRC 0x11111111) and "Session2" (0x22222222), started with the configs given, the first having invited
+/// the second.
+pub async fn connected_sessions(config1: SessionConfig, config2: SessionConfig) -> (Arc<RtpMidiSession>, Arc<RtpMidiSession>) {
+    let session1 = start_session("Session1", 0x11111111, config1).await;
+    let session2 = start_session("Session2", 0x22222222, config2).await;
+    connect(&session1, &session2).await;
+    (session1, session2)
+}
@@ -0,0 +1,135 @@
+//! Mutation fuzzing of the public parse entry points, which must never panic on what arrives off the wire. Behind the
+//! `fuzz` feature as a full run takes a while:
+//!
+//! ```sh
+//! cargo test --release --features fuzz --test fuzz
+//! ```
+//!
+//! `RTPMIDI_FUZZ_ITERATIONS` sets how many inputs are tried, and `RTPMIDI_FUZZ_SEED` repeats an earlier run. An input
+//! that panics is printed in hex before the panic is passed on, to be pasted into a regression test.
+#![cfg(feature = "fuzz")]
+
+use std::panic::{self, AssertUnwindSafe};
+
+use midi_types::{Channel, MidiMessage, Note, Value7};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rtpmidi::packets::control_packets::control_packet::ControlPacket;
+use rtpmidi::packets::midi_packets::midi_event::MidiEvent;
+use rtpmidi::packets::midi_packets::midi_packet::MidiPacket;
+use rtpmidi::packets::midi_packets::midi_stream::MidiStream;
+use rtpmidi::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use rtpmidi::packets::midi_packets::sysex::SysExMessage;
+use rtpmidi::packets::packet::RtpMidiPacket;
+use rtpmidi::packets::parse_mode::ParseMode;
+use zerocopy::network_endian::{U16, U32, U64};
+
+const DEFAULT_ITERATIONS: u64 = 200_000;
+
+/// Valid packets of each kind, for mutations to start from.
+fn seeds() -> Vec<Vec<u8>> {
+    let name = c"Fuzz";
+    let mut seeds = vec![
+        ControlPacket::new_invitation_as_bytes(U32::new(1), U32::new(2), name).to_vec(),
+        ControlPacket::new_acceptance_as_bytes(U32::new(1), U32::new(2), name).to_vec(),
+        ControlPacket::new_rejection_as_bytes(U32::new(1), U32::new(2)).to_vec(),
+        ControlPacket::new_termination_as_bytes(U32::new(1), U32::new(2)).to_vec(),
+        ControlPacket::new_clock_sync_as_bytes(1, [U64::new(1), U64::new(2), U64::new(3)], U32::new(2)).to_vec(),
+        ControlPacket::new_bitrate_limit_as_bytes(U32::new(64000), U32::new(2)).to_vec(),
+        ControlPacket::new_retransmission_request_as_bytes(U16::new(7), U16::new(2), U32::new(2)).to_vec(),
+        vec![0xFF, 0xFF, b'R', b'S', 0x00, 0x00, 0x00, 0x02, 0x00, 0x10, 0x00, 0x00],
+        vec![], // anything goes from nothing
+    ];
+
+    let note_on = RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::new(100)));
+    let control_change = RtpMidiMessage::MidiMessage(MidiMessage::ControlChange(Channel::C2, 7.into(), Value7::new(64)));
+    let sysex = [0x7E, 0x7F, 0x06, 0x01];
+    let commands = [
+        MidiEvent::new(None, note_on),
+        MidiEvent::new(Some(10), control_change),
+        MidiEvent::new(Some(300), RtpMidiMessage::SysEx(&sysex)),
+    ];
+    for count in 1..=commands.len() {
+        for z_flag in [false, true] {
+            seeds.push(MidiPacket::new_as_bytes(U16::new(7), U32::new(1000), U32::new(2), &commands[..count], z_flag).to_vec());
+        }
+    }
+    // Running status, a journal flag and a long command list header
+    seeds.push(vec![
+        0x80, 0x61, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x22, 0x22, 0x22, 0x22, 0xC0, 0x06, 0x90, 0x3C, 0x64, 0x00, 0x3E, 0x64,
+    ]);
+    // Padding, a CSRC and a header extension
+    seeds.push(vec![
+        0xB1, 0x61, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x22, 0x22, 0x22, 0x22, 0x33, 0x33, 0x33, 0x33, 0xBE, 0xDE, 0x00, 0x01, 0x01, 0x02, 0x03, 0x04, 0x03,
+        0x90, 0x3C, 0x64, 0x00, 0x00, 0x03,
+    ]);
+    seeds
+}
+
+fn mutate(rng: &mut StdRng, input: &mut Vec<u8>) {
+    const INTERESTING: [u8; 8] = [0x00, 0x01, 0x7F, 0x80, 0xF0, 0xF7, 0xFE, 0xFF];
+    for _ in 0..rng.random_range(1..=8) {
+        let len = input.len();
+        match rng.random_range(0..7) {
+            0 if len > 0 => input[rng.random_range(0..len)] ^= 1 << rng.random_range(0..8),
+            1 if len > 0 => input[rng.random_range(0..len)] = rng.random(),
+            2 if len > 0 => input[rng.random_range(0..len)] = INTERESTING[rng.random_range(0..INTERESTING.len())],
+            3 => input.insert(rng.random_range(0..=len), rng.random()),
+            4 if len > 0 => {
+                input.remove(rng.random_range(0..len));
+            }
+            5 => input.truncate(rng.random_range(0..=len)),
+            6 if len > 0 => {
+                let start = rng.random_range(0..len);
+                let end = rng.random_range(start..=len);
+                let chunk = input[start..end].to_vec();
+                let at = rng.random_range(0..=len);
+                input.splice(at..at, chunk);
+            }
+            _ => input.push(rng.random()),
+        }
+    }
+}
+
+/// Everything a caller can do with what comes off the wire: parse it either way, then look at what was parsed.
+fn exercise(input: &[u8]) {
+    for mode in [ParseMode::Strict, ParseMode::Lenient] {
+        if let Ok(packet) = RtpMidiPacket::parse(input, mode) {
+            let _ = format!("{packet:?}");
+            let _ = packet.describe();
+            if let RtpMidiPacket::Midi(packet) = packet {
+                let _ = packet.csrcs();
+                for event in packet.commands().chain(packet.commands_with_mode(mode)) {
+                    let _ = format!("{:?} {}", event.command(), event.delta_time());
+                }
+            }
+        }
+        if let Ok(packet) = ControlPacket::try_from_bytes(input, mode) {
+            let _ = format!("{packet:?}");
+            let _ = packet.describe();
+        }
+    }
+    let _ = RtpMidiMessage::try_from(input);
+    let _ = SysExMessage::parse(input).to_sysex();
+    for message in MidiStream::new(input) {
+        let _ = format!("{message:?}");
+    }
+}
+
+#[test]
+fn fuzz_parse_entry_points_never_panic() {
+    let iterations = std::env::var("RTPMIDI_FUZZ_ITERATIONS").map_or(DEFAULT_ITERATIONS, |value| value.parse().unwrap());
+    let seed = std::env::var("RTPMIDI_FUZZ_SEED").map_or_else(|_| rand::random(), |value| value.parse().unwrap());
+    println!("RTPMIDI_FUZZ_SEED={seed}");
+    let mut rng = StdRng::seed_from_u64(seed);
+    let seeds = seeds();
+
+    for _ in 0..iterations {
+        let mut input = seeds[rng.random_range(0..seeds.len())].clone();
+        mutate(&mut rng, &mut input);
+        if let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| exercise(&input))) {
+            println!("Input that panicked: {input:02X?}");
+            panic::resume_unwind(panic);
+        }
+    }
+}
@@ -1,30 +1,44 @@
 mod common;
 
-use common::find_consecutive_ports;
+use common::{connect, connected_sessions, control_addr, midi_addr, peer_sockets, start_session};
 use core::panic;
-use midi_types::{Channel, MidiMessage, Note, Value7};
-use rtpmidi::sessions::events::event_handling::{MidiMessageEvent, ParticipantJoinedEvent};
+use midi_types::{Channel, Control, MidiMessage, Note, Program, Value7, Value14};
+use rtpmidi::error::RtpMidiError;
+use rtpmidi::packets::midi_packets::midi_event::MidiEvent;
+use rtpmidi::packets::midi_packets::rtp_midi_message::RtpMidiMessage;
+use rtpmidi::sessions::authenticator::Authenticator;
+use rtpmidi::sessions::channel_map::{ChannelMap, ChannelRouting};
+use rtpmidi::sessions::client::{ClientOptions, RtpMidiClient};
+use rtpmidi::sessions::encryption::Cipher;
+use rtpmidi::sessions::events::event_handling::{
+    AddressChangedEvent, AuthenticationFailedEvent, ClockSyncRoundEvent, FloodReason, InvitationFloodEvent, MidiMessageEvent, MidiPacketEvent,
+    PacketLossThresholdEvent, ParticipantJoinedEvent, ParticipantLimitReachedEvent, ParticipantRenamed, ParticipantRenamedEvent, ProtocolVersionMismatchEvent,
+    RecoveredMidiEvent, SysExPacketEvent, TempoChange, TempoChangedEvent, TransportEvent,
+};
+use rtpmidi::sessions::interceptor::{Action, Direction};
 use rtpmidi::sessions::invite_responder::InviteResponder;
-use rtpmidi::sessions::rtp_midi_session::RtpMidiSession;
+use rtpmidi::sessions::known_peer::KnownPeer;
+use rtpmidi::sessions::loss_concealment::ReleaseNotes;
+use rtpmidi::sessions::rtp_midi_session::{INVITATION_TIMEOUT, ParticipantMatch, RtpMidiSession};
+use rtpmidi::sessions::session_config::{FloodProtection, Retransmission, SessionConfig};
+use rtpmidi::sessions::session_guard::SessionGuard;
+use rtpmidi::sessions::session_manager::SessionManager;
+use rtpmidi::sessions::transport::Transport;
+use std::cell::RefCell;
 use std::net::SocketAddr;
+use std::rc::Rc;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use tokio::sync::Notify;
 
 #[tokio::test]
 async fn test_two_session_inter_communication() {
-    let (control_port_1, _midi_port_1) = find_consecutive_ports();
-    let (control_port_2, _midi_port_2) = find_consecutive_ports();
-
     let ssrc1 = 0x11111111;
     let ssrc2 = 0x22222222;
-    let session1 = RtpMidiSession::start(control_port_1, "Session1", ssrc1, InviteResponder::Accept)
-        .await
-        .expect("Failed to start RTP MIDI session");
-    let session2 = RtpMidiSession::start(control_port_2, "Session2", ssrc2, InviteResponder::Accept)
-        .await
-        .expect("Failed to start RTP MIDI session");
+    let session1 = start_session("Session1", ssrc1, SessionConfig::default()).await;
+    let session2 = start_session("Session2", ssrc2, SessionConfig::default()).await;
 
-    let sessions_connected = Arc::new(Notify::new());
     let (session1_message_sender, mut session1_message_receiver) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
     let (session2_message_sender, mut session2_message_receiver) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
 
@@ -34,13 +48,6 @@ async fn test_two_session_inter_communication() {
         })
         .await;
 
-    let sessions_connected_clone = sessions_connected.clone();
-    session1
-        .add_listener(ParticipantJoinedEvent, move |_participant| {
-            sessions_connected_clone.notify_one();
-        })
-        .await;
-
     session2
         .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
             session2_message_sender.send(message).unwrap();
@@ -48,12 +55,9 @@ async fn test_two_session_inter_communication() {
         .await;
 
     // Invite each other
-    let addr1 = SocketAddr::new("127.0.0.1".parse().unwrap(), control_port_1);
-    let addr2 = SocketAddr::new("127.0.0.1".parse().unwrap(), control_port_2);
-    session1.invite_participant(addr2).await;
-
-    // wait for the sessions to finish connecting
-    sessions_connected.notified().await;
+    let addr1 = control_addr(&session1);
+    let addr2 = control_addr(&session2);
+    connect(&session1, &session2).await;
 
     let session1_participants = session1.participants().await;
     let session2_participants = session2.participants().await;
@@ -98,3 +102,2393 @@ async fn test_two_session_inter_communication() {
         _ => panic!("Expected a NoteOff message"),
     }
 }
+
+#[tokio::test]
+async fn test_segmented_sysex_round_trip() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let (sysex_sender, mut sysex_receiver) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+    session2
+        .add_listener(SysExPacketEvent, move |data| {
+            sysex_sender.send(data.to_vec()).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+
+    let payload: Vec<u8> = (0..3000).map(|i| (i % 0x80) as u8).collect();
+    session1.send_midi(&RtpMidiMessage::SysEx(&payload)).await.unwrap();
+
+    let received = sysex_receiver.recv().await.expect("Expected a SysEx message");
+    assert_eq!(received, payload);
+}
+
+#[tokio::test]
+async fn test_batch_too_long_for_one_command_list_is_split() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            message_sender.send(message).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+
+    // Three bytes each under running status, for a command list of about 6000 bytes
+    let notes: Vec<MidiMessage> = (0..2000)
+        .map(|i| MidiMessage::NoteOn(Channel::C1, Note::from((i % 128) as u8), Value7::from(100)))
+        .collect();
+    let batch: Vec<MidiEvent> = notes.iter().map(|&note| MidiEvent::new(Some(1), note.into())).collect();
+    // An empty batch still makes a packet, with an empty command list
+    session1.send_midi_batch(&[]).await.unwrap();
+    session1.send_midi_batch(&batch).await.unwrap();
+
+    for expected in &notes {
+        let received = message_receiver.recv().await.expect("Expected a MIDI message");
+        assert_eq!(&received, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_sent_packets_stay_within_max_sent_midi_packet_size() {
+    let config1 = SessionConfig {
+        max_sent_midi_packet_size: 600,
+        ..Default::default()
+    };
+    // Anything bigger would be dropped on the way in
+    let config2 = SessionConfig {
+        max_midi_packet_size: 600,
+        ..Default::default()
+    };
+    let session1 = start_session("Session1", 0x11111111, config1).await;
+    let session2 = start_session("Session2", 0x22222222, config2).await;
+
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            message_sender.send(message).unwrap();
+        })
+        .await;
+    let (sysex_sender, mut sysex_receiver) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+    session2
+        .add_listener(SysExPacketEvent, move |data| {
+            sysex_sender.send(data.to_vec()).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+
+    let (size_sender, mut size_receiver) = tokio::sync::mpsc::unbounded_channel::<usize>();
+    let session2_midi = midi_addr(&session2);
+    session1.set_wire_tap(move |direction, addr, bytes| {
+        if direction == Direction::Outbound && *addr == session2_midi {
+            size_sender.send(bytes.len()).unwrap();
+        }
+    });
+
+    let notes: Vec<MidiMessage> = (0..1000)
+        .map(|i| MidiMessage::NoteOn(Channel::C1, Note::from((i % 128) as u8), Value7::from(100)))
+        .collect();
+    let payload: Vec<u8> = (0..3000).map(|i| (i % 0x80) as u8).collect();
+    let mut batch: Vec<MidiEvent> = notes.iter().map(|&note| MidiEvent::new(Some(1), note.into())).collect();
+    batch.push(MidiEvent::new(Some(1), RtpMidiMessage::SysEx(&payload)));
+    session1.send_midi_batch(&batch).await.unwrap();
+
+    for expected in &notes {
+        let received = message_receiver.recv().await.expect("Expected a MIDI message");
+        assert_eq!(&received, expected);
+    }
+    assert_eq!(sysex_receiver.recv().await.expect("Expected a SysEx message"), payload);
+
+    session1.clear_wire_tap();
+    let mut sizes = Vec::new();
+    while let Ok(size) = size_receiver.try_recv() {
+        sizes.push(size);
+    }
+    assert!(sizes.len() > 2, "Expected the batch to take several packets, got {sizes:?}");
+    assert!(sizes.iter().all(|&size| size <= 600), "Expected every packet within 600 bytes, got {sizes:?}");
+}
+
+#[tokio::test]
+async fn test_batches_split_across_packets_keep_their_timing() {
+    for send_first_delta_time in [false, true] {
+        let config1 = SessionConfig {
+            max_sent_midi_packet_size: 100,
+            send_first_delta_time,
+            ..Default::default()
+        };
+        let (session1, session2) = connected_sessions(config1, SessionConfig::default()).await;
+        let (timestamp_sender, mut timestamp_receiver) = tokio::sync::mpsc::unbounded_channel::<u32>();
+        session2
+            .add_listener(MidiMessageEvent, move |(_message, timestamp)| {
+                timestamp_sender.send(timestamp).unwrap();
+            })
+            .await;
+
+        let deltas: Vec<u32> = (0..100).map(|i| i % 7 * 10 + 1).collect();
+        let note_on = MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100));
+        let batch: Vec<MidiEvent> = deltas.iter().map(|&delta| MidiEvent::new(Some(delta), note_on.into())).collect();
+        session1.send_midi_batch(&batch).await.unwrap();
+
+        let mut timestamps = Vec::new();
+        for _ in &deltas {
+            let timestamp = tokio::time::timeout(Duration::from_secs(2), timestamp_receiver.recv()).await.unwrap().unwrap();
+            timestamps.push(timestamp);
+        }
+        // Each command comes its own delta time after the one before, whichever packet it was sent in
+        for (i, pair) in timestamps.windows(2).enumerate() {
+            assert_eq!(
+                pair[1].wrapping_sub(pair[0]),
+                deltas[i + 1],
+                "command {} (first delta time sent: {send_first_delta_time})",
+                i + 1
+            );
+        }
+
+        session1.stop_gracefully().await;
+        session2.stop_gracefully().await;
+    }
+}
+
+#[tokio::test]
+async fn test_first_delta_time_is_sent_when_asked_for() {
+    let config = SessionConfig {
+        send_first_delta_time: true,
+        ..Default::default()
+    };
+    let session1 = start_session("Session1", 0x11111111, config).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let (delta_time_sender, mut delta_time_receiver) = tokio::sync::mpsc::unbounded_channel::<Vec<u32>>();
+    session2
+        .add_listener(MidiPacketEvent, move |packet| {
+            delta_time_sender.send(packet.commands().map(|command| command.delta_time()).collect()).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+    let batch = [MidiEvent::new(Some(5), note_on.into()), MidiEvent::new(Some(2), note_on.into())];
+    session1.send_midi_batch(&batch).await.unwrap();
+
+    let received = delta_time_receiver.recv().await.expect("Expected a MIDI packet");
+    assert_eq!(received, [5, 2]);
+}
+
+#[tokio::test]
+async fn test_local_listeners_can_hold_rc_state() {
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async move {
+            let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+            let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+            let joined = Rc::new(RefCell::new(Vec::new()));
+            let joined_clone = joined.clone();
+            let sessions_connected = Rc::new(Notify::new());
+            let sessions_connected_clone = sessions_connected.clone();
+            session1.on_local(ParticipantJoinedEvent, move |participant| {
+                joined_clone.borrow_mut().push(participant.name().to_owned());
+                sessions_connected_clone.notify_one();
+            });
+
+            let received = Rc::new(RefCell::new(Vec::new()));
+            let received_clone = received.clone();
+            let message_received = Rc::new(Notify::new());
+            let message_received_clone = message_received.clone();
+            session2.on_local(MidiMessageEvent, move |(message, _delta_time)| {
+                received_clone.borrow_mut().push(message);
+                message_received_clone.notify_one();
+            });
+
+            session1.invite_participant(control_addr(&session2)).await.unwrap();
+            sessions_connected.notified().await;
+            assert_eq!(*joined.borrow(), ["Session2"]);
+
+            let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+            session1.send_midi(&note_on.into()).await.unwrap();
+            message_received.notified().await;
+            assert_eq!(*received.borrow(), [note_on]);
+        })
+        .await;
+}
+
+#[cfg(all(feature = "recvmmsg", target_os = "linux"))]
+#[tokio::test]
+async fn test_bursts_are_received_in_batches() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let config = SessionConfig {
+        receive_batch: 4,
+        ..Default::default()
+    };
+    let session2 = start_session("Session2", 0x22222222, config).await;
+
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            message_sender.send(message).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+
+    // More packets than a batch takes, sent faster than they're handled
+    let notes: Vec<_> = (0..20)
+        .map(|key| MidiMessage::NoteOn(Channel::C1, Note::from(key), Value7::from(100)))
+        .collect();
+    for note in &notes {
+        session1.send_midi(&(*note).into()).await.unwrap();
+    }
+    for note in &notes {
+        let received = tokio::time::timeout(Duration::from_secs(1), message_receiver.recv()).await;
+        assert_eq!(received.expect("Expected a MIDI message"), Some(*note));
+    }
+}
+
+#[tokio::test]
+async fn test_memory_usage_follows_what_the_session_keeps() {
+    let config = SessionConfig {
+        retransmission: Some(Retransmission {
+            history: 4,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let session1 = start_session("Session1", 0x11111111, config).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let idle = session1.memory_usage().await;
+    assert_eq!(idle.retransmission_history, 0);
+    assert_eq!(idle.known_peers, 0);
+    connect(&session1, &session2).await;
+    let joined = session1.memory_usage().await;
+    assert!(joined.participants > idle.participants);
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+    session1.send_midi(&note_on.into()).await.unwrap();
+    let sent_one = session1.memory_usage().await.retransmission_history;
+    assert!(sent_one > 0);
+    // The history is bounded, so it stops growing once it's full
+    for _ in 0..3 {
+        session1.send_midi(&note_on.into()).await.unwrap();
+    }
+    let full = session1.memory_usage().await;
+    for _ in 0..10 {
+        session1.send_midi(&note_on.into()).await.unwrap();
+    }
+    let usage = session1.memory_usage().await;
+    assert!(full.retransmission_history > sent_one);
+    assert_eq!(usage.retransmission_history, full.retransmission_history);
+
+    // Controllers, transport and tempo are followed for each participant that sends them
+    let control_change = MidiMessage::ControlChange(Channel::C1, Control::new(7), Value7::from(100));
+    session2.send_midi(&control_change.into()).await.unwrap();
+    session2.send_midi(&MidiMessage::TimingClock.into()).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while session1.memory_usage().await.followed <= idle.followed {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("What session2 sends was never followed");
+
+    let usage = session1.memory_usage().await;
+    assert_eq!(
+        usage.total(),
+        usage.participants
+            + usage.pending_invitations
+            + usage.retransmission_history
+            + usage.event_queue
+            + usage.reassembly
+            + usage.followed
+            + usage.known_peers
+    );
+}
+
+#[tokio::test]
+async fn test_sessions_run_on_the_configured_runtime() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("dedicated")
+        .enable_all()
+        .build()
+        .unwrap();
+    let config = SessionConfig {
+        runtime: Some(runtime.handle().clone()),
+        ..Default::default()
+    };
+    let session1 = start_session("Session1", 0x11111111, config).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let (thread_sender, mut thread_receiver) = tokio::sync::mpsc::unbounded_channel::<Option<String>>();
+    session1
+        .add_listener(MidiMessageEvent, move |_message| {
+            thread_sender.send(std::thread::current().name().map(str::to_owned)).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+    session2.send_midi(&note_on.into()).await.unwrap();
+    let thread = thread_receiver.recv().await.expect("Expected a MIDI message");
+    assert_eq!(thread.as_deref(), Some("dedicated"));
+
+    session1.stop_gracefully().await;
+    runtime.shutdown_background();
+}
+
+#[tokio::test]
+async fn test_session_manager_runs_on_the_given_runtime() {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("dedicated")
+        .enable_all()
+        .build()
+        .unwrap();
+    let manager = SessionManager::with_runtime(runtime.handle().clone())
+        .await
+        .expect("Failed to start session manager");
+    let session_a = manager
+        .start_session(0, "A", 0x11111111, InviteResponder::Accept, SessionConfig::default())
+        .await
+        .expect("Failed to start RTP MIDI session");
+    let session_b = start_session("B", 0x22222222, SessionConfig::default()).await;
+
+    let (thread_sender, mut thread_receiver) = tokio::sync::mpsc::unbounded_channel::<Option<String>>();
+    session_a
+        .add_listener(MidiMessageEvent, move |_message| {
+            thread_sender.send(std::thread::current().name().map(str::to_owned)).unwrap();
+        })
+        .await;
+
+    connect(&session_a, &session_b).await;
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::from(100));
+    session_b.send_midi(&note_on.into()).await.unwrap();
+    let thread = thread_receiver.recv().await.expect("Expected a MIDI message");
+    assert_eq!(thread.as_deref(), Some("dedicated"));
+
+    manager.stop_gracefully().await;
+    session_b.stop_gracefully().await;
+    runtime.shutdown_background();
+}
+
+#[tokio::test]
+async fn test_sessions_run_with_small_receive_buffers() {
+    let config = || SessionConfig {
+        max_control_packet_size: 2048,
+        max_midi_packet_size: 2048,
+        event_queue_capacity: 16,
+        ..Default::default()
+    };
+    let session1 = start_session("Session1", 0x11111111, config()).await;
+    let session2 = start_session("Session2", 0x22222222, config()).await;
+
+    let (sysex_sender, mut sysex_receiver) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+    session2
+        .add_listener(SysExPacketEvent, move |data| {
+            sysex_sender.send(data.to_vec()).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+
+    // Segmented on the way, so no packet is bigger than the buffers
+    let payload: Vec<u8> = (0..3000).map(|i| (i % 0x80) as u8).collect();
+    session1.send_midi(&RtpMidiMessage::SysEx(&payload)).await.unwrap();
+
+    let received = sysex_receiver.recv().await.expect("Expected a SysEx message");
+    assert_eq!(received, payload);
+}
+
+#[tokio::test]
+async fn test_invitation_tokens_come_from_the_configured_random_source() {
+    let config = SessionConfig {
+        random: Some(Arc::new(|bytes: &mut [u8]| bytes.fill(0xAB))),
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    session.invite_participant(peer.local_addr().unwrap()).await.unwrap();
+
+    let mut buf = [0u8; 64];
+    let (amt, _) = peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'I', b'N']);
+    assert!(amt > 12);
+    assert_eq!(&buf[8..12], &[0xAB; 4]);
+}
+
+#[tokio::test]
+async fn test_protocol_version_mismatch_is_rejected() {
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+
+    let (mismatch_sender, mut mismatch_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(ProtocolVersionMismatchEvent, move |mismatch| {
+            mismatch_sender.send(mismatch.clone()).unwrap();
+        })
+        .await;
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x03, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    peer.send_to(&invitation, control_addr(&session)).await.unwrap();
+
+    let mut buf = [0u8; 64];
+    let (amt, _) = peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'N', b'O']);
+    assert_eq!(amt, 16);
+
+    let mismatch = mismatch_receiver.recv().await.unwrap();
+    assert_eq!(mismatch.version, 3);
+    assert_eq!(mismatch.ssrc, 0x22222222);
+    assert_eq!(mismatch.addr, peer.local_addr().unwrap());
+    assert!(session.participants().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_invitations_beyond_max_participants_are_rejected() {
+    let config = SessionConfig {
+        max_participants: Some(1),
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+
+    let (rejection_sender, mut rejection_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(ParticipantLimitReachedEvent, move |rejection| {
+            rejection_sender.send(rejection.clone()).unwrap();
+        })
+        .await;
+
+    let invite = async |ssrc: u8| {
+        let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let invitation = [
+            0xFF, 0xFF, b'I', b'N', // header
+            0x00, 0x00, 0x00, 0x02, // version
+            0x00, 0x00, 0x00, 0x01, // initiator token
+            ssrc, ssrc, ssrc, ssrc, // sender ssrc
+            b'P', b'e', b'e', b'r', 0x00, // name
+        ];
+        peer.send_to(&invitation, control_addr(&session)).await.unwrap();
+        let mut buf = [0u8; 64];
+        let _ = peer.recv_from(&mut buf).await.unwrap();
+        (peer, [buf[2], buf[3]])
+    };
+
+    let (_first, answer) = invite(0x22).await;
+    assert_eq!(answer, [b'O', b'K']);
+    let (second, answer) = invite(0x33).await;
+    assert_eq!(answer, [b'N', b'O']);
+
+    let rejection = tokio::time::timeout(Duration::from_secs(1), rejection_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!(rejection.ssrc, 0x33333333);
+    assert_eq!(rejection.name, "Peer");
+    assert_eq!(rejection.addr, second.local_addr().unwrap());
+    assert_eq!(rejection.max_participants, 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_unfinished_handshakes_are_given_up_on() {
+    let config = SessionConfig {
+        flood_protection: Some(FloodProtection {
+            max_handshakes: 1,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+
+    let invite = async |peer: &tokio::net::UdpSocket, ssrc: u8| {
+        let invitation = [
+            0xFF, 0xFF, b'I', b'N', // header
+            0x00, 0x00, 0x00, 0x02, // version
+            0x00, 0x00, 0x00, 0x01, // initiator token
+            ssrc, ssrc, ssrc, ssrc, // sender ssrc
+            b'P', b'e', b'e', b'r', 0x00, // name
+        ];
+        peer.send_to(&invitation, control_addr(&session)).await.unwrap();
+        let mut buf = [0u8; 64];
+        let _ = peer.recv_from(&mut buf).await.unwrap();
+        [buf[2], buf[3]]
+    };
+
+    // The first peer never sends its MIDI port invitation, taking up the only handshake
+    let first = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let second = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    assert_eq!(invite(&first, 0x22).await, [b'O', b'K']);
+    assert_eq!(invite(&second, 0x33).await, [b'N', b'O']);
+
+    tokio::time::sleep(INVITATION_TIMEOUT + Duration::from_secs(10)).await;
+    assert_eq!(invite(&second, 0x33).await, [b'O', b'K']);
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_receive_only_session_never_sends() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let config = SessionConfig {
+        receive_only: true,
+        ..Default::default()
+    };
+    let session2 = start_session("Monitor", 0x22222222, config).await;
+
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            message_sender.send(message).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+    assert_eq!(session1.participants().await[0].name(), "Monitor");
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100));
+    assert!(matches!(session2.send_midi(&note_on.into()).await, Err(RtpMidiError::InvalidState(_))));
+    assert!(matches!(session2.panic().await, Err(RtpMidiError::InvalidState(_))));
+
+    session1.send_midi(&note_on.into()).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(2), message_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!(received, note_on);
+}
+
+#[tokio::test]
+async fn test_oversized_control_packet_is_dropped() {
+    let config = SessionConfig {
+        max_control_packet_size: 32,
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+
+    let invitation = |token: u8, name: &[u8]| {
+        let mut packet = vec![
+            0xFF, 0xFF, b'I', b'N', // header
+            0x00, 0x00, 0x00, 0x02, // version
+            0x00, 0x00, 0x00, token, // initiator token
+            0x22, 0x22, 0x22, 0x22, // sender ssrc
+        ];
+        packet.extend_from_slice(name);
+        packet.push(0);
+        packet
+    };
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    peer.send_to(&invitation(1, &[b'A'; 64]), control_addr(&session)).await.unwrap();
+    peer.send_to(&invitation(2, b"Peer"), control_addr(&session)).await.unwrap();
+
+    let mut buf = [0u8; 64];
+    let (_amt, _) = peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+    assert_eq!(buf[11], 2, "only the invitation that fits should be answered");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_slow_listener_receives_every_message_in_order() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            message_sender.send(message).unwrap();
+        })
+        .await;
+
+    connect(&session1, &session2).await;
+
+    let notes: Vec<MidiMessage> = (0..20)
+        .map(|i| MidiMessage::NoteOn(Channel::C1, Note::from(60 + i), Value7::from(100)))
+        .collect();
+    for note in &notes {
+        session1.send_midi(&(*note).into()).await.unwrap();
+    }
+
+    for expected in &notes {
+        let received = message_receiver.recv().await.expect("Expected a MIDI message");
+        assert_eq!(&received, expected);
+    }
+}
+
+#[tokio::test]
+async fn test_session_name_with_nul_is_rejected() {
+    let result = RtpMidiSession::start(0, "Bad\0Name", 0x11111111, InviteResponder::Accept).await;
+    assert!(matches!(result, Err(RtpMidiError::InvalidArgument(_))));
+}
+
+#[tokio::test]
+async fn test_invite_participant_rejects_invalid_addresses() {
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+
+    for addr in ["0.0.0.0:5004", "224.0.0.251:5004", "127.0.0.1:0", "127.0.0.1:65535"] {
+        let result = session.invite_participant(addr.parse().unwrap()).await;
+        assert!(matches!(result, Err(RtpMidiError::InvalidArgument(_))), "{addr} should be rejected");
+    }
+}
+
+#[tokio::test]
+async fn test_invite_participant_rejects_duplicate_invitation() {
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+
+    // Nothing answers here, so the first invitation stays pending
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = peer.local_addr().unwrap();
+    session.invite_participant(addr).await.unwrap();
+    let result = session.invite_participant(addr).await;
+    assert!(matches!(result, Err(RtpMidiError::InvalidState(_))));
+}
+
+#[tokio::test]
+async fn test_rtpmidi_quirks_answer_repeated_invitations_and_early_clock_sync() {
+    let invitations_handled = Arc::new(AtomicUsize::new(0));
+    let invitations_handled_clone = Arc::clone(&invitations_handled);
+    let responder = InviteResponder::new(move |_packet, _name, _addr| {
+        invitations_handled_clone.fetch_add(1, Ordering::SeqCst);
+        true
+    });
+    let config = SessionConfig {
+        rtpmidi_quirks: true,
+        ..Default::default()
+    };
+    let session = RtpMidiSession::start_with_config(0, "Session", 0x11111111, responder, config)
+        .await
+        .expect("Failed to start RTP MIDI session");
+
+    let invitation = |token: u8| {
+        [
+            0xFF, 0xFF, b'I', b'N', // header
+            0x00, 0x00, 0x00, 0x02, // version
+            0x00, 0x00, 0x00, token, // initiator token
+            0x22, 0x22, 0x22, 0x22, // sender ssrc
+            b'P', b'e', b'e', b'r', 0x00, // name
+        ]
+    };
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut buf = [0u8; 64];
+    for token in [1, 2] {
+        peer.send_to(&invitation(token), control_addr(&session)).await.unwrap();
+        peer.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+        assert_eq!(buf[11], token, "the repeated invitation should be answered under its own token");
+    }
+    assert_eq!(invitations_handled.load(Ordering::SeqCst), 1);
+
+    // A clock sync before the MIDI port invitation still gets answered
+    let clock_sync = [
+        0xFF, 0xFF, b'C', b'K', // header
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        0x00, 0x00, 0x00, 0x00, // count, reserved
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // timestamp 1
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // timestamp 2
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // timestamp 3
+    ];
+    peer.send_to(&clock_sync, midi_addr(&session)).await.unwrap();
+    let (amt, _) = peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(amt, clock_sync.len());
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'C', b'K']);
+    assert_eq!(buf[8], 1);
+}
+
+#[tokio::test]
+async fn test_bitrate_limit_reaches_participant() {
+    let (session1, session2) = connected_sessions(SessionConfig::default(), SessionConfig::default()).await;
+    assert_eq!(session1.participants().await[0].bitrate_limit(), None);
+
+    session2.send_bitrate_limit(64000).await.unwrap();
+    let limit = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            if let Some(limit) = session1.participants().await[0].bitrate_limit() {
+                return limit;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The bitrate limit never arrived");
+    assert_eq!(limit, 64000);
+}
+
+#[tokio::test]
+async fn test_midi_port_invitation_without_control_handshake() {
+    let (peer_control, peer) = peer_sockets().await;
+    let config = SessionConfig {
+        accept_midi_port_invitations: true,
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    peer.send_to(&invitation, midi_addr(&session)).await.unwrap();
+
+    let mut buf = [0u8; 64];
+    peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+
+    let participants = session.participants().await;
+    assert_eq!(participants.len(), 1);
+    assert_eq!(participants[0].name(), "Peer");
+    assert_eq!(participants[0].addr(), peer_control.local_addr().unwrap());
+}
+
+#[tokio::test]
+async fn test_initiator_only_session_rejects_invitations_but_invites_others() {
+    let config = SessionConfig {
+        initiator_only: true,
+        accept_midi_port_invitations: true,
+        ..Default::default()
+    };
+    let kiosk = start_session("Kiosk", 0x11111111, config).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x33, 0x33, 0x33, 0x33, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    let mut buf = [0u8; 64];
+    for addr in [control_addr(&kiosk), midi_addr(&kiosk)] {
+        peer.send_to(&invitation, addr).await.unwrap();
+        peer.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..4], &[0xFF, 0xFF, b'N', b'O']);
+    }
+
+    connect(&kiosk, &session2).await;
+    let participants = kiosk.participants().await;
+    assert_eq!(participants.len(), 1);
+    assert_eq!(participants[0].ssrc(), 0x22222222);
+    assert_eq!(session2.participants().await.len(), 1);
+}
+
+#[tokio::test]
+async fn test_exported_peers_are_invited_again_on_start() {
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let session3 = start_session("Session3", 0x33333333, SessionConfig::default()).await;
+
+    let wait_for_peers = async |session: &RtpMidiSession, count: usize| {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while session.participants().await.len() < count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("Peers never joined")
+    };
+
+    // Both invitations are in flight at once
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let peers = [control_addr(&session2), control_addr(&session3)].map(|addr| KnownPeer { addr, name: String::new() });
+    session1.restore_peers(&peers).await.unwrap();
+    wait_for_peers(&session1, 2).await;
+
+    let mut exported = session1.export_peers().await;
+    exported.sort_by(|a, b| a.name.cmp(&b.name));
+    assert_eq!(exported.iter().map(|peer| peer.name.as_str()).collect::<Vec<_>>(), ["Session2", "Session3"]);
+    assert_eq!(exported.iter().map(|peer| peer.addr).collect::<Vec<_>>(), peers.map(|peer| peer.addr));
+    session1.stop_gracefully().await;
+
+    // After a restart, the configured peers are invited straight away
+    let config = SessionConfig {
+        peers: exported,
+        ..Default::default()
+    };
+    let restarted = start_session("Session1", 0x44444444, config).await;
+    wait_for_peers(&restarted, 2).await;
+    assert!(session2.participants().await.iter().any(|participant| participant.ssrc() == 0x44444444));
+    assert!(session3.participants().await.iter().any(|participant| participant.ssrc() == 0x44444444));
+}
+
+#[tokio::test]
+async fn test_stats_count_traffic_both_ways() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            message_sender.send(message).unwrap();
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100));
+    for _ in 0..2 {
+        session1.send_midi(&note_on.into()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(2), message_receiver.recv()).await.unwrap().unwrap();
+    }
+    let garbage = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    garbage.send_to(&[0x01, 0x02, 0x03], midi_addr(&session2)).await.unwrap();
+
+    let sent = session1.stats().await;
+    assert_eq!(sent.packets_sent, 2);
+    assert_eq!(sent.participants.len(), 1);
+    assert_eq!(sent.participants[0].name, "Session2");
+    assert_eq!(sent.participants[0].packets_sent, 2);
+    assert_eq!(sent.participants[0].bytes_sent, sent.bytes_sent);
+
+    let received = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            let stats = session2.stats().await;
+            if stats.parse_errors > 0 {
+                break stats;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The garbage was never counted");
+    assert_eq!(received.packets_received, 2);
+    assert_eq!(received.bytes_received, sent.bytes_sent);
+    assert_eq!(received.parse_errors, 1);
+    assert_eq!(received.sequence_gaps, 0);
+    assert_eq!(received.participants[0].ssrc, 0x11111111);
+    assert_eq!(received.participants[0].packets_received, 2);
+    assert_eq!(received.participants[0].bytes_received, sent.bytes_sent);
+}
+
+#[tokio::test]
+async fn test_capture_records_the_datagrams_of_a_session() {
+    let path = std::env::temp_dir().join(format!("rtpmidi-capture-{}.pcap", std::process::id()));
+    let config = SessionConfig {
+        capture: Some(path.clone()),
+        ..Default::default()
+    };
+    let (session1, _session2) = connected_sessions(config, SessionConfig::default()).await;
+    session1.send_midi(&MidiMessage::Start.into()).await.unwrap();
+
+    let capture = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(&capture[..4], &[0xD4, 0xC3, 0xB2, 0xA1]);
+    // Walk the records: IN and OK on both ports, then the MIDI packet
+    let mut payloads = Vec::new();
+    let mut records = &capture[24..];
+    while !records.is_empty() {
+        let len = u32::from_le_bytes(records[8..12].try_into().unwrap()) as usize;
+        let packet = &records[16..16 + len];
+        payloads.push(packet[28..].to_vec());
+        records = &records[16 + len..];
+    }
+    let commands: Vec<&[u8]> = payloads.iter().filter(|payload| payload[0] == 0xFF).map(|payload| &payload[2..4]).collect();
+    assert_eq!(commands[..4], [b"IN", b"OK", b"IN", b"OK"]);
+    assert!(payloads.iter().any(|payload| payload[0] == 0x80 && payload.ends_with(&[0xFA])), "{payloads:?}");
+}
+
+#[tokio::test]
+async fn test_reinvitation_updates_participant() {
+    let (peer_control, peer_midi) = peer_sockets().await;
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+    let (rename_sender, mut renames) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(ParticipantRenamedEvent, move |rename| {
+            rename_sender.send(rename.clone()).unwrap();
+        })
+        .await;
+
+    let invitation = |token: u8, ssrc: u8, name: &[u8]| {
+        let mut packet = vec![
+            0xFF, 0xFF, b'I', b'N', // header
+            0x00, 0x00, 0x00, 0x02, // version
+            0x00, 0x00, 0x00, token, // initiator token
+            ssrc, ssrc, ssrc, ssrc, // sender ssrc
+        ];
+        packet.extend_from_slice(name);
+        packet.push(0);
+        packet
+    };
+
+    let mut buf = [0u8; 64];
+    for (token, ssrc, name) in [(1, 0x22, b"Peer".as_slice()), (2, 0x33, b"Renamed".as_slice())] {
+        peer_control.send_to(&invitation(token, ssrc, name), control_addr(&session)).await.unwrap();
+        peer_control.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+        peer_midi.send_to(&invitation(token, ssrc, name), midi_addr(&session)).await.unwrap();
+        peer_midi.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+    }
+
+    let participants = session.participants().await;
+    assert_eq!(participants.len(), 1, "the re-invitation should replace the old entry");
+    assert_eq!(participants[0].name(), "Renamed");
+    assert_eq!(participants[0].ssrc(), 0x33333333);
+
+    let learned = tokio::time::timeout(Duration::from_secs(5), renames.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        learned,
+        ParticipantRenamed {
+            ssrc: 0x22222222,
+            old_name: String::new(),
+            new_name: "Peer".to_owned()
+        },
+        "the name should be reported as soon as the invitation is accepted"
+    );
+    let rename = tokio::time::timeout(Duration::from_secs(5), renames.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        rename,
+        ParticipantRenamed {
+            ssrc: 0x33333333,
+            old_name: "Peer".to_owned(),
+            new_name: "Renamed".to_owned()
+        }
+    );
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(renames.try_recv().is_err(), "the rename should only be reported once");
+}
+
+#[tokio::test]
+async fn test_invited_peer_name_is_reported_before_it_joins() {
+    let (peer_control, _peer_midi) = peer_sockets().await;
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+    let (rename_sender, mut renames) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(ParticipantRenamedEvent, move |rename| {
+            rename_sender.send(rename.clone()).unwrap();
+        })
+        .await;
+
+    session.invite_participant(peer_control.local_addr().unwrap()).await.unwrap();
+    let mut buf = [0u8; 64];
+    let (_, src) = peer_control.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'I', b'N']);
+    let mut acceptance = vec![0xFF, 0xFF, b'O', b'K', 0x00, 0x00, 0x00, 0x02];
+    acceptance.extend_from_slice(&buf[8..12]); // initiator token
+    acceptance.extend_from_slice(&[0x22, 0x22, 0x22, 0x22]);
+    acceptance.extend_from_slice(b"Peer\0");
+    peer_control.send_to(&acceptance, src).await.unwrap();
+
+    // The MIDI port is never answered, so the peer doesn't join
+    let learned = tokio::time::timeout(Duration::from_secs(5), renames.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        learned,
+        ParticipantRenamed {
+            ssrc: 0x22222222,
+            old_name: String::new(),
+            new_name: "Peer".to_owned()
+        }
+    );
+    assert!(session.participants().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_stranger_using_our_ssrc_is_rejected() {
+    let session = start_session("Session", 0x22222222, SessionConfig::default()).await;
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc, the same as the session's
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    peer.send_to(&invitation, control_addr(&session)).await.unwrap();
+
+    let mut buf = [0u8; 64];
+    peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'N', b'O']);
+    assert_eq!(session.ssrc(), 0x22222222, "a peer we don't know shouldn't make us change SSRC");
+}
+
+#[tokio::test]
+async fn test_midi_from_strangers_is_dropped() {
+    let session = start_session("Session", 0x22222222, SessionConfig::default()).await;
+    let (midi_sender, mut midi_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            midi_sender.send(message).unwrap();
+        })
+        .await;
+
+    let stranger = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let note_on = [
+        0x80, 0x61, 0x00, 0x01, // header, sequence number
+        0x00, 0x00, 0x00, 0x00, // timestamp
+        0x12, 0x34, 0x56, 0x78, // sender ssrc
+        0x03, 0x90, 0x3C, 0x64, // command list
+    ];
+    stranger.send_to(&note_on, midi_addr(&session)).await.unwrap();
+
+    let received = tokio::time::timeout(Duration::from_millis(200), midi_receiver.recv()).await;
+    assert!(received.is_err(), "MIDI from a peer that never joined was handed on: {received:?}");
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_participant_using_our_ssrc_makes_us_change_it() {
+    let (peer_control, peer_midi) = peer_sockets().await;
+    let session = start_session("Session", 0x22222222, SessionConfig::default()).await;
+
+    let invitation = |token: u8, ssrc: u8| {
+        [
+            0xFF, 0xFF, b'I', b'N', // header
+            0x00, 0x00, 0x00, 0x02, // version
+            0x00, 0x00, 0x00, token, // initiator token
+            ssrc, ssrc, ssrc, ssrc, // sender ssrc
+            b'P', b'e', b'e', b'r', 0x00, // name
+        ]
+    };
+    let mut buf = [0u8; 64];
+    peer_control.send_to(&invitation(1, 0x33), control_addr(&session)).await.unwrap();
+    peer_control.recv_from(&mut buf).await.unwrap();
+    peer_midi.send_to(&invitation(1, 0x33), midi_addr(&session)).await.unwrap();
+    peer_midi.recv_from(&mut buf).await.unwrap();
+    assert_eq!(session.participants().await.len(), 1);
+
+    // The participant has picked our SSRC for itself, and invites us again under it
+    peer_control.send_to(&invitation(2, 0x22), control_addr(&session)).await.unwrap();
+    let answer = loop {
+        let amt = peer_control.recv(&mut buf).await.unwrap();
+        // The session we had ends, and we invite the peer again, before the invitation is answered
+        if &buf[2..4] == b"OK" || &buf[2..4] == b"NO" {
+            break buf[..amt].to_vec();
+        }
+    };
+    assert_eq!(&answer[..4], &[0xFF, 0xFF, b'O', b'K']);
+    assert_ne!(session.ssrc(), 0x22222222);
+    assert_eq!(
+        u32::from_be_bytes(answer[12..16].try_into().unwrap()),
+        session.ssrc(),
+        "the answer should use the new SSRC"
+    );
+}
+
+#[tokio::test]
+async fn test_invitation_with_another_peers_ssrc_is_rejected() {
+    let (peer_control, peer_midi) = peer_sockets().await;
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    let mut buf = [0u8; 64];
+    peer_control.send_to(&invitation, control_addr(&session)).await.unwrap();
+    peer_control.recv_from(&mut buf).await.unwrap();
+    peer_midi.send_to(&invitation, midi_addr(&session)).await.unwrap();
+    peer_midi.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+
+    // Same SSRC, but not the token it joined with, so not the same peer having moved
+    let mut other_invitation = invitation;
+    other_invitation[11] = 0x02;
+    let other_peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    other_peer.send_to(&other_invitation, control_addr(&session)).await.unwrap();
+    other_peer.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'N', b'O']);
+
+    let participants = session.participants().await;
+    assert_eq!(participants.len(), 1);
+    assert_eq!(participants[0].addr(), peer_control.local_addr().unwrap());
+}
+
+#[tokio::test]
+async fn test_panic_silences_every_channel() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            message_sender.send(message).unwrap();
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    let participant = session1.participants().await.remove(0);
+    session1.panic_to(&participant).await.unwrap();
+    session1.panic().await.unwrap();
+
+    let mut silenced = Vec::new();
+    for _ in 0..2 * 16 * 3 {
+        let message = tokio::time::timeout(Duration::from_secs(2), message_receiver.recv())
+            .await
+            .expect("Expected a MIDI message")
+            .unwrap();
+        let MidiMessage::ControlChange(channel, control, value) = message else {
+            panic!("Expected a control change, got {message:?}");
+        };
+        assert_eq!(u8::from(value), 0);
+        silenced.push((u8::from(channel), u8::from(control)));
+    }
+    for channel in 0..16 {
+        for control in [64, 120, 123] {
+            assert_eq!(silenced.iter().filter(|&&sent| sent == (channel, control)).count(), 2);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_removing_participant_ends_its_active_notes() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            message_sender.send(message).unwrap();
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    for note in [60, 64, 67] {
+        let note_on = MidiMessage::NoteOn(Channel::C1, Note::from(note), Value7::from(100));
+        session1.send_midi(&note_on.into()).await.unwrap();
+    }
+    let note_off = MidiMessage::NoteOff(Channel::C1, Note::from(64), Value7::from(0));
+    session1.send_midi(&note_off.into()).await.unwrap();
+
+    let participant = session1.participants().await.remove(0);
+    session1.remove_participant(&participant).await.unwrap();
+
+    let mut received = Vec::new();
+    for _ in 0..6 {
+        let message = tokio::time::timeout(Duration::from_secs(2), message_receiver.recv())
+            .await
+            .expect("Expected a MIDI message")
+            .unwrap();
+        received.push(message);
+    }
+    assert_eq!(
+        received[4..],
+        [
+            MidiMessage::NoteOff(Channel::C1, Note::from(60), Value7::from(0)),
+            MidiMessage::NoteOff(Channel::C1, Note::from(67), Value7::from(0)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_tempo_is_estimated_from_timing_clock() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let (tempo_sender, mut tempo_receiver) = tokio::sync::mpsc::unbounded_channel::<TempoChange>();
+    session2
+        .add_listener(TempoChangedEvent, move |change| {
+            tempo_sender.send(change.clone()).unwrap();
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    // 24 ticks per beat at 120 beats per minute
+    let mut ticks = tokio::time::interval(Duration::from_micros(60_000_000 / (24 * 120)));
+    for _ in 0..24 {
+        ticks.tick().await;
+        session1.send_midi(&MidiMessage::TimingClock.into()).await.unwrap();
+    }
+
+    let change = tokio::time::timeout(Duration::from_secs(2), tempo_receiver.recv())
+        .await
+        .expect("Expected a tempo change")
+        .unwrap();
+    assert_eq!(change.ssrc, 0x11111111);
+    let participant = session2.participants().await.remove(0);
+    let bpm = session2.tempo(&participant).expect("Expected a tempo estimate");
+    assert!((bpm - 120.0).abs() < 10.0, "{bpm}");
+}
+
+#[tokio::test]
+async fn test_tempo_is_estimated_from_timing_clocks_in_one_batch() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let (tempo_sender, mut tempo_receiver) = tokio::sync::mpsc::unbounded_channel::<TempoChange>();
+    session2
+        .add_listener(TempoChangedEvent, move |change| {
+            tempo_sender.send(change.clone()).unwrap();
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    // A beat of ticks at 120 beats per minute, 208 timestamp units of 100 microseconds apart
+    let batch: Vec<MidiEvent> = (0..24).map(|_| MidiEvent::new(Some(208), MidiMessage::TimingClock.into())).collect();
+    session1.send_midi_batch(&batch).await.unwrap();
+
+    let change = tokio::time::timeout(Duration::from_secs(2), tempo_receiver.recv())
+        .await
+        .expect("Expected a tempo change")
+        .unwrap();
+    assert_eq!(change.ssrc, 0x11111111);
+    let participant = session2.participants().await.remove(0);
+    let bpm = session2.tempo(&participant).expect("Expected a tempo estimate");
+    assert!((bpm - 600_000.0 / (24.0 * 208.0)).abs() < 0.01, "{bpm}");
+}
+
+#[tokio::test]
+async fn test_transport_is_followed_by_participants() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let (update_sender, mut update_receiver) = tokio::sync::mpsc::unbounded_channel::<(bool, u16)>();
+    session2
+        .add_listener(TransportEvent, move |update| {
+            update_sender.send((update.playing, update.song_position)).unwrap();
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    let transport = Transport::new(Arc::clone(&session1));
+    transport.play().await.unwrap();
+    transport.locate(8).await.unwrap();
+    assert!(transport.is_playing().await);
+    transport.stop().await.unwrap();
+    assert!(matches!(transport.locate(0x4000).await, Err(RtpMidiError::InvalidArgument(_))));
+
+    let mut updates = Vec::new();
+    for _ in 0..5 {
+        let update = tokio::time::timeout(Duration::from_secs(2), update_receiver.recv())
+            .await
+            .expect("Expected a transport update")
+            .unwrap();
+        updates.push(update);
+    }
+    assert_eq!(updates, [(true, 0), (false, 0), (false, 8), (true, 8), (false, 8)]);
+}
+
+#[tokio::test]
+async fn test_session_manager_runs_sessions_side_by_side() {
+    let manager = SessionManager::new().await.expect("Failed to start session manager");
+
+    let session_a = manager
+        .start_session(0, "A", 0x11111111, InviteResponder::Accept, SessionConfig::default())
+        .await
+        .expect("Failed to start RTP MIDI session");
+
+    // Listeners reach sessions started before and after they were added
+    let joined = Arc::new(Notify::new());
+    let joined_clone = Arc::clone(&joined);
+    manager
+        .add_listener(ParticipantJoinedEvent, move |_session, _participant| joined_clone.notify_one())
+        .await;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    manager
+        .add_listener(MidiMessageEvent, move |session, (message, _delta_time)| {
+            sender.send((session.to_owned(), message)).unwrap();
+        })
+        .await;
+
+    let session_b = manager
+        .start_session(0, "B", 0x22222222, InviteResponder::Accept, SessionConfig::default())
+        .await
+        .expect("Failed to start RTP MIDI session");
+    let duplicate = manager
+        .start_session(0, "B", 0x33333333, InviteResponder::Accept, SessionConfig::default())
+        .await;
+    assert!(matches!(duplicate, Err(RtpMidiError::InvalidArgument(_))));
+    assert_eq!(manager.sessions().await.len(), 2);
+
+    session_a.invite_participant(control_addr(&session_b)).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(2), joined.notified()).await.expect("B never joined");
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100));
+    session_a.send_midi(&note_on.into()).await.unwrap();
+    let found = manager.session("B").await.expect("B should be found by name");
+    assert!(Arc::ptr_eq(&found, &session_b));
+    session_b.send_midi(&note_on.into()).await.unwrap();
+
+    let mut received = Vec::new();
+    for _ in 0..2 {
+        let event = tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+            .await
+            .expect("No MIDI message arrived");
+        received.push(event.unwrap());
+    }
+    received.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(received, [("A".to_owned(), note_on), ("B".to_owned(), note_on)]);
+
+    assert!(manager.stop_session("B").await);
+    assert!(!manager.stop_session("B").await);
+    assert!(manager.session("B").await.is_none());
+    manager.stop_gracefully().await;
+    assert!(manager.sessions().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_send_midi_to_group_reaches_only_its_members() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let session3 = start_session("Session3", 0x33333333, SessionConfig::default()).await;
+
+    let mut receivers = Vec::new();
+    for session in [&session2, &session3] {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        session
+            .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+                sender.send(message).unwrap();
+            })
+            .await;
+        receivers.push(receiver);
+    }
+
+    // One at a time, as only one invitation can await its reply
+    for invitee in [&session2, &session3] {
+        connect(&session1, invitee).await;
+    }
+
+    let participants = session1.participants().await;
+    let foh = participants.iter().find(|participant| participant.ssrc() == 0x22222222).unwrap();
+    session1.add_to_group("FOH", foh).await.unwrap();
+    assert_eq!(session1.groups(), ["FOH"]);
+    assert_eq!(session1.groups_of(foh), ["FOH"]);
+    assert_eq!(session1.group_members("FOH").await, std::slice::from_ref(foh));
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100));
+    session1.send_midi(&note_on.into()).await.unwrap();
+    for receiver in &mut receivers {
+        let received = tokio::time::timeout(Duration::from_secs(2), receiver.recv()).await;
+        assert_eq!(received.expect("a participant got nothing"), Some(note_on));
+    }
+    session1.send_midi_to_group("FOH", &note_on.into()).await.unwrap();
+    // Nobody is in this one
+    session1.send_midi_to_group("monitors", &note_on.into()).await.unwrap();
+
+    let received = tokio::time::timeout(Duration::from_secs(2), receivers[0].recv())
+        .await
+        .expect("FOH got nothing");
+    assert_eq!(received, Some(note_on));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(receivers[1].try_recv().is_err());
+
+    // What went to the group alone leaves no gap in the sequence numbers the others are sent
+    session1.send_midi(&note_on.into()).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(2), receivers[1].recv()).await;
+    assert_eq!(received.expect("the participant outside the group got nothing"), Some(note_on));
+    let sender = session3.participants().await.into_iter().next().unwrap();
+    assert_eq!(sender.lost_packets(), 0);
+
+    assert!(session1.remove_from_group("FOH", foh));
+    assert!(session1.groups().is_empty());
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+    session3.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_mute_and_solo_pick_who_hears_the_session() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let session3 = start_session("Session3", 0x33333333, SessionConfig::default()).await;
+
+    let mut receivers = Vec::new();
+    for session in [&session2, &session3] {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        session
+            .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+                sender.send(message).unwrap();
+            })
+            .await;
+        receivers.push(receiver);
+    }
+
+    for invitee in [&session2, &session3] {
+        connect(&session1, invitee).await;
+    }
+    let participants = session1.participants().await;
+    let find = |ssrc: u32| participants.iter().find(|participant| participant.ssrc() == ssrc).unwrap();
+    let participant2 = find(0x22222222);
+
+    // Muting ends the note left sounding on the participant, and it hears nothing more
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100));
+    let cc = MidiMessage::ControlChange(Channel::C1, 7.into(), Value7::from(64));
+    session1.send_midi(&note_on.into()).await.unwrap();
+    session1.set_muted(participant2, true).await.unwrap();
+    assert!(session1.participants().await.iter().any(|participant| participant.is_muted()));
+    session1.send_midi(&cc.into()).await.unwrap();
+
+    let mut next = async |receiver: usize| {
+        tokio::time::timeout(Duration::from_secs(2), receivers[receiver].recv())
+            .await
+            .expect("No MIDI message arrived")
+            .unwrap()
+    };
+    assert_eq!(next(0).await, note_on);
+    assert_eq!(next(0).await, MidiMessage::NoteOff(Channel::C1, Note::from(60), Value7::from(0)));
+    assert_eq!(next(1).await, note_on);
+    assert_eq!(next(1).await, cc);
+
+    // Soloing the muted participant leaves only muted ones to hear, so nobody does
+    session1.set_soloed(participant2, true).await.unwrap();
+    assert!(session1.is_soloed(participant2));
+    assert_eq!(next(1).await, MidiMessage::NoteOff(Channel::C1, Note::from(60), Value7::from(0)));
+    session1.send_midi(&cc.into()).await.unwrap();
+
+    session1.set_muted(participant2, false).await.unwrap();
+    session1.send_midi(&note_on.into()).await.unwrap();
+    assert_eq!(next(0).await, note_on);
+
+    session1.clear_solo();
+    assert!(!session1.is_soloed(participant2));
+    session1.send_midi(&cc.into()).await.unwrap();
+    assert_eq!(next(0).await, cc);
+    assert_eq!(next(1).await, cc);
+    assert!(receivers.iter_mut().all(|receiver| receiver.try_recv().is_err()));
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+    session3.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_channel_routing_on_send_and_receive() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let config = SessionConfig {
+        channel_routing: ChannelRouting {
+            incoming: ChannelMap::default().with(Channel::C1, Channel::C5),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let session2 = start_session("Session2", 0x22222222, config).await;
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            sender.send(message).unwrap();
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    let participant = session1.participants().await.remove(0);
+    let routing = ChannelRouting {
+        outgoing: ChannelMap::all_to(Channel::C1),
+        ..Default::default()
+    };
+    session1.set_participant_channel_routing(&participant, Some(routing)).await.unwrap();
+    assert_eq!(session1.channel_routing(&participant), routing);
+
+    // Moved to channel 1 on the way out, then to channel 5 on the way in
+    let note_on = |channel| MidiMessage::NoteOn(channel, Note::from(60), Value7::from(100));
+    session1.send_midi(&note_on(Channel::C3).into()).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+        .await
+        .expect("No MIDI message arrived");
+    assert_eq!(received, Some(note_on(Channel::C5)));
+
+    session1.set_participant_channel_routing(&participant, None).await.unwrap();
+    session1.send_midi(&note_on(Channel::C3).into()).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+        .await
+        .expect("No MIDI message arrived");
+    assert_eq!(received, Some(note_on(Channel::C3)));
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_interceptors_transform_sent_and_received_messages() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    // Session1 transposes what it sends up an octave, session2 ignores the SysEx and Stops it receives
+    session1.add_interceptor(|message, direction, _participant| {
+        if let (RtpMidiMessage::MidiMessage(MidiMessage::NoteOn(_, note, _)), Direction::Outbound) = (message, direction) {
+            *note = Note::from(u8::from(*note) + 12);
+        }
+        Action::Pass
+    });
+    session2.add_interceptor(|message, direction, participant| {
+        assert_eq!(participant.name(), "Session1");
+        match (message, direction) {
+            (RtpMidiMessage::SysEx(_), Direction::Inbound) => Action::Drop,
+            (RtpMidiMessage::MidiMessage(MidiMessage::Stop), Direction::Inbound) => Action::Replace(MidiMessage::Continue.into()),
+            _ => Action::Pass,
+        }
+    });
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            sender.send(message).unwrap();
+        })
+        .await;
+    let sysex_count = Arc::new(AtomicUsize::new(0));
+    let sysex_count_clone = Arc::clone(&sysex_count);
+    session2
+        .add_listener(SysExPacketEvent, move |_sysex| {
+            sysex_count_clone.fetch_add(1, Ordering::SeqCst);
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    let note_on = |note| MidiMessage::NoteOn(Channel::C1, Note::from(note), Value7::from(100));
+    session1.send_midi(&note_on(60).into()).await.unwrap();
+    session1.send_midi(&RtpMidiMessage::SysEx(&[0x7E, 0x7F, 0x06, 0x01])).await.unwrap();
+    session1.send_midi(&MidiMessage::Stop.into()).await.unwrap();
+
+    let mut next = async || {
+        tokio::time::timeout(Duration::from_secs(2), receiver.recv())
+            .await
+            .expect("No MIDI message arrived")
+            .unwrap()
+    };
+    assert_eq!(next().await, note_on(72));
+    assert_eq!(next().await, MidiMessage::Continue);
+    assert_eq!(sysex_count.load(Ordering::SeqCst), 0);
+
+    session1.clear_interceptors();
+    session1.send_midi(&note_on(60).into()).await.unwrap();
+    assert_eq!(next().await, note_on(60));
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_clock_sync_rounds_are_reported_on_both_sides() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let (inviter_sender, mut inviter_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session1
+        .add_listener(ClockSyncRoundEvent, move |round| {
+            inviter_sender.send(round.clone()).unwrap();
+        })
+        .await;
+    let (invitee_sender, mut invitee_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session2
+        .add_listener(ClockSyncRoundEvent, move |round| {
+            invitee_sender.send(round.clone()).unwrap();
+        })
+        .await;
+    session1.invite_participant(control_addr(&session2)).await.unwrap();
+
+    // On joining, the inviter sends the middle packet of an exchange, without timestamps to measure anything by
+    let opened = tokio::time::timeout(Duration::from_secs(2), invitee_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!((opened.ssrc, opened.name.as_str(), opened.count), (0x11111111, "Session1", 1));
+    assert_eq!(opened.rtt_estimate, None);
+    let closed = tokio::time::timeout(Duration::from_secs(2), inviter_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!((closed.ssrc, closed.name.as_str(), closed.count), (0x22222222, "Session2", 2));
+    assert_eq!(closed.rtt_estimate, None);
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_packet_loss_above_the_threshold_is_reported() {
+    let (_peer_control, peer) = peer_sockets().await;
+    let config = SessionConfig {
+        accept_midi_port_invitations: true,
+        loss_alert_threshold: Some(10.0),
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+    let (crossing_sender, mut crossing_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(PacketLossThresholdEvent, move |crossing| {
+            crossing_sender.send(crossing.clone()).unwrap();
+        })
+        .await;
+
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    peer.send_to(&invitation, midi_addr(&session)).await.unwrap();
+    let mut buf = [0u8; 64];
+    peer.recv_from(&mut buf).await.unwrap();
+
+    // Every other packet goes missing
+    for sequence_number in (0u16..40).step_by(2) {
+        let [high, low] = sequence_number.to_be_bytes();
+        let packet = [
+            0x80, 0x61, high, low, // RTP header, sequence number
+            0x00, 0x00, 0x00, 0x00, // timestamp
+            0x22, 0x22, 0x22, 0x22, // ssrc
+            0x03, 0x90, 0x3C, 0x64, // note on
+        ];
+        peer.send_to(&packet, midi_addr(&session)).await.unwrap();
+    }
+
+    let crossing = tokio::time::timeout(Duration::from_secs(2), crossing_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!((crossing.ssrc, crossing.name.as_str(), crossing.above), (0x22222222, "Peer", true));
+    assert!(crossing.loss_rate > 40.0);
+    assert_eq!(session.stats().await.participants[0].loss_rate, crossing.loss_rate);
+
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_missing_packets_are_asked_for_and_sent_again() {
+    let (peer_control, peer_midi) = peer_sockets().await;
+    let config = SessionConfig {
+        accept_midi_port_invitations: true,
+        retransmission: Some(Retransmission::default()),
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+    let (message_sender, mut messages) = tokio::sync::mpsc::unbounded_channel();
+    let recovered_sender = message_sender.clone();
+    session
+        .add_listener(MidiMessageEvent, move |(message, _)| {
+            message_sender.send((message, false)).unwrap();
+        })
+        .await;
+    session
+        .add_listener(RecoveredMidiEvent, move |(message, _)| {
+            recovered_sender.send((message, true)).unwrap();
+        })
+        .await;
+
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    peer_midi.send_to(&invitation, midi_addr(&session)).await.unwrap();
+    let mut buf = [0u8; 64];
+    peer_midi.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+
+    let note_on = |sequence_number: u16| {
+        let [high, low] = sequence_number.to_be_bytes();
+        [
+            0x80,
+            0x61,
+            high,
+            low, // RTP header, sequence number
+            0x00,
+            0x00,
+            0x00,
+            0x00, // timestamp
+            0x22,
+            0x22,
+            0x22,
+            0x22, // ssrc
+            0x03,
+            0x90,
+            0x3C + low,
+            0x64, // note on
+        ]
+    };
+    let note = |key: u8| MidiMessage::NoteOn(Channel::C1, Note::from(key), Value7::new(100));
+    let next =
+        async |receiver: &mut tokio::sync::mpsc::UnboundedReceiver<_>| tokio::time::timeout(Duration::from_secs(2), receiver.recv()).await.unwrap().unwrap();
+
+    // The one in between goes missing, and is asked for
+    peer_midi.send_to(&note_on(0), midi_addr(&session)).await.unwrap();
+    assert_eq!(next(&mut messages).await, (note(0x3C), false));
+    peer_midi.send_to(&note_on(2), midi_addr(&session)).await.unwrap();
+    let len = tokio::time::timeout(Duration::from_secs(2), peer_control.recv(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&buf[..len], &[0xFF, 0xFF, b'R', b'T', 0x11, 0x11, 0x11, 0x11, 0x00, 0x01, 0x00, 0x01]);
+    assert_eq!(next(&mut messages).await, (note(0x3E), false));
+    peer_midi.send_to(&note_on(1), midi_addr(&session)).await.unwrap();
+    assert_eq!(next(&mut messages).await, (note(0x3D), true));
+
+    // And the other way round, what we sent is sent again when asked for
+    session.send_midi(&note(0x40).into()).await.unwrap();
+    let mut sent = [0u8; 64];
+    let len = tokio::time::timeout(Duration::from_secs(2), peer_midi.recv(&mut sent)).await.unwrap().unwrap();
+    let sent = &sent[..len];
+    let request = [
+        [0xFF, 0xFF, b'R', b'T'].as_slice(),
+        &[0x22, 0x22, 0x22, 0x22], // sender ssrc
+        &sent[2..4],               // sequence number
+        &[0x00, 0x01],             // count
+    ]
+    .concat();
+    peer_control.send_to(&request, control_addr(&session)).await.unwrap();
+    let len = tokio::time::timeout(Duration::from_secs(2), peer_midi.recv(&mut buf)).await.unwrap().unwrap();
+    assert_eq!(&buf[..len], sent);
+
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_packets_asked_for_in_vain_are_concealed() {
+    let config = SessionConfig {
+        accept_midi_port_invitations: true,
+        retransmission: Some(Retransmission {
+            timeout: Duration::from_millis(50),
+            ..Default::default()
+        }),
+        loss_concealment: Some(Arc::new(ReleaseNotes)),
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+    let (recovered_sender, mut recovered) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(RecoveredMidiEvent, move |(message, _)| {
+            recovered_sender.send(message).unwrap();
+        })
+        .await;
+
+    let peer_midi = tokio::net::UdpSocket::bind(("127.0.0.1", 0)).await.unwrap();
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    peer_midi.send_to(&invitation, midi_addr(&session)).await.unwrap();
+    let mut buf = [0u8; 64];
+    peer_midi.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+
+    let note_on = |sequence_number: u8| {
+        [
+            0x80,
+            0x61,
+            0x00,
+            sequence_number, // RTP header, sequence number
+            0x00,
+            0x00,
+            0x00,
+            0x00, // timestamp
+            0x22,
+            0x22,
+            0x22,
+            0x22, // ssrc
+            0x03,
+            0x90,
+            0x3C,
+            0x64, // note on
+        ]
+    };
+    // The peer never sends the missing one again, as peers not using this library don't
+    peer_midi.send_to(&note_on(0), midi_addr(&session)).await.unwrap();
+    peer_midi.send_to(&note_on(2), midi_addr(&session)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(recovered.try_recv().is_err(), "nothing should be concealed while the packet may still come");
+    peer_midi.send_to(&note_on(3), midi_addr(&session)).await.unwrap();
+
+    let concealed = tokio::time::timeout(Duration::from_secs(2), recovered.recv()).await.unwrap().unwrap();
+    assert_eq!(concealed, MidiMessage::ControlChange(Channel::C1, Control::new(64), Value7::new(0)));
+
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_wire_tap_sees_datagrams_both_ways() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let (tapped_sender, mut tapped_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session1.set_wire_tap(move |direction, addr, bytes| {
+        tapped_sender.send((direction, *addr, bytes[..4].to_vec())).unwrap();
+    });
+    let session2_addr = control_addr(&session2);
+    session1.invite_participant(session2_addr).await.unwrap();
+
+    let mut next = async || tokio::time::timeout(Duration::from_secs(2), tapped_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!(next().await, (Direction::Outbound, session2_addr, vec![0xFF, 0xFF, b'I', b'N']));
+    assert_eq!(next().await, (Direction::Inbound, session2_addr, vec![0xFF, 0xFF, b'O', b'K']));
+
+    session1.clear_wire_tap();
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_flood_protection_rejects_and_bans_invitations() {
+    let config = SessionConfig {
+        flood_protection: Some(FloodProtection {
+            max_invitations: 2,
+            max_handshakes: 1,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+    let (flood_sender, mut flood_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(InvitationFloodEvent, move |flood| {
+            flood_sender.send(flood.reason).unwrap();
+        })
+        .await;
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let invite = async |ssrc: u8| {
+        let invitation = [
+            0xFF, 0xFF, b'I', b'N', // header
+            0x00, 0x00, 0x00, 0x02, // version
+            0x00, 0x00, 0x00, ssrc, // initiator token
+            0x22, 0x22, 0x22, ssrc, // sender ssrc
+            b'P', b'e', b'e', b'r', 0x00, // name
+        ];
+        peer.send_to(&invitation, control_addr(&session)).await.unwrap();
+        let mut buf = [0u8; 64];
+        let answer = tokio::time::timeout(Duration::from_millis(200), peer.recv_from(&mut buf)).await;
+        answer.ok().map(|_| [buf[2], buf[3]])
+    };
+
+    assert_eq!(invite(1).await, Some([b'O', b'K']));
+    // The first handshake hasn't finished, so there's no room for another
+    assert_eq!(invite(2).await, Some([b'N', b'O']));
+    assert_eq!(flood_receiver.recv().await, Some(FloodReason::TooManyHandshakes(1)));
+    // One invitation too many, which isn't answered at all
+    assert_eq!(invite(3).await, None);
+    assert_eq!(flood_receiver.recv().await, Some(FloodReason::Banned(FloodProtection::default().ban)));
+
+    session.stop_gracefully().await;
+}
+
+/// Not a secure proof, but enough to tell the secrets apart.
+struct SharedSecret(&'static [u8]);
+
+impl Authenticator for SharedSecret {
+    fn respond(&self, nonce: &[u8]) -> Vec<u8> {
+        nonce.iter().zip(self.0.iter().cycle()).map(|(a, b)| a ^ b).collect()
+    }
+}
+
+async fn start_authenticating_session(name: &str, ssrc: u32, secret: &'static [u8]) -> Arc<RtpMidiSession> {
+    let config = SessionConfig {
+        authenticator: Some(Arc::new(SharedSecret(secret))),
+        ..Default::default()
+    };
+    start_session(name, ssrc, config).await
+}
+
+#[tokio::test]
+async fn test_peers_with_the_same_secret_authenticate() {
+    let session1 = start_authenticating_session("Session1", 0x11111111, b"secret").await;
+    let session2 = start_authenticating_session("Session2", 0x22222222, b"secret").await;
+
+    connect(&session1, &session2).await;
+    assert_eq!(session1.participants().await[0].name(), "Session2");
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while session2.participants().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The inviter never authenticated");
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_peers_with_different_secrets_fail_to_authenticate() {
+    let session1 = start_authenticating_session("Session1", 0x11111111, b"secret").await;
+    let session2 = start_authenticating_session("Session2", 0x22222222, b"guess").await;
+
+    let (failed_sender, mut failed_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session1
+        .add_listener(AuthenticationFailedEvent, move |failure| {
+            failed_sender.send(failure.clone()).unwrap();
+        })
+        .await;
+    session1.invite_participant(control_addr(&session2)).await.unwrap();
+
+    let failure = tokio::time::timeout(Duration::from_secs(2), failed_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!((failure.ssrc, failure.name.as_str(), failure.timed_out), (0x22222222, "Session2", false));
+    assert!(session1.participants().await.is_empty());
+    assert!(session2.participants().await.is_empty());
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_challenges_from_strangers_are_not_answered() {
+    let session = start_authenticating_session("Session", 0x11111111, b"secret").await;
+
+    let stranger = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let challenge = [
+        0xFF, 0xFF, b'A', b'C', // header
+        0x12, 0x34, 0x56, 0x78, // sender ssrc
+        1, 2, 3, 4, 5, 6, 7, 8, // nonce
+    ];
+    stranger.send_to(&challenge, control_addr(&session)).await.unwrap();
+
+    let mut buf = [0u8; 64];
+    let answer = tokio::time::timeout(Duration::from_millis(200), stranger.recv_from(&mut buf)).await;
+    assert!(answer.is_err(), "A challenge from a peer we aren't joining with was answered");
+    session.stop_gracefully().await;
+}
+
+/// Not secure either, but the datagrams it seals can't be read as AppleMIDI.
+struct Scrambler(u8);
+
+impl Cipher for Scrambler {
+    fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        [b"SEAL", plaintext].concat().iter().map(|byte| byte ^ self.0).collect()
+    }
+
+    fn open(&self, sealed: &[u8]) -> Option<Vec<u8>> {
+        let opened: Vec<u8> = sealed.iter().map(|byte| byte ^ self.0).collect();
+        opened.strip_prefix(b"SEAL").map(<[u8]>::to_vec)
+    }
+}
+
+#[tokio::test]
+async fn test_encrypted_sessions_talk_to_each_other_but_not_to_cleartext_peers() {
+    let config = || SessionConfig {
+        encryption: Some(Arc::new(Scrambler(0x5A))),
+        ..Default::default()
+    };
+    let session1 = start_session("Session1", 0x11111111, config()).await;
+    let session2 = start_session("Session2", 0x22222222, config()).await;
+    let (message_sender, mut message_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session2
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            message_sender.send(message).unwrap();
+        })
+        .await;
+    connect(&session1, &session2).await;
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100));
+    session1.send_midi(&note_on.into()).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(2), message_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!(received, note_on);
+
+    let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x33, 0x33, 0x33, 0x33, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    peer.send_to(&invitation, control_addr(&session2)).await.unwrap();
+    let mut buf = [0u8; 64];
+    let answer = tokio::time::timeout(Duration::from_millis(200), peer.recv_from(&mut buf)).await;
+    assert!(answer.is_err(), "A cleartext invitation was answered");
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_participants_are_followed_to_a_new_address() {
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+    let (change_sender, mut change_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(AddressChangedEvent, move |change| {
+            change_sender.send(change.clone()).unwrap();
+        })
+        .await;
+
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    let join = async || {
+        let (control, midi) = peer_sockets().await;
+        let mut buf = [0u8; 64];
+        control.send_to(&invitation, control_addr(&session)).await.unwrap();
+        let (amt, _) = control.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[2..4], b"OK", "{:?}", &buf[..amt]);
+        midi.send_to(&invitation, midi_addr(&session)).await.unwrap();
+        let (amt, _) = midi.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[2..4], b"OK", "{:?}", &buf[..amt]);
+        (control, midi)
+    };
+
+    let (old_control, _old_midi) = join().await;
+    let old_addr = old_control.local_addr().unwrap();
+    assert_eq!(session.participants().await[0].addr(), old_addr);
+
+    // The same SSRC and token from another address, as after a WiFi roam
+    let (new_control, new_midi) = join().await;
+    let new_addr = new_control.local_addr().unwrap();
+
+    let change = tokio::time::timeout(Duration::from_secs(2), change_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        (change.ssrc, change.name.as_str(), change.old_addr, change.new_addr),
+        (0x22222222, "Peer", old_addr, new_addr)
+    );
+    let participants = session.participants().await;
+    assert_eq!(participants.len(), 1);
+    assert_eq!(participants[0].addr(), new_addr);
+
+    // MIDI goes to the new address
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::new(100));
+    session.send_midi(&RtpMidiMessage::MidiMessage(note_on)).await.unwrap();
+    let mut buf = [0u8; 64];
+    let (amt, _) = tokio::time::timeout(Duration::from_secs(2), new_midi.recv_from(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(&buf[amt - 3..amt], &[0x90, 72, 100]);
+
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_moved_participants_authenticate_again() {
+    let session = start_authenticating_session("Session", 0x11111111, b"secret").await;
+    let (change_sender, mut change_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session
+        .add_listener(AddressChangedEvent, move |change| {
+            change_sender.send(change.clone()).unwrap();
+        })
+        .await;
+
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    let join = async || {
+        let (control, midi) = peer_sockets().await;
+        let mut buf = [0u8; 64];
+        control.send_to(&invitation, control_addr(&session)).await.unwrap();
+        control.recv_from(&mut buf).await.unwrap();
+        midi.send_to(&invitation, midi_addr(&session)).await.unwrap();
+        midi.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[2..4], b"OK");
+        (control, midi)
+    };
+    // Answers the session's challenge, as the peer that invited it
+    let authenticate = async |control: &tokio::net::UdpSocket| {
+        let mut buf = [0u8; 64];
+        let (amt, _) = control.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[2..4], b"AC");
+        let challenge = [&buf[8..amt], &[0x11, 0x11, 0x11, 0x11], &[0x22, 0x22, 0x22, 0x22], &[1]].concat();
+        let response = [
+            &[0xFF, 0xFF, b'A', b'R', 0x22, 0x22, 0x22, 0x22],
+            &SharedSecret(b"secret").respond(&challenge)[..],
+        ]
+        .concat();
+        control.send_to(&response, control_addr(&session)).await.unwrap();
+    };
+    let wait_for_participants = async |count: usize| {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while session.participants().await.len() != count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The participants never changed");
+    };
+
+    let (old_control, _old_midi) = join().await;
+    authenticate(&old_control).await;
+    wait_for_participants(1).await;
+
+    // The token alone doesn't make whoever is at the new address the participant
+    let (new_control, _new_midi) = join().await;
+    assert!(session.participants().await.is_empty());
+    assert!(change_receiver.try_recv().is_err());
+
+    authenticate(&new_control).await;
+    let change = tokio::time::timeout(Duration::from_secs(2), change_receiver.recv()).await.unwrap().unwrap();
+    assert_eq!(change.new_addr, new_control.local_addr().unwrap());
+    wait_for_participants(1).await;
+    assert_eq!(session.participants().await[0].addr(), change.new_addr);
+
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_nat_keepalives_go_out_on_both_ports() {
+    let (peer_control, peer_midi) = peer_sockets().await;
+    let config = SessionConfig {
+        nat_keepalive: Some(Duration::from_millis(50)),
+        ..Default::default()
+    };
+    let session = start_session("Session", 0x11111111, config).await;
+
+    let invitation = [
+        0xFF, 0xFF, b'I', b'N', // header
+        0x00, 0x00, 0x00, 0x02, // version
+        0x00, 0x00, 0x00, 0x01, // initiator token
+        0x22, 0x22, 0x22, 0x22, // sender ssrc
+        b'P', b'e', b'e', b'r', 0x00, // name
+    ];
+    let mut buf = [0u8; 64];
+    peer_control.send_to(&invitation, control_addr(&session)).await.unwrap();
+    peer_control.recv_from(&mut buf).await.unwrap();
+    peer_midi.send_to(&invitation, midi_addr(&session)).await.unwrap();
+    peer_midi.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..4], &[0xFF, 0xFF, b'O', b'K']);
+
+    for socket in [&peer_control, &peer_midi] {
+        let (amt, _) = tokio::time::timeout(Duration::from_secs(2), socket.recv_from(&mut buf)).await.unwrap().unwrap();
+        assert_eq!(amt, 0);
+    }
+
+    // The peer's own keepalives are dropped quietly
+    peer_control.send_to(&[], control_addr(&session)).await.unwrap();
+    peer_midi.send_to(&[], midi_addr(&session)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(session.stats().await.parse_errors, 0);
+    assert_eq!(session.participants().await.len(), 1);
+
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_external_addresses_are_discovered_through_stun() {
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+    assert_eq!(session.external_addresses(), None);
+
+    // Answers each binding request with the address it came from, as seen from behind a NAT that adds 1000
+    let server = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = server.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 64];
+        loop {
+            let (amt, src) = server.recv_from(&mut buf).await.unwrap();
+            assert_eq!((amt, &buf[..2], &buf[4..8]), (20, &[0x00, 0x01][..], &[0x21, 0x12, 0xA4, 0x42][..]));
+            let mut response = vec![0x01, 0x01, 0x00, 0x0C, 0x21, 0x12, 0xA4, 0x42];
+            response.extend_from_slice(&buf[8..20]);
+            response.extend_from_slice(&[0x00, 0x20, 0x00, 0x08, 0x00, 0x01]);
+            response.extend_from_slice(&((src.port() + 1000) ^ 0x2112).to_be_bytes());
+            response.extend_from_slice(&(u32::from_be_bytes([127, 0, 0, 1]) ^ 0x2112_A442).to_be_bytes());
+            server.send_to(&response, src).await.unwrap();
+        }
+    });
+
+    let addresses = session.discover_external_addresses(server_addr).await.unwrap();
+    assert_eq!(
+        addresses.control,
+        SocketAddr::new("127.0.0.1".parse().unwrap(), control_addr(&session).port() + 1000)
+    );
+    assert_eq!(addresses.midi, SocketAddr::new("127.0.0.1".parse().unwrap(), midi_addr(&session).port() + 1000));
+    assert!(addresses.is_consecutive());
+    assert_eq!(session.external_addresses(), Some(addresses));
+    assert_eq!(session.stats().await.parse_errors, 0);
+
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_stun_discovery_times_out_without_a_server() {
+    let session = start_session("Session", 0x11111111, SessionConfig::default()).await;
+    let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+
+    let result = session.discover_external_addresses(silent.local_addr().unwrap()).await;
+    assert!(matches!(result, Err(RtpMidiError::Timeout(_))), "{result:?}");
+    assert_eq!(session.external_addresses(), None);
+
+    session.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_dropping_a_session_guard_says_goodbye() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = SessionGuard::new(start_session("Session2", 0x22222222, SessionConfig::default()).await);
+    connect(&session1, &session2).await;
+
+    // Leaving the scope, without stopping the session first
+    drop(session2);
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !session1.participants().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the participant should have said goodbye");
+
+    session1.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_client_connects_and_reconnects() {
+    let server = start_session("Server", 0x11111111, SessionConfig::default()).await;
+    let (server_sender, mut server_messages) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    server
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            server_sender.send(message).unwrap();
+        })
+        .await;
+
+    let options = ClientOptions {
+        reconnect_interval: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let mut client = RtpMidiClient::connect_with_options(control_addr(&server), options).await.unwrap();
+    assert!(client.is_connected().await);
+    assert_eq!(server.participants().await.len(), 1);
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::new(100));
+    client.send(note_on).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(5), server_messages.recv()).await.unwrap();
+    assert_eq!(received, Some(note_on));
+    server.send_midi(&note_on.into()).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(5), client.recv()).await.unwrap();
+    assert_eq!(received, Some(note_on));
+
+    // The server ends the session, and the client joins it again
+    let participant = server.participants().await.remove(0);
+    server.remove_participant(&participant).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while server.participants().await.is_empty() || !client.is_connected().await {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the client should have reconnected");
+
+    client.close().await;
+    server.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_client_times_out_without_a_peer() {
+    let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let options = ClientOptions {
+        connect_timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let result = RtpMidiClient::connect_with_options(silent.local_addr().unwrap(), options).await;
+    assert!(matches!(result, Err(RtpMidiError::Timeout(_))), "{:?}", result.err());
+}
+
+#[tokio::test]
+async fn test_waiting_for_a_participant_to_finish_joining() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let addr2 = control_addr(&session2);
+
+    let result = session1.wait_for_participant(addr2, Duration::from_millis(100)).await;
+    assert!(matches!(result, Err(RtpMidiError::Timeout(_))), "{result:?}");
+
+    session1.invite_participant(addr2).await.unwrap();
+    let participant = session1.wait_for_participant(addr2, Duration::from_secs(5)).await.unwrap();
+    assert_eq!(participant.name(), "Session2");
+    assert!(participant.is_clock_synced());
+    // Either side can wait, by name as well as by address
+    let participant = session2.wait_for_participant("Session1", Duration::from_secs(5)).await.unwrap();
+    assert_eq!(participant.ssrc().get(), 0x11111111);
+    // Already joined
+    session1.wait_for_participant(addr2.to_string(), Duration::ZERO).await.unwrap();
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_sessions_on_port_0_report_where_they_listen() {
+    let session1 = RtpMidiSession::start(0, "Session1", 0x11111111, InviteResponder::Accept)
+        .await
+        .expect("Failed to start RTP MIDI session");
+    let session2 = RtpMidiSession::start(0, "Session2", 0x22222222, InviteResponder::Accept)
+        .await
+        .expect("Failed to start RTP MIDI session");
+
+    let control_addr = session2.local_control_addr();
+    assert_ne!(control_addr.port(), 0);
+    assert_eq!(session2.local_midi_addr().port(), control_addr.port() + 1);
+    assert!(control_addr.ip().is_unspecified());
+
+    let addr2 = SocketAddr::new("127.0.0.1".parse().unwrap(), control_addr.port());
+    session1.invite_participant(addr2).await.unwrap();
+    session1.wait_for_participant(addr2, Duration::from_secs(5)).await.unwrap();
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_listeners_can_be_in_place_before_the_session_starts() {
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let addr2 = control_addr(&session2);
+    let (message_sender, mut messages) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    session2.on(MidiMessageEvent, move |(message, _delta_time)| {
+        message_sender.send(message).unwrap();
+    });
+
+    // The peer is invited as the session starts, before anything could be added to it
+    let joined = Arc::new(Notify::new());
+    let joined_clone = joined.clone();
+    let mut config = SessionConfig {
+        peers: vec![KnownPeer {
+            addr: addr2,
+            name: "Session2".to_owned(),
+        }],
+        ..Default::default()
+    };
+    config.listeners.add_listener(ParticipantJoinedEvent, move |_participant| {
+        joined_clone.notify_one();
+    });
+    let session1 = start_session("Session1", 0x11111111, config).await;
+    tokio::time::timeout(Duration::from_secs(5), joined.notified()).await.unwrap();
+
+    let note_on = MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::new(100));
+    session1.send_midi(&note_on.into()).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(5), messages.recv()).await.unwrap();
+    assert_eq!(received, Some(note_on));
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_send_helpers() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+    let (message_sender, mut messages) = tokio::sync::mpsc::unbounded_channel::<MidiMessage>();
+    session2.on(MidiMessageEvent, move |(message, _delta_time)| {
+        message_sender.send(message).unwrap();
+    });
+    connect(&session1, &session2).await;
+
+    session1.note_on(Channel::C1, Note::C4, Value7::new(100)).await.unwrap();
+    session1.note_off(Channel::C16, Note::C4, Value7::new(0)).await.unwrap();
+    session1.control_change(Channel::C2, Control::new(7), Value7::new(64)).await.unwrap();
+    session1.program_change(Channel::C3, Program::new(42)).await.unwrap();
+    session1.pitch_bend(Channel::C4, Value14::from(8192u16)).await.unwrap();
+    let mut received = Vec::new();
+    for _ in 0..5 {
+        received.push(tokio::time::timeout(Duration::from_secs(5), messages.recv()).await.unwrap().unwrap());
+    }
+    assert_eq!(
+        received,
+        [
+            MidiMessage::NoteOn(Channel::C1, Note::C4, Value7::new(100)),
+            MidiMessage::NoteOff(Channel::C16, Note::C4, Value7::new(0)),
+            MidiMessage::ControlChange(Channel::C2, Control::new(7), Value7::new(64)),
+            MidiMessage::ProgramChange(Channel::C3, Program::new(42)),
+            MidiMessage::PitchBendChange(Channel::C4, Value14::from(8192u16)),
+        ]
+    );
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
+
+#[tokio::test]
+async fn test_participants_can_be_looked_up_by_name_and_ssrc() {
+    let (session1, session2) = connected_sessions(SessionConfig::default(), SessionConfig::default()).await;
+    let addr2 = control_addr(&session2);
+
+    let by_name = session1.participant_by_name("Session2").await.expect("Participant not found by name");
+    assert_eq!(by_name.ssrc().get(), 0x22222222);
+    let by_ssrc = session1.participant_by_ssrc(0x22222222).await.expect("Participant not found by SSRC");
+    assert_eq!(by_ssrc.addr(), by_name.addr());
+    assert_eq!(by_ssrc.to_string(), format!("Participant {{ name: Session2, addr: {addr2}, ssrc: 572662306 }}"));
+    assert!(session1.participant_by_name("Session3").await.is_none());
+    assert!(session1.participant_by_ssrc(0x33333333).await.is_none());
+    for target in [addr2.to_string(), "Session2".to_owned()] {
+        assert_eq!(ParticipantMatch::from(target.as_str()).to_string(), target);
+    }
+
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}
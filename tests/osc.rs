@@ -0,0 +1,63 @@
+mod common;
+
+use common::{connect, start_session};
+use midi_types::{Channel, Control, MidiMessage, Note, Value7};
+use rtpmidi::sessions::events::event_handling::MidiMessageEvent;
+use rtpmidi::sessions::osc::address_scheme::OscAddressScheme;
+use rtpmidi::sessions::osc::osc_bridge::OscBridge;
+use rtpmidi::sessions::osc::osc_message::{OscArg, OscMessage};
+use rtpmidi::sessions::session_config::SessionConfig;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+#[tokio::test]
+async fn test_osc_bridge_passes_messages_both_ways() {
+    let session1 = start_session("Session1", 0x11111111, SessionConfig::default()).await;
+    let session2 = start_session("Session2", 0x22222222, SessionConfig::default()).await;
+
+    let (midi_sender, mut midi_receiver) = tokio::sync::mpsc::unbounded_channel();
+    session1
+        .add_listener(MidiMessageEvent, move |(message, _delta_time)| {
+            midi_sender.send(message).unwrap();
+        })
+        .await;
+
+    let osc_peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let bridge = OscBridge::start(
+        Arc::clone(&session2),
+        "127.0.0.1:0".parse().unwrap(),
+        osc_peer.local_addr().unwrap(),
+        OscAddressScheme::default(),
+    )
+    .await
+    .expect("Failed to start OSC bridge");
+
+    connect(&session1, &session2).await;
+
+    // MIDI to OSC
+    let note = MidiMessage::NoteOn(Channel::C1, Note::from(60), Value7::from(100));
+    session1.send_midi(&note.into()).await.unwrap();
+    let mut buf = [0; 1024];
+    let (amt, _) = tokio::time::timeout(Duration::from_secs(2), osc_peer.recv_from(&mut buf))
+        .await
+        .expect("No OSC message arrived")
+        .unwrap();
+    assert_eq!(
+        OscMessage::from_bytes(&buf[..amt]),
+        Some(vec![OscMessage::new("/midi/1/note_on", vec![OscArg::Int(60), OscArg::Int(100)])])
+    );
+
+    // OSC to MIDI
+    let fader = OscMessage::new("/midi/2/cc", vec![OscArg::Int(7), OscArg::Float(64.0)]);
+    osc_peer.send_to(&fader.to_bytes(), bridge.local_addr()).await.unwrap();
+    let received = tokio::time::timeout(Duration::from_secs(2), midi_receiver.recv())
+        .await
+        .expect("No MIDI message arrived")
+        .unwrap();
+    assert_eq!(received, MidiMessage::ControlChange(Channel::C2, Control::from(7), Value7::from(64)));
+
+    bridge.stop();
+    session1.stop_gracefully().await;
+    session2.stop_gracefully().await;
+}